@@ -1,19 +1,321 @@
-use crate::balancer::Balancer;
+use crate::balancer::{slot_leader_index, Balancer};
+use crate::contract::POSITION_HOOK_REPLY_ID;
 use crate::error::ContractError;
-use crate::helpers::{send_tokens, GenericBalance};
+use crate::helpers::{
+    gas_amount_ceil, parse_client_version, send_tokens, slots_to_nanos, GenericBalance,
+};
+use crate::manager::{agent_reward, slot_open};
 use crate::state::{Config, CwCroncat};
 use cosmwasm_std::{
-    has_coins, Addr, Coin, Deps, DepsMut, Env, MessageInfo, Response, StdError, StdResult, Storage,
-    SubMsg,
+    has_coins, to_binary, Addr, BankMsg, Binary, Coin, Decimal, Deps, DepsMut, Env, MessageInfo,
+    Order, Response, StdError, StdResult, Storage, SubMsg, Timestamp, Uint128, Uint64, WasmMsg,
 };
+use cw20::{Cw20Coin, Cw20CoinVerified, Cw20ExecuteMsg, Cw20QueryMsg};
 use cw_storage_plus::Bound;
 use std::ops::Div;
 
 use crate::ContractError::AgentNotRegistered;
-use cw_croncat_core::msg::{AgentTaskResponse, GetAgentIdsResponse};
-use cw_croncat_core::types::{Agent, AgentResponse, AgentStatus};
+use cw_croncat_core::msg::{
+    AgentBalanceDriftResponse, AgentEscrowResponse, AgentExecutionWindowResponse, AgentHookMsg,
+    AgentLivenessResponse, AgentTaskResponse, AgentTenureResponse, ConfigChange,
+    ConfigChangeImpactResponse, DexRouterExecuteMsg, GetAgentBalanceResponse, GetAgentIdsResponse,
+    MissedSlotStatsResponse, NetRewardEstimateResponse, NominationConfigResponse,
+    NominationStatusResponse, QueueCapacityResponse, QueueIntegrityResponse,
+    SlashableAgentResponse, SolvencyResponse, UpdateAgentParams, VestedBalanceResponse,
+};
+use cw_croncat_core::traits::{FindAndMutate, Intervals};
+use cw_croncat_core::types::{
+    Agent, AgentPool, AgentResponse, AgentStatus, Interval, NominationBlockReason, NominationMode,
+    QueueRemovalMode, RewardPreference, RewardRounding, SelectionMode, SlashDestination, SlotType,
+    Task,
+};
+
+/// How many operator keys a single agent can delegate execution to
+pub(crate) const MAX_AGENT_OPERATORS: usize = 5;
+/// How many capabilities a single agent can declare, and how long each can be
+pub(crate) const MAX_AGENT_CAPABILITIES: usize = 10;
+pub(crate) const MAX_CAPABILITY_LENGTH: usize = 32;
+/// How many agents a single `GetAgents` or `GetAllAgents` query can look up
+pub(crate) const MAX_AGENTS_PER_QUERY: usize = 50;
+/// How long an agent pool id can be
+pub(crate) const MAX_POOL_ID_LENGTH: usize = 32;
+/// How many members and join requests a single agent pool can hold, settling loops over them
+pub(crate) const MAX_AGENT_POOL_MEMBERS: usize = 20;
+/// How many vesting credits a single agent can have open, rewards are bucketed to stay below it
+pub(crate) const MAX_VESTING_CREDITS: usize = 20;
+/// How many withdrawal idempotency keys are remembered per agent, and how long each can be
+pub(crate) const MAX_IDEMPOTENCY_KEYS: usize = 10;
+pub(crate) const MAX_IDEMPOTENCY_KEY_LENGTH: usize = 64;
+/// How long a withdrawal memo can be
+pub(crate) const MAX_WITHDRAW_MEMO_LENGTH: usize = 256;
+
+/// Checks the capability list limits, repeated tags are kept once
+fn validate_capabilities(capabilities: Vec<String>) -> Result<Vec<String>, ContractError> {
+    if capabilities.len() > MAX_AGENT_CAPABILITIES {
+        return Err(ContractError::TooManyCapabilities {
+            max: MAX_AGENT_CAPABILITIES,
+        });
+    }
+    let mut validated: Vec<String> = Vec::with_capacity(capabilities.len());
+    for capability in capabilities {
+        if capability.is_empty() || capability.len() > MAX_CAPABILITY_LENGTH {
+            return Err(ContractError::InvalidCapability {
+                capability,
+                max_length: MAX_CAPABILITY_LENGTH,
+            });
+        }
+        if !validated.contains(&capability) {
+            validated.push(capability);
+        }
+    }
+    Ok(validated)
+}
+
+/// Handlers that take no funds reject attached ones, or send them back
+/// to the sender when `refund_stray_funds` is on
+fn stray_funds_refund(config: &Config, info: &MessageInfo) -> Result<Vec<SubMsg>, ContractError> {
+    if info.funds.is_empty() {
+        return Ok(vec![]);
+    }
+    if !config.refund_stray_funds {
+        return Err(ContractError::CustomError {
+            val: "Do not attach funds".to_string(),
+        });
+    }
+    Ok(vec![SubMsg::new(BankMsg::Send {
+        to_address: info.sender.to_string(),
+        amount: info.funds.clone(),
+    })])
+}
+
+/// What `from` holds over `to`, per denom and per cw20 token
+fn balance_excess(from: &GenericBalance, to: &GenericBalance) -> GenericBalance {
+    let native = from
+        .native
+        .iter()
+        .filter_map(|coin| {
+            let other = to
+                .native
+                .iter()
+                .find(|other| other.denom == coin.denom)
+                .map_or(Uint128::zero(), |other| other.amount);
+            (coin.amount > other).then(|| Coin::new((coin.amount - other).u128(), &coin.denom))
+        })
+        .collect();
+    let cw20 = from
+        .cw20
+        .iter()
+        .filter_map(|coin| {
+            let other = to
+                .cw20
+                .iter()
+                .find(|other| other.address == coin.address)
+                .map_or(Uint128::zero(), |other| other.amount);
+            (coin.amount > other).then(|| Cw20CoinVerified {
+                address: coin.address.clone(),
+                amount: coin.amount - other,
+            })
+        })
+        .collect();
+    GenericBalance { native, cw20 }
+}
+
+/// A freshly registered agent, with nothing executed or earned yet
+pub(crate) fn new_agent(
+    payable_account_id: Addr,
+    register_start: Timestamp,
+    capabilities: Vec<String>,
+) -> Agent {
+    Agent {
+        payable_account_id,
+        balance: GenericBalance::default(),
+        total_tasks_executed: 0,
+        last_missed_slot: 0,
+        // REF: https://github.com/CosmWasm/cosmwasm/blob/main/packages/std/src/types.rs#L57
+        register_start,
+        operators: vec![],
+        last_execution: None,
+        consecutive_misses: 0,
+        self_paused: false,
+        capabilities,
+        reward_preference: RewardPreference::Native,
+        bond: vec![],
+        auto_compound: false,
+        last_activation: None,
+        pending_dust: vec![],
+        pool_id: None,
+        accepted_task_types: vec![],
+        missed_nominations: 0,
+        position_hook: None,
+        reward_multiplier_bps: 10_000,
+        accepted_terms_hash: None,
+        stream_rewards: false,
+        current_streak: 0,
+        client_version: None,
+    }
+}
+
+/// Addresses that appear more than once, each reported once in order of first repeat
+fn find_duplicates(queue: &[Addr]) -> Vec<Addr> {
+    let mut dupes: Vec<Addr> = vec![];
+    for (i, agent_id) in queue.iter().enumerate() {
+        if queue[..i].contains(agent_id) && !dupes.contains(agent_id) {
+            dupes.push(agent_id.clone());
+        }
+    }
+    dupes
+}
+
+/// Removes the nonzero coin of `denom` from the list
+fn take_denom(coins: &mut Vec<Coin>, denom: &str) -> Option<Coin> {
+    let index = coins
+        .iter()
+        .position(|coin| coin.denom == denom && !coin.amount.is_zero())?;
+    Some(coins.remove(index))
+}
+
+/// How many times `task` runs over the next `horizon_slots` block slots.
+/// Cron tasks are counted over the same wall-clock span, at most once per slot
+fn runs_within_horizon(config: &Config, env: &Env, task: &Task, horizon_slots: u64) -> u64 {
+    match &task.interval {
+        // Removed after its only run
+        Interval::Once => horizon_slots.min(1),
+        Interval::Immediate => horizon_slots,
+        Interval::Block(every) => horizon_slots / (*every).max(1),
+        Interval::Cron(_) => {
+            let end = env.block.time.nanos().saturating_add(slots_to_nanos(
+                config,
+                SlotType::Block,
+                horizon_slots,
+            ));
+            let mut at = env.clone();
+            let mut runs = 0;
+            while runs < horizon_slots {
+                let (next, _) = task.interval.next(&at, task.boundary);
+                if next > end {
+                    break;
+                }
+                runs += 1;
+                at.block.time = Timestamp::from_nanos(next.saturating_add(1));
+            }
+            runs
+        }
+    }
+}
+
+/// Whether the agent missed more slots in a row than `Config.missed_slot_threshold` allows
+fn is_slashable(config: &Config, agent: &Agent) -> bool {
+    agent.consecutive_misses > config.missed_slot_threshold
+}
+
+/// Whether an agent wallet can pay for a few transactions, checked when registering
+fn covers_registration_gas(
+    wallet: &[Coin],
+    gas_price: Decimal,
+    native_denom: &str,
+) -> Result<bool, ContractError> {
+    let unit_cost = gas_amount_ceil(4, gas_price)?;
+    Ok(!wallet.is_empty() && has_coins(wallet, &Coin::new(unit_cost.u128(), native_denom)))
+}
+
+/// Rejects malformed client versions, and missing or older ones once `min_client_version` is set
+fn check_client_version(config: &Config, version: Option<&str>) -> Result<(), ContractError> {
+    let version = version.map(parse_client_version).transpose()?;
+    if let Some(min_version) = &config.min_client_version {
+        let min = parse_client_version(min_version)?;
+        let too_old = match version {
+            Some(version) => version < min,
+            None => true,
+        };
+        if too_old {
+            return Err(ContractError::CustomError {
+                val: format!("Client version must be at least {}", min_version),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Rejects paying out to the contract itself or to a blacklisted account
+fn check_payable_account(
+    config: &Config,
+    env: &Env,
+    payable_account_id: &Addr,
+) -> Result<(), ContractError> {
+    if *payable_account_id == env.contract.address {
+        return Err(ContractError::SelfPayableAccount {});
+    }
+    if config.blacklist.contains(payable_account_id) {
+        return Err(ContractError::BlacklistedPayableAccount {});
+    }
+    Ok(())
+}
+
+/// Comma separated list of native and cw20 amounts, "0" if empty
+fn format_balance(balance: &GenericBalance) -> String {
+    let amounts: Vec<String> = balance
+        .native
+        .iter()
+        .map(|c| c.to_string())
+        .chain(
+            balance
+                .cw20
+                .iter()
+                .map(|c| format!("{}{}", c.amount, c.address)),
+        )
+        .collect();
+    if amounts.is_empty() {
+        "0".to_string()
+    } else {
+        amounts.join(",")
+    }
+}
+
+/// Both agent queues, loaded once for a handler that touches them in several steps.
+/// `flush_queues` only writes back the ones that went through `*_mut`
+pub(crate) struct QueueContext {
+    pub active: Vec<Addr>,
+    pub pending: Vec<Addr>,
+    active_changed: bool,
+    pending_changed: bool,
+}
+
+impl QueueContext {
+    pub fn active_mut(&mut self) -> &mut Vec<Addr> {
+        self.active_changed = true;
+        &mut self.active
+    }
+
+    pub fn pending_mut(&mut self) -> &mut Vec<Addr> {
+        self.pending_changed = true;
+        &mut self.pending
+    }
+}
 
 impl<'a> CwCroncat<'a> {
+    pub(crate) fn load_queues(&self, storage: &dyn Storage) -> StdResult<QueueContext> {
+        Ok(QueueContext {
+            active: self.agent_active_queue.load(storage)?,
+            pending: self.agent_pending_queue.load(storage)?,
+            active_changed: false,
+            pending_changed: false,
+        })
+    }
+
+    pub(crate) fn flush_queues(
+        &self,
+        storage: &mut dyn Storage,
+        queues: &QueueContext,
+    ) -> StdResult<()> {
+        if queues.active_changed {
+            self.agent_active_queue.save(storage, &queues.active)?;
+        }
+        if queues.pending_changed {
+            self.agent_pending_queue.save(storage, &queues.pending)?;
+        }
+        Ok(())
+    }
+
     /// Get a single agent details
     /// Check's status as well, in case this agent needs to be considered for election
     pub(crate) fn query_get_agent(
@@ -29,6 +331,8 @@ impl<'a> CwCroncat<'a> {
         }
         let active: Vec<Addr> = self.agent_active_queue.load(deps.storage)?;
         let a = agent.unwrap();
+        let c: Config = self.config.load(deps.storage)?;
+        let last_seen = a.last_execution.unwrap_or(a.register_start);
         let mut agent_response = AgentResponse {
             status: AgentStatus::Pending, // Simple default
             payable_account_id: a.payable_account_id,
@@ -36,10 +340,33 @@ impl<'a> CwCroncat<'a> {
             total_tasks_executed: a.total_tasks_executed,
             last_missed_slot: a.last_missed_slot,
             register_start: a.register_start,
+            operators: a.operators,
+            last_execution: a.last_execution,
+            consecutive_misses: a.consecutive_misses,
+            self_paused: a.self_paused,
+            capabilities: a.capabilities,
+            reward_preference: a.reward_preference,
+            bond: a.bond,
+            auto_compound: a.auto_compound,
+            last_activation: a.last_activation,
+            pending_dust: a.pending_dust,
+            pool_id: a.pool_id,
+            accepted_task_types: a.accepted_task_types,
+            missed_nominations: a.missed_nominations,
+            position_hook: a.position_hook,
+            reward_multiplier_bps: a.reward_multiplier_bps,
+            accepted_terms_hash: a.accepted_terms_hash,
+            stream_rewards: a.stream_rewards,
+            current_streak: a.current_streak,
+            client_version: a.client_version,
+            is_stale: false,
         };
 
         if active.contains(&account_id) {
             agent_response.status = AgentStatus::Active;
+            // Only reported, the agent stays in the active queue until it gets removed
+            agent_response.is_stale =
+                env.block.time.nanos().saturating_sub(last_seen.nanos()) > c.stale_timeout_nanos;
             return Ok(Some(agent_response));
         }
 
@@ -56,1335 +383,10260 @@ impl<'a> CwCroncat<'a> {
         Ok(Some(agent_response))
     }
 
-    /// Get a list of agent addresses
-    pub(crate) fn query_get_agent_ids(&self, deps: Deps) -> StdResult<GetAgentIdsResponse> {
-        let active: Vec<Addr> = self.agent_active_queue.load(deps.storage)?;
-        let pending: Vec<Addr> = self.agent_pending_queue.load(deps.storage)?;
-
-        Ok(GetAgentIdsResponse { active, pending })
-    }
-
-    // TODO: Change this to solid round-table implementation. Setup this simple version for PoC
-    /// Get how many tasks an agent can execute
-    /// TODO: Remove this function, replaced by balancer
-    pub(crate) fn query_get_agent_tasks(
-        &mut self,
+    /// Same as `GetAgent` for several agents at once, none for the ones that aren't registered
+    pub(crate) fn query_get_agents(
+        &self,
         deps: Deps,
         env: Env,
-        account_id: String,
-    ) -> StdResult<Option<AgentTaskResponse>> {
-        let account_id = deps.api.addr_validate(&account_id)?;
-        let active = self.agent_active_queue.load(deps.storage)?;
-        if !active.contains(&account_id) {
-            // TODO: unsure if we can return AgentNotRegistered
-            return Err(StdError::GenericErr {
-                msg: AgentNotRegistered {}.to_string(),
-            });
+        account_ids: Vec<String>,
+    ) -> StdResult<Vec<(Addr, Option<AgentResponse>)>> {
+        if account_ids.len() > MAX_AGENTS_PER_QUERY {
+            return Err(StdError::generic_err(format!(
+                "Can't query more than {} agents at once",
+                MAX_AGENTS_PER_QUERY
+            )));
         }
-        // Get all tasks (the final None means no limit when we take)
-        let block_slots = self
-            .block_slots
-            .range(
-                deps.storage,
-                None,
-                Some(Bound::inclusive(env.block.height)),
-                cosmwasm_std::Order::Ascending,
-            )
-            .count();
+        account_ids
+            .into_iter()
+            .map(|account_id| {
+                let account = deps.api.addr_validate(&account_id)?;
+                let agent = self.query_get_agent(deps, env.clone(), account_id)?;
+                Ok((account, agent))
+            })
+            .collect()
+    }
 
-        let time_slots = self
-            .time_slots
-            .range(
+    /// Full details of every registered agent, ordered by address.
+    /// Pass the last address of a page as `start_after` to get the next one
+    pub(crate) fn query_all_agents(
+        &self,
+        deps: Deps,
+        env: Env,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    ) -> StdResult<Vec<(Addr, AgentResponse)>> {
+        let start_after = start_after
+            .map(|addr| deps.api.addr_validate(&addr))
+            .transpose()?;
+        let limit = limit
+            .map_or(MAX_AGENTS_PER_QUERY, |limit| limit as usize)
+            .min(MAX_AGENTS_PER_QUERY);
+        let account_ids = self
+            .agents
+            .keys(
                 deps.storage,
+                start_after.as_ref().map(Bound::exclusive),
                 None,
-                Some(Bound::inclusive(env.block.time.nanos())),
-                cosmwasm_std::Order::Ascending,
+                Order::Ascending,
             )
-            .count();
+            .take(limit)
+            .collect::<StdResult<Vec<Addr>>>()?;
+        account_ids
+            .into_iter()
+            .map(|account_id| {
+                let agent = self
+                    .query_get_agent(deps, env.clone(), account_id.to_string())?
+                    .ok_or_else(|| StdError::not_found("Agent"))?;
+                Ok((account_id, agent))
+            })
+            .collect()
+    }
 
-        if (block_slots, time_slots) == (0, 0) {
-            return Ok(None);
-        }
+    /// Get an agent's reward balance, flattened for display
+    pub(crate) fn query_get_agent_balance(
+        &self,
+        deps: Deps,
+        account_id: String,
+    ) -> StdResult<Option<GetAgentBalanceResponse>> {
+        let account_id = deps.api.addr_validate(&account_id)?;
+        let agent = self.agents.may_load(deps.storage, &account_id)?;
+        Ok(agent.map(|a| GetAgentBalanceResponse {
+            native: a.balance.native,
+            cw20: a
+                .balance
+                .cw20
+                .into_iter()
+                .map(|c| Cw20Coin {
+                    address: c.address.to_string(),
+                    amount: c.amount,
+                })
+                .collect(),
+        }))
+    }
 
-        self.balancer.get_agent_tasks(
-            &deps,
-            &env,
-            &self.config,
-            &self.agent_active_queue,
-            account_id,
-            (Some(block_slots as u64), Some(time_slots as u64)),
-        )
+    /// Zero-based position of the agent in the active queue, none if it isn't active.
+    /// Together with the current slot, tells a keeper when its turn is
+    pub(crate) fn query_agent_rank(
+        &self,
+        deps: Deps,
+        account_id: String,
+    ) -> StdResult<Option<u64>> {
+        let account_id = deps.api.addr_validate(&account_id)?;
+        let active = self.agent_active_queue.load(deps.storage)?;
+        Ok(active
+            .iter()
+            .position(|addr| *addr == account_id)
+            .map(|rank| rank as u64))
     }
 
-    /// Add any account as an agent that will be able to execute tasks.
-    /// Registering allows for rewards accruing with micro-payments which will accumulate to more long-term.
-    ///
-    /// Optional Parameters:
-    /// "payable_account_id" - Allows a different account id to be specified, so a user can receive funds at a different account than the agent account.
-    pub fn register_agent(
+    /// Share of the current time slot for the agent by rank, so active agents don't all submit
+    /// at once. The slot is `slot_granularity` long, the cron slot length, not the block slot
+    /// `slot_duration_nanos`. It is cut in equal parts of at least a nanosecond,
+    /// the last window also gets what doesn't divide evenly.
+    /// None if it isn't active, or its rank is past the nanoseconds of the slot
+    pub(crate) fn query_agent_execution_window(
         &self,
-        deps: DepsMut,
-        info: MessageInfo,
+        deps: Deps,
         env: Env,
-        payable_account_id: Option<String>,
-    ) -> Result<Response, ContractError> {
-        if !info.funds.is_empty() {
-            return Err(ContractError::CustomError {
-                val: "Do not attach funds".to_string(),
-            });
-        }
+        account_id: String,
+    ) -> StdResult<Option<AgentExecutionWindowResponse>> {
+        let account_id = deps.api.addr_validate(&account_id)?;
+        let active = self.agent_active_queue.load(deps.storage)?;
+        let rank = match active.iter().position(|addr| *addr == account_id) {
+            Some(rank) => rank as u64,
+            None => return Ok(None),
+        };
         let c: Config = self.config.load(deps.storage)?;
-        if c.paused {
-            return Err(ContractError::ContractPaused {
-                val: "Register agent paused".to_string(),
-            });
-        }
-
-        let account = info.sender;
-
-        // REF: https://github.com/CosmWasm/cw-tokens/tree/main/contracts/cw20-escrow
-        // Check if native token balance is sufficient for a few txns, in this case 4 txns
-        // TODO: Adjust gas & costs based on real usage cost
-        let agent_wallet_balances = deps.querier.query_all_balances(account.clone())?;
-        let unit_cost = c.gas_price * 4;
-        if !has_coins(
-            &agent_wallet_balances,
-            &Coin::new(u128::from(unit_cost), c.native_denom),
-        ) || agent_wallet_balances.is_empty()
-        {
-            return Err(ContractError::CustomError {
-                val: "Insufficient funds".to_string(),
-            });
+        let granularity = c.slot_granularity.max(1);
+        let now = env.block.time.nanos();
+        let slot_start = now - now % granularity;
+        // With more agents than nanoseconds in a slot, only the front of the queue gets a window
+        let windows = (active.len() as u64).min(granularity);
+        if rank >= windows {
+            return Ok(None);
         }
-
-        let payable_id = if let Some(addr) = payable_account_id {
-            deps.api.addr_validate(&addr)?
+        let width = granularity / windows;
+        let window_start_nanos = slot_start + rank * width;
+        let window_end_nanos = if rank + 1 == windows {
+            slot_start + granularity
         } else {
-            account.clone()
+            window_start_nanos + width
         };
+        Ok(Some(AgentExecutionWindowResponse {
+            window_start_nanos,
+            window_end_nanos,
+        }))
+    }
 
-        let mut active_agents: Vec<Addr> = self.agent_active_queue.load(deps.storage)?;
-        let total_agents = active_agents.len();
-        let agent_status = if total_agents == 0 {
-            active_agents.push(account.clone());
-            self.agent_active_queue.save(deps.storage, &active_agents)?;
-            AgentStatus::Active
-        } else {
-            let mut pending_agents = self.agent_pending_queue.load(deps.storage)?;
-            pending_agents.push(account.clone());
-            self.agent_pending_queue
-                .save(deps.storage, &pending_agents)?;
-            AgentStatus::Pending
+    /// First slot from `from_slot` on that the balancer leads with the agent,
+    /// see `slot_leader_index`. Without `rotate_queue_each_slot` the first agent leads every slot.
+    /// None if it isn't active or never leads, and under weighted or lottery selection,
+    /// where the leader depends on executions and bonds at the time of the slot.
+    /// Agents declining a task's type can still be skipped for it
+    pub(crate) fn query_next_slot_for_agent(
+        &self,
+        deps: Deps,
+        account_id: String,
+        from_slot: u64,
+    ) -> StdResult<Option<u64>> {
+        let account_id = deps.api.addr_validate(&account_id)?;
+        let c: Config = self.config.load(deps.storage)?;
+        if c.weighted_selection || c.selection == SelectionMode::Lottery {
+            return Ok(None);
+        }
+        // Self-paused agents are left out, same as in the balancer
+        let mut active = vec![];
+        for addr in self.agent_active_queue.load(deps.storage)? {
+            let paused = match self.agents.may_load(deps.storage, &addr)? {
+                Some(agent) => addr != account_id && agent.self_paused,
+                None => false,
+            };
+            if !paused {
+                active.push(addr);
+            }
+        }
+        let index = match active.iter().position(|addr| *addr == account_id) {
+            Some(index) => index as u64,
+            None => return Ok(None),
         };
-        let agent = self.agents.update(
-            deps.storage,
-            &account,
-            |a: Option<Agent>| -> Result<_, ContractError> {
-                match a {
-                    // make sure that account isn't already added
-                    Some(_) => Err(ContractError::CustomError {
-                        val: "Agent already exists".to_string(),
-                    }),
-                    None => {
-                        Ok(Agent {
-                            payable_account_id: payable_id,
-                            balance: GenericBalance::default(),
-                            total_tasks_executed: 0,
-                            last_missed_slot: 0,
-                            // REF: https://github.com/CosmWasm/cosmwasm/blob/main/packages/std/src/types.rs#L57
-                            register_start: env.block.time,
-                        })
-                    }
-                }
-            },
-        )?;
-
-        Ok(Response::new()
-            .add_attribute("method", "register_agent")
-            .add_attribute("agent_status", format!("{:?}", agent_status))
-            .add_attribute("register_start", agent.register_start.nanos().to_string())
-            .add_attribute("payable_account_id", agent.payable_account_id))
+        // Leaders repeat every `active.len()` slots
+        for step in 0..active.len() as u64 {
+            let slot = match from_slot.checked_add(step) {
+                Some(slot) => slot,
+                None => break,
+            };
+            if slot_leader_index(&deps, &c, &self.agents, &active, slot)? == index {
+                return Ok(Some(slot));
+            }
+        }
+        Ok(None)
     }
 
-    /// Update agent details, specifically the payable account id for an agent.
-    pub fn update_agent(
+    /// Rough estimate of what the agent would earn over the next `horizon_slots` slots.
+    /// Assumes nothing changes over the horizon: every current task runs as often as its interval
+    /// allows, split evenly between active agents, each paying the agent's current reward.
+    /// Agents not active yet are counted as joining the active set
+    pub(crate) fn query_projected_rewards(
         &self,
-        deps: DepsMut,
-        info: MessageInfo,
-        _env: Env,
-        payable_account_id: String,
-    ) -> Result<Response, ContractError> {
-        let payable_account_id = deps.api.addr_validate(&payable_account_id)?;
+        deps: Deps,
+        env: Env,
+        account_id: String,
+        horizon_slots: u64,
+    ) -> StdResult<Coin> {
+        let account_id = deps.api.addr_validate(&account_id)?;
         let c: Config = self.config.load(deps.storage)?;
-        if c.paused {
-            return Err(ContractError::ContractPaused {
-                val: "Register agent paused".to_string(),
-            });
+        let agent = match self.agents.may_load(deps.storage, &account_id)? {
+            Some(agent) => agent,
+            // Would register now
+            None => new_agent(account_id.clone(), env.block.time, vec![]),
+        };
+        let active = self.agent_active_queue.load(deps.storage)?;
+        let mut active_count = active.len() as u64;
+        if !active.contains(&account_id) {
+            active_count += 1;
+        }
+        let mut total_runs = 0u64;
+        for item in self.tasks.range(deps.storage, None, None, Order::Ascending) {
+            let (_, task) = item?;
+            total_runs =
+                total_runs.saturating_add(runs_within_horizon(&c, &env, &task, horizon_slots));
         }
 
-        let agent = self.agents.update(
-            deps.storage,
-            &info.sender,
-            |a: Option<Agent>| -> Result<_, ContractError> {
-                match a {
-                    Some(agent) => {
-                        let mut ag = agent;
-                        ag.payable_account_id = payable_account_id;
-                        Ok(ag)
-                    }
-                    None => Err(ContractError::AgentNotRegistered {}),
-                }
-            },
-        )?;
-
-        Ok(Response::new()
-            .add_attribute("method", "update_agent")
-            .add_attribute("payable_account_id", agent.payable_account_id))
+        let reward = agent_reward(&c, env.block.time, &agent);
+        let projected = reward.amount.checked_mul(total_runs.into())? / Uint128::from(active_count);
+        Ok(Coin::new(projected.u128(), reward.denom))
     }
 
-    /// Allows an agent to withdraw all rewards, paid to the specified payable account id.
-    pub(crate) fn withdraw_balances(
+    /// Get the denom the agent's rewards get paid out in.
+    /// A cw20 preference is only honored through the dex router at withdrawal, without one it's native
+    pub(crate) fn query_agent_reward_denom(
         &self,
-        storage: &mut dyn Storage,
-        info: MessageInfo,
-    ) -> Result<Vec<SubMsg>, ContractError> {
-        let mut agent = self
+        deps: Deps,
+        account_id: String,
+    ) -> StdResult<String> {
+        let account_id = deps.api.addr_validate(&account_id)?;
+        let agent = self
             .agents
-            .may_load(storage, &info.sender)?
-            .ok_or(AgentNotRegistered {})?;
+            .may_load(deps.storage, &account_id)?
+            .ok_or_else(|| StdError::generic_err(AgentNotRegistered {}.to_string()))?;
+        let c: Config = self.config.load(deps.storage)?;
+        match (agent.reward_preference, c.dex_router) {
+            (RewardPreference::Cw20(token), Some(_)) => Ok(token.to_string()),
+            _ => Ok(c.agent_fee.denom),
+        }
+    }
 
-        // This will send all token balances to Agent
-        let (messages, balances) = send_tokens(&agent.payable_account_id, &agent.balance)?;
-        agent.balance.checked_sub_generic(&balances)?;
-        let mut config = self.config.load(storage)?;
-        config
-            .available_balance
-            .checked_sub_native(&balances.native)?;
-        self.agents.save(storage, &info.sender, &agent)?;
-        self.config.save(storage, &config)?;
-
-        Ok(messages)
+    /// Estimate what the agent keeps from its next task once gas is paid,
+    /// counting `gas_base_fee` at `gas_price` as the gas a task takes.
+    /// The task pays the agent back `gas_base_fee` like proxy calls do.
+    /// Errors when the agent fee isn't in the native denom, the amounts wouldn't add up
+    pub(crate) fn query_net_reward_estimate(
+        &self,
+        deps: Deps,
+        env: Env,
+        account_id: String,
+    ) -> StdResult<NetRewardEstimateResponse> {
+        let account_id = deps.api.addr_validate(&account_id)?;
+        let agent = self
+            .agents
+            .may_load(deps.storage, &account_id)?
+            .ok_or_else(|| StdError::generic_err(AgentNotRegistered {}.to_string()))?;
+        let c: Config = self.config.load(deps.storage)?;
+        if c.agent_fee.denom != c.native_denom {
+            return Err(StdError::generic_err(
+                "Agent fee isn't in the native denom, net reward can't be estimated",
+            ));
+        }
+        let gross_reward = agent_reward(&c, env.block.time, &agent);
+        let gas_reimbursement = Uint128::from(c.gas_base_fee);
+        let gas_cost = gas_amount_ceil(c.gas_base_fee, c.gas_price)
+            .map_err(|err| StdError::generic_err(err.to_string()))?;
+        let net = gross_reward
+            .amount
+            .checked_add(gas_reimbursement)?
+            .saturating_sub(gas_cost);
+        Ok(NetRewardEstimateResponse {
+            net: Coin::new(net.u128(), c.native_denom.clone()),
+            profitable: !net.is_zero(),
+            gas_reimbursement: Coin::new(gas_reimbursement.u128(), c.native_denom.clone()),
+            estimated_gas_cost: Coin::new(gas_cost.u128(), c.native_denom),
+            gross_reward,
+        })
     }
 
-    /// Allows an agent to withdraw all rewards, paid to the specified payable account id.
-    pub fn withdraw_agent_balance(
+    /// Get the vesting rewards of an agent, split in what can be withdrawn now and what's still locked
+    pub(crate) fn query_vested_balance(
         &self,
-        deps: DepsMut,
-        info: MessageInfo,
-        _env: Env,
-    ) -> Result<Response, ContractError> {
-        let messages = self.withdraw_balances(deps.storage, info.clone())?;
+        deps: Deps,
+        env: Env,
+        account_id: String,
+    ) -> StdResult<VestedBalanceResponse> {
+        let account_id = deps.api.addr_validate(&account_id)?;
+        let credits = self
+            .agent_vesting
+            .may_load(deps.storage, &account_id)?
+            .unwrap_or_default();
+        let mut vested: Vec<Coin> = vec![];
+        let mut vesting: Vec<Coin> = vec![];
+        for credit in credits {
+            let vested_amount = credit.vested(env.block.time.nanos());
+            for (coins, amount) in [
+                (&mut vested, vested_amount - credit.released),
+                (&mut vesting, credit.amount.amount - vested_amount),
+            ] {
+                if amount.is_zero() {
+                    continue;
+                }
+                match coins.iter_mut().find(|c| c.denom == credit.amount.denom) {
+                    Some(coin) => coin.amount = coin.amount.checked_add(amount)?,
+                    None => coins.push(Coin::new(amount.u128(), credit.amount.denom.clone())),
+                }
+            }
+        }
+        Ok(VestedBalanceResponse { vested, vesting })
+    }
 
-        Ok(Response::new()
-            .add_attribute("method", "withdraw_agent_balance")
-            .add_attribute("account_id", info.sender)
-            .add_submessages(messages))
+    /// Get how long ago an agent last executed a proxy call
+    pub(crate) fn query_agent_liveness(
+        &self,
+        deps: Deps,
+        env: Env,
+        account_id: String,
+    ) -> StdResult<Option<AgentLivenessResponse>> {
+        let account_id = deps.api.addr_validate(&account_id)?;
+        let agent = self.agents.may_load(deps.storage, &account_id)?;
+        Ok(agent.map(|a| match a.last_execution {
+            Some(last_execution) => AgentLivenessResponse {
+                last_execution_nanos: last_execution.nanos(),
+                nanos_since: env
+                    .block
+                    .time
+                    .nanos()
+                    .saturating_sub(last_execution.nanos()),
+            },
+            // Never executed
+            None => AgentLivenessResponse {
+                last_execution_nanos: 0,
+                nanos_since: u64::MAX,
+            },
+        }))
     }
 
-    /// Allows an agent to accept a nomination within a certain amount of time to become an active agent.
-    pub fn accept_nomination_agent(
+    /// How long ago the agent registered, so clients don't have to work it out from nanos
+    pub(crate) fn query_agent_tenure(
         &self,
-        deps: DepsMut,
-        info: MessageInfo,
+        deps: Deps,
         env: Env,
-    ) -> Result<Response, ContractError> {
-        // Compare current time and Config's agent_nomination_begin_time to see if agent can join
+        account_id: String,
+    ) -> StdResult<AgentTenureResponse> {
+        let account_id = deps.api.addr_validate(&account_id)?;
+        let agent = self
+            .agents
+            .may_load(deps.storage, &account_id)?
+            .ok_or_else(|| StdError::generic_err(AgentNotRegistered {}.to_string()))?;
+        Ok(AgentTenureResponse {
+            register_start_nanos: agent.register_start.nanos(),
+            tenure_seconds: env
+                .block
+                .time
+                .nanos()
+                .saturating_sub(agent.register_start.nanos())
+                / 1_000_000_000,
+        })
+    }
+
+    /// Get the sum of all agent balances
+    pub(crate) fn query_total_agent_value(&self, deps: Deps) -> StdResult<GenericBalance> {
         let c: Config = self.config.load(deps.storage)?;
+        Ok(c.total_agent_balance)
+    }
 
-        let time_difference =
-            if let Some(nomination_start) = self.agent_nomination_begin_time.load(deps.storage)? {
-                env.block.time.seconds() - nomination_start.seconds()
-            } else {
-                // No agents can join yet
-                return Err(ContractError::CustomError {
-                    val: "Not accepting new agents".to_string(),
-                });
-            };
-        // Agent must be in the pending queue
-        let pending_queue = self.agent_pending_queue.load(deps.storage)?;
-        // Get the position in the pending queue
-        if let Some(agent_position) = pending_queue
-            .iter()
-            .position(|address| address == &info.sender)
-        {
-            // It works out such that the time difference between when this is called,
-            // and the agent nomination begin time can be divided by the nomination
-            // duration and we get an integer. We use that integer to determine if an
-            // agent is allowed to get let in. If their position in the pending queue is
-            // less than or equal to that integer, they get let in.
-            let max_index = time_difference.div(c.agent_nomination_duration as u64);
-            if agent_position as u64 <= max_index {
-                // Make this agent active
-                // Update state removing from pending queue
-                let mut pending_agents: Vec<Addr> = self.agent_pending_queue.load(deps.storage)?;
-                // Remove this agent and all ahead of them in the queue (they missed out)
-                for idx_to_remove in (0..=agent_position).rev() {
-                    pending_agents.remove(idx_to_remove);
-                }
-                self.agent_pending_queue
-                    .save(deps.storage, &pending_agents)?;
+    /// Compare `total_agent_balance` against the sum of all agent balances, bonds, pending dust,
+    /// escrowed, vesting and pooled rewards, over native and cw20 alike
+    pub(crate) fn query_agent_balance_drift(
+        &self,
+        deps: Deps,
+    ) -> StdResult<AgentBalanceDriftResponse> {
+        let c: Config = self.config.load(deps.storage)?;
+        let computed = self.agent_liabilities(deps.storage)?;
+        Ok(AgentBalanceDriftResponse {
+            surplus: balance_excess(&c.total_agent_balance, &computed),
+            deficit: balance_excess(&computed, &c.total_agent_balance),
+            tracked: c.total_agent_balance,
+            computed,
+        })
+    }
 
-                // and adding to active queue
-                let mut active_agents: Vec<Addr> = self.agent_active_queue.load(deps.storage)?;
-                active_agents.push(info.sender.clone());
-                self.agent_active_queue.save(deps.storage, &active_agents)?;
+    /// Whether the contract holds enough of each token to pay out everything owed to agents,
+    /// see `agent_liabilities`. Balances come from the bank and the cw20 contracts themselves
+    pub(crate) fn query_solvency(&self, deps: Deps, env: Env) -> StdResult<SolvencyResponse> {
+        let total_liabilities = self.agent_liabilities(deps.storage)?;
+        let native = deps
+            .querier
+            .query_all_balances(env.contract.address.clone())?;
+        // Only the tokens owed are worth looking up
+        let cw20 = total_liabilities
+            .cw20
+            .iter()
+            .map(|coin| {
+                let res: cw20::BalanceResponse = deps.querier.query_wasm_smart(
+                    coin.address.clone(),
+                    &Cw20QueryMsg::Balance {
+                        address: env.contract.address.to_string(),
+                    },
+                )?;
+                Ok(Cw20CoinVerified {
+                    address: coin.address.clone(),
+                    amount: res.balance,
+                })
+            })
+            .collect::<StdResult<Vec<_>>>()?;
+        let contract_holdings = GenericBalance { native, cw20 };
+        let shortfall = balance_excess(&total_liabilities, &contract_holdings);
+        Ok(SolvencyResponse {
+            solvent: shortfall.native.is_empty() && shortfall.cw20.is_empty(),
+            total_liabilities,
+            contract_holdings,
+        })
+    }
 
-                // and update the config, setting the nomination begin time to None,
-                // which indicates no one will be nominated until more tasks arrive
-                self.agent_nomination_begin_time.save(deps.storage, &None)?;
-                self.config.save(deps.storage, &c)?;
-            } else {
-                return Err(ContractError::CustomError {
-                    val: "Must wait longer before accepting nomination".to_string(),
-                });
+    /// Everything the contract owes agents: balances, bonds, pending dust,
+    /// escrowed, vesting and pooled rewards
+    pub(crate) fn agent_liabilities(&self, storage: &dyn Storage) -> StdResult<GenericBalance> {
+        let mut computed = GenericBalance::default();
+        let mut add = |native: &[Coin], cw20: &[Cw20CoinVerified]| -> StdResult<()> {
+            computed
+                .checked_add_native(native)
+                .and_then(|_| computed.checked_add_cw20(cw20))
+                .map_err(|err| StdError::generic_err(err.to_string()))
+        };
+        for item in self.agents.range(storage, None, None, Order::Ascending) {
+            let (_, agent) = item?;
+            add(&agent.balance.native, &agent.balance.cw20)?;
+            add(&agent.bond, &[])?;
+            add(&agent.pending_dust, &[])?;
+        }
+        for item in self
+            .agent_escrow
+            .range(storage, None, None, Order::Ascending)
+        {
+            let (_, balance) = item?;
+            add(&balance.native, &balance.cw20)?;
+        }
+        for item in self
+            .agent_vesting
+            .range(storage, None, None, Order::Ascending)
+        {
+            let (_, credits) = item?;
+            for credit in credits {
+                add(
+                    &[Coin::new(
+                        (credit.amount.amount - credit.released).u128(),
+                        credit.amount.denom,
+                    )],
+                    &[],
+                )?;
             }
-        } else {
-            // Sender's address does not exist in the agent pending queue
-            return Err(ContractError::AgentNotRegistered {});
         }
-        // Find difference
-        Ok(Response::new().add_attribute("method", "accept_nomination_agent"))
+        for item in self
+            .agent_pool_balances
+            .range(storage, None, None, Order::Ascending)
+        {
+            let (_, balance) = item?;
+            add(&balance, &[])?;
+        }
+        Ok(computed)
     }
 
-    /// Removes the agent from the active set of agents.
-    /// Withdraws all reward balances to the agent payable account id.
-    pub fn unregister_agent(
+    /// Get the rewards an agent has in escrow, including ones past the window but not yet withdrawn
+    pub(crate) fn query_agent_escrow(
         &self,
-        deps: DepsMut,
-        info: MessageInfo,
-        _env: Env,
-    ) -> Result<Response, ContractError> {
-        // Get withdraw messages, if any
-        // NOTE: Since this also checks if agent exists, safe to not have redundant logic
-        let messages = self.withdraw_balances(deps.storage, info.clone())?;
-        let agent_id = info.sender;
-        self.agents.remove(deps.storage, &agent_id);
+        deps: Deps,
+        account_id: String,
+    ) -> StdResult<Vec<AgentEscrowResponse>> {
+        let account_id = deps.api.addr_validate(&account_id)?;
+        self.agent_escrow
+            .sub_prefix(&account_id)
+            .range(deps.storage, None, None, Order::Ascending)
+            .map(|item| -> StdResult<AgentEscrowResponse> {
+                let ((task_hash, release_time_nanos), balance) = item?;
+                Ok(AgentEscrowResponse {
+                    task_hash: String::from_utf8(task_hash)?,
+                    balance,
+                    release_time_nanos,
+                })
+            })
+            .collect()
+    }
 
-        // Remove from the list of active agents if the agent in this list
-        let mut active_agents: Vec<Addr> = self
-            .agent_active_queue
-            .may_load(deps.storage)?
-            .unwrap_or_default();
-        if let Some(index) = active_agents.iter().position(|addr| *addr == agent_id) {
-            //Notify the balancer agent has been removed, to rebalance itself
-            self.balancer.on_agent_unregister(
-                deps.storage,
-                &self.config,
-                &self.agent_active_queue,
-                agent_id.clone(),
-            );
-            active_agents.remove(index);
+    /// Get a list of agent addresses
+    pub(crate) fn query_get_agent_ids(&self, deps: Deps) -> StdResult<GetAgentIdsResponse> {
+        let active: Vec<Addr> = self.agent_active_queue.load(deps.storage)?;
+        let pending: Vec<Addr> = self.agent_pending_queue.load(deps.storage)?;
 
-            self.agent_active_queue.save(deps.storage, &active_agents)?;
-        } else {
-            // Agent can't be both in active and pending vector
-            // Remove from the pending queue
-            let mut pending_agents: Vec<Addr> = self
-                .agent_pending_queue
-                .may_load(deps.storage)?
-                .unwrap_or_default();
-            if let Some(index) = pending_agents.iter().position(|addr| *addr == agent_id) {
-                pending_agents.remove(index);
-                self.agent_pending_queue
-                    .save(deps.storage, &pending_agents)?;
+        Ok(GetAgentIdsResponse { active, pending })
+    }
+
+    /// Get every agent paying out to `payable_account_id`
+    pub(crate) fn query_agents_by_payable(
+        &self,
+        deps: Deps,
+        payable_account_id: String,
+    ) -> StdResult<Vec<Addr>> {
+        let payable_account_id = deps.api.addr_validate(&payable_account_id)?;
+        Ok(self
+            .agents_by_payable
+            .may_load(deps.storage, &payable_account_id)?
+            .unwrap_or_default())
+    }
+
+    /// Get the active agents that declared `capability`
+    pub(crate) fn query_agents_with_capability(
+        &self,
+        deps: Deps,
+        capability: String,
+    ) -> StdResult<Vec<Addr>> {
+        let active: Vec<Addr> = self.agent_active_queue.load(deps.storage)?;
+        let mut agents = vec![];
+        for agent_id in active {
+            let agent = self.agents.load(deps.storage, &agent_id)?;
+            if agent.capabilities.contains(&capability) {
+                agents.push(agent_id);
             }
         }
+        Ok(agents)
+    }
 
-        let responses = Response::new()
-            .add_attribute("method", "unregister_agent")
-            .add_attribute("account_id", agent_id);
-
-        if messages.is_empty() {
-            Ok(responses)
-        } else {
-            Ok(responses.add_submessages(messages))
+    /// Get the pending agent that registered first, with its `register_start` in nanos.
+    /// Ties go to the one ahead in the pending queue
+    pub(crate) fn query_oldest_pending_agent(&self, deps: Deps) -> StdResult<Option<(Addr, u64)>> {
+        let pending: Vec<Addr> = self.agent_pending_queue.load(deps.storage)?;
+        let mut oldest: Option<(Addr, u64)> = None;
+        for agent_id in pending {
+            let register_start = self.agents.load(deps.storage, &agent_id)?.register_start;
+            let older = match &oldest {
+                Some((_, start)) => register_start.nanos() < *start,
+                None => true,
+            };
+            if older {
+                oldest = Some((agent_id, register_start.nanos()));
+            }
         }
+        Ok(oldest)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::error::ContractError;
-    use crate::helpers::CwTemplateContract;
-    use cosmwasm_std::testing::{mock_env, mock_info, MOCK_CONTRACT_ADDR};
-    use cosmwasm_std::{coin, coins, from_slice, Addr, BlockInfo, CosmosMsg, Empty, StakingMsg};
-    use cw_croncat_core::msg::{ExecuteMsg, InstantiateMsg, QueryMsg, TaskRequest, TaskResponse};
-    use cw_croncat_core::types::{Action, Interval};
-    use cw_multi_test::{
-        App, AppBuilder, AppResponse, BankSudo, Contract, ContractWrapper, Executor, SudoMsg,
-    };
+    /// Agents that would fail the registration checks under `proposed`,
+    /// going by their current wallet balances. Nothing gets applied
+    pub(crate) fn query_config_change_impact(
+        &self,
+        deps: Deps,
+        proposed: ConfigChange,
+    ) -> StdResult<ConfigChangeImpactResponse> {
+        let c: Config = self.config.load(deps.storage)?;
+        let gas_price = proposed.gas_price.unwrap_or(c.gas_price);
+        let blacklist = match proposed.blacklist {
+            Some(blacklist) => blacklist
+                .iter()
+                .map(|addr| deps.api.addr_validate(addr))
+                .collect::<StdResult<Vec<Addr>>>()?,
+            None => c.blacklist,
+        };
+        let mut agents_evicted = vec![];
+        for item in self
+            .agents
+            .range(deps.storage, None, None, Order::Ascending)
+        {
+            let (agent_id, agent) = item?;
+            let wallet = deps.querier.query_all_balances(agent_id.clone())?;
+            if blacklist.contains(&agent.payable_account_id)
+                || !covers_registration_gas(&wallet, gas_price, &c.native_denom)
+                    .map_err(|err| StdError::generic_err(err.to_string()))?
+            {
+                agents_evicted.push(agent_id);
+            }
+        }
+        Ok(ConfigChangeImpactResponse {
+            agents_affected: agents_evicted.len() as u64,
+            agents_evicted,
+        })
+    }
 
-    pub fn contract_template() -> Box<dyn Contract<Empty>> {
-        let contract = ContractWrapper::new(
-            crate::entry::execute,
-            crate::entry::instantiate,
-            crate::entry::query,
-        );
-        Box::new(contract)
+    /// Get the active agents that missed more slots in a row than `missed_slot_threshold`
+    pub(crate) fn query_slashable_agents(
+        &self,
+        deps: Deps,
+    ) -> StdResult<Vec<SlashableAgentResponse>> {
+        let c: Config = self.config.load(deps.storage)?;
+        let active: Vec<Addr> = self.agent_active_queue.load(deps.storage)?;
+        let mut slashable = vec![];
+        for agent_id in active {
+            let agent = self.agents.load(deps.storage, &agent_id)?;
+            if is_slashable(&c, &agent) {
+                slashable.push(SlashableAgentResponse {
+                    agent: agent_id,
+                    consecutive_misses: agent.consecutive_misses,
+                });
+            }
+        }
+        Ok(slashable)
     }
 
-    const AGENT0: &str = "cosmos1a7uhnpqthunr2rzj0ww0hwurpn42wyun6c5puz";
-    const AGENT1: &str = "cosmos17muvdgkep4ndptnyg38eufxsssq8jr3wnkysy8";
-    const AGENT2: &str = "cosmos1qxywje86amll9ptzxmla5ah52uvsd9f7drs2dl";
-    const AGENT3: &str = "cosmos1c3cy3wzzz3698ypklvh7shksvmefj69xhm89z2";
-    const AGENT4: &str = "cosmos1ykfcyj8fl6xzs88tsls05x93gmq68a7km05m4j";
-    const AGENT_BENEFICIARY: &str = "cosmos1t5u0jfg3ljsjrh2m9e47d4ny2hea7eehxrzdgd";
-    const ADMIN: &str = "cosmos1sjllsnramtg3ewxqwwrwjxfgc4n4ef9u0tvx7u";
-    const PARTICIPANT0: &str = "cosmos1055rfv3fv0zxsp8h3x88mctnm7x9mlgmf4m4d6";
-    const PARTICIPANT1: &str = "cosmos1c3cy3wzzz3698ypklvh7shksvmefj69xhm89z2";
-    const PARTICIPANT2: &str = "cosmos1far5cqkvny7k9wq53aw0k42v3f76rcylzzv05n";
-    const PARTICIPANT3: &str = "cosmos1xj3xagnprtqpfnvyp7k393kmes73rpuxqgamd8";
-    const PARTICIPANT4: &str = "cosmos1t5u0jfg3ljsjrh2m9e47d4ny2hea7eehxrzdgd";
-    const PARTICIPANT5: &str = "cosmos1k5k7y4hgy5lkq0kj3k3e9k38lquh0m66kxsu5c";
-    const PARTICIPANT6: &str = "cosmos14a8clxc49z9e3mjzhamhkprt2hgf0y53zczzj0";
-    const NATIVE_DENOM: &str = "atom";
+    /// Agent with the most successful executions in a row, the first one in address order on ties.
+    /// None if no agent has a streak going
+    pub(crate) fn query_top_streak(&self, deps: Deps) -> StdResult<Option<(Addr, u64)>> {
+        let mut top: Option<(Addr, u64)> = None;
+        for item in self
+            .agents
+            .range(deps.storage, None, None, Order::Ascending)
+        {
+            let (agent_id, agent) = item?;
+            if agent.current_streak > top.as_ref().map_or(0, |(_, streak)| *streak) {
+                top = Some((agent_id, agent.current_streak));
+            }
+        }
+        Ok(top)
+    }
 
-    fn mock_app() -> App {
-        AppBuilder::new().build(|router, _, storage| {
-            let accounts: Vec<(u128, String)> = vec![
-                (2_000_000, ADMIN.to_string()),
-                (1, AGENT0.to_string()),
-                (2_000_000, AGENT1.to_string()),
-                (2_000_000, AGENT2.to_string()),
-                (2_000_000, AGENT3.to_string()),
-                (2_000_000, AGENT4.to_string()),
-                (500_0000, PARTICIPANT0.to_string()),
-                (500_0000, PARTICIPANT1.to_string()),
-                (500_0000, PARTICIPANT2.to_string()),
-                (500_0000, PARTICIPANT3.to_string()),
-                (500_0000, PARTICIPANT4.to_string()),
-                (500_0000, PARTICIPANT5.to_string()),
-                (500_0000, PARTICIPANT6.to_string()),
-                (2_000_000, AGENT_BENEFICIARY.to_string()),
-            ];
-            for (amt, address) in accounts.iter() {
-                router
-                    .bank
-                    .init_balance(
-                        storage,
-                        &Addr::unchecked(address),
-                        vec![coin(amt.clone(), NATIVE_DENOM.to_string())],
-                    )
-                    .unwrap();
+    /// Missed slot totals kept in `Config`, plus how many active agents are on a miss
+    /// streak and which one has the longest
+    pub(crate) fn query_missed_slot_stats(&self, deps: Deps) -> StdResult<MissedSlotStatsResponse> {
+        let c: Config = self.config.load(deps.storage)?;
+        let active: Vec<Addr> = self.agent_active_queue.load(deps.storage)?;
+        let mut agents_with_misses = 0;
+        let mut worst_agent: Option<(Addr, u64)> = None;
+        for agent_id in active {
+            let consecutive_misses = self
+                .agents
+                .load(deps.storage, &agent_id)?
+                .consecutive_misses;
+            if consecutive_misses == 0 {
+                continue;
+            }
+            agents_with_misses += 1;
+            let worse = match &worst_agent {
+                Some((_, worst)) => consecutive_misses > *worst,
+                None => true,
+            };
+            if worse {
+                worst_agent = Some((agent_id, consecutive_misses));
             }
+        }
+        Ok(MissedSlotStatsResponse {
+            total_misses: c.total_missed_slots,
+            agents_with_misses,
+            worst_agent,
         })
     }
 
-    fn proper_instantiate() -> (App, CwTemplateContract) {
-        let mut app = mock_app();
-        let cw_template_id = app.store_code(contract_template());
-        let owner_addr = Addr::unchecked(ADMIN);
-
-        let msg = InstantiateMsg {
-            denom: "atom".to_string(),
-            owner_id: Some(owner_addr.to_string()),
+    /// Get the active agents that never executed a task once `idle_grace_nanos` passed
+    /// since their activation. Pages over positions in the active queue
+    pub(crate) fn query_idle_agents(
+        &self,
+        deps: Deps,
+        env: Env,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> StdResult<Vec<Addr>> {
+        let c: Config = self.config.load(deps.storage)?;
+        let active: Vec<Addr> = self.agent_active_queue.load(deps.storage)?;
+        let mut idle = vec![];
+        for agent_id in active
+            .into_iter()
+            .skip(from_index.unwrap_or_default() as usize)
+            .take(limit.unwrap_or(c.limit).min(c.limit) as usize)
+        {
+            let agent = self.agents.load(deps.storage, &agent_id)?;
+            let active_since = agent.last_activation.unwrap_or(agent.register_start);
+            if agent.total_tasks_executed == 0
+                && env.block.time.nanos() >= active_since.nanos().saturating_add(c.idle_grace_nanos)
+            {
+                idle.push(agent_id);
+            }
+        }
+        Ok(idle)
+    }
+
+    /// Get the tasks each active agent executed and its share of what all active agents executed, in bps.
+    /// Pages over positions in the active queue, the share is still out of the whole queue
+    pub(crate) fn query_execution_distribution(
+        &self,
+        deps: Deps,
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    ) -> StdResult<Vec<(Addr, u64, u16)>> {
+        let c: Config = self.config.load(deps.storage)?;
+        let active: Vec<Addr> = self.agent_active_queue.load(deps.storage)?;
+        let executed = active
+            .into_iter()
+            .map(|agent_id| {
+                let agent = self.agents.load(deps.storage, &agent_id)?;
+                Ok((agent_id, agent.total_tasks_executed))
+            })
+            .collect::<StdResult<Vec<(Addr, u64)>>>()?;
+        let total: u64 = executed.iter().map(|(_, tasks)| tasks).sum();
+        Ok(executed
+            .into_iter()
+            .skip(from_index.unwrap_or_default() as usize)
+            .take(limit.unwrap_or(c.limit).min(c.limit) as usize)
+            .map(|(agent_id, tasks)| {
+                let share_bps = if total == 0 {
+                    0
+                } else {
+                    Uint128::from(tasks)
+                        .multiply_ratio(10_000u128, total)
+                        .u128() as u16
+                };
+                (agent_id, tasks, share_bps)
+            })
+            .collect())
+    }
+
+    /// Scan both agent queues for duplicates and for entries missing from `agents`
+    pub(crate) fn query_queue_integrity(&self, deps: Deps) -> StdResult<QueueIntegrityResponse> {
+        let active: Vec<Addr> = self.agent_active_queue.load(deps.storage)?;
+        let pending: Vec<Addr> = self.agent_pending_queue.load(deps.storage)?;
+
+        let mut orphaned_queue_entries: Vec<Addr> = vec![];
+        for agent_id in active.iter().chain(pending.iter()) {
+            if !self.agents.has(deps.storage, agent_id)
+                && !orphaned_queue_entries.contains(agent_id)
+            {
+                orphaned_queue_entries.push(agent_id.clone());
+            }
+        }
+
+        Ok(QueueIntegrityResponse {
+            active_dupes: find_duplicates(&active),
+            pending_dupes: find_duplicates(&pending),
+            orphaned_queue_entries,
+        })
+    }
+
+    /// Current nomination parameters, so pending agents can tell when they'll get nominated
+    pub(crate) fn query_nomination_config(
+        &self,
+        deps: Deps,
+        env: Env,
+    ) -> StdResult<NominationConfigResponse> {
+        let c: Config = self.config.load(deps.storage)?;
+        let num_active_agents = self.agent_active_queue.load(deps.storage)?.len() as u64;
+        let total_tasks = self.task_total(deps.storage)?;
+
+        Ok(NominationConfigResponse {
+            mode: NominationMode::Fifo,
+            nomination_duration: c.agent_nomination_duration,
+            open_slots: self.agents_to_let_in(
+                &c.min_tasks_per_agent,
+                &num_active_agents,
+                &total_tasks,
+            ),
+            min_tasks_per_agent: c.min_tasks_per_agent,
+            nomination_begin_time: self.agent_nomination_begin_time.load(deps.storage)?,
+            nomination_suspended: self.nomination_suspended(deps.storage, &env.block, &c)?,
+        })
+    }
+
+    /// Whether the agent is nominated to check in, and what holds it back otherwise.
+    /// Follows the same checks as `get_agent_status`
+    pub(crate) fn query_nomination_status(
+        &self,
+        deps: Deps,
+        env: Env,
+        account_id: String,
+    ) -> StdResult<NominationStatusResponse> {
+        let account_id = deps.api.addr_validate(&account_id)?;
+        if !self.agents.has(deps.storage, &account_id) {
+            return Err(StdError::generic_err(AgentNotRegistered {}.to_string()));
+        }
+        let blocked = |reason: NominationBlockReason| -> StdResult<NominationStatusResponse> {
+            Ok(NominationStatusResponse {
+                nominated: false,
+                reason: Some(reason),
+            })
+        };
+        let pending = self.agent_pending_queue.load(deps.storage)?;
+        let position = match pending.iter().position(|addr| *addr == account_id) {
+            Some(position) => position as u64,
+            None => return blocked(NominationBlockReason::NotPending),
+        };
+        let c: Config = self.config.load(deps.storage)?;
+        if self.nomination_suspended(deps.storage, &env.block, &c)? {
+            return blocked(NominationBlockReason::Suspended);
+        }
+        let num_active_agents = self.agent_active_queue.load(deps.storage)?.len() as u64;
+        let total_tasks = self.task_total(deps.storage)?;
+        let num_agents_to_accept =
+            self.agents_to_let_in(&c.min_tasks_per_agent, &num_active_agents, &total_tasks);
+        if num_agents_to_accept == 0 {
+            return blocked(NominationBlockReason::NoOpenSlots);
+        }
+        let begin_time = match self.agent_nomination_begin_time.load(deps.storage)? {
+            Some(begin_time) => begin_time,
+            None => return blocked(NominationBlockReason::InGracePeriod),
+        };
+        let time_difference = env
+            .block
+            .time
+            .seconds()
+            .saturating_sub(begin_time.seconds());
+        let max_index =
+            (time_difference / c.agent_nomination_duration as u64).max(num_agents_to_accept - 1);
+        if position > max_index {
+            return blocked(NominationBlockReason::BehindInQueue { position });
+        }
+        Ok(NominationStatusResponse {
+            nominated: true,
+            reason: None,
+        })
+    }
+
+    /// How full the agent queues are compared to `max_agents`/`max_pending_agents`
+    pub(crate) fn query_queue_capacity(&self, deps: Deps) -> StdResult<QueueCapacityResponse> {
+        let c: Config = self.config.load(deps.storage)?;
+        let active: Vec<Addr> = self.agent_active_queue.load(deps.storage)?;
+        let pending: Vec<Addr> = self.agent_pending_queue.load(deps.storage)?;
+
+        Ok(QueueCapacityResponse {
+            active_used: active.len() as u64,
+            active_max: c.max_agents,
+            pending_used: pending.len() as u64,
+            pending_max: c.max_pending_agents,
+        })
+    }
+
+    // TODO: Change this to solid round-table implementation. Setup this simple version for PoC
+    /// Get how many tasks an agent can execute
+    /// TODO: Remove this function, replaced by balancer
+    pub(crate) fn query_get_agent_tasks(
+        &mut self,
+        deps: Deps,
+        env: Env,
+        account_id: String,
+    ) -> StdResult<Option<AgentTaskResponse>> {
+        let account_id = deps.api.addr_validate(&account_id)?;
+        let active = self.agent_active_queue.load(deps.storage)?;
+        if !active.contains(&account_id) {
+            // TODO: unsure if we can return AgentNotRegistered
+            return Err(StdError::GenericErr {
+                msg: AgentNotRegistered {}.to_string(),
+            });
+        }
+        let agent = self.agents.load(deps.storage, &account_id)?;
+        // Self-paused agents stay active but don't get tasks
+        if agent.self_paused {
+            return Ok(Some(AgentTaskResponse {
+                num_block_tasks: Uint64::zero(),
+                num_block_tasks_extra: Uint64::zero(),
+                num_cron_tasks: Uint64::zero(),
+                num_cron_tasks_extra: Uint64::zero(),
+            }));
+        }
+        // Specialized agents only count the tasks of a type they accept
+        let count_tasks = |task_hashes: &[Vec<u8>]| -> StdResult<u64> {
+            if agent.accepted_task_types.is_empty() {
+                return Ok(task_hashes.len() as u64);
+            }
+            let mut count = 0;
+            for task_hash in task_hashes {
+                if let Some(task) = self.tasks.may_load(deps.storage, task_hash)? {
+                    if agent.accepts_task(&task) {
+                        count += 1;
+                    }
+                }
+            }
+            Ok(count)
+        };
+        // Past the exclusivity window an overdue slot is open to every active agent,
+        // only the slots still inside it go through the balancer
+        let c: Config = self.config.load(deps.storage)?;
+        // Get all tasks (the final None means no limit when we take)
+        let (mut block_tasks, mut open_block_tasks) = (0u64, 0u64);
+        for slot in self.block_slots.range(
+            deps.storage,
+            None,
+            Some(Bound::inclusive(env.block.height)),
+            cosmwasm_std::Order::Ascending,
+        ) {
+            let (slot, task_hashes) = slot?;
+            let count = count_tasks(&task_hashes)?;
+            if slot_open(&c, &env.block, slot, SlotType::Block) {
+                open_block_tasks += count;
+            } else {
+                block_tasks += count;
+            }
+        }
+
+        let (mut time_tasks, mut open_time_tasks) = (0u64, 0u64);
+        for slot in self.time_slots.range(
+            deps.storage,
+            None,
+            Some(Bound::inclusive(env.block.time.nanos())),
+            cosmwasm_std::Order::Ascending,
+        ) {
+            let (slot, task_hashes) = slot?;
+            let count = count_tasks(&task_hashes)?;
+            if slot_open(&c, &env.block, slot, SlotType::Cron) {
+                open_time_tasks += count;
+            } else {
+                time_tasks += count;
+            }
+        }
+
+        if block_tasks + time_tasks + open_block_tasks + open_time_tasks == 0 {
+            return Ok(None);
+        }
+
+        // Same slot proxy_call executes next, so the leader holds for the whole slot
+        let (next_block_slot, next_time_slot) =
+            self.get_current_slot_items(&env.block, deps.storage, Some(1));
+        let mut agent_tasks = self.balancer.get_agent_tasks(
+            &deps,
+            &env,
+            &self.config,
+            &self.agent_active_queue,
+            &self.agents,
+            account_id,
+            (Some(block_tasks), Some(time_tasks)),
+            next_block_slot
+                .or(next_time_slot)
+                .unwrap_or(env.block.height),
+            None,
+        )?;
+        if let Some(agent_tasks) = agent_tasks.as_mut() {
+            agent_tasks.num_block_tasks += Uint64::from(open_block_tasks);
+            agent_tasks.num_cron_tasks += Uint64::from(open_time_tasks);
+        }
+        Ok(agent_tasks)
+    }
+
+    /// Add any account as an agent that will be able to execute tasks.
+    /// Registering allows for rewards accruing with micro-payments which will accumulate to more long-term.
+    ///
+    /// Optional Parameters:
+    /// "payable_account_id" - Allows a different account id to be specified, so a user can receive funds at a different account than the agent account.
+    /// "accepted_terms" - Terms of service hash the agent accepts, required when `Config.required_terms_hash` is set.
+    /// "client_version" - MAJOR.MINOR.PATCH of the agent software, required when `Config.min_client_version` is set.
+    #[allow(clippy::too_many_arguments)]
+    pub fn register_agent(
+        &self,
+        deps: DepsMut,
+        info: MessageInfo,
+        env: Env,
+        payable_account_id: Option<String>,
+        capabilities: Option<Vec<String>>,
+        accepted_terms: Option<Binary>,
+        client_version: Option<String>,
+    ) -> Result<Response, ContractError> {
+        let account = info.sender.clone();
+        self.register_agent_account(
+            deps,
+            info,
+            env,
+            account,
+            payable_account_id,
+            capabilities,
+            accepted_terms,
+            client_version,
+        )
+    }
+
+    /// Registers `target` as an agent on its behalf, only for `Config.onboarders`.
+    /// The onboarder pays any registration fee, while `target`'s wallet must cover the gas check.
+    /// Agents have to accept required terms and report their version themselves,
+    /// so it fails while terms or a minimum client version are required
+    pub fn register_agent_for(
+        &self,
+        deps: DepsMut,
+        info: MessageInfo,
+        env: Env,
+        target: String,
+        payable_account_id: Option<String>,
+    ) -> Result<Response, ContractError> {
+        let target = deps.api.addr_validate(&target)?;
+        let c: Config = self.config.load(deps.storage)?;
+        if !c.onboarders.contains(&info.sender) {
+            return Err(ContractError::Unauthorized {});
+        }
+        let onboarder = info.sender.clone();
+        let response = self.register_agent_account(
+            deps,
+            info,
+            env,
+            target,
+            payable_account_id,
+            None,
+            None,
+            None,
+        )?;
+        Ok(response.add_attribute("onboarder", onboarder))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn register_agent_account(
+        &self,
+        deps: DepsMut,
+        info: MessageInfo,
+        env: Env,
+        account: Addr,
+        payable_account_id: Option<String>,
+        capabilities: Option<Vec<String>>,
+        accepted_terms: Option<Binary>,
+        client_version: Option<String>,
+    ) -> Result<Response, ContractError> {
+        let mut c: Config = self.config.load(deps.storage)?;
+        if c.required_terms_hash.is_some() && accepted_terms != c.required_terms_hash {
+            return Err(ContractError::CustomError {
+                val: "Must accept the required terms".to_string(),
+            });
+        }
+        check_client_version(&c, client_version.as_deref())?;
+        match &c.registration_fee {
+            // Exactly the fee, nothing more or less
+            Some(fee) => {
+                if info.funds != vec![fee.clone()] {
+                    return Err(ContractError::CustomError {
+                        val: format!("Must attach exactly the registration fee of {}", fee),
+                    });
+                }
+            }
+            None => {
+                if !info.funds.is_empty() {
+                    return Err(ContractError::CustomError {
+                        val: "Do not attach funds".to_string(),
+                    });
+                }
+            }
+        }
+        if c.paused.register {
+            return Err(ContractError::ContractPaused {
+                val: "Register agent paused".to_string(),
+            });
+        }
+
+        // Operators act on behalf of another agent, can't become one themselves
+        if self.agent_operators.has(deps.storage, &account) {
+            return Err(ContractError::OperatorInUse {
+                operator: account.to_string(),
+            });
+        }
+
+        // REF: https://github.com/CosmWasm/cw-tokens/tree/main/contracts/cw20-escrow
+        // Check if native token balance is sufficient for a few txns, in this case 4 txns
+        // TODO: Adjust gas & costs based on real usage cost
+        let agent_wallet_balances = deps.querier.query_all_balances(account.clone())?;
+        if !covers_registration_gas(&agent_wallet_balances, c.gas_price, &c.native_denom)? {
+            return Err(ContractError::CustomError {
+                val: "Insufficient funds".to_string(),
+            });
+        }
+
+        let payable_id = if let Some(addr) = payable_account_id {
+            deps.api.addr_validate(&addr)?
+        } else {
+            account.clone()
+        };
+        check_payable_account(&c, &env, &payable_id)?;
+        let capabilities = validate_capabilities(capabilities.unwrap_or_default())?;
+
+        let mut active_agents: Vec<Addr> = self.agent_active_queue.load(deps.storage)?;
+        let mut pending_agents = self.agent_pending_queue.load(deps.storage)?;
+        let total_agents = active_agents.len();
+        // Agents already waiting go first
+        let below_max = match c.max_agents {
+            Some(max_agents) => (total_agents as u64) < max_agents,
+            None => true,
+        };
+        let slot_open = c.auto_activate_open_slots && pending_agents.is_empty() && below_max;
+        let agent_status = if total_agents == 0 || slot_open {
+            active_agents.push(account.clone());
+            self.agent_active_queue.save(deps.storage, &active_agents)?;
+            AgentStatus::Active
+        } else {
+            if let Some(max_pending_agents) = c.max_pending_agents {
+                if pending_agents.len() as u64 >= max_pending_agents {
+                    return Err(ContractError::CustomError {
+                        val: "Pending agent queue is full".to_string(),
+                    });
+                }
+            }
+            pending_agents.push(account.clone());
+            self.agent_pending_queue
+                .save(deps.storage, &pending_agents)?;
+            AgentStatus::Pending
+        };
+        let agent = self.agents.update(
+            deps.storage,
+            &account,
+            |a: Option<Agent>| -> Result<_, ContractError> {
+                match a {
+                    // make sure that account isn't already added
+                    Some(_) => Err(ContractError::CustomError {
+                        val: "Agent already exists".to_string(),
+                    }),
+                    None => Ok(Agent {
+                        accepted_terms_hash: accepted_terms,
+                        client_version,
+                        ..new_agent(payable_id, env.block.time, capabilities)
+                    }),
+                }
+            },
+        )?;
+
+        self.index_payable(deps.storage, &agent.payable_account_id, &account)?;
+
+        let mut response = Response::new()
+            .add_attribute("method", "register_agent")
+            .add_attribute("agent", &account)
+            .add_attribute("agent_status", format!("{:?}", agent_status))
+            .add_attribute("register_start", agent.register_start.nanos().to_string())
+            .add_attribute("payable_account_id", agent.payable_account_id)
+            .add_attribute("starting_balance", format_balance(&agent.balance));
+
+        // Registration fee is non-refundable, goes to the contract
+        if let Some(fee) = c.registration_fee.clone() {
+            c.available_balance.checked_add_native(&info.funds)?;
+            c.protocol_revenue.checked_add_native(&info.funds)?;
+            self.config.save(deps.storage, &c)?;
+            response = response.add_attribute("registration_fee", fee.to_string());
+        }
+
+        Ok(response)
+    }
+
+    /// Registers `account` as the only active agent while instantiating,
+    /// it doesn't need any balance so the network can start without an empty active set
+    pub(crate) fn register_genesis_agent(
+        &self,
+        storage: &mut dyn Storage,
+        env: &Env,
+        account: Addr,
+    ) -> StdResult<()> {
+        self.agents.save(
+            storage,
+            &account,
+            &new_agent(account.clone(), env.block.time, vec![]),
+        )?;
+        self.index_payable(storage, &account, &account)?;
+        self.agent_active_queue.save(storage, &vec![account])
+    }
+
+    /// Update agent details, specifically the payable account id for an agent.
+    pub fn update_agent(
+        &self,
+        deps: DepsMut,
+        info: MessageInfo,
+        env: Env,
+        payable_account_id: String,
+        capabilities: Option<Vec<String>>,
+        client_version: Option<String>,
+    ) -> Result<Response, ContractError> {
+        let payable_account_id = deps.api.addr_validate(&payable_account_id)?;
+        let capabilities = capabilities.map(validate_capabilities).transpose()?;
+        let c: Config = self.config.load(deps.storage)?;
+        check_payable_account(&c, &env, &payable_account_id)?;
+        if client_version.is_some() {
+            check_client_version(&c, client_version.as_deref())?;
+        }
+        let refund = stray_funds_refund(&c, &info)?;
+        if c.paused.register {
+            return Err(ContractError::ContractPaused {
+                val: "Register agent paused".to_string(),
+            });
+        }
+
+        let previous_payable = self
+            .agents
+            .may_load(deps.storage, &info.sender)?
+            .map(|a| a.payable_account_id);
+        let agent = self.agents.update(
+            deps.storage,
+            &info.sender,
+            |a: Option<Agent>| -> Result<_, ContractError> {
+                match a {
+                    Some(agent) => {
+                        let mut ag = agent;
+                        ag.payable_account_id = payable_account_id;
+                        if let Some(capabilities) = capabilities {
+                            ag.capabilities = capabilities;
+                        }
+                        if client_version.is_some() {
+                            ag.client_version = client_version;
+                        }
+                        Ok(ag)
+                    }
+                    None => Err(ContractError::AgentNotRegistered {}),
+                }
+            },
+        )?;
+
+        if let Some(previous_payable) = previous_payable {
+            if previous_payable != agent.payable_account_id {
+                self.unindex_payable(deps.storage, &previous_payable, &info.sender)?;
+                self.index_payable(deps.storage, &agent.payable_account_id, &info.sender)?;
+            }
+        }
+
+        Ok(Response::new()
+            .add_attribute("method", "update_agent")
+            .add_attribute("agent", info.sender)
+            .add_attribute("payable_account_id", agent.payable_account_id)
+            .add_submessages(refund))
+    }
+
+    /// Adds `agent_id` to the agents paying out to `payable_account_id`
+    pub(crate) fn index_payable(
+        &self,
+        storage: &mut dyn Storage,
+        payable_account_id: &Addr,
+        agent_id: &Addr,
+    ) -> StdResult<()> {
+        let mut agents = self
+            .agents_by_payable
+            .may_load(storage, payable_account_id)?
+            .unwrap_or_default();
+        if !agents.contains(agent_id) {
+            agents.push(agent_id.clone());
+            self.agents_by_payable
+                .save(storage, payable_account_id, &agents)?;
+        }
+        Ok(())
+    }
+
+    /// Drops `agent_id` from the agents paying out to `payable_account_id`,
+    /// removing the entry once nobody pays out to it
+    fn unindex_payable(
+        &self,
+        storage: &mut dyn Storage,
+        payable_account_id: &Addr,
+        agent_id: &Addr,
+    ) -> StdResult<()> {
+        let mut agents = self
+            .agents_by_payable
+            .may_load(storage, payable_account_id)?
+            .unwrap_or_default();
+        agents.retain(|agent| agent != agent_id);
+        if agents.is_empty() {
+            self.agents_by_payable.remove(storage, payable_account_id);
+        } else {
+            self.agents_by_payable
+                .save(storage, payable_account_id, &agents)?;
+        }
+        Ok(())
+    }
+
+    /// Pick the asset rewards get credited in, a cw20 must be whitelisted
+    pub fn update_reward_preference(
+        &self,
+        deps: DepsMut,
+        info: MessageInfo,
+        preference: RewardPreference,
+    ) -> Result<Response, ContractError> {
+        let c: Config = self.config.load(deps.storage)?;
+        if let RewardPreference::Cw20(token) = &preference {
+            if !c.cw20_whitelist.contains(token) {
+                return Err(ContractError::NotInWhitelist {});
+            }
+        }
+        let agent = self.agents.update(
+            deps.storage,
+            &info.sender,
+            |a: Option<Agent>| -> Result<_, ContractError> {
+                let mut agent = a.ok_or(ContractError::AgentNotRegistered {})?;
+                agent.reward_preference = preference;
+                Ok(agent)
+            },
+        )?;
+
+        Ok(Response::new()
+            .add_attribute("method", "update_reward_preference")
+            .add_attribute("agent", info.sender)
+            .add_attribute(
+                "reward_preference",
+                format!("{:?}", agent.reward_preference),
+            ))
+    }
+
+    /// Lets an agent stop getting tasks without unregistering, calling it again resumes.
+    /// The agent keeps its place in the queue while paused.
+    pub fn toggle_agent_pause(
+        &self,
+        deps: DepsMut,
+        info: MessageInfo,
+        env: Env,
+    ) -> Result<Response, ContractError> {
+        let mut agent = self
+            .agents
+            .may_load(deps.storage, &info.sender)?
+            .ok_or(ContractError::AgentNotRegistered {})?;
+        if !agent.self_paused {
+            let active = self.agent_active_queue.load(deps.storage)?;
+            self.check_activation_cooldown(
+                deps.storage,
+                &active,
+                &info.sender,
+                &agent,
+                env.block.time,
+            )?;
+        }
+        agent.self_paused = !agent.self_paused;
+        self.agents.save(deps.storage, &info.sender, &agent)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "toggle_agent_pause")
+            .add_attribute("agent", info.sender)
+            .add_attribute("self_paused", agent.self_paused.to_string()))
+    }
+
+    /// Switches rewards between compounding into the bond and the withdrawable balance.
+    /// Only rewards credited from now on are affected
+    pub fn toggle_auto_compound(
+        &self,
+        deps: DepsMut,
+        info: MessageInfo,
+    ) -> Result<Response, ContractError> {
+        let agent = self.agents.update(
+            deps.storage,
+            &info.sender,
+            |a: Option<Agent>| -> Result<_, ContractError> {
+                let mut agent = a.ok_or(ContractError::AgentNotRegistered {})?;
+                agent.auto_compound = !agent.auto_compound;
+                Ok(agent)
+            },
+        )?;
+
+        Ok(Response::new()
+            .add_attribute("method", "toggle_auto_compound")
+            .add_attribute("agent", info.sender)
+            .add_attribute("auto_compound", agent.auto_compound.to_string()))
+    }
+
+    /// Starts a pool of agents sharing their rewards, with the sender as creator and first member.
+    /// The creator starts at a weight of 1
+    pub fn create_agent_pool(
+        &self,
+        deps: DepsMut,
+        info: MessageInfo,
+        pool_id: String,
+    ) -> Result<Response, ContractError> {
+        if pool_id.is_empty() || pool_id.len() > MAX_POOL_ID_LENGTH {
+            return Err(ContractError::CustomError {
+                val: format!("Pool id must be 1 to {} characters", MAX_POOL_ID_LENGTH),
+            });
+        }
+        if self.agent_pools.has(deps.storage, &pool_id) {
+            return Err(ContractError::CustomError {
+                val: "Pool already exists".to_string(),
+            });
+        }
+        let mut pool = AgentPool {
+            creator: info.sender.clone(),
+            members: vec![],
+            join_requests: vec![],
+        };
+        self.add_pool_member(deps.storage, &info.sender, &pool_id, &mut pool, 1)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "create_agent_pool")
+            .add_attribute("agent", info.sender)
+            .add_attribute("pool_id", pool_id))
+    }
+
+    /// Asks to join an existing pool, the pool creator has to approve it
+    pub fn join_agent_pool(
+        &self,
+        deps: DepsMut,
+        info: MessageInfo,
+        pool_id: String,
+    ) -> Result<Response, ContractError> {
+        let mut pool = self
+            .agent_pools
+            .may_load(deps.storage, &pool_id)?
+            .ok_or_else(|| ContractError::CustomError {
+                val: "Pool doesn't exist".to_string(),
+            })?;
+        let agent = self
+            .agents
+            .may_load(deps.storage, &info.sender)?
+            .ok_or(AgentNotRegistered {})?;
+        if agent.pool_id.is_some() {
+            return Err(ContractError::CustomError {
+                val: "Agent is already in a pool".to_string(),
+            });
+        }
+        if pool.join_requests.contains(&info.sender) {
+            return Err(ContractError::CustomError {
+                val: "Agent already asked to join".to_string(),
+            });
+        }
+        if pool.members.len() + pool.join_requests.len() >= MAX_AGENT_POOL_MEMBERS {
+            return Err(ContractError::CustomError {
+                val: format!("Pools are limited to {} members", MAX_AGENT_POOL_MEMBERS),
+            });
+        }
+        pool.join_requests.push(info.sender.clone());
+        self.agent_pools.save(deps.storage, &pool_id, &pool)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "join_agent_pool")
+            .add_attribute("agent", info.sender)
+            .add_attribute("pool_id", pool_id))
+    }
+
+    /// Lets an agent that asked to join into the pool at `weight`,
+    /// or changes the weight of a member. Pool creator only
+    pub fn approve_agent_pool_member(
+        &self,
+        deps: DepsMut,
+        info: MessageInfo,
+        pool_id: String,
+        agent_id: String,
+        weight: u64,
+    ) -> Result<Response, ContractError> {
+        let agent_id = deps.api.addr_validate(&agent_id)?;
+        let pool = self
+            .agent_pools
+            .may_load(deps.storage, &pool_id)?
+            .ok_or_else(|| ContractError::CustomError {
+                val: "Pool doesn't exist".to_string(),
+            })?;
+        if info.sender != pool.creator {
+            return Err(ContractError::Unauthorized {});
+        }
+        if weight == 0 {
+            return Err(ContractError::CustomError {
+                val: "Pool member weight must be at least 1".to_string(),
+            });
+        }
+        // Rewards earned so far are split at the old weights
+        self.settle_agent_pool(deps.storage, &pool_id)?;
+        let mut pool = self.agent_pools.load(deps.storage, &pool_id)?;
+        if let Some((_, member_weight)) = pool
+            .members
+            .iter_mut()
+            .find(|(member, _)| *member == agent_id)
+        {
+            *member_weight = weight;
+            self.agent_pools.save(deps.storage, &pool_id, &pool)?;
+        } else {
+            let index = pool
+                .join_requests
+                .iter()
+                .position(|request| *request == agent_id)
+                .ok_or_else(|| ContractError::CustomError {
+                    val: "Agent didn't ask to join".to_string(),
+                })?;
+            pool.join_requests.remove(index);
+            self.add_pool_member(deps.storage, &agent_id, &pool_id, &mut pool, weight)?;
+        }
+
+        Ok(Response::new()
+            .add_attribute("method", "approve_agent_pool_member")
+            .add_attribute("pool_id", pool_id)
+            .add_attribute("agent", agent_id)
+            .add_attribute("weight", weight.to_string()))
+    }
+
+    /// Leaves the pool with the agent's share of its rewards so far.
+    /// The last member to leave closes the pool
+    pub fn leave_agent_pool(
+        &self,
+        deps: DepsMut,
+        info: MessageInfo,
+    ) -> Result<Response, ContractError> {
+        let pool_id = self
+            .agents
+            .may_load(deps.storage, &info.sender)?
+            .ok_or(AgentNotRegistered {})?
+            .pool_id
+            .ok_or_else(|| ContractError::CustomError {
+                val: "Agent isn't in a pool".to_string(),
+            })?;
+        self.settle_agent_pool(deps.storage, &pool_id)?;
+        self.remove_pool_member(deps.storage, &info.sender, &pool_id)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "leave_agent_pool")
+            .add_attribute("agent", info.sender)
+            .add_attribute("pool_id", pool_id))
+    }
+
+    /// Saves `pool` with the agent added as a member
+    fn add_pool_member(
+        &self,
+        storage: &mut dyn Storage,
+        agent_id: &Addr,
+        pool_id: &str,
+        pool: &mut AgentPool,
+        weight: u64,
+    ) -> Result<(), ContractError> {
+        let mut agent = self
+            .agents
+            .may_load(storage, agent_id)?
+            .ok_or(AgentNotRegistered {})?;
+        if agent.pool_id.is_some() {
+            return Err(ContractError::CustomError {
+                val: "Agent is already in a pool".to_string(),
+            });
+        }
+        if pool.members.len() >= MAX_AGENT_POOL_MEMBERS {
+            return Err(ContractError::CustomError {
+                val: format!("Pools are limited to {} members", MAX_AGENT_POOL_MEMBERS),
+            });
+        }
+        pool.members.push((agent_id.clone(), weight));
+        self.agent_pools.save(storage, pool_id, pool)?;
+        agent.pool_id = Some(pool_id.to_string());
+        self.agents.save(storage, agent_id, &agent)?;
+        Ok(())
+    }
+
+    /// Caller is responsible for settling the pool first, or the member's share stays behind.
+    /// Whatever the last member leaves in the pool is booked as protocol revenue
+    fn remove_pool_member(
+        &self,
+        storage: &mut dyn Storage,
+        agent_id: &Addr,
+        pool_id: &str,
+    ) -> Result<(), ContractError> {
+        let mut pool = self.agent_pools.load(storage, pool_id)?;
+        pool.members.retain(|(member, _)| member != agent_id);
+        match pool.members.first() {
+            None => {
+                self.agent_pools.remove(storage, pool_id);
+                let leftover: Vec<Coin> = self
+                    .agent_pool_balances
+                    .may_load(storage, pool_id)?
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|coin| !coin.amount.is_zero())
+                    .collect();
+                if !leftover.is_empty() {
+                    let mut config: Config = self.config.load(storage)?;
+                    config.total_agent_balance.checked_sub_native(&leftover)?;
+                    config.protocol_revenue.checked_add_native(&leftover)?;
+                    self.config.save(storage, &config)?;
+                }
+                self.agent_pool_balances.remove(storage, pool_id);
+            }
+            Some((next_member, _)) => {
+                if pool.creator == *agent_id {
+                    pool.creator = next_member.clone();
+                }
+                self.agent_pools.save(storage, pool_id, &pool)?;
+            }
+        }
+        if let Some(mut agent) = self.agents.may_load(storage, agent_id)? {
+            agent.pool_id = None;
+            self.agents.save(storage, agent_id, &agent)?;
+        }
+        Ok(())
+    }
+
+    /// Splits the pool rewards into the members' balances by their weight.
+    /// Whatever doesn't divide evenly stays in the pool for next time
+    pub(crate) fn settle_agent_pool(
+        &self,
+        storage: &mut dyn Storage,
+        pool_id: &str,
+    ) -> Result<(), ContractError> {
+        let pool = self.agent_pools.load(storage, pool_id)?;
+        let mut balance = self
+            .agent_pool_balances
+            .may_load(storage, pool_id)?
+            .unwrap_or_default();
+        let total_weight: u128 = pool
+            .members
+            .iter()
+            .map(|(_, weight)| u128::from(*weight))
+            .sum();
+        if balance.is_empty() || total_weight == 0 {
+            return Ok(());
+        }
+        // Shares are of the pool before any of them is paid out
+        let settled = balance.clone();
+        for (member, weight) in pool.members.iter() {
+            let shares: Vec<Coin> = settled
+                .iter()
+                .map(|coin| Coin {
+                    denom: coin.denom.clone(),
+                    amount: coin.amount.multiply_ratio(*weight, total_weight),
+                })
+                .filter(|share| !share.amount.is_zero())
+                .collect();
+            if shares.is_empty() {
+                continue;
+            }
+            let mut agent = self.agents.load(storage, member)?;
+            agent.balance.checked_add_native(&shares)?;
+            self.agents.save(storage, member, &agent)?;
+            for share in shares.iter() {
+                balance.find_checked_sub(share)?;
+            }
+        }
+        self.agent_pool_balances.save(storage, pool_id, &balance)?;
+        Ok(())
+    }
+
+    /// Limit the agent to tasks of the given types, an empty list accepts all of them.
+    /// Types follow the same limits as capabilities
+    pub fn set_accepted_task_types(
+        &self,
+        deps: DepsMut,
+        info: MessageInfo,
+        task_types: Vec<String>,
+    ) -> Result<Response, ContractError> {
+        let task_types = validate_capabilities(task_types)?;
+        let mut agent = self
+            .agents
+            .may_load(deps.storage, &info.sender)?
+            .ok_or(AgentNotRegistered {})?;
+        agent.accepted_task_types = task_types;
+        self.agents.save(deps.storage, &info.sender, &agent)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "set_accepted_task_types")
+            .add_attribute("agent", info.sender)
+            .add_attribute("task_types", agent.accepted_task_types.join(",")))
+    }
+
+    /// Sets the contract pinged as the agent advances in the pending queue, none removes it
+    pub fn set_position_hook(
+        &self,
+        deps: DepsMut,
+        info: MessageInfo,
+        hook: Option<String>,
+    ) -> Result<Response, ContractError> {
+        let hook = hook.map(|hook| deps.api.addr_validate(&hook)).transpose()?;
+        let mut agent = self
+            .agents
+            .may_load(deps.storage, &info.sender)?
+            .ok_or(AgentNotRegistered {})?;
+        agent.position_hook = hook;
+        self.agents.save(deps.storage, &info.sender, &agent)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "set_position_hook")
+            .add_attribute("agent", info.sender)
+            .add_attribute(
+                "position_hook",
+                agent
+                    .position_hook
+                    .map(Addr::into_string)
+                    .unwrap_or_default(),
+            ))
+    }
+
+    /// Toggles sending rewards to the payable account on each execution
+    pub fn set_stream_rewards(
+        &self,
+        deps: DepsMut,
+        info: MessageInfo,
+        stream: bool,
+    ) -> Result<Response, ContractError> {
+        let mut agent = self
+            .agents
+            .may_load(deps.storage, &info.sender)?
+            .ok_or(AgentNotRegistered {})?;
+        agent.stream_rewards = stream;
+        self.agents.save(deps.storage, &info.sender, &agent)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "set_stream_rewards")
+            .add_attribute("agent", info.sender)
+            .add_attribute("stream_rewards", stream.to_string()))
+    }
+
+    /// Pings the position hooks of agents that moved into a lower bucket of
+    /// `Config.position_hook_step` positions going from `before` to `after`
+    fn position_hook_msgs(
+        &self,
+        storage: &dyn Storage,
+        config: &Config,
+        before: &[Addr],
+        after: &[Addr],
+    ) -> StdResult<Vec<SubMsg>> {
+        let step = config.position_hook_step.max(1);
+        let mut messages = vec![];
+        for (position, agent_id) in after.iter().enumerate() {
+            let position = position as u64;
+            let previous_position = match before.iter().position(|addr| addr == agent_id) {
+                Some(previous_position) => previous_position as u64,
+                None => continue,
+            };
+            if position / step >= previous_position / step {
+                continue;
+            }
+            let hook = match self
+                .agents
+                .may_load(storage, agent_id)?
+                .and_then(|agent| agent.position_hook)
+            {
+                Some(hook) => hook,
+                None => continue,
+            };
+            let msg = WasmMsg::Execute {
+                contract_addr: hook.into_string(),
+                msg: to_binary(&AgentHookMsg::PendingPositionChanged {
+                    agent: agent_id.to_string(),
+                    previous_position,
+                    position,
+                })?,
+                funds: vec![],
+            };
+            messages.push(SubMsg::reply_on_error(msg, POSITION_HOOK_REPLY_ID));
+        }
+        Ok(messages)
+    }
+
+    /// Update several agent fields in one go, each validated like its own handler would.
+    /// Fields left as None keep their current value.
+    /// Stray funds are refunded once here, the handlers are called without them
+    pub fn update_agent_full(
+        &self,
+        mut deps: DepsMut,
+        info: MessageInfo,
+        env: Env,
+        params: UpdateAgentParams,
+    ) -> Result<Response, ContractError> {
+        let agent = self
+            .agents
+            .may_load(deps.storage, &info.sender)?
+            .ok_or(AgentNotRegistered {})?;
+        let c: Config = self.config.load(deps.storage)?;
+        let refund = stray_funds_refund(&c, &info)?;
+        let info = MessageInfo {
+            sender: info.sender,
+            funds: vec![],
+        };
+        let mut updated: Vec<&str> = vec![];
+        // Submessages and attributes of each handler, less the ones naming the call
+        let mut merged = Response::new();
+        let mut merge = |res: Response| {
+            merged = std::mem::take(&mut merged)
+                .add_submessages(res.messages)
+                .add_events(res.events)
+                .add_attributes(
+                    res.attributes
+                        .into_iter()
+                        .filter(|attr| attr.key != "method" && attr.key != "agent"),
+                );
+        };
+
+        if params.payable_account_id.is_some() || params.capabilities.is_some() {
+            if params.payable_account_id.is_some() {
+                updated.push("payable_account_id");
+            }
+            if params.capabilities.is_some() {
+                updated.push("capabilities");
+            }
+            let payable_account_id = params
+                .payable_account_id
+                .unwrap_or_else(|| agent.payable_account_id.to_string());
+            merge(self.update_agent(
+                deps.branch(),
+                info.clone(),
+                env,
+                payable_account_id,
+                params.capabilities,
+                None,
+            )?);
+        }
+        if let Some(operators) = params.operators {
+            merge(self.update_agent_operators(deps.branch(), info.clone(), operators)?);
+            updated.push("operators");
+        }
+        if let Some(preference) = params.reward_preference {
+            merge(self.update_reward_preference(deps.branch(), info.clone(), preference)?);
+            updated.push("reward_preference");
+        }
+        if let Some(task_types) = params.accepted_task_types {
+            merge(self.set_accepted_task_types(deps.branch(), info.clone(), task_types)?);
+            updated.push("accepted_task_types");
+        }
+        if let Some(auto_compound) = params.auto_compound {
+            if auto_compound != agent.auto_compound {
+                merge(self.toggle_auto_compound(deps.branch(), info.clone())?);
+            }
+            updated.push("auto_compound");
+        }
+        if updated.is_empty() {
+            return Err(ContractError::CustomError {
+                val: "No agent fields to update".to_string(),
+            });
+        }
+
+        Ok(Response::new()
+            .add_attribute("method", "update_agent_full")
+            .add_attribute("agent", info.sender)
+            .add_attribute("updated", updated.join(","))
+            .add_attributes(merged.attributes)
+            .add_submessages(refund)
+            .add_submessages(merged.messages)
+            .add_events(merged.events))
+    }
+
+    /// Set the operator keys allowed to execute tasks on behalf of the agent.
+    /// Replaces any previously set operators, rewards keep accruing to the agent.
+    pub fn update_agent_operators(
+        &self,
+        deps: DepsMut,
+        info: MessageInfo,
+        operators: Vec<String>,
+    ) -> Result<Response, ContractError> {
+        if operators.len() > MAX_AGENT_OPERATORS {
+            return Err(ContractError::TooManyOperators {
+                max: MAX_AGENT_OPERATORS,
+            });
+        }
+        let mut agent = self
+            .agents
+            .may_load(deps.storage, &info.sender)?
+            .ok_or(AgentNotRegistered {})?;
+
+        let mut validated: Vec<Addr> = Vec::with_capacity(operators.len());
+        for operator in operators {
+            let operator = deps.api.addr_validate(&operator)?;
+            // An operator can serve only one agent and can't be an agent itself
+            let serves_other = match self.agent_operators.may_load(deps.storage, &operator)? {
+                Some(agent_id) => agent_id != info.sender,
+                None => false,
+            };
+            let in_use = self.agents.has(deps.storage, &operator) || serves_other;
+            if in_use {
+                return Err(ContractError::OperatorInUse {
+                    operator: operator.to_string(),
+                });
+            }
+            if !validated.contains(&operator) {
+                validated.push(operator);
+            }
+        }
+
+        for operator in agent.operators.iter() {
+            self.agent_operators.remove(deps.storage, operator);
+        }
+        for operator in validated.iter() {
+            self.agent_operators
+                .save(deps.storage, operator, &info.sender)?;
+        }
+        agent.operators = validated;
+        self.agents.save(deps.storage, &info.sender, &agent)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "update_agent_operators")
+            .add_attribute("agent", info.sender)
+            .add_attribute(
+                "operators",
+                agent
+                    .operators
+                    .iter()
+                    .map(Addr::as_str)
+                    .collect::<Vec<&str>>()
+                    .join(","),
+            ))
+    }
+
+    /// Moves the agent record to a new address, keeping balance, tenure and queue position.
+    /// Operators, escrowed and vesting rewards, withdrawal keys and pool membership follow the agent,
+    /// for rotating keys. A payable account left at the old address moves to the new one too
+    pub fn migrate_agent(
+        &self,
+        deps: DepsMut,
+        info: MessageInfo,
+        env: Env,
+        new_address: String,
+    ) -> Result<Response, ContractError> {
+        let new_address = deps.api.addr_validate(&new_address)?;
+        let c: Config = self.config.load(deps.storage)?;
+        if c.paused.register {
+            return Err(ContractError::ContractPaused {
+                val: "Register agent paused".to_string(),
+            });
+        }
+        let mut agent = self
+            .agents
+            .may_load(deps.storage, &info.sender)?
+            .ok_or(AgentNotRegistered {})?;
+        if self.agents.has(deps.storage, &new_address) {
+            return Err(ContractError::CustomError {
+                val: "New address is already an agent".to_string(),
+            });
+        }
+        if let Some(agent_id) = self.agent_operators.may_load(deps.storage, &new_address)? {
+            // Our own operator can take over, it just stops being an operator
+            if agent_id != info.sender {
+                return Err(ContractError::OperatorInUse {
+                    operator: new_address.to_string(),
+                });
+            }
+        }
+        // The old key paying out to itself would strand rewards on a rotated-away key
+        let payable_account_id = if agent.payable_account_id == info.sender {
+            new_address.clone()
+        } else {
+            agent.payable_account_id.clone()
+        };
+        check_payable_account(&c, &env, &payable_account_id)?;
+
+        agent.operators.retain(|operator| *operator != new_address);
+        self.agent_operators.remove(deps.storage, &new_address);
+        for operator in agent.operators.iter() {
+            self.agent_operators
+                .save(deps.storage, operator, &new_address)?;
+        }
+
+        let escrows = self
+            .agent_escrow
+            .sub_prefix(&info.sender)
+            .range(deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<_>>>()?;
+        for ((task_hash, release_time), escrow) in escrows {
+            self.agent_escrow.remove(
+                deps.storage,
+                (&info.sender, task_hash.as_slice(), release_time),
+            );
+            self.agent_escrow.save(
+                deps.storage,
+                (&new_address, task_hash.as_slice(), release_time),
+                &escrow,
+            )?;
+        }
+        if let Some(credits) = self.agent_vesting.may_load(deps.storage, &info.sender)? {
+            self.agent_vesting.remove(deps.storage, &info.sender);
+            self.agent_vesting
+                .save(deps.storage, &new_address, &credits)?;
+        }
+        if let Some(history) = self.slash_history.may_load(deps.storage, &info.sender)? {
+            self.slash_history.remove(deps.storage, &info.sender);
+            self.slash_history
+                .save(deps.storage, &new_address, &history)?;
+        }
+        if let Some(keys) = self.withdraw_keys.may_load(deps.storage, &info.sender)? {
+            self.withdraw_keys.remove(deps.storage, &info.sender);
+            self.withdraw_keys.save(deps.storage, &new_address, &keys)?;
+        }
+
+        // Same position, so balancer indices stay valid
+        for queue in [&self.agent_active_queue, &self.agent_pending_queue] {
+            let mut agents: Vec<Addr> = queue.load(deps.storage)?;
+            if let Some(index) = agents.iter().position(|addr| *addr == info.sender) {
+                agents[index] = new_address.clone();
+                queue.save(deps.storage, &agents)?;
+            }
+        }
+
+        self.unindex_payable(deps.storage, &agent.payable_account_id, &info.sender)?;
+        agent.payable_account_id = payable_account_id;
+        self.index_payable(deps.storage, &agent.payable_account_id, &new_address)?;
+        if let Some(pool_id) = agent.pool_id.as_deref() {
+            let mut pool = self.agent_pools.load(deps.storage, pool_id)?;
+            for (member, _) in pool
+                .members
+                .iter_mut()
+                .filter(|(member, _)| *member == info.sender)
+            {
+                *member = new_address.clone();
+            }
+            if pool.creator == info.sender {
+                pool.creator = new_address.clone();
+            }
+            self.agent_pools.save(deps.storage, pool_id, &pool)?;
+        }
+        self.agents.remove(deps.storage, &info.sender);
+        self.agents.save(deps.storage, &new_address, &agent)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "migrate_agent")
+            .add_attribute("agent", info.sender)
+            .add_attribute("new_address", new_address))
+    }
+
+    /// Allows an agent to withdraw all rewards, paid to the specified payable account id.
+    /// Escrowed rewards are included once their dispute window has passed.
+    pub(crate) fn withdraw_balances(
+        &self,
+        storage: &mut dyn Storage,
+        info: MessageInfo,
+        now: Timestamp,
+    ) -> Result<Vec<SubMsg>, ContractError> {
+        let mut config: Config = self.config.load(storage)?;
+        if config.paused.withdraw {
+            return Err(ContractError::ContractPaused {
+                val: "Withdraw agent balance".to_string(),
+            });
+        }
+        if let Some(pool_id) = self
+            .agents
+            .may_load(storage, &info.sender)?
+            .and_then(|agent| agent.pool_id)
+        {
+            self.settle_agent_pool(storage, &pool_id)?;
+        }
+        let mut agent = self
+            .agents
+            .may_load(storage, &info.sender)?
+            .ok_or(AgentNotRegistered {})?;
+        self.release_escrow(storage, &info.sender, &mut agent, now)?;
+        self.release_vested(storage, &info.sender, &mut agent, now)?;
+
+        // Rewards get swapped into the preferred cw20, the router pays the payable account
+        let mut payout = agent.balance.clone();
+        let mut messages = vec![];
+        let mut swapped = vec![];
+        if let (RewardPreference::Cw20(token), Some(router)) =
+            (&agent.reward_preference, &config.dex_router)
+        {
+            if let Some(index) = payout
+                .native
+                .iter()
+                .position(|coin| coin.denom == config.agent_fee.denom)
+            {
+                let reward = payout.native.remove(index);
+                messages.push(SubMsg::new(WasmMsg::Execute {
+                    contract_addr: router.to_string(),
+                    msg: to_binary(&DexRouterExecuteMsg::Swap {
+                        ask_token: token.to_string(),
+                        max_slippage: config.dex_max_slippage,
+                        recipient: agent.payable_account_id.to_string(),
+                    })?,
+                    funds: vec![reward.clone()],
+                }));
+                swapped.push(reward);
+            }
+        }
+
+        // This will send all remaining token balances to Agent
+        let (transfers, mut balances) = send_tokens(&agent.payable_account_id, &payout)?;
+        messages.extend(transfers);
+        balances.checked_add_native(&swapped)?;
+        agent.balance.checked_sub_generic(&balances)?;
+        config.available_balance.checked_sub_generic(&balances)?;
+        config.total_agent_balance.checked_sub_generic(&balances)?;
+        self.agents.save(storage, &info.sender, &agent)?;
+        self.config.save(storage, &config)?;
+
+        Ok(messages)
+    }
+
+    /// Holds `reward` until `release_time` (nanos).
+    /// Every execution keeps its own release time, so repeat runs of a task don't hold back earlier rewards
+    pub(crate) fn escrow_reward(
+        &self,
+        storage: &mut dyn Storage,
+        agent_id: &Addr,
+        task_hash: &[u8],
+        reward: &Coin,
+        release_time: u64,
+    ) -> Result<(), ContractError> {
+        let key = (agent_id, task_hash, release_time);
+        let mut balance = self
+            .agent_escrow
+            .may_load(storage, key)?
+            .unwrap_or_default();
+        balance.native.find_checked_add(reward)?;
+        self.agent_escrow.save(storage, key, &balance)?;
+        Ok(())
+    }
+
+    /// Moves escrowed rewards past their dispute window into the agent balance.
+    /// Caller is responsible for saving the agent afterwards
+    fn release_escrow(
+        &self,
+        storage: &mut dyn Storage,
+        agent_id: &Addr,
+        agent: &mut Agent,
+        now: Timestamp,
+    ) -> Result<(), ContractError> {
+        let escrows = self
+            .agent_escrow
+            .sub_prefix(agent_id)
+            .range(storage, None, None, Order::Ascending)
+            .collect::<StdResult<Vec<_>>>()?;
+        for ((task_hash, release_time), balance) in escrows {
+            if release_time <= now.nanos() {
+                agent.balance.checked_add_native(&balance.native)?;
+                agent.balance.checked_add_cw20(&balance.cw20)?;
+                self.agent_escrow
+                    .remove(storage, (agent_id, task_hash.as_slice(), release_time));
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves the vested part of the agent's vesting rewards into its balance.
+    /// Caller is responsible for saving the agent afterwards
+    fn release_vested(
+        &self,
+        storage: &mut dyn Storage,
+        agent_id: &Addr,
+        agent: &mut Agent,
+        now: Timestamp,
+    ) -> Result<(), ContractError> {
+        let mut credits = match self.agent_vesting.may_load(storage, agent_id)? {
+            Some(credits) => credits,
+            None => return Ok(()),
+        };
+        for credit in credits.iter_mut() {
+            let vested = credit.vested(now.nanos());
+            let release = vested - credit.released;
+            if !release.is_zero() {
+                agent
+                    .balance
+                    .native
+                    .find_checked_add(&Coin::new(release.u128(), credit.amount.denom.clone()))?;
+                credit.released = vested;
+            }
+        }
+        credits.retain(|credit| credit.released < credit.amount.amount);
+        if credits.is_empty() {
+            self.agent_vesting.remove(storage, agent_id);
+        } else {
+            self.agent_vesting.save(storage, agent_id, &credits)?;
+        }
+        Ok(())
+    }
+
+    /// Sends whatever is above `Config.max_agent_balance` to the agent's payable account.
+    /// Caller is responsible for saving the agent afterwards
+    pub(crate) fn withdraw_agent_excess(
+        &self,
+        storage: &mut dyn Storage,
+        agent: &mut Agent,
+    ) -> Result<Option<SubMsg>, ContractError> {
+        let mut config: Config = self.config.load(storage)?;
+        let max_balance = match &config.max_agent_balance {
+            Some(max_balance) => max_balance,
+            None => return Ok(None),
+        };
+        let balance = agent
+            .balance
+            .native
+            .iter()
+            .find(|c| c.denom == max_balance.denom)
+            .map(|c| c.amount)
+            .unwrap_or_default();
+        if balance <= max_balance.amount {
+            return Ok(None);
+        }
+
+        let excess = vec![Coin::new(
+            (balance - max_balance.amount).u128(),
+            max_balance.denom.clone(),
+        )];
+        agent.balance.checked_sub_native(&excess)?;
+        config.available_balance.checked_sub_native(&excess)?;
+        config.total_agent_balance.checked_sub_native(&excess)?;
+        self.config.save(storage, &config)?;
+
+        Ok(Some(SubMsg::new(BankMsg::Send {
+            to_address: agent.payable_account_id.to_string(),
+            amount: excess,
+        })))
+    }
+
+    /// Allows an agent to withdraw all rewards, paid to the specified payable account id.
+    /// Bank sends can't carry a memo, so it's only emitted as an attribute
+    pub fn withdraw_agent_balance(
+        &self,
+        deps: DepsMut,
+        info: MessageInfo,
+        env: Env,
+        idempotency_key: Option<String>,
+        memo: Option<String>,
+    ) -> Result<Response, ContractError> {
+        let mut response = Response::new()
+            .add_attribute("method", "withdraw_agent_balance")
+            .add_attribute("agent", info.sender.clone());
+        if let Some(memo) = memo {
+            if memo.len() > MAX_WITHDRAW_MEMO_LENGTH {
+                return Err(ContractError::CustomError {
+                    val: format!(
+                        "Memo can't be longer than {} characters",
+                        MAX_WITHDRAW_MEMO_LENGTH
+                    ),
+                });
+            }
+            response = response.add_attribute("memo", memo);
+        }
+        let key = match idempotency_key {
+            None => {
+                let messages =
+                    self.withdraw_balances(deps.storage, info.clone(), env.block.time)?;
+                return Ok(response.add_submessages(messages));
+            }
+            Some(key) => key,
+        };
+        if key.is_empty() || key.len() > MAX_IDEMPOTENCY_KEY_LENGTH {
+            return Err(ContractError::CustomError {
+                val: format!(
+                    "Idempotency key must be 1 to {} characters",
+                    MAX_IDEMPOTENCY_KEY_LENGTH
+                ),
+            });
+        }
+
+        // A retried withdrawal already went through, nothing left to send
+        let mut used = self
+            .withdraw_keys
+            .may_load(deps.storage, &info.sender)?
+            .unwrap_or_default();
+        if let Some((_, withdrawn_at)) = used.iter().find(|(used_key, _)| *used_key == key) {
+            return Ok(response
+                .add_attribute("idempotency_key", key)
+                .add_attribute("replayed_from", withdrawn_at.to_string()));
+        }
+
+        let messages = self.withdraw_balances(deps.storage, info.clone(), env.block.time)?;
+        used.push((key.clone(), env.block.time.nanos()));
+        if used.len() > MAX_IDEMPOTENCY_KEYS {
+            used.remove(0);
+        }
+        self.withdraw_keys.save(deps.storage, &info.sender, &used)?;
+
+        Ok(response
+            .add_attribute("idempotency_key", key)
+            .add_submessages(messages))
+    }
+
+    /// Withdraws the balances of the agents paying out to the caller as one payout.
+    /// Agents are taken by address, at most `MAX_AGENTS_PER_QUERY` of them after `start_after`,
+    /// pass the `last_agent_id` attribute of a page as `start_after` to withdraw the next one.
+    /// Natives go out in a single bank send, preferred cw20 swaps are skipped
+    pub fn withdraw_all_for_payable(
+        &self,
+        deps: DepsMut,
+        info: MessageInfo,
+        env: Env,
+        start_after: Option<String>,
+        limit: Option<u32>,
+    ) -> Result<Response, ContractError> {
+        let c: Config = self.config.load(deps.storage)?;
+        if c.paused.withdraw {
+            return Err(ContractError::ContractPaused {
+                val: "Withdraw agent balance".to_string(),
+            });
+        }
+        let mut agent_ids = self
+            .agents_by_payable
+            .may_load(deps.storage, &info.sender)?
+            .unwrap_or_default();
+        if agent_ids.is_empty() {
+            return Err(ContractError::CustomError {
+                val: "No agents pay out to this account".to_string(),
+            });
+        }
+        let start_after = start_after
+            .map(|addr| deps.api.addr_validate(&addr))
+            .transpose()?;
+        let limit = limit
+            .map_or(MAX_AGENTS_PER_QUERY, |limit| limit as usize)
+            .min(MAX_AGENTS_PER_QUERY);
+        agent_ids.sort();
+        let agent_ids: Vec<Addr> = agent_ids
+            .into_iter()
+            .filter(|agent_id| match &start_after {
+                Some(start) => agent_id > start,
+                None => true,
+            })
+            .take(limit)
+            .collect();
+        let last_agent_id = agent_ids.last().map(Addr::to_string).unwrap_or_default();
+
+        let mut total = GenericBalance::default();
+        for agent_id in &agent_ids {
+            if let Some(pool_id) = self.agents.load(deps.storage, agent_id)?.pool_id {
+                self.settle_agent_pool(deps.storage, &pool_id)?;
+            }
+            let mut agent = self.agents.load(deps.storage, agent_id)?;
+            self.release_escrow(deps.storage, agent_id, &mut agent, env.block.time)?;
+            self.release_vested(deps.storage, agent_id, &mut agent, env.block.time)?;
+            let balance = std::mem::take(&mut agent.balance);
+            total.checked_add_native(&balance.native)?;
+            total.checked_add_cw20(&balance.cw20)?;
+            self.agents.save(deps.storage, agent_id, &agent)?;
+        }
+
+        // Settling pools can touch the config, so it's loaded again
+        let mut config: Config = self.config.load(deps.storage)?;
+        let (messages, sent) = send_tokens(&info.sender, &total)?;
+        config.available_balance.checked_sub_generic(&sent)?;
+        config.total_agent_balance.checked_sub_generic(&sent)?;
+        self.config.save(deps.storage, &config)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "withdraw_all_for_payable")
+            .add_attribute("payable_account_id", info.sender)
+            .add_attribute("agents", agent_ids.len().to_string())
+            .add_attribute("last_agent_id", last_agent_id)
+            .add_submessages(messages))
+    }
+
+    /// Swaps an active agent with a pending one, owner only.
+    /// The promoted agent takes over the exact position in the active queue,
+    /// the removed agent goes to the back of the pending queue.
+    pub fn replace_active_agent(
+        &self,
+        deps: DepsMut,
+        info: MessageInfo,
+        remove: String,
+        promote: String,
+    ) -> Result<Response, ContractError> {
+        let remove = deps.api.addr_validate(&remove)?;
+        let promote = deps.api.addr_validate(&promote)?;
+        let c: Config = self.config.load(deps.storage)?;
+        if info.sender != c.owner_id {
+            return Err(ContractError::Unauthorized {});
+        }
+
+        let mut queues = self.load_queues(deps.storage)?;
+        let active_index = queues
+            .active
+            .iter()
+            .position(|addr| *addr == remove)
+            .ok_or_else(|| ContractError::CustomError {
+                val: "Agent to remove is not active".to_string(),
+            })?;
+        let pending_index = queues
+            .pending
+            .iter()
+            .position(|addr| *addr == promote)
+            .ok_or_else(|| ContractError::CustomError {
+                val: "Agent to promote is not pending".to_string(),
+            })?;
+
+        // Same position, so balancer indices stay valid
+        let pending_before = queues.pending.clone();
+        queues.active_mut()[active_index] = promote.clone();
+        let pending_agents = queues.pending_mut();
+        // Pending order is nomination order, it's kept whatever the active queue does
+        QueueRemovalMode::PreserveOrder.remove_at(pending_agents, pending_index);
+        pending_agents.push(remove.clone());
+        let hook_msgs =
+            self.position_hook_msgs(deps.storage, &c, &pending_before, &queues.pending)?;
+        self.flush_queues(deps.storage, &queues)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "replace_active_agent")
+            .add_attribute("removed", remove)
+            .add_attribute("promoted", promote)
+            .add_submessages(hook_msgs))
+    }
+
+    /// Demotes the whole active set to the back of the pending queue and promotes the front
+    /// of it instead, owner only. Promotes up to `max_agents`, or as many as were active
+    pub fn rotate_active_set(
+        &self,
+        deps: DepsMut,
+        info: MessageInfo,
+        env: Env,
+    ) -> Result<Response, ContractError> {
+        let c: Config = self.config.load(deps.storage)?;
+        if info.sender != c.owner_id {
+            return Err(ContractError::Unauthorized {});
+        }
+
+        let mut queues = self.load_queues(deps.storage)?;
+        let count = c
+            .max_agents
+            .map_or(queues.active.len(), |max_agents| max_agents as usize)
+            .min(queues.pending.len());
+        if count == 0 {
+            return Err(ContractError::CustomError {
+                val: "No pending agents to rotate in".to_string(),
+            });
+        }
+        let pending_before = queues.pending.clone();
+        let promoted: Vec<Addr> = queues.pending_mut().drain(..count).collect();
+        let demoted = std::mem::replace(queues.active_mut(), promoted.clone());
+        queues.pending_mut().extend(demoted.iter().cloned());
+        let hook_msgs =
+            self.position_hook_msgs(deps.storage, &c, &pending_before, &queues.pending)?;
+        self.flush_queues(deps.storage, &queues)?;
+        for agent_id in promoted.iter() {
+            self.agents.update(
+                deps.storage,
+                agent_id,
+                |a: Option<Agent>| -> Result<_, ContractError> {
+                    let mut agent = a.ok_or(AgentNotRegistered {})?;
+                    agent.last_activation = Some(env.block.time);
+                    Ok(agent)
+                },
+            )?;
+        }
+
+        let join = |agents: &[Addr]| {
+            agents
+                .iter()
+                .map(Addr::as_str)
+                .collect::<Vec<_>>()
+                .join(",")
+        };
+        Ok(Response::new()
+            .add_attribute("method", "rotate_active_set")
+            .add_attribute("demoted", join(&demoted))
+            .add_attribute("promoted", join(&promoted))
+            .add_submessages(hook_msgs))
+    }
+
+    /// Dedupes the agent queues and drops entries without an agent record, owner only.
+    /// Recovery path for whatever `GetQueueIntegrity` reports
+    pub fn repair_queues(
+        &self,
+        deps: DepsMut,
+        info: MessageInfo,
+    ) -> Result<Response, ContractError> {
+        let c: Config = self.config.load(deps.storage)?;
+        if info.sender != c.owner_id {
+            return Err(ContractError::Unauthorized {});
+        }
+
+        let active: Vec<Addr> = self.agent_active_queue.load(deps.storage)?;
+        let mut repaired_active = active.clone();
+        let (active_dupes, active_orphans) =
+            self.clean_queue(deps.storage, c.queue_removal_mode, &mut repaired_active);
+        if repaired_active.len() != active.len() {
+            // Indices point at queue positions, let the balancer reset them
+            let removed_agent = active_dupes
+                .first()
+                .or_else(|| active_orphans.first())
+                .cloned()
+                .unwrap();
+            self.balancer.on_agent_unregister(
+                deps.storage,
+                &self.config,
+                &self.agent_active_queue,
+                removed_agent,
+            );
+            self.agent_active_queue
+                .save(deps.storage, &repaired_active)?;
+        }
+
+        let pending: Vec<Addr> = self.agent_pending_queue.load(deps.storage)?;
+        let mut repaired_pending = pending.clone();
+        let (pending_dupes, pending_orphans) = self.clean_queue(
+            deps.storage,
+            QueueRemovalMode::PreserveOrder,
+            &mut repaired_pending,
+        );
+        if repaired_pending.len() != pending.len() {
+            self.agent_pending_queue
+                .save(deps.storage, &repaired_pending)?;
+        }
+
+        let mut orphans = active_orphans;
+        for agent_id in pending_orphans {
+            if !orphans.contains(&agent_id) {
+                orphans.push(agent_id);
+            }
+        }
+        let join = |addrs: &[Addr]| {
+            addrs
+                .iter()
+                .map(|a| a.to_string())
+                .collect::<Vec<String>>()
+                .join(",")
+        };
+
+        Ok(Response::new()
+            .add_attribute("method", "repair_queues")
+            .add_attribute("active_dupes", join(&active_dupes))
+            .add_attribute("pending_dupes", join(&pending_dupes))
+            .add_attribute("orphaned_queue_entries", join(&orphans)))
+    }
+
+    /// Drops repeats and entries without an agent record from the queue,
+    /// returning the dupes and orphans it dropped
+    fn clean_queue(
+        &self,
+        storage: &dyn Storage,
+        mode: QueueRemovalMode,
+        queue: &mut Vec<Addr>,
+    ) -> (Vec<Addr>, Vec<Addr>) {
+        let dupes = find_duplicates(queue);
+        let mut seen: Vec<&Addr> = vec![];
+        let mut orphans: Vec<Addr> = vec![];
+        let mut dropped: Vec<usize> = vec![];
+        for (index, agent_id) in queue.iter().enumerate() {
+            if seen.contains(&agent_id) {
+                dropped.push(index);
+                continue;
+            }
+            seen.push(agent_id);
+            if !self.agents.has(storage, agent_id) {
+                orphans.push(agent_id.clone());
+                dropped.push(index);
+            }
+        }
+        // Back to front, so a swapped in entry is never one still to drop
+        for index in dropped.into_iter().rev() {
+            mode.remove_at(queue, index);
+        }
+        (dupes, orphans)
+    }
+
+    /// Takes the accrued reward balance of an agent, including escrowed rewards, owner only.
+    /// With `Config.slash_denom` set only that denom is taken, from the balance and the bond.
+    /// Slashed funds are routed according to `Config.slash_destination`
+    pub fn slash_agent(
+        &self,
+        deps: DepsMut,
+        info: MessageInfo,
+        env: Env,
+        agent_id: String,
+    ) -> Result<Response, ContractError> {
+        let agent_id = deps.api.addr_validate(&agent_id)?;
+        let mut config: Config = self.config.load(deps.storage)?;
+        if info.sender != config.owner_id {
+            return Err(ContractError::Unauthorized {});
+        }
+        let mut agent = self
+            .agents
+            .may_load(deps.storage, &agent_id)?
+            .ok_or(AgentNotRegistered {})?;
+        if !is_slashable(&config, &agent) {
+            return Err(ContractError::CustomError {
+                val: "Agent hasn't missed enough slots to be slashed".to_string(),
+            });
+        }
+
+        let mut slashed = GenericBalance::default();
+        if config.slash_bps > 0 {
+            // Only a share of the bond, limited to `slash_denom` if set
+            let slash_denom = config.slash_denom.as_deref();
+            let slashable = |coin: &&mut Coin| match slash_denom {
+                Some(denom) => coin.denom == denom,
+                None => true,
+            };
+            for coin in agent.bond.iter_mut().filter(slashable) {
+                let (amount, _) = config
+                    .reward_rounding
+                    .split(coin.amount, u64::from(config.slash_bps));
+                if !amount.is_zero() {
+                    coin.amount -= amount;
+                    slashed.checked_add_native(&[Coin::new(amount.u128(), &coin.denom)])?;
+                }
+            }
+            agent.bond.retain(|coin| !coin.amount.is_zero());
+            if slashed.native.is_empty() {
+                return Err(ContractError::CustomError {
+                    val: "Agent has no bond to slash".to_string(),
+                });
+            }
+        } else if let Some(slash_denom) = config.slash_denom.as_deref() {
+            for coins in [&mut agent.balance.native, &mut agent.bond] {
+                if let Some(coin) = take_denom(coins, slash_denom) {
+                    slashed.checked_add_native(&[coin])?;
+                }
+            }
+            if slashed.native.is_empty() {
+                return Err(ContractError::CustomError {
+                    val: format!("Agent holds no {} to slash", slash_denom),
+                });
+            }
+        } else {
+            slashed = std::mem::take(&mut agent.balance);
+            slashed.checked_add_native(&std::mem::take(&mut agent.bond))?;
+            slashed.checked_add_native(&std::mem::take(&mut agent.pending_dust))?;
+            for credit in self
+                .agent_vesting
+                .may_load(deps.storage, &agent_id)?
+                .unwrap_or_default()
+            {
+                slashed.checked_add_native(&[Coin::new(
+                    (credit.amount.amount - credit.released).u128(),
+                    credit.amount.denom,
+                )])?;
+            }
+            self.agent_vesting.remove(deps.storage, &agent_id);
+            let escrows = self
+                .agent_escrow
+                .sub_prefix(&agent_id)
+                .range(deps.storage, None, None, Order::Ascending)
+                .collect::<StdResult<Vec<_>>>()?;
+            for ((task_hash, release_time), balance) in escrows {
+                slashed.checked_add_native(&balance.native)?;
+                slashed.checked_add_cw20(&balance.cw20)?;
+                self.agent_escrow.remove(
+                    deps.storage,
+                    (&agent_id, task_hash.as_slice(), release_time),
+                );
+            }
+        }
+        config.total_agent_balance.checked_sub_generic(&slashed)?;
+        let messages = self.route_slashed_funds(&mut config, &slashed)?;
+        if !slashed.native.is_empty() {
+            let mut history = self
+                .slash_history
+                .may_load(deps.storage, &agent_id)?
+                .unwrap_or_default();
+            for coin in slashed.native.iter() {
+                history.push((
+                    env.block.time.nanos(),
+                    coin.clone(),
+                    config.slash_destination.clone(),
+                ));
+            }
+            self.slash_history.save(deps.storage, &agent_id, &history)?;
+        }
+        self.agents.save(deps.storage, &agent_id, &agent)?;
+        self.config.save(deps.storage, &config)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "slash_agent")
+            .add_attribute("agent", agent_id)
+            .add_attribute(
+                "slash_destination",
+                format!("{:?}", config.slash_destination),
+            )
+            .add_submessages(messages))
+    }
+
+    /// Clears the slash history of an agent and moves it back to the active queue, owner only.
+    /// With `max_agents` active already it goes to the front of the pending queue instead.
+    /// With `refund`, the natively slashed funds go back to the agent balance out of `protocol_revenue`.
+    /// Only treasury slashes can be refunded, burned or sent away funds are gone
+    pub fn reinstate_agent(
+        &self,
+        deps: DepsMut,
+        info: MessageInfo,
+        account_id: String,
+        refund: bool,
+    ) -> Result<Response, ContractError> {
+        let account_id = deps.api.addr_validate(&account_id)?;
+        let mut config: Config = self.config.load(deps.storage)?;
+        if info.sender != config.owner_id {
+            return Err(ContractError::Unauthorized {});
+        }
+        let mut agent = self
+            .agents
+            .may_load(deps.storage, &account_id)?
+            .ok_or(AgentNotRegistered {})?;
+        let history = self
+            .slash_history
+            .may_load(deps.storage, &account_id)?
+            .ok_or_else(|| ContractError::CustomError {
+                val: "Agent has not been slashed".to_string(),
+            })?;
+
+        let mut refunded: Vec<Coin> = vec![];
+        if refund {
+            for (_, coin, destination) in history {
+                if destination != SlashDestination::Treasury {
+                    return Err(ContractError::CustomError {
+                        val: "Only funds slashed to the treasury can be refunded".to_string(),
+                    });
+                }
+                refunded.find_checked_add(&coin)?;
+            }
+            // Treasury slashes never left `available_balance`, they were booked as revenue
+            config
+                .protocol_revenue
+                .checked_sub_native(&refunded)
+                .map_err(|_| ContractError::CustomError {
+                    val: "Slashed funds were already withdrawn from protocol revenue".to_string(),
+                })?;
+            agent.balance.checked_add_native(&refunded)?;
+            config.total_agent_balance.checked_add_native(&refunded)?;
+        }
+        agent.consecutive_misses = 0;
+        agent.missed_nominations = 0;
+        self.agents.save(deps.storage, &account_id, &agent)?;
+        self.slash_history.remove(deps.storage, &account_id);
+        self.config.save(deps.storage, &config)?;
+
+        let mut queues = self.load_queues(deps.storage)?;
+        let pending_before = queues.pending.clone();
+        if queues.pending.contains(&account_id) {
+            QueueRemovalMode::PreserveOrder.remove(queues.pending_mut(), &account_id);
+        }
+        let full = match config.max_agents {
+            Some(max_agents) => queues.active.len() as u64 >= max_agents,
+            None => false,
+        };
+        let status = if queues.active.contains(&account_id) {
+            AgentStatus::Active
+        } else if full {
+            // No room yet, it's first in line for the next opening
+            queues.pending_mut().insert(0, account_id.clone());
+            AgentStatus::Pending
+        } else {
+            queues.active_mut().push(account_id.clone());
+            AgentStatus::Active
+        };
+        let hook_msgs =
+            self.position_hook_msgs(deps.storage, &config, &pending_before, &queues.pending)?;
+        self.flush_queues(deps.storage, &queues)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "reinstate_agent")
+            .add_attribute("agent", account_id)
+            .add_attribute("status", format!("{:?}", status))
+            .add_attribute("refunded", format!("{:?}", refunded))
+            .add_submessages(hook_msgs))
+    }
+
+    /// Boosts or cuts the rewards of a single agent, owner only.
+    /// 10_000 bps pays the normal reward, at most `Config.max_reward_multiplier_bps`
+    pub fn set_agent_reward_multiplier(
+        &self,
+        deps: DepsMut,
+        info: MessageInfo,
+        account_id: String,
+        multiplier_bps: u16,
+    ) -> Result<Response, ContractError> {
+        let account_id = deps.api.addr_validate(&account_id)?;
+        let c: Config = self.config.load(deps.storage)?;
+        if info.sender != c.owner_id {
+            return Err(ContractError::Unauthorized {});
+        }
+        if multiplier_bps > c.max_reward_multiplier_bps {
+            return Err(ContractError::CustomError {
+                val: format!(
+                    "Reward multiplier can't be above {} bps",
+                    c.max_reward_multiplier_bps
+                ),
+            });
+        }
+        let mut agent = self
+            .agents
+            .may_load(deps.storage, &account_id)?
+            .ok_or(AgentNotRegistered {})?;
+        agent.reward_multiplier_bps = multiplier_bps;
+        self.agents.save(deps.storage, &account_id, &agent)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "set_agent_reward_multiplier")
+            .add_attribute("agent", account_id)
+            .add_attribute("multiplier_bps", multiplier_bps.to_string()))
+    }
+
+    /// Splits the attached `total` equally between the active agents, owner only.
+    /// The `total % agents` remainder is split by `Config.reward_rounding`: rounded down it goes
+    /// to the treasury, rounded up the front of the active queue gets one more unit each.
+    /// Shares are credited like rewards, to pools, vesting or streams
+    pub fn distribute_bonus(
+        &self,
+        deps: DepsMut,
+        info: MessageInfo,
+        env: Env,
+        total: Coin,
+    ) -> Result<Response, ContractError> {
+        let mut c: Config = self.config.load(deps.storage)?;
+        if info.sender != c.owner_id {
+            return Err(ContractError::Unauthorized {});
+        }
+        if total.amount.is_zero() || info.funds != vec![total.clone()] {
+            return Err(ContractError::CustomError {
+                val: "Must attach exactly the bonus total".to_string(),
+            });
+        }
+        let active = self.agent_active_queue.load(deps.storage)?;
+        if active.is_empty() {
+            return Err(ContractError::CustomError {
+                val: "No active agents to distribute to".to_string(),
+            });
+        }
+
+        // Streamed shares are sent out of the available balance
+        c.available_balance.checked_add_native(&info.funds)?;
+        self.config.save(deps.storage, &c)?;
+        let agents = active.len() as u128;
+        let base_share = total.amount.multiply_ratio(1u128, agents);
+        let mut remainder = Uint128::new(total.amount.u128() % agents);
+        let mut credited = Uint128::zero();
+        let mut stream_msgs = vec![];
+        for agent_id in active.iter() {
+            let mut share = base_share;
+            if c.reward_rounding == RewardRounding::RoundUp && !remainder.is_zero() {
+                share += Uint128::new(1);
+                remainder -= Uint128::new(1);
+            }
+            if share.is_zero() {
+                break;
+            }
+            let mut agent = self
+                .agents
+                .may_load(deps.storage, agent_id)?
+                .ok_or(AgentNotRegistered {})?;
+            let reward = Coin::new(share.u128(), &total.denom);
+            match self.credit_agent(
+                deps.storage,
+                &c,
+                agent_id,
+                &mut agent,
+                &reward,
+                env.block.time,
+            )? {
+                Some(stream_msg) => stream_msgs.push(stream_msg),
+                None => credited += share,
+            }
+            self.agents.save(deps.storage, agent_id, &agent)?;
+        }
+
+        let mut c: Config = self.config.load(deps.storage)?;
+        if !credited.is_zero() {
+            c.total_agent_balance
+                .checked_add_native(&[Coin::new(credited.u128(), &total.denom)])?;
+        }
+        if !remainder.is_zero() {
+            c.protocol_revenue
+                .checked_add_native(&[Coin::new(remainder.u128(), &total.denom)])?;
+        }
+        self.config.save(deps.storage, &c)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "distribute_bonus")
+            .add_attribute("total", total.to_string())
+            .add_attribute("agents", active.len().to_string())
+            .add_attribute("remainder", remainder.to_string())
+            .add_submessages(stream_msgs))
+    }
+
+    /// Routes slashed funds to the configured destination.
+    /// Agent balances are already tallied in `available_balance`,
+    /// so `Treasury` keeps them there as protocol revenue, while `Burn` and `Address` move them out.
+    pub(crate) fn route_slashed_funds(
+        &self,
+        config: &mut Config,
+        slashed: &GenericBalance,
+    ) -> Result<Vec<SubMsg>, ContractError> {
+        let messages = match &config.slash_destination {
+            SlashDestination::Treasury => {
+                config
+                    .protocol_revenue
+                    .checked_add_native(&slashed.native)?;
+                config.protocol_revenue.checked_add_cw20(&slashed.cw20)?;
+                vec![]
+            }
+            SlashDestination::Burn => {
+                let mut messages = vec![];
+                if !slashed.native.is_empty() {
+                    messages.push(SubMsg::new(BankMsg::Burn {
+                        amount: slashed.native.clone(),
+                    }));
+                }
+                for coin in slashed.cw20.iter() {
+                    messages.push(SubMsg::new(WasmMsg::Execute {
+                        contract_addr: coin.address.to_string(),
+                        msg: to_binary(&Cw20ExecuteMsg::Burn {
+                            amount: coin.amount,
+                        })?,
+                        funds: vec![],
+                    }));
+                }
+                messages
+            }
+            SlashDestination::Address(addr) => send_tokens(addr, slashed)?.0,
+        };
+        if !messages.is_empty() {
+            config.available_balance.checked_sub_generic(slashed)?;
+        }
+        Ok(messages)
+    }
+
+    /// Allows an agent to accept a nomination within a certain amount of time to become an active agent.
+    /// Nobody is let in while nomination is suspended, see `min_tasks_for_nomination`.
+    /// Accepting after the agent's own nomination duration has passed sends it to the back of pending instead.
+    pub fn accept_nomination_agent(
+        &self,
+        deps: DepsMut,
+        info: MessageInfo,
+        env: Env,
+    ) -> Result<Response, ContractError> {
+        // Compare current time and Config's agent_nomination_begin_time to see if agent can join
+        let c: Config = self.config.load(deps.storage)?;
+        let refund = stray_funds_refund(&c, &info)?;
+
+        let time_difference =
+            if let Some(nomination_start) = self.agent_nomination_begin_time.load(deps.storage)? {
+                env.block.time.seconds() - nomination_start.seconds()
+            } else {
+                // No agents can join yet
+                return Err(ContractError::CustomError {
+                    val: "Not accepting new agents".to_string(),
+                });
+            };
+        if self.nomination_suspended(deps.storage, &env.block, &c)? {
+            return Err(ContractError::CustomError {
+                val: "Nomination is suspended while few tasks are due".to_string(),
+            });
+        }
+        // Agent must be in the pending queue
+        let mut queues = self.load_queues(deps.storage)?;
+        let pending_before = queues.pending.clone();
+        let mut unregistered: Vec<Addr> = vec![];
+        let mut payouts: Vec<SubMsg> = vec![];
+        let mut hook_msgs: Vec<SubMsg> = vec![];
+        // Get the position in the pending queue
+        if let Some(agent_position) = queues
+            .pending
+            .iter()
+            .position(|address| address == &info.sender)
+        {
+            // It works out such that the time difference between when this is called,
+            // and the agent nomination begin time can be divided by the nomination
+            // duration and we get an integer. We use that integer to determine if an
+            // agent is allowed to get let in. If their position in the pending queue is
+            // less than or equal to that integer, they get let in.
+            let max_index = time_difference.div(c.agent_nomination_duration as u64);
+            // Nominated at `agent_position` durations in, the nomination only lasts one duration.
+            // Ok rather than an error, so the requeue sticks
+            if (agent_position as u64) < max_index {
+                QueueRemovalMode::PreserveOrder.remove_at(queues.pending_mut(), agent_position);
+                let mut response = Response::new()
+                    .add_attribute("method", "accept_nomination_agent")
+                    .add_attribute("agent", info.sender.clone())
+                    .add_attribute("nomination_expired", "true");
+                match self.record_missed_nomination(deps.storage, &c, &info.sender)? {
+                    None => queues.pending_mut().push(info.sender),
+                    Some(payout) => {
+                        response = response
+                            .add_attribute("unregistered_agents", info.sender)
+                            .add_submessages(payout);
+                    }
+                }
+                hook_msgs =
+                    self.position_hook_msgs(deps.storage, &c, &pending_before, &queues.pending)?;
+                self.flush_queues(deps.storage, &queues)?;
+                return Ok(response.add_submessages(hook_msgs).add_submessages(refund));
+            }
+            if agent_position as u64 <= max_index {
+                // Make this agent active
+                // Update state removing from pending queue
+                let pending_agents = queues.pending_mut();
+                let mut missed: Vec<Addr> = pending_agents.drain(..=agent_position).collect();
+                missed.pop();
+                // Everyone ahead of this agent let their nomination expire, they go to the back
+                for agent_id in missed {
+                    match self.record_missed_nomination(deps.storage, &c, &agent_id)? {
+                        None => pending_agents.push(agent_id),
+                        Some(payout) => {
+                            unregistered.push(agent_id);
+                            payouts.extend(payout);
+                        }
+                    }
+                }
+
+                // and adding to active queue
+                if let Some(max_agents) = c.max_agents {
+                    if queues.active.len() as u64 >= max_agents {
+                        return Err(ContractError::CustomError {
+                            val: "Active agent queue is full".to_string(),
+                        });
+                    }
+                }
+                queues.active_mut().push(info.sender.clone());
+                hook_msgs =
+                    self.position_hook_msgs(deps.storage, &c, &pending_before, &queues.pending)?;
+                self.flush_queues(deps.storage, &queues)?;
+                self.agents.update(
+                    deps.storage,
+                    &info.sender,
+                    |a: Option<Agent>| -> Result<_, ContractError> {
+                        let mut agent = a.ok_or(AgentNotRegistered {})?;
+                        agent.last_activation = Some(env.block.time);
+                        Ok(agent)
+                    },
+                )?;
+
+                // and update the config, setting the nomination begin time to None,
+                // which indicates no one will be nominated until more tasks arrive
+                self.agent_nomination_begin_time.save(deps.storage, &None)?;
+            } else {
+                return Err(ContractError::CustomError {
+                    val: "Must wait longer before accepting nomination".to_string(),
+                });
+            }
+        } else {
+            // Sender's address does not exist in the agent pending queue
+            return Err(ContractError::AgentNotRegistered {});
+        }
+        // Find difference
+        let mut response = Response::new()
+            .add_attribute("method", "accept_nomination_agent")
+            .add_attribute("agent", info.sender);
+        if !unregistered.is_empty() {
+            response = response.add_attribute(
+                "unregistered_agents",
+                unregistered
+                    .iter()
+                    .map(Addr::as_str)
+                    .collect::<Vec<_>>()
+                    .join(","),
+            );
+        }
+        Ok(response
+            .add_submessages(payouts)
+            .add_submessages(hook_msgs)
+            .add_submessages(refund))
+    }
+
+    /// Counts an expired nomination against a pending agent. If that takes it over
+    /// `max_missed_nominations` it gets unregistered, its balance, bond and dust paid out to
+    /// its payable account like `unregister_agent` does, and the payout messages are returned
+    /// for the caller, which leaves it out of the pending queue. Returns None while it stays
+    /// registered, so does an agent with rewards still in escrow or vesting, those can't be paid yet
+    fn record_missed_nomination(
+        &self,
+        storage: &mut dyn Storage,
+        config: &Config,
+        agent_id: &Addr,
+    ) -> Result<Option<Vec<SubMsg>>, ContractError> {
+        let mut agent = match self.agents.may_load(storage, agent_id)? {
+            Some(agent) => agent,
+            None => return Ok(Some(vec![])),
+        };
+        agent.missed_nominations = agent.missed_nominations.saturating_add(1);
+        if config.max_missed_nominations == 0
+            || agent.missed_nominations < config.max_missed_nominations
+            || self.holds_locked_rewards(storage, agent_id)?
+        {
+            self.agents.save(storage, agent_id, &agent)?;
+            return Ok(None);
+        }
+        // Its share of the pool is its own too
+        if let Some(pool_id) = agent.pool_id.as_deref() {
+            self.settle_agent_pool(storage, pool_id)?;
+            agent.balance = self.agents.load(storage, agent_id)?.balance;
+        }
+        let mut payout = std::mem::take(&mut agent.balance);
+        payout.checked_add_native(&agent.bond)?;
+        payout.checked_add_native(&agent.pending_dust)?;
+        payout.native.retain(|coin| !coin.amount.is_zero());
+        payout.cw20.retain(|coin| !coin.amount.is_zero());
+        let (messages, paid) = send_tokens(&agent.payable_account_id, &payout)?;
+        let mut c: Config = self.config.load(storage)?;
+        c.available_balance.checked_sub_generic(&paid)?;
+        c.total_agent_balance.checked_sub_generic(&paid)?;
+        self.config.save(storage, &c)?;
+        for operator in agent.operators.iter() {
+            self.agent_operators.remove(storage, operator);
+        }
+        self.unindex_payable(storage, &agent.payable_account_id, agent_id)?;
+        if let Some(pool_id) = agent.pool_id {
+            self.remove_pool_member(storage, agent_id, &pool_id)?;
+        }
+        self.agents.remove(storage, agent_id);
+        self.withdraw_keys.remove(storage, agent_id);
+        Ok(Some(messages))
+    }
+
+    /// Whether the agent has rewards in escrow or still vesting
+    fn holds_locked_rewards(&self, storage: &dyn Storage, agent_id: &Addr) -> StdResult<bool> {
+        if self.agent_vesting.has(storage, agent_id) {
+            return Ok(true);
+        }
+        Ok(self
+            .agent_escrow
+            .sub_prefix(agent_id)
+            .range(storage, None, None, Order::Ascending)
+            .next()
+            .is_some())
+    }
+
+    /// Logs an active agent leaving and trips the mass exit breaker if too many left
+    /// within the window, pausing execution. Returns true when it trips
+    fn record_active_exit(
+        &self,
+        storage: &mut dyn Storage,
+        now: Timestamp,
+    ) -> Result<bool, ContractError> {
+        let mut config: Config = self.config.load(storage)?;
+        let threshold = match config.mass_exit_threshold {
+            Some(threshold) => threshold,
+            None => return Ok(false),
+        };
+        let mut exit_times = self.agent_exit_times.may_load(storage)?.unwrap_or_default();
+        exit_times.push(now.nanos());
+        let window_start = now.nanos().saturating_sub(threshold.window_nanos);
+        exit_times.retain(|exit_time| *exit_time >= window_start);
+        let capacity = threshold.max_exits.saturating_add(1) as usize;
+        if exit_times.len() > capacity {
+            exit_times.drain(..exit_times.len() - capacity);
+        }
+
+        let tripped = exit_times.len() as u64 > threshold.max_exits;
+        if tripped {
+            config.paused.execute = true;
+            self.config.save(storage, &config)?;
+            // Start counting fresh once the owner resumes
+            exit_times.clear();
+        }
+        self.agent_exit_times.save(storage, &exit_times)?;
+        Ok(tripped)
+    }
+
+    /// Stepping down right after activation would let an agent skip unfavorable slots,
+    /// so an active agent can't leave or pause before `activation_cooldown_nanos` passed
+    fn check_activation_cooldown(
+        &self,
+        storage: &dyn Storage,
+        active: &[Addr],
+        agent_id: &Addr,
+        agent: &Agent,
+        now: Timestamp,
+    ) -> Result<(), ContractError> {
+        if let Some(last_activation) = agent.last_activation {
+            let cooldown = self.config.load(storage)?.activation_cooldown_nanos;
+            if active.contains(agent_id)
+                && now.nanos() < last_activation.nanos().saturating_add(cooldown)
+            {
+                return Err(ContractError::CustomError {
+                    val: "Agent can't step down during the activation cooldown".to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes the agent from the active set of agents.
+    /// Withdraws all reward balances to the agent payable account id.
+    /// A nominated agent leaving hands the nomination to the next pending agent.
+    /// Too many active agents leaving at once pauses execution, see `mass_exit_threshold`.
+    /// The last active agent can only leave with no one pending if `allow_empty_active_set` is on.
+    /// An agent that accepted a nomination can't leave before `activation_cooldown_nanos` passed.
+    pub fn unregister_agent(
+        &self,
+        deps: DepsMut,
+        info: MessageInfo,
+        env: Env,
+    ) -> Result<Response, ContractError> {
+        let mut queues = self.load_queues(deps.storage)?;
+        // The bond is only locked while registered, pay it out with the rest
+        if let Some(mut agent) = self.agents.may_load(deps.storage, &info.sender)? {
+            self.check_activation_cooldown(
+                deps.storage,
+                &queues.active,
+                &info.sender,
+                &agent,
+                env.block.time,
+            )?;
+            if !agent.bond.is_empty() || !agent.pending_dust.is_empty() {
+                let bond = std::mem::take(&mut agent.bond);
+                agent.balance.checked_add_native(&bond)?;
+                // Dust too, nothing else would credit it after unregistering
+                let dust = std::mem::take(&mut agent.pending_dust);
+                agent.balance.checked_add_native(&dust)?;
+                self.agents.save(deps.storage, &info.sender, &agent)?;
+            }
+        }
+        // Get withdraw messages, if any
+        // NOTE: Since this also checks if agent exists, safe to not have redundant logic
+        let messages = self.withdraw_balances(deps.storage, info.clone(), env.block.time)?;
+        let agent_id = info.sender;
+        // Whatever is still escrowed would be lost with the agent record
+        if self
+            .agent_escrow
+            .sub_prefix(&agent_id)
+            .range(deps.storage, None, None, Order::Ascending)
+            .next()
+            .is_some()
+        {
+            return Err(ContractError::CustomError {
+                val: "Agent has rewards in the dispute window".to_string(),
+            });
+        }
+        // Vested rewards were released by the withdrawal, the rest is still locked
+        if self.agent_vesting.has(deps.storage, &agent_id) {
+            return Err(ContractError::CustomError {
+                val: "Agent has rewards still vesting".to_string(),
+            });
+        }
+        let was_nominated = matches!(
+            self.get_agent_status(deps.storage, env.clone(), agent_id.clone()),
+            Ok(AgentStatus::Nominated)
+        );
+        // Nobody would be left to execute tasks
+        let stalls_network = queues.active == vec![agent_id.clone()] && queues.pending.is_empty();
+        if stalls_network && !self.config.load(deps.storage)?.allow_empty_active_set {
+            return Err(ContractError::CustomError {
+                val: "Last active agent can't leave until a replacement is pending".to_string(),
+            });
+        }
+        if let Some(agent) = self.agents.may_load(deps.storage, &agent_id)? {
+            for operator in agent.operators.iter() {
+                self.agent_operators.remove(deps.storage, operator);
+            }
+            self.unindex_payable(deps.storage, &agent.payable_account_id, &agent_id)?;
+            // Its share was paid out with the withdrawal above
+            if let Some(pool_id) = agent.pool_id {
+                self.remove_pool_member(deps.storage, &agent_id, &pool_id)?;
+            }
+        }
+        self.agents.remove(deps.storage, &agent_id);
+        self.withdraw_keys.remove(deps.storage, &agent_id);
+
+        // Remove from the list of active agents if the agent in this list
+        let mut mass_exit = false;
+        let mut hook_msgs: Vec<SubMsg> = vec![];
+        if queues.active.contains(&agent_id) {
+            //Notify the balancer agent has been removed, to rebalance itself
+            self.balancer.on_agent_unregister(
+                deps.storage,
+                &self.config,
+                &self.agent_active_queue,
+                agent_id.clone(),
+            );
+            // The balancer reset its indices, so reordering the rest is safe
+            let c: Config = self.config.load(deps.storage)?;
+            c.queue_removal_mode.remove(queues.active_mut(), &agent_id);
+            mass_exit = self.record_active_exit(deps.storage, env.block.time)?;
+        } else {
+            // Agent can't be both in active and pending vector
+            // Remove from the pending queue
+            if queues.pending.contains(&agent_id) {
+                let pending_before = queues.pending.clone();
+                QueueRemovalMode::PreserveOrder.remove(queues.pending_mut(), &agent_id);
+                let c: Config = self.config.load(deps.storage)?;
+                hook_msgs =
+                    self.position_hook_msgs(deps.storage, &c, &pending_before, &queues.pending)?;
+            }
+
+            // Don't leave the nomination dangling: restart the window for whoever is next,
+            // or close it if nobody is left to check in
+            if was_nominated {
+                let nomination_begin_time = if queues.pending.is_empty() {
+                    None
+                } else {
+                    Some(env.block.time)
+                };
+                self.agent_nomination_begin_time
+                    .save(deps.storage, &nomination_begin_time)?;
+            }
+        }
+        self.flush_queues(deps.storage, &queues)?;
+
+        let mut responses = Response::new()
+            .add_attribute("method", "unregister_agent")
+            .add_attribute("agent", agent_id);
+        if was_nominated {
+            responses = responses.add_attribute("revoked_nomination", "true");
+        }
+        if mass_exit {
+            responses = responses.add_attribute("alert", "mass_agent_exit");
+        }
+        if stalls_network {
+            responses = responses.add_attribute("warning", "network_stalled");
+        }
+        responses = responses.add_submessages(hook_msgs);
+
+        if messages.is_empty() {
+            Ok(responses)
+        } else {
+            Ok(responses.add_submessages(messages))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ContractError;
+    use crate::helpers::test_helpers::{mock_init, update_settings_msg};
+    use crate::helpers::CwTemplateContract;
+    use cosmwasm_std::testing::{
+        mock_dependencies_with_balance, mock_env, mock_info, MockApi, MockQuerier, MockStorage,
+        MOCK_CONTRACT_ADDR,
+    };
+    use cosmwasm_std::{
+        coin, coins, from_binary, from_slice, Addr, Binary, BlockInfo, CosmosMsg, Decimal, Empty,
+        OwnedDeps, Record, StakingMsg, Timestamp,
+    };
+    use cw20::Cw20CoinVerified;
+    use cw_croncat_core::msg::{
+        ExecuteMsg, GetBalancesResponse, InstantiateMsg, QueryMsg, TaskRequest, TaskResponse,
+    };
+    use cw_croncat_core::types::{
+        Action, BoundaryValidated, Interval, MassExitThreshold, QueueRemovalMode, RewardRounding,
+        Task,
+    };
+    use cw_multi_test::{
+        App, AppBuilder, AppResponse, BankSudo, Contract, ContractWrapper, Executor, SudoMsg,
+    };
+    use std::cell::RefCell;
+    use std::marker::PhantomData;
+
+    pub fn contract_template() -> Box<dyn Contract<Empty>> {
+        let contract = ContractWrapper::new(
+            crate::entry::execute,
+            crate::entry::instantiate,
+            crate::entry::query,
+        );
+        Box::new(contract)
+    }
+
+    const AGENT0: &str = "cosmos1a7uhnpqthunr2rzj0ww0hwurpn42wyun6c5puz";
+    const AGENT1: &str = "cosmos17muvdgkep4ndptnyg38eufxsssq8jr3wnkysy8";
+    const AGENT2: &str = "cosmos1qxywje86amll9ptzxmla5ah52uvsd9f7drs2dl";
+    const AGENT3: &str = "cosmos1c3cy3wzzz3698ypklvh7shksvmefj69xhm89z2";
+    const AGENT4: &str = "cosmos1ykfcyj8fl6xzs88tsls05x93gmq68a7km05m4j";
+    const AGENT_BENEFICIARY: &str = "cosmos1t5u0jfg3ljsjrh2m9e47d4ny2hea7eehxrzdgd";
+    const ADMIN: &str = "cosmos1sjllsnramtg3ewxqwwrwjxfgc4n4ef9u0tvx7u";
+    const PARTICIPANT0: &str = "cosmos1055rfv3fv0zxsp8h3x88mctnm7x9mlgmf4m4d6";
+    const PARTICIPANT1: &str = "cosmos1c3cy3wzzz3698ypklvh7shksvmefj69xhm89z2";
+    const PARTICIPANT2: &str = "cosmos1far5cqkvny7k9wq53aw0k42v3f76rcylzzv05n";
+    const PARTICIPANT3: &str = "cosmos1xj3xagnprtqpfnvyp7k393kmes73rpuxqgamd8";
+    const PARTICIPANT4: &str = "cosmos1t5u0jfg3ljsjrh2m9e47d4ny2hea7eehxrzdgd";
+    const PARTICIPANT5: &str = "cosmos1k5k7y4hgy5lkq0kj3k3e9k38lquh0m66kxsu5c";
+    const PARTICIPANT6: &str = "cosmos14a8clxc49z9e3mjzhamhkprt2hgf0y53zczzj0";
+    const NATIVE_DENOM: &str = "atom";
+
+    fn mock_app() -> App {
+        AppBuilder::new().build(|router, _, storage| {
+            let accounts: Vec<(u128, String)> = vec![
+                (2_000_000, ADMIN.to_string()),
+                (1, AGENT0.to_string()),
+                (2_000_000, AGENT1.to_string()),
+                (2_000_000, AGENT2.to_string()),
+                (2_000_000, AGENT3.to_string()),
+                (2_000_000, AGENT4.to_string()),
+                (500_0000, PARTICIPANT0.to_string()),
+                (500_0000, PARTICIPANT1.to_string()),
+                (500_0000, PARTICIPANT2.to_string()),
+                (500_0000, PARTICIPANT3.to_string()),
+                (500_0000, PARTICIPANT4.to_string()),
+                (500_0000, PARTICIPANT5.to_string()),
+                (500_0000, PARTICIPANT6.to_string()),
+                (2_000_000, AGENT_BENEFICIARY.to_string()),
+            ];
+            for (amt, address) in accounts.iter() {
+                router
+                    .bank
+                    .init_balance(
+                        storage,
+                        &Addr::unchecked(address),
+                        vec![coin(amt.clone(), NATIVE_DENOM.to_string())],
+                    )
+                    .unwrap();
+            }
+        })
+    }
+
+    fn proper_instantiate() -> (App, CwTemplateContract) {
+        let mut app = mock_app();
+        let cw_template_id = app.store_code(contract_template());
+        let owner_addr = Addr::unchecked(ADMIN);
+
+        let msg = InstantiateMsg {
+            denom: "atom".to_string(),
+            owner_id: Some(owner_addr.to_string()),
+            gas_base_fee: None,
+            agent_nomination_duration: Some(360),
+            cw_rules_addr: "todo".to_string(),
+            initial_agent: None,
+        };
+        let cw_template_contract_addr = app
+            .instantiate_contract(cw_template_id, owner_addr, &msg, &[], "Manager", None)
+            .unwrap();
+
+        let cw_template_contract = CwTemplateContract(cw_template_contract_addr);
+
+        (app, cw_template_contract)
+    }
+
+    fn get_task_total(app: &App, contract_addr: &Addr) -> usize {
+        let res: Vec<TaskResponse> = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr,
+                &QueryMsg::GetTasks {
+                    from_index: None,
+                    limit: None,
+                },
+            )
+            .unwrap();
+        res.len()
+    }
+
+    fn add_task_exec(app: &mut App, contract_addr: &Addr, sender: &str) -> AppResponse {
+        let validator = String::from("you");
+        let amount = coin(3, NATIVE_DENOM);
+        let stake = StakingMsg::Delegate { validator, amount };
+        let msg: CosmosMsg = stake.clone().into();
+        let send_funds = coins(500_000, NATIVE_DENOM);
+        app.execute_contract(
+            Addr::unchecked(sender),
+            contract_addr.clone(),
+            &ExecuteMsg::CreateTask {
+                task: TaskRequest {
+                    interval: Interval::Immediate,
+                    boundary: None,
+                    stop_on_fail: false,
+                    actions: vec![Action {
+                        msg,
+                        gas_limit: Some(150_000),
+                    }],
+                    rules: None,
+                    cw20_coins: vec![],
+                    task_type: None,
+                },
+            },
+            send_funds.as_ref(),
+        )
+        .expect("Error adding task")
+    }
+
+    fn add_block_task_exec(
+        app: &mut App,
+        contract_addr: &Addr,
+        sender: &str,
+        block_num: u64,
+    ) -> AppResponse {
+        let validator = String::from("you");
+        let amount = coin(3, NATIVE_DENOM);
+        let stake = StakingMsg::Delegate { validator, amount };
+        let msg: CosmosMsg = stake.clone().into();
+        let send_funds = coins(500_000, NATIVE_DENOM);
+        app.execute_contract(
+            Addr::unchecked(sender),
+            contract_addr.clone(),
+            &ExecuteMsg::CreateTask {
+                task: TaskRequest {
+                    interval: Interval::Block(block_num),
+                    boundary: None,
+                    stop_on_fail: false,
+                    actions: vec![Action {
+                        msg,
+                        gas_limit: Some(150_000),
+                    }],
+                    rules: None,
+                    cw20_coins: vec![],
+                    task_type: None,
+                },
+            },
+            send_funds.as_ref(),
+        )
+        .expect("Error adding task")
+    }
+
+    fn add_cron_task_exec(
+        app: &mut App,
+        contract_addr: &Addr,
+        sender: &str,
+        num_minutes: u64,
+    ) -> AppResponse {
+        let validator = String::from("you");
+        let amount = coin(3, NATIVE_DENOM);
+        let stake = StakingMsg::Delegate { validator, amount };
+        let msg: CosmosMsg = stake.clone().into();
+        let send_funds = coins(500_000, NATIVE_DENOM);
+        app.execute_contract(
+            Addr::unchecked(sender),
+            contract_addr.clone(),
+            &ExecuteMsg::CreateTask {
+                task: TaskRequest {
+                    interval: Interval::Cron(format!("* {} * * * *", num_minutes)),
+                    boundary: None,
+                    stop_on_fail: false,
+                    actions: vec![Action {
+                        msg,
+                        gas_limit: Some(150_000),
+                    }],
+                    rules: None,
+                    cw20_coins: vec![],
+                    task_type: None,
+                },
+            },
+            send_funds.as_ref(),
+        )
+        .expect("Error adding task")
+    }
+
+    fn contract_create_task(
+        contract: &CwCroncat,
+        deps: DepsMut,
+        info: &MessageInfo,
+    ) -> Result<Response, ContractError> {
+        // try adding task without app
+        let validator = String::from("you");
+        let amount = coin(3, NATIVE_DENOM);
+        let stake = StakingMsg::Delegate { validator, amount };
+        let msg: CosmosMsg = stake.clone().into();
+        // let send_funds = coins(1, NATIVE_DENOM);
+
+        contract.create_task(
+            deps,
+            info.clone(),
+            mock_env(),
+            TaskRequest {
+                interval: Interval::Immediate,
+                boundary: None,
+                stop_on_fail: false,
+                actions: vec![Action {
+                    msg: msg.clone(),
+                    gas_limit: Some(150_000),
+                }],
+                rules: None,
+                cw20_coins: vec![],
+                task_type: None,
+            },
+        )
+    }
+
+    fn contract_register_agent(
+        sender: &str,
+        contract: &mut CwCroncat,
+        deps: DepsMut,
+    ) -> Result<Response, ContractError> {
+        contract.execute(
+            deps,
+            mock_env(),
+            MessageInfo {
+                sender: Addr::unchecked(sender),
+                funds: vec![],
+            },
+            ExecuteMsg::RegisterAgent {
+                payable_account_id: Some(AGENT_BENEFICIARY.to_string()),
+                capabilities: None,
+                accepted_terms: None,
+                client_version: None,
+            },
+        )
+    }
+
+    fn get_stored_agent_status(app: &mut App, contract_addr: &Addr, agent: &str) -> AgentStatus {
+        let agent_info: AgentResponse = app
+            .wrap()
+            .query_wasm_smart(
+                &contract_addr.clone(),
+                &QueryMsg::GetAgent {
+                    account_id: agent.to_string(),
+                },
+            )
+            .expect("Error getting agent status");
+        agent_info.status
+    }
+
+    fn register_agent_exec(
+        app: &mut App,
+        contract_addr: &Addr,
+        agent: &str,
+        beneficiary: &str,
+    ) -> AppResponse {
+        app.execute_contract(
+            Addr::unchecked(agent),
+            contract_addr.clone(),
+            &ExecuteMsg::RegisterAgent {
+                payable_account_id: Some(beneficiary.to_string()),
+                capabilities: None,
+                accepted_terms: None,
+                client_version: None,
+            },
+            &[],
+        )
+        .expect("Error registering agent")
+    }
+
+    fn check_in_exec(
+        app: &mut App,
+        contract_addr: &Addr,
+        agent: &str,
+    ) -> Result<AppResponse, anyhow::Error> {
+        app.execute_contract(
+            Addr::unchecked(agent),
+            contract_addr.clone(),
+            &ExecuteMsg::CheckInAgent {},
+            &[],
+        )
+    }
+
+    fn get_agent_ids(app: &App, contract_addr: &Addr) -> (GetAgentIdsResponse, usize, usize) {
+        let res: GetAgentIdsResponse = app
+            .wrap()
+            .query_wasm_smart(contract_addr, &QueryMsg::GetAgentIds {})
+            .unwrap();
+        (res.clone(), res.active.len(), res.pending.len())
+    }
+
+    pub fn add_little_time(block: &mut BlockInfo) {
+        // block.time = block.time.plus_seconds(360);
+        block.time = block.time.plus_seconds(19);
+        block.height += 1;
+    }
+
+    pub fn add_one_duration_of_time(block: &mut BlockInfo) {
+        // block.time = block.time.plus_seconds(360);
+        block.time = block.time.plus_seconds(420);
+        block.height += 1;
+    }
+
+    #[test]
+    fn test_instantiate_sets_balance() {
+        let mut app = App::default();
+        let croncat_code = app.store_code(contract_template());
+
+        let sent_funds = coins(50, "ugrape");
+
+        app.sudo(SudoMsg::Bank(BankSudo::Mint {
+            to_address: "grapestem".to_string(),
+            amount: sent_funds.clone(),
+        }))
+        .unwrap();
+
+        let croncat = app
+            .instantiate_contract(
+                croncat_code,
+                Addr::unchecked("grapestem"),
+                &InstantiateMsg {
+                    denom: "grape".to_string(),
+                    cw_rules_addr: "grapestem".to_string(),
+                    owner_id: None,
+                    gas_base_fee: None,
+                    agent_nomination_duration: None,
+                    initial_agent: None,
+                },
+                &sent_funds,
+                "cw croncat",
+                None,
+            )
+            .unwrap();
+
+        let config: Config = from_slice(
+            &app.wrap()
+                .query_wasm_raw(&croncat, b"config")
+                .unwrap()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(config.available_balance.native, sent_funds)
+    }
+
+    #[test]
+    fn register_agent_fail_cases() {
+        let (mut app, cw_template_contract) = proper_instantiate();
+        let contract_addr = cw_template_contract.addr();
+
+        // start first register
+        let msg = ExecuteMsg::RegisterAgent {
+            payable_account_id: Some(AGENT_BENEFICIARY.to_string()),
+            capabilities: None,
+            accepted_terms: None,
+            client_version: None,
+        };
+
+        // Test funds fail register if sent
+        let rereg_err = app
+            .execute_contract(
+                Addr::unchecked(AGENT1),
+                contract_addr.clone(),
+                &msg,
+                &coins(37, "atom"),
+            )
+            .unwrap_err();
+        assert_eq!(
+            ContractError::CustomError {
+                val: "Do not attach funds".to_string()
+            },
+            rereg_err.downcast().unwrap()
+        );
+
+        // Test Can't register if contract is paused
+        let mut payload_1 = update_settings_msg();
+        if let ExecuteMsg::UpdateSettings { paused, .. } = &mut payload_1 {
+            *paused = Some(true);
+        }
+
+        app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &payload_1,
+            &[],
+        )
+        .unwrap();
+        let rereg_err = app
+            .execute_contract(Addr::unchecked(AGENT1), contract_addr.clone(), &msg, &[])
+            .unwrap_err();
+        assert_eq!(
+            ContractError::ContractPaused {
+                val: "Register agent paused".to_string()
+            },
+            rereg_err.downcast().unwrap()
+        );
+
+        // Test wallet rejected if doesnt have enough funds
+        let mut payload_2 = update_settings_msg();
+        if let ExecuteMsg::UpdateSettings { paused, .. } = &mut payload_2 {
+            *paused = Some(false);
+        }
+
+        app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &payload_2,
+            &[],
+        )
+        .unwrap();
+        let rereg_err = app
+            .execute_contract(Addr::unchecked(AGENT0), contract_addr.clone(), &msg, &[])
+            .unwrap_err();
+        assert_eq!(
+            ContractError::CustomError {
+                val: "Insufficient funds".to_string()
+            },
+            rereg_err.downcast().unwrap()
+        );
+    }
+
+    #[test]
+    fn register_agent() {
+        let (mut app, cw_template_contract) = proper_instantiate();
+        let contract_addr = cw_template_contract.addr();
+        let blk_time = app.block_info().time;
+
+        // start first register
+        let msg = ExecuteMsg::RegisterAgent {
+            payable_account_id: Some(AGENT_BENEFICIARY.to_string()),
+            capabilities: None,
+            accepted_terms: None,
+            client_version: None,
+        };
+        app.execute_contract(Addr::unchecked(AGENT1), contract_addr.clone(), &msg, &[])
+            .unwrap();
+
+        // check state to see if worked
+        let (_, num_active_agents, num_pending_agents) = get_agent_ids(&app, &contract_addr);
+        assert_eq!(1, num_active_agents);
+        assert_eq!(0, num_pending_agents);
+
+        // message response matches expectations (same block, all the defaults)
+        let agent_info: AgentResponse = app
+            .wrap()
+            .query_wasm_smart(
+                &contract_addr.clone(),
+                &QueryMsg::GetAgent {
+                    account_id: AGENT1.to_string(),
+                },
+            )
+            .unwrap();
+        println!("agent_infoagent_info {:?}", agent_info);
+        assert_eq!(AgentStatus::Active, agent_info.status);
+        assert_eq!(
+            Addr::unchecked(AGENT_BENEFICIARY),
+            agent_info.payable_account_id
+        );
+        assert_eq!(GenericBalance::default(), agent_info.balance);
+        assert_eq!(0, agent_info.total_tasks_executed);
+        assert_eq!(0, agent_info.last_missed_slot);
+        assert_eq!(blk_time, agent_info.register_start);
+
+        // test fail if try to re-register
+        let rereg_err = app
+            .execute_contract(Addr::unchecked(AGENT1), contract_addr.clone(), &msg, &[])
+            .unwrap_err();
+        assert_eq!(
+            ContractError::CustomError {
+                val: "Agent already exists".to_string()
+            },
+            rereg_err.downcast().unwrap()
+        );
+
+        // test another register, put into pending queue
+        let msg2 = ExecuteMsg::RegisterAgent {
+            payable_account_id: Some(AGENT_BENEFICIARY.to_string()),
+            capabilities: None,
+            accepted_terms: None,
+            client_version: None,
+        };
+        app.execute_contract(Addr::unchecked(AGENT2), contract_addr.clone(), &msg2, &[])
+            .unwrap();
+
+        // check state to see if worked
+
+        let (_, num_active_agents, num_pending_agents) = get_agent_ids(&app, &contract_addr);
+        assert_eq!(1, num_active_agents);
+        assert_eq!(1, num_pending_agents);
+    }
+
+    #[test]
+    fn update_agent() {
+        let (mut app, cw_template_contract) = proper_instantiate();
+        let contract_addr = cw_template_contract.addr();
+
+        // start first register
+        let msg1 = ExecuteMsg::RegisterAgent {
+            payable_account_id: Some(AGENT_BENEFICIARY.to_string()),
+            capabilities: None,
+            accepted_terms: None,
+            client_version: None,
+        };
+        app.execute_contract(Addr::unchecked(AGENT1), contract_addr.clone(), &msg1, &[])
+            .unwrap();
+
+        // Fails for non-existent agents
+        let msg = ExecuteMsg::UpdateAgent {
+            payable_account_id: AGENT0.to_string(),
+            capabilities: None,
+            client_version: None,
+        };
+        let update_err = app
+            .execute_contract(Addr::unchecked(AGENT0), contract_addr.clone(), &msg, &[])
+            .unwrap_err();
+        assert_eq!(
+            ContractError::AgentNotRegistered {},
+            update_err.downcast().unwrap()
+        );
+
+        app.execute_contract(Addr::unchecked(AGENT1), contract_addr.clone(), &msg, &[])
+            .unwrap();
+
+        // payable account was in fact updated
+        let agent_info: Agent = app
+            .wrap()
+            .query_wasm_smart(
+                &contract_addr.clone(),
+                &QueryMsg::GetAgent {
+                    account_id: AGENT1.to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(Addr::unchecked(AGENT0), agent_info.payable_account_id);
+    }
+
+    #[test]
+    fn unregister_agent() {
+        let (mut app, cw_template_contract) = proper_instantiate();
+        let contract_addr = cw_template_contract.addr();
+
+        // start first register
+        let msg1 = ExecuteMsg::RegisterAgent {
+            payable_account_id: Some(AGENT_BENEFICIARY.to_string()),
+            capabilities: None,
+            accepted_terms: None,
+            client_version: None,
+        };
+        app.execute_contract(Addr::unchecked(AGENT1), contract_addr.clone(), &msg1, &[])
+            .unwrap();
+
+        // Fails for non-exist agents
+        let unreg_msg = ExecuteMsg::UnregisterAgent {};
+        let update_err = app
+            .execute_contract(
+                Addr::unchecked(AGENT0),
+                contract_addr.clone(),
+                &unreg_msg,
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            ContractError::AgentNotRegistered {},
+            update_err.downcast().unwrap()
+        );
+
+        // Get quick data about account before, to compare later
+        let agent_bal = app
+            .wrap()
+            .query_balance(&Addr::unchecked(AGENT1), NATIVE_DENOM)
+            .unwrap();
+        assert_eq!(agent_bal, coin(2_000_000, NATIVE_DENOM));
+
+        // Attempt the unregister
+        app.execute_contract(
+            Addr::unchecked(AGENT1),
+            contract_addr.clone(),
+            &unreg_msg,
+            &[],
+        )
+        .unwrap();
+
+        // Agent should not exist now
+        let update_err = app
+            .execute_contract(
+                Addr::unchecked(AGENT1),
+                contract_addr.clone(),
+                &unreg_msg,
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            ContractError::AgentNotRegistered {},
+            update_err.downcast().unwrap()
+        );
+
+        // Check that the agent was removed from the list of active or pending agents
+        let (_, num_active_agents, num_pending_agents) = get_agent_ids(&app, &contract_addr);
+        assert_eq!(0, num_active_agents);
+        assert_eq!(0, num_pending_agents);
+
+        // Agent should have appropriate balance change
+        // NOTE: Needs further checks when tasks can be performed
+        let agent_bal = app
+            .wrap()
+            .query_balance(&Addr::unchecked(AGENT1), NATIVE_DENOM)
+            .unwrap();
+        assert_eq!(agent_bal, coin(2000000, NATIVE_DENOM));
+    }
+
+    #[test]
+    fn withdraw_agent_balance() {
+        let (mut app, cw_template_contract) = proper_instantiate();
+        let contract_addr = cw_template_contract.addr();
+
+        // start first register
+        let msg1 = ExecuteMsg::RegisterAgent {
+            payable_account_id: Some(AGENT_BENEFICIARY.to_string()),
+            capabilities: None,
+            accepted_terms: None,
+            client_version: None,
+        };
+        app.execute_contract(Addr::unchecked(AGENT1), contract_addr.clone(), &msg1, &[])
+            .unwrap();
+
+        // Fails for non-existent agents
+        let wthdrw_msg = ExecuteMsg::WithdrawReward {
+            idempotency_key: None,
+            memo: None,
+        };
+        let update_err = app
+            .execute_contract(
+                Addr::unchecked(AGENT0),
+                contract_addr.clone(),
+                &wthdrw_msg,
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            ContractError::AgentNotRegistered {},
+            update_err.downcast().unwrap()
+        );
+
+        // Get quick data about account before, to compare later
+        let agent_bal = app
+            .wrap()
+            .query_balance(&Addr::unchecked(AGENT1), NATIVE_DENOM)
+            .unwrap();
+        assert_eq!(agent_bal, coin(2_000_000, NATIVE_DENOM));
+
+        // Attempt the withdraw
+        app.execute_contract(
+            Addr::unchecked(AGENT1),
+            contract_addr.clone(),
+            &wthdrw_msg,
+            &[],
+        )
+        .unwrap();
+
+        // Agent should have appropriate balance change
+        // NOTE: Needs further checks when tasks can be performed
+        let agent_bal = app
+            .wrap()
+            .query_balance(&Addr::unchecked(AGENT1), NATIVE_DENOM)
+            .unwrap();
+        assert_eq!(agent_bal, coin(2_000_000, NATIVE_DENOM));
+    }
+
+    #[test]
+    fn accept_nomination_agent() {
+        let (mut app, cw_template_contract) = proper_instantiate();
+        let contract_addr = cw_template_contract.addr();
+
+        // Register AGENT1, who immediately becomes active
+        register_agent_exec(&mut app, &contract_addr, AGENT1, &AGENT_BENEFICIARY);
+        let res = add_task_exec(&mut app, &contract_addr, PARTICIPANT0);
+        let task_hash = res.events[1].attributes[4].clone().value;
+        assert_eq!(
+            "7ea9a6d5ef5c78cb168afa96b43b5843b8f880627aa0580f4311403f907cbf93", task_hash,
+            "Unexpected task hash"
+        );
+
+        let msg_query_task = QueryMsg::GetTask { task_hash };
+        let query_task_res: StdResult<Option<TaskResponse>> = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &msg_query_task);
+        assert!(
+            query_task_res.is_ok(),
+            "Did not successfully find the newly added task"
+        );
+
+        let mut num_tasks = get_task_total(&app, &contract_addr);
+        assert_eq!(num_tasks, 1);
+
+        // Now the task ratio is 1:2 (one agent per two tasks)
+        // No agent should be allowed to join or accept nomination
+        // Check that this fails
+
+        // Register two agents
+        register_agent_exec(&mut app, &contract_addr, AGENT2, &AGENT_BENEFICIARY);
+        // Later, we'll have this agent try to nominate themselves before their time
+        register_agent_exec(&mut app, &contract_addr, AGENT3, &AGENT_BENEFICIARY);
+
+        let (agent_ids_res, num_active_agents, _) = get_agent_ids(&app, &contract_addr);
+        assert_eq!(1, num_active_agents);
+        assert_eq!(2, agent_ids_res.pending.len());
+
+        // Add three more tasks, so we can nominate another agent
+        add_task_exec(&mut app, &contract_addr, PARTICIPANT1);
+        add_task_exec(&mut app, &contract_addr, PARTICIPANT2);
+        add_task_exec(&mut app, &contract_addr, PARTICIPANT3);
+
+        num_tasks = get_task_total(&app, &contract_addr);
+        assert_eq!(num_tasks, 4);
+
+        // Fast forward time a little
+        app.update_block(add_little_time);
+
+        let mut agent_status = get_stored_agent_status(&mut app, &contract_addr, AGENT3);
+        assert_eq!(AgentStatus::Pending, agent_status);
+        agent_status = get_stored_agent_status(&mut app, &contract_addr, AGENT2);
+        assert_eq!(AgentStatus::Nominated, agent_status);
+
+        // Attempt to accept nomination
+        // First try with the agent second in line in the pending queue.
+        // This should fail because it's not time for them yet.
+        let mut check_in_res = check_in_exec(&mut app, &contract_addr, AGENT3);
+        assert!(
+            &check_in_res.is_err(),
+            "Should throw error when agent in second position tries to nominate before their time."
+        );
+        assert_eq!(
+            ContractError::CustomError {
+                val: "Must wait longer before accepting nomination".to_string()
+            },
+            check_in_res.unwrap_err().downcast().unwrap()
+        );
+
+        // Now try from person at the beginning of the pending queue
+        // This agent should succeed
+        check_in_res = check_in_exec(&mut app, &contract_addr, AGENT2);
+        assert!(
+            check_in_res.is_ok(),
+            "Agent at the front of the pending queue should be allowed to nominate themselves"
+        );
+
+        // Check that active and pending queues are correct
+        let (agent_ids_res, num_active_agents, _) = get_agent_ids(&app, &contract_addr);
+        assert_eq!(2, num_active_agents);
+        assert_eq!(1, agent_ids_res.pending.len());
+
+        // The agent that was second in the queue is now first,
+        // tries again, but there aren't enough tasks
+        check_in_res = check_in_exec(&mut app, &contract_addr, AGENT3);
+
+        let error_msg = check_in_res.unwrap_err();
+        assert_eq!(
+            ContractError::CustomError {
+                val: "Not accepting new agents".to_string()
+            },
+            error_msg.downcast().unwrap()
+        );
+
+        agent_status = get_stored_agent_status(&mut app, &contract_addr, AGENT3);
+        assert_eq!(AgentStatus::Pending, agent_status);
+
+        // Again, add three more tasks so we can nominate another agent
+        add_task_exec(&mut app, &contract_addr, PARTICIPANT4);
+        add_task_exec(&mut app, &contract_addr, PARTICIPANT5);
+        add_task_exec(&mut app, &contract_addr, PARTICIPANT6);
+
+        num_tasks = get_task_total(&app, &contract_addr);
+        assert_eq!(num_tasks, 7);
+
+        // Add another agent, since there's now the need
+        register_agent_exec(&mut app, &contract_addr, AGENT4, &AGENT_BENEFICIARY);
+        // Fast forward time past the duration of the first pending agent,
+        // allowing the second to nominate themselves
+        app.update_block(add_one_duration_of_time);
+
+        // Now that enough time has passed, both agents should see they're nominated
+        agent_status = get_stored_agent_status(&mut app, &contract_addr, AGENT3);
+        assert_eq!(AgentStatus::Nominated, agent_status);
+        agent_status = get_stored_agent_status(&mut app, &contract_addr, AGENT4);
+        assert_eq!(AgentStatus::Nominated, agent_status);
+
+        // Agent second in line nominates themself
+        check_in_res = check_in_exec(&mut app, &contract_addr, AGENT4);
+        assert!(
+            check_in_res.is_ok(),
+            "Agent second in line should be able to nominate themselves"
+        );
+
+        let (agent_ids_res, _, _) = get_agent_ids(&app, &contract_addr);
+
+        // The earlier index let its nomination expire and waits at the back again
+        assert_eq!(
+            agent_ids_res.pending,
+            vec![Addr::unchecked(AGENT3)],
+            "Expect the agent that missed its nomination at the back of the pending queue"
+        );
+        let agent: Option<AgentResponse> = app
+            .wrap()
+            .query_wasm_smart(
+                &contract_addr,
+                &QueryMsg::GetAgent {
+                    account_id: AGENT3.to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(agent.unwrap().missed_nominations, 1);
+    }
+
+    #[test]
+    fn test_get_agent_status() {
+        // Give the contract and the agents balances
+        let mut deps = cosmwasm_std::testing::mock_dependencies_with_balances(&[
+            (&MOCK_CONTRACT_ADDR, &[coin(6000, "atom")]),
+            (&AGENT0, &[coin(2_000_000, "atom")]),
+            (&AGENT1, &[coin(2_000_000, "atom")]),
+        ]);
+        let mut contract = CwCroncat::default();
+
+        // Instantiate
+        let msg = InstantiateMsg {
+            denom: "atom".to_string(),
+            owner_id: None,
             gas_base_fee: None,
             agent_nomination_duration: Some(360),
             cw_rules_addr: "todo".to_string(),
+            initial_agent: None,
+        };
+        let mut info = mock_info(AGENT0, &coins(900_000, "atom"));
+        let res_init = contract
+            .instantiate(deps.as_mut(), mock_env(), info.clone(), msg)
+            .unwrap();
+        assert_eq!(0, res_init.messages.len());
+
+        let mut agent_status_res =
+            contract.get_agent_status(&deps.storage, mock_env(), Addr::unchecked(AGENT0));
+        assert_eq!(Err(ContractError::AgentNotRegistered {}), agent_status_res);
+
+        let agent_active_queue_opt: Vec<Addr> =
+            match deps.storage.get("agent_active_queue".as_bytes()) {
+                Some(vec) => from_slice(vec.as_ref()).expect("Could not load agent active queue"),
+                None => {
+                    panic!("Uninitialized agent_active_queue_opt");
+                }
+            };
+        assert!(
+            agent_active_queue_opt.is_empty(),
+            "Should not have an active queue yet"
+        );
+
+        // First registered agent becomes active
+        let mut register_agent_res = contract_register_agent(AGENT0, &mut contract, deps.as_mut());
+        assert!(
+            register_agent_res.is_ok(),
+            "Registering agent should succeed"
+        );
+
+        agent_status_res =
+            contract.get_agent_status(&deps.storage, mock_env(), Addr::unchecked(AGENT0));
+        assert_eq!(AgentStatus::Active, agent_status_res.unwrap());
+
+        // Add two tasks
+        let mut res_add_task = contract_create_task(&contract, deps.as_mut(), &info);
+        assert!(res_add_task.is_ok(), "Adding task should succeed.");
+        // Change sender so it's not a duplicate task
+        info.sender = Addr::unchecked(PARTICIPANT0);
+        res_add_task = contract_create_task(&contract, deps.as_mut(), &info);
+        assert!(res_add_task.is_ok(), "Adding task should succeed.");
+
+        // Register an agent and make sure the status comes back as pending
+        register_agent_res = contract_register_agent(AGENT1, &mut contract, deps.as_mut());
+        assert!(
+            register_agent_res.is_ok(),
+            "Registering agent should succeed"
+        );
+        agent_status_res =
+            contract.get_agent_status(&deps.storage, mock_env(), Addr::unchecked(AGENT1));
+        assert_eq!(
+            AgentStatus::Pending,
+            agent_status_res.unwrap(),
+            "New agent should be pending"
+        );
+
+        // Two more tasks are added
+        info.sender = Addr::unchecked(PARTICIPANT1);
+        res_add_task = contract_create_task(&contract, deps.as_mut(), &info);
+        assert!(res_add_task.is_ok(), "Adding task should succeed.");
+        info.sender = Addr::unchecked(PARTICIPANT2);
+        res_add_task = contract_create_task(&contract, deps.as_mut(), &info);
+        assert!(res_add_task.is_ok(), "Adding task should succeed.");
+
+        // Agent status is nominated
+        agent_status_res =
+            contract.get_agent_status(&deps.storage, mock_env(), Addr::unchecked(AGENT1));
+        assert_eq!(
+            AgentStatus::Nominated,
+            agent_status_res.unwrap(),
+            "New agent should have nominated status"
+        );
+    }
+
+    #[test]
+    fn test_query_get_agent_tasks() {
+        let (mut app, cw_template_contract) = proper_instantiate();
+        let contract_addr = cw_template_contract.addr();
+        let block_info = app.block_info();
+        println!(
+            "test aloha\n\tcurrent block: {}\n\tcurrent time: {}",
+            block_info.height,
+            block_info.time.nanos()
+        );
+
+        // Register AGENT1, who immediately becomes active
+        register_agent_exec(&mut app, &contract_addr, AGENT1, &AGENT_BENEFICIARY);
+        // Add five tasks total
+        // Three of them are block-based
+        add_block_task_exec(
+            &mut app,
+            &contract_addr,
+            PARTICIPANT0,
+            block_info.height + 6,
+        );
+        add_block_task_exec(
+            &mut app,
+            &contract_addr,
+            PARTICIPANT1,
+            block_info.height + 66,
+        );
+        add_block_task_exec(
+            &mut app,
+            &contract_addr,
+            PARTICIPANT2,
+            block_info.height + 67,
+        );
+        // add_block_task_exec(&mut app, &contract_addr, PARTICIPANT3, block_info.height + 131);
+        // Two tasks use Cron instead of Block (for task interval)
+        add_cron_task_exec(&mut app, &contract_addr, PARTICIPANT4, 6); // 3 minutes
+        add_cron_task_exec(&mut app, &contract_addr, PARTICIPANT5, 53); // 53 minutes
+        let num_tasks = get_task_total(&app, &contract_addr);
+        assert_eq!(num_tasks, 5);
+
+        // Now the task ratio is 1:2 (one agent per two tasks)
+        // Register two agents, the first one succeeding
+        register_agent_exec(&mut app, &contract_addr, AGENT2, &AGENT_BENEFICIARY);
+        assert!(check_in_exec(&mut app, &contract_addr, AGENT2).is_ok());
+        // This next agent should fail because there's no enough tasks yet
+        // Later, we'll have this agent try to nominate themselves before their time
+        register_agent_exec(&mut app, &contract_addr, AGENT3, &AGENT_BENEFICIARY);
+        let failed_check_in = check_in_exec(&mut app, &contract_addr, AGENT3);
+        assert_eq!(
+            ContractError::CustomError {
+                val: "Not accepting new agents".to_string()
+            },
+            failed_check_in.unwrap_err().downcast().unwrap()
+        );
+
+        let (_, num_active_agents, num_pending_agents) = get_agent_ids(&app, &contract_addr);
+        assert_eq!(2, num_active_agents);
+        assert_eq!(1, num_pending_agents);
+
+        // Fast forward time a little
+        app.update_block(|block| {
+            let height = 666;
+            block.time = block.time.plus_seconds(6 * height); // ~6 sec block time
+            block.height = block.height + height;
+        });
+
+        // What happens when the only active agent queries to see if there's work for them
+        // calls:
+        // fn query_get_agent_tasks
+        let mut msg_agent_tasks = QueryMsg::GetAgentTasks {
+            account_id: AGENT1.to_string(),
+        };
+        let mut query_task_res: StdResult<Option<AgentTaskResponse>> = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &msg_agent_tasks);
+        println!(
+            "test aloha query_task_res0 {:#?}",
+            query_task_res.as_ref().unwrap()
+        );
+        assert!(
+            query_task_res.is_ok(),
+            "Did not successfully find the newly added task"
+        );
+        msg_agent_tasks = QueryMsg::GetAgentTasks {
+            account_id: AGENT2.to_string(),
+        };
+        query_task_res = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &msg_agent_tasks);
+        println!("test aloha query_task_res1 {:#?}", query_task_res.unwrap());
+        // Should fail for random user not in the active queue
+        msg_agent_tasks = QueryMsg::GetAgentTasks {
+            // rando account
+            account_id: "juno1kqfjv53g7ll9u6ngvsu5l5nfv9ht24m4q4gdqz".to_string(),
+        };
+        query_task_res = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &msg_agent_tasks);
+        println!("aloha query_task_res {:?}", query_task_res);
+    }
+
+    // Past the missed slot threshold, so the owner can slash it
+    fn make_slashable(store: &CwCroncat, storage: &mut dyn Storage, agent_id: &str) {
+        let threshold = store.config.load(storage).unwrap().missed_slot_threshold;
+        let agent_id = Addr::unchecked(agent_id);
+        let mut agent = store.agents.load(storage, &agent_id).unwrap();
+        agent.consecutive_misses = threshold + 1;
+        store.agents.save(storage, &agent_id, &agent).unwrap();
+    }
+
+    fn slash_with_destination(destination: SlashDestination) -> (Response, Config) {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.slash_destination = destination;
+        config.total_agent_balance.native = coins(10, "meow");
+        store.config.save(&mut deps.storage, &config).unwrap();
+        store
+            .agents
+            .save(
+                &mut deps.storage,
+                &Addr::unchecked(AGENT1),
+                &Agent {
+                    balance: GenericBalance {
+                        native: coins(10, "meow"),
+                        cw20: vec![],
+                    },
+                    total_tasks_executed: 1,
+                    ..new_agent(
+                        Addr::unchecked(AGENT_BENEFICIARY),
+                        mock_env().block.time,
+                        vec![],
+                    )
+                },
+            )
+            .unwrap();
+
+        let slash_msg = ExecuteMsg::SlashAgent {
+            agent_id: AGENT1.to_string(),
+        };
+        let res_err = store
+            .execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info(AGENT1, &[]),
+                slash_msg.clone(),
+            )
+            .unwrap_err();
+        assert_eq!(res_err, ContractError::Unauthorized {});
+
+        // Not even the owner can slash an agent keeping up with its slots
+        let res_err = store
+            .execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("creator", &[]),
+                slash_msg.clone(),
+            )
+            .unwrap_err();
+        assert_eq!(
+            res_err,
+            ContractError::CustomError {
+                val: "Agent hasn't missed enough slots to be slashed".to_string()
+            }
+        );
+
+        make_slashable(&store, &mut deps.storage, AGENT1);
+        let res = store
+            .execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("creator", &[]),
+                slash_msg,
+            )
+            .unwrap();
+        let agent = store
+            .agents
+            .load(&deps.storage, &Addr::unchecked(AGENT1))
+            .unwrap();
+        assert_eq!(agent.balance, GenericBalance::default());
+
+        (res, store.config.load(&deps.storage).unwrap())
+    }
+
+    #[test]
+    fn slash_agent_to_treasury() {
+        let (res, config) = slash_with_destination(SlashDestination::Treasury);
+        assert!(res.messages.is_empty());
+        assert_eq!(config.available_balance.native, coins(1000, "meow"));
+    }
+
+    #[test]
+    fn slash_agent_burn() {
+        let (res, config) = slash_with_destination(SlashDestination::Burn);
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Burn {
+                amount: coins(10, "meow")
+            })]
+        );
+        assert_eq!(config.available_balance.native, coins(990, "meow"));
+    }
+
+    #[test]
+    fn slash_agent_to_address() {
+        let (res, config) =
+            slash_with_destination(SlashDestination::Address(Addr::unchecked(PARTICIPANT0)));
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: PARTICIPANT0.to_string(),
+                amount: coins(10, "meow")
+            })]
+        );
+        assert_eq!(config.available_balance.native, coins(990, "meow"));
+    }
+
+    #[test]
+    fn query_agent_is_stale() {
+        let (mut app, cw_template_contract) = proper_instantiate();
+        let contract_addr = cw_template_contract.addr();
+
+        let msg = ExecuteMsg::RegisterAgent {
+            payable_account_id: Some(AGENT_BENEFICIARY.to_string()),
+            capabilities: None,
+            accepted_terms: None,
+            client_version: None,
+        };
+        app.execute_contract(Addr::unchecked(AGENT1), contract_addr.clone(), &msg, &[])
+            .unwrap();
+
+        let get_agent = QueryMsg::GetAgent {
+            account_id: AGENT1.to_string(),
+        };
+        let agent_info: AgentResponse = app
+            .wrap()
+            .query_wasm_smart(&contract_addr, &get_agent)
+            .unwrap();
+        assert_eq!(AgentStatus::Active, agent_info.status);
+        assert_eq!(None, agent_info.last_execution);
+        assert!(!agent_info.is_stale);
+
+        // No executions for longer than the default timeout
+        app.update_block(|block| {
+            block.time = block.time.plus_seconds(86_401);
+            block.height += 1;
+        });
+        let agent_info: AgentResponse = app
+            .wrap()
+            .query_wasm_smart(&contract_addr, &get_agent)
+            .unwrap();
+        assert_eq!(AgentStatus::Active, agent_info.status);
+        assert!(agent_info.is_stale);
+    }
+
+    #[test]
+    fn query_agent_balance_detailed() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+
+        let cw20_addr = Addr::unchecked("cw20_reward");
+        store
+            .agents
+            .save(
+                &mut deps.storage,
+                &Addr::unchecked(AGENT1),
+                &Agent {
+                    balance: GenericBalance {
+                        native: coins(10, "meow"),
+                        cw20: vec![Cw20CoinVerified {
+                            address: cw20_addr.clone(),
+                            amount: 25u128.into(),
+                        }],
+                    },
+                    total_tasks_executed: 1,
+                    ..new_agent(
+                        Addr::unchecked(AGENT_BENEFICIARY),
+                        mock_env().block.time,
+                        vec![],
+                    )
+                },
+            )
+            .unwrap();
+
+        let res = store
+            .query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::GetAgentBalance {
+                    account_id: AGENT1.to_string(),
+                },
+            )
+            .unwrap();
+        let balance: Option<GetAgentBalanceResponse> = from_binary(&res).unwrap();
+        assert_eq!(
+            balance,
+            Some(GetAgentBalanceResponse {
+                native: coins(10, "meow"),
+                cw20: vec![Cw20Coin {
+                    address: cw20_addr.to_string(),
+                    amount: 25u128.into(),
+                }],
+            })
+        );
+
+        // Unknown agent
+        let res = store
+            .query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::GetAgentBalance {
+                    account_id: AGENT2.to_string(),
+                },
+            )
+            .unwrap();
+        let balance: Option<GetAgentBalanceResponse> = from_binary(&res).unwrap();
+        assert_eq!(balance, None);
+    }
+
+    #[test]
+    fn register_agent_with_fee() {
+        let (mut app, cw_template_contract) = proper_instantiate();
+        let contract_addr = cw_template_contract.addr();
+        let fee = coin(1000, NATIVE_DENOM);
+
+        let mut settings_msg = update_settings_msg();
+        if let ExecuteMsg::UpdateSettings {
+            registration_fee, ..
+        } = &mut settings_msg
+        {
+            *registration_fee = Some(fee.clone());
+        }
+        app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &settings_msg,
+            &[],
+        )
+        .unwrap();
+
+        let msg = ExecuteMsg::RegisterAgent {
+            payable_account_id: None,
+            capabilities: None,
+            accepted_terms: None,
+            client_version: None,
+        };
+        let expected_err = ContractError::CustomError {
+            val: format!("Must attach exactly the registration fee of {}", fee),
+        };
+
+        // No funds
+        let err = app
+            .execute_contract(Addr::unchecked(AGENT1), contract_addr.clone(), &msg, &[])
+            .unwrap_err();
+        assert_eq!(expected_err, err.downcast().unwrap());
+
+        // Underpayment
+        let err = app
+            .execute_contract(
+                Addr::unchecked(AGENT1),
+                contract_addr.clone(),
+                &msg,
+                &coins(999, NATIVE_DENOM),
+            )
+            .unwrap_err();
+        assert_eq!(expected_err, err.downcast().unwrap());
+
+        // Overpayment
+        let err = app
+            .execute_contract(
+                Addr::unchecked(AGENT1),
+                contract_addr.clone(),
+                &msg,
+                &coins(1001, NATIVE_DENOM),
+            )
+            .unwrap_err();
+        assert_eq!(expected_err, err.downcast().unwrap());
+
+        // Exact fee
+        let res = app
+            .execute_contract(
+                Addr::unchecked(AGENT1),
+                contract_addr.clone(),
+                &msg,
+                std::slice::from_ref(&fee),
+            )
+            .unwrap();
+        let attribute = |key: &str| {
+            res.events
+                .iter()
+                .flat_map(|e| e.attributes.iter())
+                .find(|a| a.key == key)
+                .map(|a| a.value.clone())
+        };
+        assert_eq!(Some("0".to_string()), attribute("starting_balance"));
+        assert_eq!(Some("1000atom".to_string()), attribute("registration_fee"));
+        let (_, num_active_agents, _) = get_agent_ids(&app, &contract_addr);
+        assert_eq!(1, num_active_agents);
+
+        let balances: GetBalancesResponse = app
+            .wrap()
+            .query_wasm_smart(&contract_addr, &QueryMsg::GetBalances {})
+            .unwrap();
+        assert_eq!(vec![fee], balances.available_balance.native);
+    }
+
+    #[test]
+    fn query_config_change_impact() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        for (agent, wallet) in [(AGENT0, 200), (AGENT1, 100), (AGENT2, 150)] {
+            deps.querier
+                .update_balance(agent, coins(wallet, NATIVE_DENOM));
+            store
+                .register_agent(
+                    deps.as_mut(),
+                    mock_info(agent, &[]),
+                    mock_env(),
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap();
+        }
+        let config_before = store.config.load(&deps.storage).unwrap();
+        let impact = |store: &mut CwCroncat,
+                      deps: Deps,
+                      proposed: ConfigChange|
+         -> ConfigChangeImpactResponse {
+            let res = store
+                .query(
+                    deps,
+                    mock_env(),
+                    QueryMsg::GetConfigChangeImpact { proposed },
+                )
+                .unwrap();
+            from_binary(&res).unwrap()
+        };
+
+        assert_eq!(
+            impact(&mut store, deps.as_ref(), ConfigChange::default()),
+            ConfigChangeImpactResponse {
+                agents_affected: 0,
+                agents_evicted: vec![],
+            }
+        );
+
+        // 4 txns now cost 160, more than two of the wallets hold
+        let mut evicted = vec![Addr::unchecked(AGENT1), Addr::unchecked(AGENT2)];
+        evicted.sort();
+        assert_eq!(
+            impact(
+                &mut store,
+                deps.as_ref(),
+                ConfigChange {
+                    gas_price: Some(Decimal::from_ratio(40u128, 1u128)),
+                    blacklist: None,
+                }
+            ),
+            ConfigChangeImpactResponse {
+                agents_affected: 2,
+                agents_evicted: evicted,
+            }
+        );
+
+        assert_eq!(
+            impact(
+                &mut store,
+                deps.as_ref(),
+                ConfigChange {
+                    gas_price: None,
+                    blacklist: Some(vec![AGENT0.to_string()]),
+                }
+            ),
+            ConfigChangeImpactResponse {
+                agents_affected: 1,
+                agents_evicted: vec![Addr::unchecked(AGENT0)],
+            }
+        );
+        // Only a preview
+        assert_eq!(store.config.load(&deps.storage).unwrap(), config_before);
+    }
+
+    #[test]
+    fn query_slashable_agents() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.missed_slot_threshold = 3;
+        store.config.save(&mut deps.storage, &config).unwrap();
+
+        // Compliant, at the threshold, and over the threshold
+        let agents = [(AGENT1, 0), (AGENT2, 3), (AGENT3, 5)];
+        for (agent_id, consecutive_misses) in agents {
+            store
+                .agents
+                .save(
+                    &mut deps.storage,
+                    &Addr::unchecked(agent_id),
+                    &Agent {
+                        consecutive_misses,
+                        ..new_agent(Addr::unchecked(agent_id), mock_env().block.time, vec![])
+                    },
+                )
+                .unwrap();
+        }
+        store
+            .agent_active_queue
+            .save(
+                &mut deps.storage,
+                &agents
+                    .map(|(agent_id, _)| Addr::unchecked(agent_id))
+                    .to_vec(),
+            )
+            .unwrap();
+
+        let res = store
+            .query(deps.as_ref(), mock_env(), QueryMsg::GetSlashableAgents {})
+            .unwrap();
+        let slashable: Vec<SlashableAgentResponse> = from_binary(&res).unwrap();
+        assert_eq!(
+            slashable,
+            vec![SlashableAgentResponse {
+                agent: Addr::unchecked(AGENT3),
+                consecutive_misses: 5,
+            }]
+        );
+    }
+
+    #[test]
+    fn replace_active_agent() {
+        let (mut app, cw_template_contract) = proper_instantiate();
+        let contract_addr = cw_template_contract.addr();
+
+        // AGENT1 becomes active, AGENT2 and AGENT3 are pending
+        for agent in [AGENT1, AGENT2, AGENT3] {
+            app.execute_contract(
+                Addr::unchecked(agent),
+                contract_addr.clone(),
+                &ExecuteMsg::RegisterAgent {
+                    payable_account_id: None,
+                    capabilities: None,
+                    accepted_terms: None,
+                    client_version: None,
+                },
+                &[],
+            )
+            .unwrap();
+        }
+
+        // Only owner
+        let err = app
+            .execute_contract(
+                Addr::unchecked(AGENT2),
+                contract_addr.clone(),
+                &ExecuteMsg::ReplaceActiveAgent {
+                    remove: AGENT1.to_string(),
+                    promote: AGENT2.to_string(),
+                },
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err.downcast().unwrap());
+
+        // Agent to remove must be active
+        let err = app
+            .execute_contract(
+                Addr::unchecked(ADMIN),
+                contract_addr.clone(),
+                &ExecuteMsg::ReplaceActiveAgent {
+                    remove: AGENT3.to_string(),
+                    promote: AGENT2.to_string(),
+                },
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            ContractError::CustomError {
+                val: "Agent to remove is not active".to_string()
+            },
+            err.downcast().unwrap()
+        );
+
+        // Agent to promote must be pending
+        let err = app
+            .execute_contract(
+                Addr::unchecked(ADMIN),
+                contract_addr.clone(),
+                &ExecuteMsg::ReplaceActiveAgent {
+                    remove: AGENT1.to_string(),
+                    promote: AGENT4.to_string(),
+                },
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            ContractError::CustomError {
+                val: "Agent to promote is not pending".to_string()
+            },
+            err.downcast().unwrap()
+        );
+
+        app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::ReplaceActiveAgent {
+                remove: AGENT1.to_string(),
+                promote: AGENT3.to_string(),
+            },
+            &[],
+        )
+        .unwrap();
+        let (agent_ids, _, _) = get_agent_ids(&app, &contract_addr);
+        assert_eq!(vec![Addr::unchecked(AGENT3)], agent_ids.active);
+        assert_eq!(
+            vec![Addr::unchecked(AGENT2), Addr::unchecked(AGENT1)],
+            agent_ids.pending
+        );
+    }
+
+    #[test]
+    fn rotate_active_set() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        let agents = [AGENT0, AGENT1, AGENT2, AGENT3, AGENT4];
+        for agent in agents {
+            deps.querier.update_balance(agent, coins(100, NATIVE_DENOM));
+            store
+                .register_agent(
+                    deps.as_mut(),
+                    mock_info(agent, &[]),
+                    mock_env(),
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap();
+        }
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.max_agents = Some(2);
+        store.config.save(&mut deps.storage, &config).unwrap();
+        let agents = agents.map(Addr::unchecked);
+        store
+            .agent_active_queue
+            .save(&mut deps.storage, &agents[..2].to_vec())
+            .unwrap();
+        store
+            .agent_pending_queue
+            .save(&mut deps.storage, &agents[2..].to_vec())
+            .unwrap();
+
+        let err = store
+            .rotate_active_set(deps.as_mut(), mock_info(AGENT2, &[]), mock_env())
+            .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_seconds(60);
+        let res = store
+            .rotate_active_set(deps.as_mut(), mock_info("creator", &[]), env.clone())
+            .unwrap();
+        let attribute = |key: &str| {
+            res.attributes
+                .iter()
+                .find(|attr| attr.key == key)
+                .map(|attr| attr.value.clone())
+                .unwrap()
+        };
+        assert_eq!(attribute("demoted"), format!("{},{}", AGENT0, AGENT1));
+        assert_eq!(attribute("promoted"), format!("{},{}", AGENT2, AGENT3));
+        assert_eq!(
+            store.agent_active_queue.load(&deps.storage).unwrap(),
+            vec![agents[2].clone(), agents[3].clone()]
+        );
+        assert_eq!(
+            store.agent_pending_queue.load(&deps.storage).unwrap(),
+            vec![agents[4].clone(), agents[0].clone(), agents[1].clone()]
+        );
+        for agent_id in &agents[2..4] {
+            let agent = store.agents.load(&deps.storage, agent_id).unwrap();
+            assert_eq!(agent.last_activation, Some(env.block.time));
+        }
+
+        // Nothing to promote
+        store
+            .agent_pending_queue
+            .save(&mut deps.storage, &vec![])
+            .unwrap();
+        let err = store
+            .rotate_active_set(deps.as_mut(), mock_info("creator", &[]), mock_env())
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::CustomError {
+                val: "No pending agents to rotate in".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn query_agent_tenure() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        deps.querier
+            .update_balance(AGENT0, coins(100, NATIVE_DENOM));
+        let registered_env = mock_env();
+        store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                registered_env.clone(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+
+        let mut query_tenure = |env: Env, account_id: &str| {
+            store.query(
+                deps.as_ref(),
+                env,
+                QueryMsg::GetAgentTenure {
+                    account_id: account_id.to_string(),
+                },
+            )
+        };
+
+        // A day and half a second later, partial seconds are dropped
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_nanos(86_400_500_000_000);
+        let tenure: AgentTenureResponse = from_binary(&query_tenure(env, AGENT0).unwrap()).unwrap();
+        assert_eq!(
+            tenure,
+            AgentTenureResponse {
+                register_start_nanos: registered_env.block.time.nanos(),
+                tenure_seconds: 86_400,
+            }
+        );
+
+        let err = query_tenure(mock_env(), AGENT1).unwrap_err();
+        assert_eq!(
+            err,
+            StdError::generic_err(AgentNotRegistered {}.to_string())
+        );
+    }
+
+    #[test]
+    fn query_agent_liveness() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+
+        let env = mock_env();
+        let executed_at = Timestamp::from_nanos(env.block.time.nanos() - 30_000_000_000);
+        for (agent_id, last_execution) in [(AGENT1, Some(executed_at)), (AGENT2, None)] {
+            store
+                .agents
+                .save(
+                    &mut deps.storage,
+                    &Addr::unchecked(agent_id),
+                    &Agent {
+                        last_execution,
+                        ..new_agent(Addr::unchecked(agent_id), executed_at, vec![])
+                    },
+                )
+                .unwrap();
+        }
+
+        let mut query_liveness = |account_id: &str| -> Option<AgentLivenessResponse> {
+            let res = store
+                .query(
+                    deps.as_ref(),
+                    env.clone(),
+                    QueryMsg::GetAgentLiveness {
+                        account_id: account_id.to_string(),
+                    },
+                )
+                .unwrap();
+            from_binary(&res).unwrap()
+        };
+
+        // Executed 30 seconds ago
+        assert_eq!(
+            query_liveness(AGENT1),
+            Some(AgentLivenessResponse {
+                last_execution_nanos: executed_at.nanos(),
+                nanos_since: 30_000_000_000,
+            })
+        );
+        // Never executed
+        assert_eq!(
+            query_liveness(AGENT2),
+            Some(AgentLivenessResponse {
+                last_execution_nanos: 0,
+                nanos_since: u64::MAX,
+            })
+        );
+    }
+
+    #[test]
+    fn total_agent_value_tracks_balances() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+
+        for agent_id in [AGENT1, AGENT2] {
+            store
+                .agents
+                .save(
+                    &mut deps.storage,
+                    &Addr::unchecked(agent_id),
+                    &new_agent(Addr::unchecked(agent_id), mock_env().block.time, vec![]),
+                )
+                .unwrap();
+        }
+        let total_agent_value = |store: &mut CwCroncat, deps: Deps| -> GenericBalance {
+            let res = store
+                .query(deps, mock_env(), QueryMsg::GetTotalAgentValue {})
+                .unwrap();
+            from_binary(&res).unwrap()
+        };
+        let credit = |amount: u128| GenericBalance {
+            native: coins(amount, "meow"),
+            cw20: vec![],
+        };
+
+        // Credits
+        store
+            .batch_credit_agents(
+                &mut deps.storage,
+                mock_env().block.time,
+                vec![
+                    (Addr::unchecked(AGENT1), credit(10)),
+                    (Addr::unchecked(AGENT2), credit(20)),
+                ],
+            )
+            .unwrap();
+        store
+            .batch_credit_agents(
+                &mut deps.storage,
+                mock_env().block.time,
+                vec![(Addr::unchecked(AGENT1), credit(5))],
+            )
+            .unwrap();
+        assert_eq!(credit(35), total_agent_value(&mut store, deps.as_ref()));
+
+        // Withdrawal
+        store
+            .execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info(AGENT1, &[]),
+                ExecuteMsg::WithdrawReward {
+                    idempotency_key: None,
+                    memo: None,
+                },
+            )
+            .unwrap();
+        assert_eq!(credit(20), total_agent_value(&mut store, deps.as_ref()));
+
+        // Slash
+        make_slashable(&store, &mut deps.storage, AGENT2);
+        store
+            .execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("creator", &[]),
+                ExecuteMsg::SlashAgent {
+                    agent_id: AGENT2.to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            GenericBalance::default(),
+            total_agent_value(&mut store, deps.as_ref())
+        );
+    }
+
+    #[test]
+    fn toggle_agent_pause() {
+        let (mut app, cw_template_contract) = proper_instantiate();
+        let contract_addr = cw_template_contract.addr();
+
+        register_agent_exec(&mut app, &contract_addr, AGENT1, AGENT_BENEFICIARY);
+        add_task_exec(&mut app, &contract_addr, PARTICIPANT0);
+        app.update_block(add_little_time);
+
+        let get_agent_tasks = |app: &App| -> AgentTaskResponse {
+            app.wrap()
+                .query_wasm_smart::<Option<AgentTaskResponse>>(
+                    &contract_addr,
+                    &QueryMsg::GetAgentTasks {
+                        account_id: AGENT1.to_string(),
+                    },
+                )
+                .unwrap()
+                .unwrap()
+        };
+        assert_eq!(1, get_agent_tasks(&app).num_block_tasks.u64());
+
+        // Only registered agents
+        let err = app
+            .execute_contract(
+                Addr::unchecked(AGENT2),
+                contract_addr.clone(),
+                &ExecuteMsg::ToggleAgentPause {},
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(
+            ContractError::AgentNotRegistered {},
+            err.downcast().unwrap()
+        );
+
+        // Paused, no tasks but still active
+        app.execute_contract(
+            Addr::unchecked(AGENT1),
+            contract_addr.clone(),
+            &ExecuteMsg::ToggleAgentPause {},
+            &[],
+        )
+        .unwrap();
+        assert_eq!(0, get_agent_tasks(&app).num_block_tasks.u64());
+        let (_, num_active_agents, _) = get_agent_ids(&app, &contract_addr);
+        assert_eq!(1, num_active_agents);
+        let err = app
+            .execute_contract(
+                Addr::unchecked(AGENT1),
+                contract_addr.clone(),
+                &ExecuteMsg::ProxyCall { task_hash: None },
+                &[],
+            )
+            .unwrap_err();
+        assert_eq!(ContractError::AgentPaused {}, err.downcast().unwrap());
+
+        // Resumed
+        app.execute_contract(
+            Addr::unchecked(AGENT1),
+            contract_addr.clone(),
+            &ExecuteMsg::ToggleAgentPause {},
+            &[],
+        )
+        .unwrap();
+        assert_eq!(1, get_agent_tasks(&app).num_block_tasks.u64());
+    }
+
+    #[test]
+    fn query_queue_integrity() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+
+        for agent_id in [AGENT1, AGENT2] {
+            store
+                .agents
+                .save(
+                    &mut deps.storage,
+                    &Addr::unchecked(agent_id),
+                    &new_agent(Addr::unchecked(agent_id), mock_env().block.time, vec![]),
+                )
+                .unwrap();
+        }
+        // Healthy queues
+        store
+            .agent_active_queue
+            .save(&mut deps.storage, &vec![Addr::unchecked(AGENT1)])
+            .unwrap();
+        store
+            .agent_pending_queue
+            .save(&mut deps.storage, &vec![Addr::unchecked(AGENT2)])
+            .unwrap();
+        let res = store
+            .query(deps.as_ref(), mock_env(), QueryMsg::GetQueueIntegrity {})
+            .unwrap();
+        let integrity: QueueIntegrityResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            integrity,
+            QueueIntegrityResponse {
+                active_dupes: vec![],
+                pending_dupes: vec![],
+                orphaned_queue_entries: vec![],
+            }
+        );
+
+        // Duplicate in each queue, and an agent that was removed from the map but not the queue
+        store
+            .agent_active_queue
+            .save(
+                &mut deps.storage,
+                &vec![
+                    Addr::unchecked(AGENT1),
+                    Addr::unchecked(AGENT3),
+                    Addr::unchecked(AGENT1),
+                    Addr::unchecked(AGENT1),
+                ],
+            )
+            .unwrap();
+        store
+            .agent_pending_queue
+            .save(
+                &mut deps.storage,
+                &vec![
+                    Addr::unchecked(AGENT2),
+                    Addr::unchecked(AGENT2),
+                    Addr::unchecked(AGENT3),
+                ],
+            )
+            .unwrap();
+        let res = store
+            .query(deps.as_ref(), mock_env(), QueryMsg::GetQueueIntegrity {})
+            .unwrap();
+        let integrity: QueueIntegrityResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            integrity,
+            QueueIntegrityResponse {
+                active_dupes: vec![Addr::unchecked(AGENT1)],
+                pending_dupes: vec![Addr::unchecked(AGENT2)],
+                orphaned_queue_entries: vec![Addr::unchecked(AGENT3)],
+            }
+        );
+    }
+
+    #[test]
+    fn repair_queues() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+
+        for agent_id in [AGENT1, AGENT2] {
+            store
+                .agents
+                .save(
+                    &mut deps.storage,
+                    &Addr::unchecked(agent_id),
+                    &new_agent(Addr::unchecked(agent_id), mock_env().block.time, vec![]),
+                )
+                .unwrap();
+        }
+        // AGENT3 got removed from the map but not from the queues
+        store
+            .agent_active_queue
+            .save(
+                &mut deps.storage,
+                &vec![
+                    Addr::unchecked(AGENT1),
+                    Addr::unchecked(AGENT3),
+                    Addr::unchecked(AGENT1),
+                ],
+            )
+            .unwrap();
+        store
+            .agent_pending_queue
+            .save(
+                &mut deps.storage,
+                &vec![
+                    Addr::unchecked(AGENT2),
+                    Addr::unchecked(AGENT3),
+                    Addr::unchecked(AGENT2),
+                ],
+            )
+            .unwrap();
+
+        // Owner only
+        let err = store
+            .execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info(AGENT1, &[]),
+                ExecuteMsg::RepairQueues {},
+            )
+            .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+
+        let res = store
+            .execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("creator", &[]),
+                ExecuteMsg::RepairQueues {},
+            )
+            .unwrap();
+        let attribute = |key: &str| {
+            res.attributes
+                .iter()
+                .find(|a| a.key == key)
+                .unwrap()
+                .value
+                .clone()
+        };
+        assert_eq!(attribute("active_dupes"), AGENT1);
+        assert_eq!(attribute("pending_dupes"), AGENT2);
+        assert_eq!(attribute("orphaned_queue_entries"), AGENT3);
+
+        let res = store
+            .query(deps.as_ref(), mock_env(), QueryMsg::GetAgentIds {})
+            .unwrap();
+        let agent_ids: GetAgentIdsResponse = from_binary(&res).unwrap();
+        assert_eq!(agent_ids.active, vec![Addr::unchecked(AGENT1)]);
+        assert_eq!(agent_ids.pending, vec![Addr::unchecked(AGENT2)]);
+
+        let res = store
+            .query(deps.as_ref(), mock_env(), QueryMsg::GetQueueIntegrity {})
+            .unwrap();
+        let integrity: QueueIntegrityResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            integrity,
+            QueueIntegrityResponse {
+                active_dupes: vec![],
+                pending_dupes: vec![],
+                orphaned_queue_entries: vec![],
+            }
+        );
+
+        // Repairs go through the removal mode, pending order is always kept
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.queue_removal_mode = QueueRemovalMode::SwapRemove;
+        store.config.save(&mut deps.storage, &config).unwrap();
+        let queue = vec![
+            Addr::unchecked(AGENT3),
+            Addr::unchecked(AGENT1),
+            Addr::unchecked(AGENT2),
+        ];
+        store
+            .agent_active_queue
+            .save(&mut deps.storage, &queue)
+            .unwrap();
+        store
+            .agent_pending_queue
+            .save(&mut deps.storage, &queue)
+            .unwrap();
+        store
+            .execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("creator", &[]),
+                ExecuteMsg::RepairQueues {},
+            )
+            .unwrap();
+        let res = store
+            .query(deps.as_ref(), mock_env(), QueryMsg::GetAgentIds {})
+            .unwrap();
+        let agent_ids: GetAgentIdsResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            agent_ids.active,
+            vec![Addr::unchecked(AGENT2), Addr::unchecked(AGENT1)]
+        );
+        assert_eq!(
+            agent_ids.pending,
+            vec![Addr::unchecked(AGENT1), Addr::unchecked(AGENT2)]
+        );
+    }
+
+    #[test]
+    fn unregister_nominated_agent_passes_nomination() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+
+        for agent_id in [AGENT0, AGENT1, AGENT2] {
+            store
+                .agents
+                .save(
+                    &mut deps.storage,
+                    &Addr::unchecked(agent_id),
+                    &new_agent(Addr::unchecked(agent_id), mock_env().block.time, vec![]),
+                )
+                .unwrap();
+        }
+        store
+            .agent_active_queue
+            .save(&mut deps.storage, &vec![Addr::unchecked(AGENT0)])
+            .unwrap();
+        store
+            .agent_pending_queue
+            .save(
+                &mut deps.storage,
+                &vec![Addr::unchecked(AGENT1), Addr::unchecked(AGENT2)],
+            )
+            .unwrap();
+        // One more task than the active agent covers, so one agent is nominated
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.min_tasks_per_agent = 1;
+        store.config.save(&mut deps.storage, &config).unwrap();
+        store.task_total.save(&mut deps.storage, &2).unwrap();
+        let mut env = mock_env();
+        store
+            .agent_nomination_begin_time
+            .save(&mut deps.storage, &Some(env.block.time))
+            .unwrap();
+
+        env.block.time = env.block.time.plus_seconds(10);
+        let status = |deps: Deps, agent_id: &str| {
+            store
+                .get_agent_status(deps.storage, env.clone(), Addr::unchecked(agent_id))
+                .unwrap()
+        };
+        assert_eq!(status(deps.as_ref(), AGENT1), AgentStatus::Nominated);
+        assert_eq!(status(deps.as_ref(), AGENT2), AgentStatus::Pending);
+
+        // Nomination passes to the next in line, with a fresh window
+        let res = store
+            .unregister_agent(deps.as_mut(), mock_info(AGENT1, &[]), env.clone())
+            .unwrap();
+        assert!(res
+            .attributes
+            .iter()
+            .any(|a| a.key == "revoked_nomination" && a.value == "true"));
+        assert_eq!(status(deps.as_ref(), AGENT2), AgentStatus::Nominated);
+        assert_eq!(
+            store
+                .agent_nomination_begin_time
+                .load(&deps.storage)
+                .unwrap(),
+            Some(env.block.time)
+        );
+
+        // Nobody left to nominate
+        store
+            .unregister_agent(deps.as_mut(), mock_info(AGENT2, &[]), env.clone())
+            .unwrap();
+        assert_eq!(
+            store
+                .agent_nomination_begin_time
+                .load(&deps.storage)
+                .unwrap(),
+            None
+        );
+
+        // Agents that weren't nominated leave the window alone
+        store
+            .agent_nomination_begin_time
+            .save(&mut deps.storage, &Some(mock_env().block.time))
+            .unwrap();
+        store
+            .unregister_agent(deps.as_mut(), mock_info(AGENT0, &[]), env)
+            .unwrap();
+        assert_eq!(
+            store
+                .agent_nomination_begin_time
+                .load(&deps.storage)
+                .unwrap(),
+            Some(mock_env().block.time)
+        );
+    }
+
+    #[test]
+    fn query_queue_capacity() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+
+        // Unlimited by default
+        let res = store
+            .query(deps.as_ref(), mock_env(), QueryMsg::GetQueueCapacity {})
+            .unwrap();
+        let capacity: QueueCapacityResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            capacity,
+            QueueCapacityResponse {
+                active_used: 0,
+                active_max: None,
+                pending_used: 0,
+                pending_max: None,
+            }
+        );
+
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.max_agents = Some(3);
+        config.max_pending_agents = Some(2);
+        store.config.save(&mut deps.storage, &config).unwrap();
+        for agent_id in [AGENT0, AGENT1, AGENT2, AGENT3] {
+            deps.querier
+                .update_balance(agent_id, coins(100, NATIVE_DENOM));
+        }
+        for agent_id in [AGENT0, AGENT1, AGENT2] {
+            store
+                .register_agent(
+                    deps.as_mut(),
+                    mock_info(agent_id, &[]),
+                    mock_env(),
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap();
+        }
+
+        let res = store
+            .query(deps.as_ref(), mock_env(), QueryMsg::GetQueueCapacity {})
+            .unwrap();
+        let capacity: QueueCapacityResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            capacity,
+            QueueCapacityResponse {
+                active_used: 1,
+                active_max: Some(3),
+                pending_used: 2,
+                pending_max: Some(2),
+            }
+        );
+
+        // No headroom left in the pending queue
+        let err = store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT3, &[]),
+                mock_env(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::CustomError {
+                val: "Pending agent queue is full".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn register_agent_fractional_gas_price() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+
+        // 4 * 1.3 = 5.2, rounded up wallet requirement is 6
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.gas_price = Decimal::permille(1300);
+        store.config.save(&mut deps.storage, &config).unwrap();
+
+        deps.querier.update_balance(AGENT0, coins(5, NATIVE_DENOM));
+        let err = store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::CustomError {
+                val: "Insufficient funds".to_string()
+            }
+        );
+
+        deps.querier.update_balance(AGENT0, coins(6, NATIVE_DENOM));
+        store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+
+        // A gas price too large to price 4 units is an error, not a panic
+        config.gas_price = Decimal::MAX;
+        store.config.save(&mut deps.storage, &config).unwrap();
+        let err = store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT1, &[]),
+                mock_env(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::CustomError {
+                val: "Gas cost overflows".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn query_agents_with_capability() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        for agent_id in [AGENT0, AGENT1, AGENT2, AGENT3] {
+            deps.querier
+                .update_balance(agent_id, coins(100, NATIVE_DENOM));
+        }
+
+        // Tag limits
+        let err = store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                None,
+                Some(vec!["x".repeat(MAX_CAPABILITY_LENGTH + 1)]),
+                None,
+                None,
+            )
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::InvalidCapability {
+                capability: "x".repeat(MAX_CAPABILITY_LENGTH + 1),
+                max_length: MAX_CAPABILITY_LENGTH,
+            }
+        );
+        let err = store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                None,
+                Some(vec!["tag".to_string(); MAX_AGENT_CAPABILITIES + 1]),
+                None,
+                None,
+            )
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::TooManyCapabilities {
+                max: MAX_AGENT_CAPABILITIES
+            }
+        );
+
+        let ibc_relay = || Some(vec!["ibc-relay".to_string(), "ibc-relay".to_string()]);
+        store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                None,
+                ibc_relay(),
+                None,
+                None,
+            )
+            .unwrap();
+        store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT1, &[]),
+                mock_env(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        // Pending, not counted until active
+        store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT2, &[]),
+                mock_env(),
+                None,
+                ibc_relay(),
+                None,
+                None,
+            )
+            .unwrap();
+        store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT3, &[]),
+                mock_env(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        store
+            .update_agent(
+                deps.as_mut(),
+                mock_info(AGENT3, &[]),
+                mock_env(),
+                AGENT3.to_string(),
+                ibc_relay(),
+                None,
+            )
+            .unwrap();
+        store
+            .agent_active_queue
+            .save(
+                &mut deps.storage,
+                &vec![
+                    Addr::unchecked(AGENT0),
+                    Addr::unchecked(AGENT1),
+                    Addr::unchecked(AGENT3),
+                ],
+            )
+            .unwrap();
+
+        let res = store
+            .query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::GetAgentsWithCapability {
+                    capability: "ibc-relay".to_string(),
+                },
+            )
+            .unwrap();
+        let agents: Vec<Addr> = from_binary(&res).unwrap();
+        assert_eq!(
+            agents,
+            vec![Addr::unchecked(AGENT0), Addr::unchecked(AGENT3)]
+        );
+        let agent = store
+            .agents
+            .load(&deps.storage, &Addr::unchecked(AGENT0))
+            .unwrap();
+        assert_eq!(agent.capabilities, vec!["ibc-relay".to_string()]);
+
+        // Updating without capabilities keeps them
+        store
+            .update_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                AGENT0.to_string(),
+                None,
+                None,
+            )
+            .unwrap();
+        let agent = store
+            .agents
+            .load(&deps.storage, &Addr::unchecked(AGENT0))
+            .unwrap();
+        assert_eq!(agent.capabilities, vec!["ibc-relay".to_string()]);
+    }
+
+    #[test]
+    fn escrowed_reward_withdrawable_after_dispute_window() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        deps.querier
+            .update_balance(AGENT0, coins(100, NATIVE_DENOM));
+        store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+
+        // As if a proxy call escrowed the reward
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.available_balance.native = coins(105, NATIVE_DENOM);
+        config.total_agent_balance.native = coins(15, NATIVE_DENOM);
+        store.config.save(&mut deps.storage, &config).unwrap();
+        let mut env = mock_env();
+        let release_time = env.block.time.plus_seconds(100).nanos();
+        store
+            .escrow_reward(
+                &mut deps.storage,
+                &Addr::unchecked(AGENT0),
+                b"task",
+                &coin(10, NATIVE_DENOM),
+                release_time,
+            )
+            .unwrap();
+        // A later run of the same task gets its own window
+        let later_release_time = env.block.time.plus_seconds(150).nanos();
+        store
+            .escrow_reward(
+                &mut deps.storage,
+                &Addr::unchecked(AGENT0),
+                b"task",
+                &coin(5, NATIVE_DENOM),
+                later_release_time,
+            )
+            .unwrap();
+
+        let res = store
+            .query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::GetAgentEscrow {
+                    account_id: AGENT0.to_string(),
+                },
+            )
+            .unwrap();
+        let escrows: Vec<AgentEscrowResponse> = from_binary(&res).unwrap();
+        assert_eq!(
+            escrows,
+            vec![
+                AgentEscrowResponse {
+                    task_hash: "task".to_string(),
+                    balance: GenericBalance {
+                        native: coins(10, NATIVE_DENOM),
+                        cw20: vec![],
+                    },
+                    release_time_nanos: release_time,
+                },
+                AgentEscrowResponse {
+                    task_hash: "task".to_string(),
+                    balance: GenericBalance {
+                        native: coins(5, NATIVE_DENOM),
+                        cw20: vec![],
+                    },
+                    release_time_nanos: later_release_time,
+                },
+            ]
+        );
+
+        // Within the window, nothing to withdraw and can't unregister
+        let res = store
+            .withdraw_agent_balance(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                env.clone(),
+                None,
+                None,
+            )
+            .unwrap();
+        assert!(res.messages.is_empty());
+        let err = store
+            .unregister_agent(deps.as_mut(), mock_info(AGENT0, &[]), env.clone())
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::CustomError {
+                val: "Agent has rewards in the dispute window".to_string()
+            }
+        );
+
+        // Past the first window, the later reward is still held
+        env.block.time = env.block.time.plus_seconds(100);
+        let res = store
+            .withdraw_agent_balance(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                env.clone(),
+                None,
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: AGENT0.to_string(),
+                amount: coins(10, NATIVE_DENOM),
+            })]
+        );
+        let err = store
+            .unregister_agent(deps.as_mut(), mock_info(AGENT0, &[]), env.clone())
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::CustomError {
+                val: "Agent has rewards in the dispute window".to_string()
+            }
+        );
+
+        // Past the second window
+        env.block.time = env.block.time.plus_seconds(50);
+        let res = store
+            .withdraw_agent_balance(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                env.clone(),
+                None,
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: AGENT0.to_string(),
+                amount: coins(5, NATIVE_DENOM),
+            })]
+        );
+        let res = store
+            .query(
+                deps.as_ref(),
+                env.clone(),
+                QueryMsg::GetAgentEscrow {
+                    account_id: AGENT0.to_string(),
+                },
+            )
+            .unwrap();
+        let escrows: Vec<AgentEscrowResponse> = from_binary(&res).unwrap();
+        assert!(escrows.is_empty());
+        let config = store.config.load(&deps.storage).unwrap();
+        assert_eq!(config.available_balance.native, coins(90, NATIVE_DENOM));
+        assert!(config.total_agent_balance.native.is_empty());
+        store
+            .unregister_agent(deps.as_mut(), mock_info(AGENT0, &[]), env)
+            .unwrap();
+    }
+
+    #[test]
+    fn contract_as_payable_account_rejected() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        deps.querier
+            .update_balance(AGENT0, coins(100, NATIVE_DENOM));
+
+        let err = store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                Some(MOCK_CONTRACT_ADDR.to_string()),
+                None,
+                None,
+                None,
+            )
+            .unwrap_err();
+        assert_eq!(err, ContractError::SelfPayableAccount {});
+
+        store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        let err = store
+            .update_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                MOCK_CONTRACT_ADDR.to_string(),
+                None,
+                None,
+            )
+            .unwrap_err();
+        assert_eq!(err, ContractError::SelfPayableAccount {});
+        let agent = store
+            .agents
+            .load(&deps.storage, &Addr::unchecked(AGENT0))
+            .unwrap();
+        assert_eq!(agent.payable_account_id, Addr::unchecked(AGENT0));
+    }
+
+    #[test]
+    fn query_nomination_config() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+
+        // 1 active agent covering 2 tasks, 5 tasks need 2 more agents
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.min_tasks_per_agent = 2;
+        config.agent_nomination_duration = 60;
+        store.config.save(&mut deps.storage, &config).unwrap();
+        store
+            .agent_active_queue
+            .save(&mut deps.storage, &vec![Addr::unchecked(AGENT0)])
+            .unwrap();
+        store.task_total.save(&mut deps.storage, &5).unwrap();
+        store
+            .agent_nomination_begin_time
+            .save(&mut deps.storage, &Some(mock_env().block.time))
+            .unwrap();
+
+        let res = store
+            .query(deps.as_ref(), mock_env(), QueryMsg::GetNominationConfig {})
+            .unwrap();
+        let nomination_config: NominationConfigResponse = from_binary(&res).unwrap();
+        assert_eq!(
+            nomination_config,
+            NominationConfigResponse {
+                mode: NominationMode::Fifo,
+                nomination_duration: 60,
+                open_slots: 2,
+                min_tasks_per_agent: 2,
+                nomination_begin_time: Some(mock_env().block.time),
+                nomination_suspended: false,
+            }
+        );
+    }
+
+    #[test]
+    fn migrate_agent() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        for agent in [AGENT0, AGENT1] {
+            deps.querier.update_balance(agent, coins(100, NATIVE_DENOM));
+            store
+                .register_agent(
+                    deps.as_mut(),
+                    mock_info(agent, &[]),
+                    mock_env(),
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap();
+        }
+        store
+            .update_agent_operators(
+                deps.as_mut(),
+                mock_info(AGENT1, &[]),
+                vec![AGENT3.to_string()],
+            )
+            .unwrap();
+        let mut agent = store
+            .agents
+            .load(&deps.storage, &Addr::unchecked(AGENT1))
+            .unwrap();
+        agent.balance.native = coins(7, NATIVE_DENOM);
+        agent.total_tasks_executed = 3;
+        store
+            .agents
+            .save(&mut deps.storage, &Addr::unchecked(AGENT1), &agent)
+            .unwrap();
+        let withdraw_keys = vec![("key".to_string(), 1)];
+        store
+            .withdraw_keys
+            .save(&mut deps.storage, &Addr::unchecked(AGENT1), &withdraw_keys)
+            .unwrap();
+
+        // Can't take over another agent
+        let err = store
+            .migrate_agent(
+                deps.as_mut(),
+                mock_info(AGENT1, &[]),
+                mock_env(),
+                AGENT0.to_string(),
+            )
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::CustomError {
+                val: "New address is already an agent".to_string()
+            }
+        );
+        // The defaulted payable account follows the key, so the usual payable checks apply
+        let err = store
+            .migrate_agent(
+                deps.as_mut(),
+                mock_info(AGENT1, &[]),
+                mock_env(),
+                MOCK_CONTRACT_ADDR.to_string(),
+            )
+            .unwrap_err();
+        assert_eq!(err, ContractError::SelfPayableAccount {});
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.blacklist = vec![Addr::unchecked(AGENT2)];
+        store.config.save(&mut deps.storage, &config).unwrap();
+        let err = store
+            .migrate_agent(
+                deps.as_mut(),
+                mock_info(AGENT1, &[]),
+                mock_env(),
+                AGENT2.to_string(),
+            )
+            .unwrap_err();
+        assert_eq!(err, ContractError::BlacklistedPayableAccount {});
+        config.blacklist = vec![];
+        store.config.save(&mut deps.storage, &config).unwrap();
+
+        let res = store
+            .migrate_agent(
+                deps.as_mut(),
+                mock_info(AGENT1, &[]),
+                mock_env(),
+                AGENT2.to_string(),
+            )
+            .unwrap();
+        assert!(res
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "new_address" && attr.value == AGENT2));
+        assert!(!store.agents.has(&deps.storage, &Addr::unchecked(AGENT1)));
+        let migrated = store
+            .agents
+            .load(&deps.storage, &Addr::unchecked(AGENT2))
+            .unwrap();
+        // Defaulted payable account follows the key
+        assert_eq!(
+            migrated,
+            Agent {
+                payable_account_id: Addr::unchecked(AGENT2),
+                ..agent
+            }
+        );
+        assert!(!store
+            .agents_by_payable
+            .has(&deps.storage, &Addr::unchecked(AGENT1)));
+        assert_eq!(
+            store
+                .agents_by_payable
+                .load(&deps.storage, &Addr::unchecked(AGENT2))
+                .unwrap(),
+            vec![Addr::unchecked(AGENT2)]
+        );
+        assert!(!store
+            .withdraw_keys
+            .has(&deps.storage, &Addr::unchecked(AGENT1)));
+        assert_eq!(
+            store
+                .withdraw_keys
+                .load(&deps.storage, &Addr::unchecked(AGENT2))
+                .unwrap(),
+            withdraw_keys
+        );
+        assert_eq!(
+            store.agent_pending_queue.load(&deps.storage).unwrap(),
+            vec![Addr::unchecked(AGENT2)]
+        );
+        assert_eq!(
+            store
+                .agent_operators
+                .load(&deps.storage, &Addr::unchecked(AGENT3))
+                .unwrap(),
+            Addr::unchecked(AGENT2)
+        );
+    }
+
+    #[test]
+    fn reward_credited_by_preference() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.cw20_whitelist = vec![Addr::unchecked("cw20")];
+        store.config.save(&mut deps.storage, &config).unwrap();
+        for agent in [AGENT0, AGENT1] {
+            deps.querier.update_balance(agent, coins(100, NATIVE_DENOM));
+            store
+                .register_agent(
+                    deps.as_mut(),
+                    mock_info(agent, &[]),
+                    mock_env(),
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap();
+        }
+
+        let err = store
+            .update_reward_preference(
+                deps.as_mut(),
+                mock_info(AGENT1, &[]),
+                RewardPreference::Cw20(Addr::unchecked("not_whitelisted")),
+            )
+            .unwrap_err();
+        assert_eq!(err, ContractError::NotInWhitelist {});
+        store
+            .update_reward_preference(
+                deps.as_mut(),
+                mock_info(AGENT1, &[]),
+                RewardPreference::Cw20(Addr::unchecked("cw20")),
+            )
+            .unwrap();
+
+        // No price oracle to convert with, so both get the native agent fee
+        for (agent_id, preference) in [
+            (AGENT0, RewardPreference::Native),
+            (AGENT1, RewardPreference::Cw20(Addr::unchecked("cw20"))),
+        ] {
+            let agent_id = Addr::unchecked(agent_id);
+            let agent = store.agents.load(&deps.storage, &agent_id).unwrap();
+            assert_eq!(agent.reward_preference, preference);
+            store
+                .send_base_agent_reward(&mut deps.storage, &mock_env(), agent, &agent_id)
+                .unwrap();
+            let agent = store.agents.load(&deps.storage, &agent_id).unwrap();
+            assert_eq!(agent.balance.native, vec![config.agent_fee.clone()]);
+            assert!(agent.balance.cw20.is_empty());
+        }
+    }
+
+    #[test]
+    fn mass_agent_exit_pauses_execution() {
+        let agents = [AGENT0, AGENT1, AGENT2, AGENT3];
+        let unregister_all = |seconds_apart: u64| {
+            let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+            let store = CwCroncat::default();
+            mock_init(&store, deps.as_mut()).unwrap();
+            let mut config = store.config.load(&deps.storage).unwrap();
+            config.mass_exit_threshold = Some(MassExitThreshold {
+                max_exits: 2,
+                window_nanos: 60_000_000_000,
+            });
+            store.config.save(&mut deps.storage, &config).unwrap();
+            for agent in agents {
+                deps.querier.update_balance(agent, coins(100, NATIVE_DENOM));
+                store
+                    .register_agent(
+                        deps.as_mut(),
+                        mock_info(agent, &[]),
+                        mock_env(),
+                        None,
+                        None,
+                        None,
+                        None,
+                    )
+                    .unwrap();
+            }
+            store
+                .agent_active_queue
+                .save(
+                    &mut deps.storage,
+                    &agents.iter().map(|a| Addr::unchecked(*a)).collect(),
+                )
+                .unwrap();
+            store
+                .agent_pending_queue
+                .save(&mut deps.storage, &vec![])
+                .unwrap();
+
+            let mut env = mock_env();
+            let mut alerts = vec![];
+            for agent in agents {
+                env.block.time = env.block.time.plus_seconds(seconds_apart);
+                let res = store
+                    .unregister_agent(deps.as_mut(), mock_info(agent, &[]), env.clone())
+                    .unwrap();
+                alerts.push(
+                    res.attributes
+                        .iter()
+                        .any(|attr| attr.key == "alert" && attr.value == "mass_agent_exit"),
+                );
+            }
+            let config = store.config.load(&deps.storage).unwrap();
+            (alerts, config.paused.execute)
+        };
+
+        // Third exit within a minute trips the breaker
+        assert_eq!(unregister_all(1), (vec![false, false, true, false], true));
+        // Spread out exits never add up
+        assert_eq!(unregister_all(61), (vec![false; 4], false));
+    }
+
+    #[test]
+    fn query_agent_execution_window() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.slot_granularity = 100;
+        store.config.save(&mut deps.storage, &config).unwrap();
+        let mut env = mock_env();
+        env.block.time = Timestamp::from_nanos(1_050);
+        let window = |store: &mut CwCroncat,
+                      deps: Deps,
+                      account_id: &str|
+         -> Option<AgentExecutionWindowResponse> {
+            let res = store
+                .query(
+                    deps,
+                    env.clone(),
+                    QueryMsg::GetAgentExecutionWindow {
+                        account_id: account_id.to_string(),
+                    },
+                )
+                .unwrap();
+            from_binary(&res).unwrap()
+        };
+
+        // A single agent gets the whole slot
+        store
+            .agent_active_queue
+            .save(&mut deps.storage, &vec![Addr::unchecked(AGENT0)])
+            .unwrap();
+        assert_eq!(
+            window(&mut store, deps.as_ref(), AGENT0),
+            Some(AgentExecutionWindowResponse {
+                window_start_nanos: 1_000,
+                window_end_nanos: 1_100,
+            })
+        );
+
+        let active = [AGENT0, AGENT1, AGENT2];
+        store
+            .agent_active_queue
+            .save(
+                &mut deps.storage,
+                &active.iter().map(|agent| Addr::unchecked(*agent)).collect(),
+            )
+            .unwrap();
+        let windows: Vec<AgentExecutionWindowResponse> = active
+            .iter()
+            .map(|agent| window(&mut store, deps.as_ref(), agent).unwrap())
+            .collect();
+        assert_eq!(
+            windows
+                .iter()
+                .map(|w| (w.window_start_nanos, w.window_end_nanos))
+                .collect::<Vec<_>>(),
+            vec![(1_000, 1_033), (1_033, 1_066), (1_066, 1_100)]
+        );
+        // Back to back without overlapping
+        for pair in windows.windows(2) {
+            assert_eq!(pair[0].window_end_nanos, pair[1].window_start_nanos);
+        }
+        assert_eq!(window(&mut store, deps.as_ref(), AGENT3), None);
+
+        // Each window is at least a nanosecond long, agents past the slot's length get none
+        config.slot_granularity = 2;
+        store.config.save(&mut deps.storage, &config).unwrap();
+        assert_eq!(
+            window(&mut store, deps.as_ref(), AGENT0),
+            Some(AgentExecutionWindowResponse {
+                window_start_nanos: 1_050,
+                window_end_nanos: 1_051,
+            })
+        );
+        assert_eq!(
+            window(&mut store, deps.as_ref(), AGENT1),
+            Some(AgentExecutionWindowResponse {
+                window_start_nanos: 1_051,
+                window_end_nanos: 1_052,
+            })
+        );
+        assert_eq!(window(&mut store, deps.as_ref(), AGENT2), None);
+    }
+
+    #[test]
+    fn query_agent_rank() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        store
+            .agent_active_queue
+            .save(
+                &mut deps.storage,
+                &vec![
+                    Addr::unchecked(AGENT0),
+                    Addr::unchecked(AGENT1),
+                    Addr::unchecked(AGENT2),
+                ],
+            )
+            .unwrap();
+        store
+            .agent_pending_queue
+            .save(&mut deps.storage, &vec![Addr::unchecked(AGENT3)])
+            .unwrap();
+
+        for (agent, expected_rank) in [(AGENT0, Some(0)), (AGENT1, Some(1)), (AGENT3, None)] {
+            let res = store
+                .query(
+                    deps.as_ref(),
+                    mock_env(),
+                    QueryMsg::GetAgentRank {
+                        account_id: agent.to_string(),
+                    },
+                )
+                .unwrap();
+            let rank: Option<u64> = from_binary(&res).unwrap();
+            assert_eq!(rank, expected_rank);
+        }
+    }
+
+    #[test]
+    fn agents_sharing_payable_account() {
+        fn agents_by_payable(
+            store: &mut CwCroncat,
+            deps: Deps,
+            payable_account_id: &str,
+        ) -> Vec<Addr> {
+            let res = store
+                .query(
+                    deps,
+                    mock_env(),
+                    QueryMsg::GetAgentsByPayable {
+                        payable_account_id: payable_account_id.to_string(),
+                    },
+                )
+                .unwrap();
+            from_binary(&res).unwrap()
+        }
+
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        for agent in [AGENT0, AGENT1] {
+            deps.querier.update_balance(agent, coins(100, NATIVE_DENOM));
+            store
+                .register_agent(
+                    deps.as_mut(),
+                    mock_info(agent, &[]),
+                    mock_env(),
+                    Some(AGENT_BENEFICIARY.to_string()),
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap();
+        }
+        assert_eq!(
+            agents_by_payable(&mut store, deps.as_ref(), AGENT_BENEFICIARY),
+            vec![Addr::unchecked(AGENT0), Addr::unchecked(AGENT1)]
+        );
+
+        // Moving one agent's payouts leaves the other indexed
+        store
+            .update_agent(
+                deps.as_mut(),
+                mock_info(AGENT1, &[]),
+                mock_env(),
+                AGENT1.to_string(),
+                None,
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            agents_by_payable(&mut store, deps.as_ref(), AGENT_BENEFICIARY),
+            vec![Addr::unchecked(AGENT0)]
+        );
+        assert_eq!(
+            agents_by_payable(&mut store, deps.as_ref(), AGENT1),
+            vec![Addr::unchecked(AGENT1)]
+        );
+
+        // Last agent leaving drops the entry
+        store
+            .unregister_agent(deps.as_mut(), mock_info(AGENT0, &[]), mock_env())
+            .unwrap();
+        assert!(agents_by_payable(&mut store, deps.as_ref(), AGENT_BENEFICIARY).is_empty());
+    }
+
+    #[test]
+    fn withdraw_all_for_payable() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        for (agent, reward) in [(AGENT0, 10), (AGENT1, 20), (AGENT2, 30)] {
+            deps.querier.update_balance(agent, coins(100, NATIVE_DENOM));
+            store
+                .register_agent(
+                    deps.as_mut(),
+                    mock_info(agent, &[]),
+                    mock_env(),
+                    Some(AGENT_BENEFICIARY.to_string()),
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap();
+            let agent_id = Addr::unchecked(agent);
+            let mut agent = store.agents.load(&deps.storage, &agent_id).unwrap();
+            agent.balance.native = coins(reward, NATIVE_DENOM);
+            store
+                .agents
+                .save(&mut deps.storage, &agent_id, &agent)
+                .unwrap();
+        }
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.available_balance.native = coins(100, NATIVE_DENOM);
+        config.total_agent_balance.native = coins(60, NATIVE_DENOM);
+        store.config.save(&mut deps.storage, &config).unwrap();
+
+        // Only the payable account can withdraw
+        let err = store
+            .withdraw_all_for_payable(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                None,
+                None,
+            )
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::CustomError {
+                val: "No agents pay out to this account".to_string()
+            }
+        );
+
+        // Pages follow address order, the last agent of a page starts the next one
+        let mut agent_ids = [AGENT0, AGENT1, AGENT2];
+        agent_ids.sort_unstable();
+        let rewards: Vec<u128> = agent_ids
+            .iter()
+            .map(|agent| match *agent {
+                AGENT0 => 10,
+                AGENT1 => 20,
+                _ => 30,
+            })
+            .collect();
+        let res = store
+            .withdraw_all_for_payable(
+                deps.as_mut(),
+                mock_info(AGENT_BENEFICIARY, &[]),
+                mock_env(),
+                None,
+                Some(2),
+            )
+            .unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: AGENT_BENEFICIARY.to_string(),
+                amount: coins(rewards[0] + rewards[1], NATIVE_DENOM),
+            })]
+        );
+        assert!(res
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "last_agent_id" && attr.value == agent_ids[1]));
+        let res = store
+            .withdraw_all_for_payable(
+                deps.as_mut(),
+                mock_info(AGENT_BENEFICIARY, &[]),
+                mock_env(),
+                Some(agent_ids[1].to_string()),
+                Some(2),
+            )
+            .unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: AGENT_BENEFICIARY.to_string(),
+                amount: coins(rewards[2], NATIVE_DENOM),
+            })]
+        );
+        for agent in [AGENT0, AGENT1, AGENT2] {
+            let agent = store
+                .agents
+                .load(&deps.storage, &Addr::unchecked(agent))
+                .unwrap();
+            assert!(agent
+                .balance
+                .native
+                .iter()
+                .all(|coin| coin.amount.is_zero()));
+        }
+        let config = store.config.load(&deps.storage).unwrap();
+        assert_eq!(config.available_balance.native, coins(40, NATIVE_DENOM));
+        assert!(config
+            .total_agent_balance
+            .native
+            .iter()
+            .all(|coin| coin.amount.is_zero()));
+    }
+
+    #[test]
+    fn agent_attribute_on_agent_handlers() {
+        fn agent_attribute(res: &Response) -> Option<&str> {
+            res.attributes
+                .iter()
+                .find(|attr| attr.key == "agent")
+                .map(|attr| attr.value.as_str())
+        }
+
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        for agent in [AGENT0, AGENT1] {
+            deps.querier.update_balance(agent, coins(100, NATIVE_DENOM));
+            let res = store
+                .register_agent(
+                    deps.as_mut(),
+                    mock_info(agent, &[]),
+                    mock_env(),
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap();
+            assert_eq!(agent_attribute(&res), Some(agent));
+        }
+
+        let res = store
+            .update_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                AGENT_BENEFICIARY.to_string(),
+                None,
+                None,
+            )
+            .unwrap();
+        assert_eq!(agent_attribute(&res), Some(AGENT0));
+
+        store
+            .agent_nomination_begin_time
+            .save(&mut deps.storage, &Some(mock_env().block.time))
+            .unwrap();
+        let res = store
+            .accept_nomination_agent(deps.as_mut(), mock_info(AGENT1, &[]), mock_env())
+            .unwrap();
+        assert_eq!(agent_attribute(&res), Some(AGENT1));
+
+        let res = store
+            .withdraw_agent_balance(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                None,
+                None,
+            )
+            .unwrap();
+        assert_eq!(agent_attribute(&res), Some(AGENT0));
+
+        make_slashable(&store, &mut deps.storage, AGENT1);
+        let res = store
+            .slash_agent(
+                deps.as_mut(),
+                mock_info("creator", &[]),
+                mock_env(),
+                AGENT1.to_string(),
+            )
+            .unwrap();
+        assert_eq!(agent_attribute(&res), Some(AGENT1));
+
+        let res = store
+            .unregister_agent(deps.as_mut(), mock_info(AGENT0, &[]), mock_env())
+            .unwrap();
+        assert_eq!(agent_attribute(&res), Some(AGENT0));
+    }
+
+    #[test]
+    fn query_get_agents() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        deps.querier
+            .update_balance(AGENT0, coins(100, NATIVE_DENOM));
+        store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+
+        let res = store
+            .query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::GetAgents {
+                    account_ids: vec![AGENT0.to_string(), AGENT1.to_string()],
+                },
+            )
+            .unwrap();
+        let agents: Vec<(Addr, Option<AgentResponse>)> = from_binary(&res).unwrap();
+        assert_eq!(agents.len(), 2);
+        assert_eq!(agents[0].0, Addr::unchecked(AGENT0));
+        assert_eq!(agents[0].1.as_ref().unwrap().status, AgentStatus::Active);
+        assert_eq!(agents[1], (Addr::unchecked(AGENT1), None));
+
+        let err = store
+            .query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::GetAgents {
+                    account_ids: vec![AGENT0.to_string(); MAX_AGENTS_PER_QUERY + 1],
+                },
+            )
+            .unwrap_err();
+        assert_eq!(
+            err,
+            StdError::generic_err(format!(
+                "Can't query more than {} agents at once",
+                MAX_AGENTS_PER_QUERY
+            ))
+        );
+    }
+
+    #[test]
+    fn query_all_agents() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        for agent in [AGENT0, AGENT1, AGENT2, AGENT3] {
+            deps.querier.update_balance(agent, coins(100, NATIVE_DENOM));
+            store
+                .register_agent(
+                    deps.as_mut(),
+                    mock_info(agent, &[]),
+                    mock_env(),
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap();
+        }
+        let mut expected = vec![AGENT0, AGENT1, AGENT2, AGENT3];
+        expected.sort_unstable();
+        let mut all_agents = |start_after: Option<Addr>, limit: Option<u32>| {
+            let res = store
+                .query(
+                    deps.as_ref(),
+                    mock_env(),
+                    QueryMsg::GetAllAgents {
+                        start_after: start_after.map(String::from),
+                        limit,
+                    },
+                )
+                .unwrap();
+            let agents: Vec<(Addr, AgentResponse)> = from_binary(&res).unwrap();
+            agents
+        };
+
+        let agents = all_agents(None, None);
+        assert_eq!(
+            agents
+                .iter()
+                .map(|(addr, _)| addr.as_str())
+                .collect::<Vec<_>>(),
+            expected
+        );
+        assert_eq!(
+            agents
+                .iter()
+                .filter(|(_, agent)| agent.status == AgentStatus::Active)
+                .count(),
+            1
+        );
+
+        // Walk the pages, each one picking up after the previous cursor
+        let mut cursor = None;
+        let mut paged = vec![];
+        loop {
+            let page = all_agents(cursor.clone(), Some(3));
+            assert!(page.len() <= 3);
+            if page.is_empty() {
+                break;
+            }
+            cursor = page.last().map(|(addr, _)| addr.clone());
+            paged.extend(page);
+        }
+        assert_eq!(paged, agents);
+        assert_eq!(all_agents(Some(Addr::unchecked(expected[3])), None), vec![]);
+    }
+
+    #[test]
+    fn last_active_agent_unregister() {
+        for allow_empty_active_set in [false, true] {
+            let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+            let store = CwCroncat::default();
+            mock_init(&store, deps.as_mut()).unwrap();
+            let mut config = store.config.load(&deps.storage).unwrap();
+            config.allow_empty_active_set = allow_empty_active_set;
+            store.config.save(&mut deps.storage, &config).unwrap();
+            deps.querier
+                .update_balance(AGENT0, coins(100, NATIVE_DENOM));
+            store
+                .register_agent(
+                    deps.as_mut(),
+                    mock_info(AGENT0, &[]),
+                    mock_env(),
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap();
+
+            let res = store.unregister_agent(deps.as_mut(), mock_info(AGENT0, &[]), mock_env());
+            if allow_empty_active_set {
+                assert!(res
+                    .unwrap()
+                    .attributes
+                    .iter()
+                    .any(|attr| attr.key == "warning" && attr.value == "network_stalled"));
+                assert!(store
+                    .agent_active_queue
+                    .load(&deps.storage)
+                    .unwrap()
+                    .is_empty());
+            } else {
+                assert_eq!(
+                    res.unwrap_err(),
+                    ContractError::CustomError {
+                        val: "Last active agent can't leave until a replacement is pending"
+                            .to_string()
+                    }
+                );
+                assert_eq!(
+                    store.agent_active_queue.load(&deps.storage).unwrap(),
+                    vec![Addr::unchecked(AGENT0)]
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn query_projected_rewards() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        deps.querier
+            .update_balance(AGENT0, coins(100, NATIVE_DENOM));
+        store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.agent_fee = coin(3, NATIVE_DENOM);
+        store.config.save(&mut deps.storage, &config).unwrap();
+        // Each 6 second block slot, the cron task only runs at the top of the minute
+        for interval in [
+            Interval::Immediate,
+            Interval::Block(5),
+            Interval::Once,
+            Interval::Cron("0 * * * * *".to_string()),
+        ] {
+            store
+                .create_task(
+                    deps.as_mut(),
+                    mock_info(PARTICIPANT0, &coins(1_000_000, NATIVE_DENOM)),
+                    mock_env(),
+                    TaskRequest {
+                        interval,
+                        boundary: None,
+                        stop_on_fail: false,
+                        actions: vec![Action {
+                            msg: BankMsg::Send {
+                                to_address: PARTICIPANT1.to_string(),
+                                amount: coins(1, NATIVE_DENOM),
+                            }
+                            .into(),
+                            gas_limit: None,
+                        }],
+                        rules: None,
+                        cw20_coins: vec![],
+                        task_type: None,
+                    },
+                )
+                .unwrap();
+        }
+
+        let mut projected_rewards = |account_id: &str| -> Coin {
+            let res = store
+                .query(
+                    deps.as_ref(),
+                    mock_env(),
+                    QueryMsg::GetProjectedRewards {
+                        account_id: account_id.to_string(),
+                        horizon_slots: 10,
+                    },
+                )
+                .unwrap();
+            from_binary(&res).unwrap()
+        };
+        // Sole active agent: (10 immediate + 2 every 5 blocks + 1 once + 1 cron) runs * 3
+        assert_eq!(projected_rewards(AGENT0), coin(42, NATIVE_DENOM));
+        // Joining splits the tasks with the active agent
+        assert_eq!(projected_rewards(AGENT1), coin(21, NATIVE_DENOM));
+    }
+
+    #[test]
+    fn register_agent_for() {
+        const ONBOARDER: &str = "cosmos1m5cdspw6p5xqwsu3xgqtm8ppvattyl0fhskao2";
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.onboarders = vec![Addr::unchecked(ONBOARDER)];
+        store.config.save(&mut deps.storage, &config).unwrap();
+
+        // Only the target's wallet counts for the gas check
+        let err = store
+            .register_agent_for(
+                deps.as_mut(),
+                mock_info(ONBOARDER, &[]),
+                mock_env(),
+                AGENT1.to_string(),
+                None,
+            )
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::CustomError {
+                val: "Insufficient funds".to_string()
+            }
+        );
+        deps.querier
+            .update_balance(AGENT1, coins(100, NATIVE_DENOM));
+
+        let err = store
+            .register_agent_for(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                AGENT1.to_string(),
+                None,
+            )
+            .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+
+        let res = store
+            .register_agent_for(
+                deps.as_mut(),
+                mock_info(ONBOARDER, &[]),
+                mock_env(),
+                AGENT1.to_string(),
+                Some(AGENT_BENEFICIARY.to_string()),
+            )
+            .unwrap();
+        assert!(res
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "onboarder" && attr.value == ONBOARDER));
+        let agent = store
+            .agents
+            .load(&deps.storage, &Addr::unchecked(AGENT1))
+            .unwrap();
+        assert_eq!(agent.payable_account_id, Addr::unchecked(AGENT_BENEFICIARY));
+        assert!(!store.agents.has(&deps.storage, &Addr::unchecked(ONBOARDER)));
+    }
+
+    #[test]
+    fn auto_compound_rewards_into_bond() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        // As if task deposits paid for the rewards
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.available_balance.native = coins(100, NATIVE_DENOM);
+        store.config.save(&mut deps.storage, &config).unwrap();
+        for agent in [AGENT0, AGENT1] {
+            deps.querier.update_balance(agent, coins(100, NATIVE_DENOM));
+            store
+                .register_agent(
+                    deps.as_mut(),
+                    mock_info(agent, &[]),
+                    mock_env(),
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap();
+        }
+        let res = store
+            .toggle_auto_compound(deps.as_mut(), mock_info(AGENT1, &[]))
+            .unwrap();
+        assert!(res
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "auto_compound" && attr.value == "true"));
+
+        let agent_fee = store.config.load(&deps.storage).unwrap().agent_fee;
+        for agent_id in [AGENT0, AGENT1] {
+            let agent_id = Addr::unchecked(agent_id);
+            for _ in 0..2 {
+                let agent = store.agents.load(&deps.storage, &agent_id).unwrap();
+                store
+                    .send_base_agent_reward(&mut deps.storage, &mock_env(), agent, &agent_id)
+                    .unwrap();
+            }
+        }
+        let two_rewards = vec![coin(agent_fee.amount.u128() * 2, NATIVE_DENOM)];
+
+        // Non-compounding agent can withdraw its rewards
+        let agent = store
+            .agents
+            .load(&deps.storage, &Addr::unchecked(AGENT0))
+            .unwrap();
+        assert_eq!(agent.balance.native, two_rewards);
+        assert!(agent.bond.is_empty());
+
+        // Compounding agent's bond grows instead
+        let agent = store
+            .agents
+            .load(&deps.storage, &Addr::unchecked(AGENT1))
+            .unwrap();
+        assert!(agent.balance.native.is_empty());
+        assert_eq!(agent.bond, two_rewards);
+        let res = store
+            .withdraw_agent_balance(
+                deps.as_mut(),
+                mock_info(AGENT1, &[]),
+                mock_env(),
+                None,
+                None,
+            )
+            .unwrap();
+        assert!(res.messages.is_empty());
+
+        // Bond is paid out on unregister
+        let res = store
+            .unregister_agent(deps.as_mut(), mock_info(AGENT1, &[]), mock_env())
+            .unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: AGENT1.to_string(),
+                amount: two_rewards,
+            })]
+        );
+    }
+
+    #[test]
+    fn nomination_suspended_during_low_task_volume() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        // AGENT0 is active, AGENT1 is pending
+        for agent in [AGENT0, AGENT1] {
+            deps.querier.update_balance(agent, coins(100, NATIVE_DENOM));
+            store
+                .register_agent(
+                    deps.as_mut(),
+                    mock_info(agent, &[]),
+                    mock_env(),
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap();
+        }
+
+        // Enough tasks for another agent, but only one of them due now
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.min_tasks_per_agent = 1;
+        config.min_tasks_for_nomination = 2;
+        store.config.save(&mut deps.storage, &config).unwrap();
+        store.task_total.save(&mut deps.storage, &2).unwrap();
+        store
+            .agent_nomination_begin_time
+            .save(&mut deps.storage, &Some(mock_env().block.time))
+            .unwrap();
+        let height = mock_env().block.height;
+        store
+            .block_slots
+            .save(&mut deps.storage, height, &vec![b"task1".to_vec()])
+            .unwrap();
+
+        fn nomination(store: &mut CwCroncat, deps: Deps) -> (AgentStatus, bool) {
+            let res = store
+                .query(
+                    deps,
+                    mock_env(),
+                    QueryMsg::GetAgent {
+                        account_id: AGENT1.to_string(),
+                    },
+                )
+                .unwrap();
+            let agent: Option<AgentResponse> = from_binary(&res).unwrap();
+            let res = store
+                .query(deps, mock_env(), QueryMsg::GetNominationConfig {})
+                .unwrap();
+            let nomination_config: NominationConfigResponse = from_binary(&res).unwrap();
+            (
+                agent.unwrap().status,
+                nomination_config.nomination_suspended,
+            )
+        }
+
+        // Low volume keeps AGENT1 pending
+        assert_eq!(
+            (AgentStatus::Pending, true),
+            nomination(&mut store, deps.as_ref())
+        );
+        let err = store
+            .accept_nomination_agent(deps.as_mut(), mock_info(AGENT1, &[]), mock_env())
+            .unwrap_err();
+        assert_eq!(
+            ContractError::CustomError {
+                val: "Nomination is suspended while few tasks are due".to_string()
+            },
+            err
+        );
+
+        // A second due task lets AGENT1 in
+        store
+            .block_slots
+            .save(
+                &mut deps.storage,
+                height,
+                &vec![b"task1".to_vec(), b"task2".to_vec()],
+            )
+            .unwrap();
+        assert_eq!(
+            (AgentStatus::Nominated, false),
+            nomination(&mut store, deps.as_ref())
+        );
+        store
+            .accept_nomination_agent(deps.as_mut(), mock_info(AGENT1, &[]), mock_env())
+            .unwrap();
+        let active = store.agent_active_queue.load(&deps.storage).unwrap();
+        assert_eq!(
+            active,
+            vec![Addr::unchecked(AGENT0), Addr::unchecked(AGENT1)]
+        );
+    }
+
+    #[test]
+    fn query_agent_reward_denom() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.cw20_whitelist = vec![Addr::unchecked("cw20")];
+        store.config.save(&mut deps.storage, &config).unwrap();
+        for agent in [AGENT0, AGENT1] {
+            deps.querier.update_balance(agent, coins(100, NATIVE_DENOM));
+            store
+                .register_agent(
+                    deps.as_mut(),
+                    mock_info(agent, &[]),
+                    mock_env(),
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap();
+        }
+        store
+            .update_reward_preference(
+                deps.as_mut(),
+                mock_info(AGENT1, &[]),
+                RewardPreference::Cw20(Addr::unchecked("cw20")),
+            )
+            .unwrap();
+
+        // No dex router, the cw20 preference gets the native denom too
+        for agent in [AGENT0, AGENT1] {
+            let res = store
+                .query(
+                    deps.as_ref(),
+                    mock_env(),
+                    QueryMsg::GetAgentRewardDenom {
+                        account_id: agent.to_string(),
+                    },
+                )
+                .unwrap();
+            let denom: String = from_binary(&res).unwrap();
+            assert_eq!(NATIVE_DENOM, denom);
+        }
+
+        // With a router the cw20 preference gets swapped into at withdrawal
+        config.dex_router = Some(Addr::unchecked("dex"));
+        store.config.save(&mut deps.storage, &config).unwrap();
+        for (agent, expected) in [(AGENT0, NATIVE_DENOM), (AGENT1, "cw20")] {
+            let res = store
+                .query(
+                    deps.as_ref(),
+                    mock_env(),
+                    QueryMsg::GetAgentRewardDenom {
+                        account_id: agent.to_string(),
+                    },
+                )
+                .unwrap();
+            let denom: String = from_binary(&res).unwrap();
+            assert_eq!(expected, denom);
+        }
+
+        let err = store
+            .query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::GetAgentRewardDenom {
+                    account_id: AGENT2.to_string(),
+                },
+            )
+            .unwrap_err();
+        assert_eq!(
+            StdError::generic_err(AgentNotRegistered {}.to_string()),
+            err
+        );
+    }
+
+    #[test]
+    fn activation_cooldown() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        for agent in [AGENT0, AGENT1] {
+            deps.querier.update_balance(agent, coins(100, NATIVE_DENOM));
+            store
+                .register_agent(
+                    deps.as_mut(),
+                    mock_info(agent, &[]),
+                    mock_env(),
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap();
+        }
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.min_tasks_per_agent = 1;
+        config.activation_cooldown_nanos = 100;
+        store.config.save(&mut deps.storage, &config).unwrap();
+        store.task_total.save(&mut deps.storage, &2).unwrap();
+        store
+            .agent_nomination_begin_time
+            .save(&mut deps.storage, &Some(mock_env().block.time))
+            .unwrap();
+
+        store
+            .accept_nomination_agent(deps.as_mut(), mock_info(AGENT1, &[]), mock_env())
+            .unwrap();
+        let agent = store
+            .agents
+            .load(&deps.storage, &Addr::unchecked(AGENT1))
+            .unwrap();
+        assert_eq!(Some(mock_env().block.time), agent.last_activation);
+
+        // Too soon
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_nanos(99);
+        let err = store
+            .unregister_agent(deps.as_mut(), mock_info(AGENT1, &[]), env.clone())
+            .unwrap_err();
+        assert_eq!(
+            ContractError::CustomError {
+                val: "Agent can't step down during the activation cooldown".to_string()
+            },
+            err
+        );
+        // Pausing would step down all the same
+        let err = store
+            .toggle_agent_pause(deps.as_mut(), mock_info(AGENT1, &[]), env.clone())
+            .unwrap_err();
+        assert_eq!(
+            ContractError::CustomError {
+                val: "Agent can't step down during the activation cooldown".to_string()
+            },
+            err
+        );
+
+        // Cooldown is over
+        env.block.time = env.block.time.plus_nanos(1);
+        store
+            .unregister_agent(deps.as_mut(), mock_info(AGENT1, &[]), env)
+            .unwrap();
+        let active = store.agent_active_queue.load(&deps.storage).unwrap();
+        assert_eq!(active, vec![Addr::unchecked(AGENT0)]);
+    }
+
+    #[test]
+    fn agent_pool_splits_rewards() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        // As if task deposits paid for the rewards
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.available_balance.native = coins(100, NATIVE_DENOM);
+        store.config.save(&mut deps.storage, &config).unwrap();
+        for agent in [AGENT0, AGENT1, AGENT2] {
+            deps.querier.update_balance(agent, coins(100, NATIVE_DENOM));
+            store
+                .register_agent(
+                    deps.as_mut(),
+                    mock_info(agent, &[]),
+                    mock_env(),
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap();
+        }
+        let pool_id = "pool".to_string();
+        store
+            .create_agent_pool(deps.as_mut(), mock_info(AGENT0, &[]), pool_id.clone())
+            .unwrap();
+        let err = store
+            .create_agent_pool(deps.as_mut(), mock_info(AGENT2, &[]), pool_id.clone())
+            .unwrap_err();
+        assert_eq!(
+            ContractError::CustomError {
+                val: "Pool already exists".to_string()
+            },
+            err
+        );
+        store
+            .join_agent_pool(deps.as_mut(), mock_info(AGENT1, &[]), pool_id.clone())
+            .unwrap();
+        // Not a member until the creator lets it in
+        assert_eq!(
+            store.agent_pools.load(&deps.storage, &pool_id).unwrap(),
+            AgentPool {
+                creator: Addr::unchecked(AGENT0),
+                members: vec![(Addr::unchecked(AGENT0), 1)],
+                join_requests: vec![Addr::unchecked(AGENT1)],
+            }
+        );
+        let agent = store
+            .agents
+            .load(&deps.storage, &Addr::unchecked(AGENT1))
+            .unwrap();
+        assert_eq!(agent.pool_id, None);
+        let err = store
+            .approve_agent_pool_member(
+                deps.as_mut(),
+                mock_info(AGENT2, &[]),
+                pool_id.clone(),
+                AGENT1.to_string(),
+                1,
+            )
+            .unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err);
+        let err = store
+            .approve_agent_pool_member(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                pool_id.clone(),
+                AGENT2.to_string(),
+                1,
+            )
+            .unwrap_err();
+        assert_eq!(
+            ContractError::CustomError {
+                val: "Agent didn't ask to join".to_string()
+            },
+            err
+        );
+        store
+            .approve_agent_pool_member(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                pool_id.clone(),
+                AGENT1.to_string(),
+                1,
+            )
+            .unwrap();
+        assert_eq!(
+            store.agent_pools.load(&deps.storage, &pool_id).unwrap(),
+            AgentPool {
+                creator: Addr::unchecked(AGENT0),
+                members: vec![(Addr::unchecked(AGENT0), 1), (Addr::unchecked(AGENT1), 1)],
+                join_requests: vec![],
+            }
+        );
+
+        // Only AGENT0 executes, the agent fee of 5 goes to the pool each time
+        let agent0 = Addr::unchecked(AGENT0);
+        for _ in 0..2 {
+            let agent = store.agents.load(&deps.storage, &agent0).unwrap();
+            store
+                .send_base_agent_reward(&mut deps.storage, &mock_env(), agent, &agent0)
+                .unwrap();
+        }
+        let agent = store.agents.load(&deps.storage, &agent0).unwrap();
+        assert!(agent.balance.native.is_empty());
+        assert_eq!(
+            store
+                .agent_pool_balances
+                .load(&deps.storage, &pool_id)
+                .unwrap(),
+            coins(10, NATIVE_DENOM)
+        );
+
+        // Withdrawing splits the pool 50/50
+        let res = store
+            .withdraw_agent_balance(
+                deps.as_mut(),
+                mock_info(AGENT1, &[]),
+                mock_env(),
+                None,
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: AGENT1.to_string(),
+                amount: coins(5, NATIVE_DENOM),
+            })]
+        );
+        let agent = store.agents.load(&deps.storage, &agent0).unwrap();
+        assert_eq!(agent.balance.native, coins(5, NATIVE_DENOM));
+        assert!(store
+            .agent_pool_balances
+            .load(&deps.storage, &pool_id)
+            .unwrap()
+            .is_empty());
+
+        // At 1 to 3 the next 10 splits 2 and 7, the odd one stays in the pool
+        store
+            .approve_agent_pool_member(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                pool_id.clone(),
+                AGENT1.to_string(),
+                3,
+            )
+            .unwrap();
+        for _ in 0..2 {
+            let agent = store.agents.load(&deps.storage, &agent0).unwrap();
+            store
+                .send_base_agent_reward(&mut deps.storage, &mock_env(), agent, &agent0)
+                .unwrap();
+        }
+        let res = store
+            .withdraw_agent_balance(
+                deps.as_mut(),
+                mock_info(AGENT1, &[]),
+                mock_env(),
+                None,
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: AGENT1.to_string(),
+                amount: coins(7, NATIVE_DENOM),
+            })]
+        );
+        let agent = store.agents.load(&deps.storage, &agent0).unwrap();
+        assert_eq!(agent.balance.native, coins(7, NATIVE_DENOM));
+        assert_eq!(
+            store
+                .agent_pool_balances
+                .load(&deps.storage, &pool_id)
+                .unwrap(),
+            coins(1, NATIVE_DENOM)
+        );
+
+        // The creator leaving hands the pool over, the last member leaving closes it
+        store
+            .leave_agent_pool(deps.as_mut(), mock_info(AGENT0, &[]))
+            .unwrap();
+        let pool = store.agent_pools.load(&deps.storage, &pool_id).unwrap();
+        assert_eq!(pool.creator, Addr::unchecked(AGENT1));
+        store
+            .leave_agent_pool(deps.as_mut(), mock_info(AGENT1, &[]))
+            .unwrap();
+        for agent in [AGENT0, AGENT1] {
+            let agent = store
+                .agents
+                .load(&deps.storage, &Addr::unchecked(agent))
+                .unwrap();
+            assert_eq!(agent.pool_id, None);
+        }
+        assert!(!store.agent_pools.has(&deps.storage, &pool_id));
+        // Settled alone, the last member got the odd one
+        let agent = store
+            .agents
+            .load(&deps.storage, &Addr::unchecked(AGENT1))
+            .unwrap();
+        assert_eq!(agent.balance.native, coins(1, NATIVE_DENOM));
+
+        // Anything still pooled when it closes isn't lost with it
+        store
+            .create_agent_pool(deps.as_mut(), mock_info(AGENT2, &[]), pool_id.clone())
+            .unwrap();
+        store
+            .agent_pool_balances
+            .save(&mut deps.storage, &pool_id, &coins(3, NATIVE_DENOM))
+            .unwrap();
+        let config_before = store.config.load(&deps.storage).unwrap();
+        store
+            .remove_pool_member(&mut deps.storage, &Addr::unchecked(AGENT2), &pool_id)
+            .unwrap();
+        assert!(!store.agent_pool_balances.has(&deps.storage, &pool_id));
+        let config = store.config.load(&deps.storage).unwrap();
+        let mut expected_total = config_before.total_agent_balance.clone();
+        expected_total
+            .checked_sub_native(&coins(3, NATIVE_DENOM))
+            .unwrap();
+        assert_eq!(config.total_agent_balance, expected_total);
+        let mut expected_revenue = config_before.protocol_revenue;
+        expected_revenue
+            .checked_add_native(&coins(3, NATIVE_DENOM))
+            .unwrap();
+        assert_eq!(config.protocol_revenue, expected_revenue);
+    }
+
+    #[test]
+    fn query_oldest_pending_agent() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        fn oldest(store: &mut CwCroncat, deps: Deps) -> Option<(Addr, u64)> {
+            let res = store
+                .query(deps, mock_env(), QueryMsg::GetOldestPendingAgent {})
+                .unwrap();
+            from_binary(&res).unwrap()
+        }
+        assert_eq!(None, oldest(&mut store, deps.as_ref()));
+
+        // AGENT0 becomes active, the rest are pending in this order
+        for (agent, seconds) in [(AGENT0, 0), (AGENT1, 10), (AGENT2, 5), (AGENT3, 20)] {
+            let mut env = mock_env();
+            env.block.time = env.block.time.plus_seconds(seconds);
+            deps.querier.update_balance(agent, coins(100, NATIVE_DENOM));
+            store
+                .register_agent(
+                    deps.as_mut(),
+                    mock_info(agent, &[]),
+                    env,
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap();
+        }
+        assert_eq!(
+            Some((
+                Addr::unchecked(AGENT2),
+                mock_env().block.time.plus_seconds(5).nanos()
+            )),
+            oldest(&mut store, deps.as_ref())
+        );
+    }
+
+    #[test]
+    fn unregister_queue_removal_mode() {
+        for (mode, remaining) in [
+            (
+                QueueRemovalMode::PreserveOrder,
+                vec![AGENT0, AGENT2, AGENT3],
+            ),
+            // Last active agent takes over the freed spot
+            (QueueRemovalMode::SwapRemove, vec![AGENT0, AGENT3, AGENT2]),
+        ] {
+            let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+            let store = CwCroncat::default();
+            mock_init(&store, deps.as_mut()).unwrap();
+            let mut config = store.config.load(&deps.storage).unwrap();
+            config.queue_removal_mode = mode;
+            store.config.save(&mut deps.storage, &config).unwrap();
+            for agent in [AGENT0, AGENT1, AGENT2, AGENT3] {
+                deps.querier.update_balance(agent, coins(100, NATIVE_DENOM));
+                store
+                    .register_agent(
+                        deps.as_mut(),
+                        mock_info(agent, &[]),
+                        mock_env(),
+                        None,
+                        None,
+                        None,
+                        None,
+                    )
+                    .unwrap();
+            }
+            let active: Vec<Addr> = [AGENT0, AGENT1, AGENT2, AGENT3]
+                .iter()
+                .map(|agent| Addr::unchecked(*agent))
+                .collect();
+            store
+                .agent_active_queue
+                .save(&mut deps.storage, &active)
+                .unwrap();
+            store
+                .agent_pending_queue
+                .save(&mut deps.storage, &vec![])
+                .unwrap();
+
+            store
+                .unregister_agent(deps.as_mut(), mock_info(AGENT1, &[]), mock_env())
+                .unwrap();
+            let remaining: Vec<Addr> = remaining.into_iter().map(Addr::unchecked).collect();
+            assert_eq!(
+                store.agent_active_queue.load(&deps.storage).unwrap(),
+                remaining
+            );
+        }
+    }
+
+    #[test]
+    fn agent_tasks_filtered_by_task_type() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        deps.querier
+            .update_balance(AGENT0, coins(100, NATIVE_DENOM));
+        store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+
+        // One due block slot per type, two swap tasks share theirs
+        let height = mock_env().block.height;
+        let mut slots: Vec<Vec<Vec<u8>>> = vec![vec![]; 3];
+        for (index, (offset, task_type)) in [
+            (0, Some("swap")),
+            (0, Some("swap")),
+            (1, Some("compound")),
+            (2, None),
+        ]
+        .iter()
+        .copied()
+        .enumerate()
+        {
+            let task = Task {
+                // Tasks hash by owner, not type
+                owner_id: Addr::unchecked(format!("owner{}", index)),
+                interval: Interval::Once,
+                boundary: BoundaryValidated {
+                    start: None,
+                    end: None,
+                },
+                funds_withdrawn_recurring: Uint128::zero(),
+                stop_on_fail: false,
+                total_deposit: GenericBalance::default(),
+                amount_for_one_task: GenericBalance::default(),
+                actions: vec![],
+                rules: None,
+                task_type: task_type.map(String::from),
+            };
+            let hash = task.to_hash_vec();
+            store.tasks.save(&mut deps.storage, &hash, &task).unwrap();
+            slots[offset].push(hash);
+        }
+        for (offset, task_hashes) in slots.iter().enumerate() {
+            store
+                .block_slots
+                .save(&mut deps.storage, height - offset as u64, task_hashes)
+                .unwrap();
+        }
+
+        // Tasks are counted, not slots
+        for (task_types, block_tasks) in [
+            (vec![], Some(4)),
+            (vec!["swap"], Some(2)),
+            (vec!["swap", "compound"], Some(3)),
+            // Nothing due it accepts
+            (vec!["ibc"], None),
+        ] {
+            store
+                .set_accepted_task_types(
+                    deps.as_mut(),
+                    mock_info(AGENT0, &[]),
+                    task_types.into_iter().map(String::from).collect(),
+                )
+                .unwrap();
+            let tasks = store
+                .query_get_agent_tasks(deps.as_ref(), mock_env(), AGENT0.to_string())
+                .unwrap();
+            assert_eq!(tasks.map(|tasks| tasks.num_block_tasks.u64()), block_tasks);
+        }
+    }
+
+    #[test]
+    fn missed_nominations() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.max_missed_nominations = 2;
+        store.config.save(&mut deps.storage, &config).unwrap();
+        // AGENT0 is active, the rest pending
+        for agent in [AGENT0, AGENT1, AGENT2] {
+            deps.querier.update_balance(agent, coins(100, NATIVE_DENOM));
+            store
+                .register_agent(
+                    deps.as_mut(),
+                    mock_info(agent, &[]),
+                    mock_env(),
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap();
+        }
+        // Long enough for the second pending agent to get nominated too
+        let nominate_two = |storage: &mut dyn Storage| {
+            store
+                .agent_nomination_begin_time
+                .save(
+                    storage,
+                    &Some(
+                        mock_env()
+                            .block
+                            .time
+                            .minus_seconds(config.agent_nomination_duration as u64),
+                    ),
+                )
+                .unwrap();
+        };
+
+        // AGENT1 let its nomination expire, it goes to the back
+        nominate_two(&mut deps.storage);
+        store
+            .accept_nomination_agent(deps.as_mut(), mock_info(AGENT2, &[]), mock_env())
+            .unwrap();
+        deps.querier
+            .update_balance(AGENT3, coins(100, NATIVE_DENOM));
+        store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT3, &[]),
+                mock_env(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            store.agent_pending_queue.load(&deps.storage).unwrap(),
+            vec![Addr::unchecked(AGENT1), Addr::unchecked(AGENT3)]
+        );
+        let agent = store
+            .agents
+            .load(&deps.storage, &Addr::unchecked(AGENT1))
+            .unwrap();
+        assert_eq!(agent.missed_nominations, 1);
+
+        // Second miss reaches the limit
+        nominate_two(&mut deps.storage);
+        let res = store
+            .accept_nomination_agent(deps.as_mut(), mock_info(AGENT3, &[]), mock_env())
+            .unwrap();
+        assert!(res
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "unregistered_agents" && attr.value == AGENT1));
+        assert!(store
+            .agent_pending_queue
+            .load(&deps.storage)
+            .unwrap()
+            .is_empty());
+        assert!(!store.agents.has(&deps.storage, &Addr::unchecked(AGENT1)));
+        assert_eq!(
+            store.agent_active_queue.load(&deps.storage).unwrap(),
+            vec![
+                Addr::unchecked(AGENT0),
+                Addr::unchecked(AGENT2),
+                Addr::unchecked(AGENT3)
+            ]
+        );
+    }
+
+    #[test]
+    fn missed_nominations_pay_out_funded_agents() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.max_missed_nominations = 1;
+        config.available_balance.native = coins(100, NATIVE_DENOM);
+        config.total_agent_balance.native = coins(20, NATIVE_DENOM);
+        store.config.save(&mut deps.storage, &config).unwrap();
+        // AGENT0 is active, the rest pending
+        for agent in [AGENT0, AGENT1, AGENT2] {
+            deps.querier.update_balance(agent, coins(100, NATIVE_DENOM));
+            store
+                .register_agent(
+                    deps.as_mut(),
+                    mock_info(agent, &[]),
+                    mock_env(),
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap();
+        }
+        let agent1 = Addr::unchecked(AGENT1);
+        let mut agent = store.agents.load(&deps.storage, &agent1).unwrap();
+        agent.balance.native = coins(5, NATIVE_DENOM);
+        agent.bond = coins(10, NATIVE_DENOM);
+        store
+            .agents
+            .save(&mut deps.storage, &agent1, &agent)
+            .unwrap();
+        store
+            .agent_nomination_begin_time
+            .save(
+                &mut deps.storage,
+                &Some(
+                    mock_env()
+                        .block
+                        .time
+                        .minus_seconds(config.agent_nomination_duration as u64),
+                ),
+            )
+            .unwrap();
+
+        // Over the limit, balance and bond are paid out before unregistering
+        let res = store
+            .accept_nomination_agent(deps.as_mut(), mock_info(AGENT2, &[]), mock_env())
+            .unwrap();
+        assert!(res
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "unregistered_agents" && attr.value == AGENT1));
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: AGENT1.to_string(),
+                amount: coins(15, NATIVE_DENOM),
+            })]
+        );
+        assert!(store
+            .agent_pending_queue
+            .load(&deps.storage)
+            .unwrap()
+            .is_empty());
+        assert!(!store.agents.has(&deps.storage, &agent1));
+        let config = store.config.load(&deps.storage).unwrap();
+        assert_eq!(config.available_balance.native, coins(85, NATIVE_DENOM));
+        assert_eq!(config.total_agent_balance.native, coins(5, NATIVE_DENOM));
+    }
+
+    #[test]
+    fn stale_nomination() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        let config = store.config.load(&deps.storage).unwrap();
+        // AGENT0 is active, the rest pending
+        for agent in [AGENT0, AGENT1, AGENT2, AGENT3] {
+            deps.querier.update_balance(agent, coins(100, NATIVE_DENOM));
+            store
+                .register_agent(
+                    deps.as_mut(),
+                    mock_info(agent, &[]),
+                    mock_env(),
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap();
+        }
+        let nominated_for = |storage: &mut dyn Storage, durations: u64| {
+            store
+                .agent_nomination_begin_time
+                .save(
+                    storage,
+                    &Some(
+                        mock_env()
+                            .block
+                            .time
+                            .minus_seconds(config.agent_nomination_duration as u64 * durations),
+                    ),
+                )
+                .unwrap();
         };
-        let cw_template_contract_addr = app
-            .instantiate_contract(cw_template_id, owner_addr, &msg, &[], "Manager", None)
+
+        // AGENT1's nomination ran out a duration ago, it goes to the back instead
+        nominated_for(&mut deps.storage, 1);
+        let res = store
+            .accept_nomination_agent(deps.as_mut(), mock_info(AGENT1, &[]), mock_env())
+            .unwrap();
+        assert!(res
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "nomination_expired" && attr.value == "true"));
+        assert_eq!(
+            store.agent_pending_queue.load(&deps.storage).unwrap(),
+            vec![
+                Addr::unchecked(AGENT2),
+                Addr::unchecked(AGENT3),
+                Addr::unchecked(AGENT1)
+            ]
+        );
+        assert_eq!(
+            store.agent_active_queue.load(&deps.storage).unwrap(),
+            vec![Addr::unchecked(AGENT0)]
+        );
+        let agent = store
+            .agents
+            .load(&deps.storage, &Addr::unchecked(AGENT1))
             .unwrap();
+        assert_eq!(agent.missed_nominations, 1);
+        assert_eq!(agent.last_activation, None);
 
-        let cw_template_contract = CwTemplateContract(cw_template_contract_addr);
+        // AGENT3 is second now, still within its own nomination
+        let res = store
+            .accept_nomination_agent(deps.as_mut(), mock_info(AGENT3, &[]), mock_env())
+            .unwrap();
+        assert!(res
+            .attributes
+            .iter()
+            .all(|attr| attr.key != "nomination_expired"));
+        assert_eq!(
+            store.agent_active_queue.load(&deps.storage).unwrap(),
+            vec![Addr::unchecked(AGENT0), Addr::unchecked(AGENT3)]
+        );
 
-        (app, cw_template_contract)
+        // Past `max_missed_nominations` a late accept unregisters, paying out the balance
+        let mut limited_config = store.config.load(&deps.storage).unwrap();
+        limited_config.max_missed_nominations = 1;
+        limited_config.available_balance.native = coins(10, NATIVE_DENOM);
+        limited_config.total_agent_balance.native = coins(10, NATIVE_DENOM);
+        store
+            .config
+            .save(&mut deps.storage, &limited_config)
+            .unwrap();
+        // AGENT2 missed its turn when AGENT3 accepted, so AGENT1 is first again
+        assert_eq!(
+            store.agent_pending_queue.load(&deps.storage).unwrap(),
+            vec![Addr::unchecked(AGENT1), Addr::unchecked(AGENT2)]
+        );
+        let late_agent = Addr::unchecked(AGENT1);
+        let mut agent = store.agents.load(&deps.storage, &late_agent).unwrap();
+        agent.balance.native = coins(10, NATIVE_DENOM);
+        store
+            .agents
+            .save(&mut deps.storage, &late_agent, &agent)
+            .unwrap();
+        nominated_for(&mut deps.storage, 1);
+        let res = store
+            .accept_nomination_agent(
+                deps.as_mut(),
+                mock_info(late_agent.as_str(), &[]),
+                mock_env(),
+            )
+            .unwrap();
+        assert!(res
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "unregistered_agents" && attr.value == late_agent));
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: late_agent.to_string(),
+                amount: coins(10, NATIVE_DENOM),
+            })]
+        );
+        assert_eq!(
+            store.agent_pending_queue.load(&deps.storage).unwrap(),
+            vec![Addr::unchecked(AGENT2)]
+        );
+        assert!(store
+            .agents
+            .may_load(&deps.storage, &late_agent)
+            .unwrap()
+            .is_none());
     }
 
-    fn get_task_total(app: &App, contract_addr: &Addr) -> usize {
-        let res: Vec<TaskResponse> = app
-            .wrap()
-            .query_wasm_smart(
-                contract_addr,
-                &QueryMsg::GetTasks {
-                    from_index: None,
-                    limit: None,
-                },
+    #[test]
+    fn query_net_reward_estimate() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        deps.querier
+            .update_balance(AGENT0, coins(100, NATIVE_DENOM));
+        store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                None,
+                None,
+                None,
+                None,
             )
             .unwrap();
-        res.len()
+
+        // 400_000 gas at a price of 1 is paid back, the agent keeps the fee of 5
+        let estimate = store
+            .query_net_reward_estimate(deps.as_ref(), mock_env(), AGENT0.to_string())
+            .unwrap();
+        assert_eq!(
+            estimate,
+            NetRewardEstimateResponse {
+                gross_reward: coin(5, NATIVE_DENOM),
+                gas_reimbursement: coin(400_000, NATIVE_DENOM),
+                estimated_gas_cost: coin(400_000, NATIVE_DENOM),
+                net: coin(5, NATIVE_DENOM),
+                profitable: true,
+            }
+        );
+
+        // At a price of 2 the fee doesn't cover the other half of the gas
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.gas_price = Decimal::from_ratio(2u128, 1u128);
+        store.config.save(&mut deps.storage, &config).unwrap();
+        let estimate = store
+            .query_net_reward_estimate(deps.as_ref(), mock_env(), AGENT0.to_string())
+            .unwrap();
+        assert_eq!(estimate.estimated_gas_cost, coin(800_000, NATIVE_DENOM));
+        assert_eq!(estimate.net, coin(0, NATIVE_DENOM));
+        assert!(!estimate.profitable);
+
+        config.agent_fee = coin(500_000, NATIVE_DENOM);
+        store.config.save(&mut deps.storage, &config).unwrap();
+        let estimate = store
+            .query_net_reward_estimate(deps.as_ref(), mock_env(), AGENT0.to_string())
+            .unwrap();
+        assert_eq!(estimate.net, coin(100_000, NATIVE_DENOM));
+        assert!(estimate.profitable);
+
+        // Fee and gas in different denoms don't net out
+        config.agent_fee = coin(5, "meow");
+        store.config.save(&mut deps.storage, &config).unwrap();
+        let err = store
+            .query_net_reward_estimate(deps.as_ref(), mock_env(), AGENT0.to_string())
+            .unwrap_err();
+        assert_eq!(
+            err,
+            StdError::generic_err(
+                "Agent fee isn't in the native denom, net reward can't be estimated"
+            )
+        );
     }
 
-    fn add_task_exec(app: &mut App, contract_addr: &Addr, sender: &str) -> AppResponse {
-        let validator = String::from("you");
-        let amount = coin(3, NATIVE_DENOM);
-        let stake = StakingMsg::Delegate { validator, amount };
-        let msg: CosmosMsg = stake.clone().into();
-        let send_funds = coins(500_000, NATIVE_DENOM);
-        app.execute_contract(
-            Addr::unchecked(sender),
-            contract_addr.clone(),
-            &ExecuteMsg::CreateTask {
-                task: TaskRequest {
-                    interval: Interval::Immediate,
-                    boundary: None,
-                    stop_on_fail: false,
-                    actions: vec![Action {
-                        msg,
-                        gas_limit: Some(150_000),
-                    }],
-                    rules: None,
-                    cw20_coins: vec![],
+    #[test]
+    fn update_agent_full() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        deps.querier
+            .update_balance(AGENT0, coins(100, NATIVE_DENOM));
+        store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                None,
+                Some(vec!["ibc-relay".to_string()]),
+                None,
+                None,
+            )
+            .unwrap();
+        let before = store
+            .agents
+            .load(&deps.storage, &Addr::unchecked(AGENT0))
+            .unwrap();
+
+        let res = store
+            .update_agent_full(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                UpdateAgentParams {
+                    payable_account_id: Some(AGENT_BENEFICIARY.to_string()),
+                    operators: Some(vec![AGENT1.to_string()]),
+                    auto_compound: Some(true),
+                    ..Default::default()
                 },
-            },
-            send_funds.as_ref(),
-        )
-        .expect("Error adding task")
-    }
+            )
+            .unwrap();
+        assert!(res.attributes.iter().any(|attr| attr.key == "updated"
+            && attr.value == "payable_account_id,operators,auto_compound"));
+        // Each handler's attributes are kept, with a single method
+        for (key, value) in [
+            ("method", "update_agent_full"),
+            ("payable_account_id", AGENT_BENEFICIARY),
+            ("operators", AGENT1),
+            ("auto_compound", "true"),
+        ] {
+            assert_eq!(
+                res.attributes
+                    .iter()
+                    .filter(|attr| attr.key == key)
+                    .map(|attr| attr.value.as_str())
+                    .collect::<Vec<_>>(),
+                vec![value]
+            );
+        }
 
-    fn add_block_task_exec(
-        app: &mut App,
-        contract_addr: &Addr,
-        sender: &str,
-        block_num: u64,
-    ) -> AppResponse {
-        let validator = String::from("you");
-        let amount = coin(3, NATIVE_DENOM);
-        let stake = StakingMsg::Delegate { validator, amount };
-        let msg: CosmosMsg = stake.clone().into();
-        let send_funds = coins(500_000, NATIVE_DENOM);
-        app.execute_contract(
-            Addr::unchecked(sender),
-            contract_addr.clone(),
-            &ExecuteMsg::CreateTask {
-                task: TaskRequest {
-                    interval: Interval::Block(block_num),
-                    boundary: None,
-                    stop_on_fail: false,
-                    actions: vec![Action {
-                        msg,
-                        gas_limit: Some(150_000),
-                    }],
-                    rules: None,
-                    cw20_coins: vec![],
+        let after = store
+            .agents
+            .load(&deps.storage, &Addr::unchecked(AGENT0))
+            .unwrap();
+        assert_eq!(
+            after,
+            Agent {
+                payable_account_id: Addr::unchecked(AGENT_BENEFICIARY),
+                operators: vec![Addr::unchecked(AGENT1)],
+                auto_compound: true,
+                ..before
+            }
+        );
+        assert_eq!(
+            store
+                .agent_operators
+                .load(&deps.storage, &Addr::unchecked(AGENT1))
+                .unwrap(),
+            Addr::unchecked(AGENT0)
+        );
+
+        // Each field is validated like its own handler
+        let err = store
+            .update_agent_full(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                UpdateAgentParams {
+                    reward_preference: Some(RewardPreference::Cw20(Addr::unchecked("cw20"))),
+                    ..Default::default()
                 },
-            },
-            send_funds.as_ref(),
-        )
-        .expect("Error adding task")
+            )
+            .unwrap_err();
+        assert_eq!(err, ContractError::NotInWhitelist {});
+
+        let err = store
+            .update_agent_full(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                UpdateAgentParams::default(),
+            )
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::CustomError {
+                val: "No agent fields to update".to_string()
+            }
+        );
+    }
+
+    // AGENT0 active, AGENT1 pending and slashed of its 10 meow reward
+    fn slashed_pending_agent() -> (
+        OwnedDeps<MockStorage, MockApi, MockQuerier>,
+        CwCroncat<'static>,
+    ) {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        for agent in [AGENT0, AGENT1] {
+            deps.querier.update_balance(agent, coins(100, NATIVE_DENOM));
+            store
+                .register_agent(
+                    deps.as_mut(),
+                    mock_info(agent, &[]),
+                    mock_env(),
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap();
+        }
+        let mut agent = store
+            .agents
+            .load(&deps.storage, &Addr::unchecked(AGENT1))
+            .unwrap();
+        agent.balance.native = coins(10, "meow");
+        store
+            .agents
+            .save(&mut deps.storage, &Addr::unchecked(AGENT1), &agent)
+            .unwrap();
+        make_slashable(&store, &mut deps.storage, AGENT1);
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.total_agent_balance.native = coins(10, "meow");
+        store.config.save(&mut deps.storage, &config).unwrap();
+
+        store
+            .slash_agent(
+                deps.as_mut(),
+                mock_info("creator", &[]),
+                mock_env(),
+                AGENT1.to_string(),
+            )
+            .unwrap();
+        assert_eq!(
+            store
+                .slash_history
+                .load(&deps.storage, &Addr::unchecked(AGENT1))
+                .unwrap(),
+            vec![(
+                mock_env().block.time.nanos(),
+                coin(10, "meow"),
+                SlashDestination::Treasury
+            )]
+        );
+        (deps, store)
+    }
+
+    #[test]
+    fn reinstate_agent_without_refund() {
+        let (mut deps, store) = slashed_pending_agent();
+        let err = store
+            .reinstate_agent(
+                deps.as_mut(),
+                mock_info(AGENT1, &[]),
+                AGENT1.to_string(),
+                false,
+            )
+            .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        let err = store
+            .reinstate_agent(
+                deps.as_mut(),
+                mock_info("creator", &[]),
+                AGENT0.to_string(),
+                false,
+            )
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::CustomError {
+                val: "Agent has not been slashed".to_string()
+            }
+        );
+
+        store
+            .reinstate_agent(
+                deps.as_mut(),
+                mock_info("creator", &[]),
+                AGENT1.to_string(),
+                false,
+            )
+            .unwrap();
+        let agent = store
+            .agents
+            .load(&deps.storage, &Addr::unchecked(AGENT1))
+            .unwrap();
+        assert_eq!(agent.balance, GenericBalance::default());
+        assert_eq!(agent.consecutive_misses, 0);
+        assert_eq!(
+            store.agent_active_queue.load(&deps.storage).unwrap(),
+            vec![Addr::unchecked(AGENT0), Addr::unchecked(AGENT1)]
+        );
+        assert!(store
+            .agent_pending_queue
+            .load(&deps.storage)
+            .unwrap()
+            .is_empty());
+        assert!(!store
+            .slash_history
+            .has(&deps.storage, &Addr::unchecked(AGENT1)));
+        let config = store.config.load(&deps.storage).unwrap();
+        assert_eq!(config.available_balance.native, coins(1000, "meow"));
+        assert_eq!(config.protocol_revenue.native, coins(10, "meow"));
+    }
+
+    #[test]
+    fn reinstate_agent_with_full_active_queue() {
+        let (mut deps, store) = slashed_pending_agent();
+        deps.querier
+            .update_balance(AGENT2, coins(100, NATIVE_DENOM));
+        store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT2, &[]),
+                mock_env(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        store
+            .agent_pending_queue
+            .save(
+                &mut deps.storage,
+                &vec![Addr::unchecked(AGENT2), Addr::unchecked(AGENT1)],
+            )
+            .unwrap();
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.max_agents = Some(1);
+        store.config.save(&mut deps.storage, &config).unwrap();
+
+        // No room among the active agents, it goes to the front of pending instead
+        let res = store
+            .reinstate_agent(
+                deps.as_mut(),
+                mock_info("creator", &[]),
+                AGENT1.to_string(),
+                false,
+            )
+            .unwrap();
+        assert!(res
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "status" && attr.value == "Pending"));
+        assert_eq!(
+            store.agent_active_queue.load(&deps.storage).unwrap(),
+            vec![Addr::unchecked(AGENT0)]
+        );
+        assert_eq!(
+            store.agent_pending_queue.load(&deps.storage).unwrap(),
+            vec![Addr::unchecked(AGENT1), Addr::unchecked(AGENT2)]
+        );
+        assert!(!store
+            .slash_history
+            .has(&deps.storage, &Addr::unchecked(AGENT1)));
+    }
+
+    #[test]
+    fn reinstate_agent_with_refund() {
+        let (mut deps, store) = slashed_pending_agent();
+        let res = store
+            .reinstate_agent(
+                deps.as_mut(),
+                mock_info("creator", &[]),
+                AGENT1.to_string(),
+                true,
+            )
+            .unwrap();
+        assert!(res.attributes.iter().any(|attr| attr.key == "refunded"));
+
+        let agent = store
+            .agents
+            .load(&deps.storage, &Addr::unchecked(AGENT1))
+            .unwrap();
+        assert_eq!(agent.balance.native, coins(10, "meow"));
+        assert_eq!(
+            store.agent_active_queue.load(&deps.storage).unwrap(),
+            vec![Addr::unchecked(AGENT0), Addr::unchecked(AGENT1)]
+        );
+        // The refund only moves from revenue back to the agents
+        let config = store.config.load(&deps.storage).unwrap();
+        assert_eq!(config.available_balance.native, coins(1000, "meow"));
+        assert_eq!(config.total_agent_balance.native, coins(10, "meow"));
+        assert!(config.protocol_revenue.native.is_empty());
+
+        // The history is cleared, nothing is refunded twice
+        let err = store
+            .reinstate_agent(
+                deps.as_mut(),
+                mock_info("creator", &[]),
+                AGENT1.to_string(),
+                true,
+            )
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::CustomError {
+                val: "Agent has not been slashed".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn reinstate_agent_refunds_only_treasury_slashes() {
+        let (mut deps, store) = slashed_pending_agent();
+        // Burned instead, those funds are gone
+        store
+            .slash_history
+            .save(
+                &mut deps.storage,
+                &Addr::unchecked(AGENT1),
+                &vec![(
+                    mock_env().block.time.nanos(),
+                    coin(10, "meow"),
+                    SlashDestination::Burn,
+                )],
+            )
+            .unwrap();
+        let err = store
+            .reinstate_agent(
+                deps.as_mut(),
+                mock_info("creator", &[]),
+                AGENT1.to_string(),
+                true,
+            )
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::CustomError {
+                val: "Only funds slashed to the treasury can be refunded".to_string()
+            }
+        );
+
+        // Reinstating without a refund still works
+        store
+            .reinstate_agent(
+                deps.as_mut(),
+                mock_info("creator", &[]),
+                AGENT1.to_string(),
+                false,
+            )
+            .unwrap();
+        let agent = store
+            .agents
+            .load(&deps.storage, &Addr::unchecked(AGENT1))
+            .unwrap();
+        assert_eq!(agent.balance, GenericBalance::default());
     }
 
-    fn add_cron_task_exec(
-        app: &mut App,
-        contract_addr: &Addr,
-        sender: &str,
-        num_minutes: u64,
-    ) -> AppResponse {
-        let validator = String::from("you");
-        let amount = coin(3, NATIVE_DENOM);
-        let stake = StakingMsg::Delegate { validator, amount };
-        let msg: CosmosMsg = stake.clone().into();
-        let send_funds = coins(500_000, NATIVE_DENOM);
-        app.execute_contract(
-            Addr::unchecked(sender),
-            contract_addr.clone(),
-            &ExecuteMsg::CreateTask {
-                task: TaskRequest {
-                    interval: Interval::Cron(format!("* {} * * * *", num_minutes)),
+    #[test]
+    fn exclusivity_window() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        for agent in [AGENT0, AGENT1] {
+            deps.querier.update_balance(agent, coins(100, NATIVE_DENOM));
+            store
+                .register_agent(
+                    deps.as_mut(),
+                    mock_info(agent, &[]),
+                    mock_env(),
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap();
+        }
+        store
+            .agent_active_queue
+            .save(
+                &mut deps.storage,
+                &vec![Addr::unchecked(AGENT0), Addr::unchecked(AGENT1)],
+            )
+            .unwrap();
+        store
+            .agent_pending_queue
+            .save(&mut deps.storage, &vec![])
+            .unwrap();
+
+        // A single due block slot, assigned to AGENT0
+        let height = mock_env().block.height;
+        store
+            .block_slots
+            .save(&mut deps.storage, height, &vec![b"task".to_vec()])
+            .unwrap();
+        let block_tasks = |store: &mut CwCroncat<'static>, deps: Deps, agent: &str, offset: u64| {
+            let mut env = mock_env();
+            env.block.height = height + offset;
+            store
+                .query_get_agent_tasks(deps, env, agent.to_string())
+                .unwrap()
+                .unwrap()
+                .num_block_tasks
+                .u64()
+        };
+
+        // Without a window the slot stays assigned
+        assert_eq!(block_tasks(&mut store, deps.as_ref(), AGENT0, 5), 1);
+        assert_eq!(block_tasks(&mut store, deps.as_ref(), AGENT1, 5), 0);
+
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.exclusivity_window_slots = 2;
+        store.config.save(&mut deps.storage, &config).unwrap();
+        for offset in [0, 1] {
+            assert_eq!(block_tasks(&mut store, deps.as_ref(), AGENT0, offset), 1);
+            assert_eq!(block_tasks(&mut store, deps.as_ref(), AGENT1, offset), 0);
+        }
+        // Unexecuted past the window, anyone may execute it
+        for offset in [2, 3] {
+            assert_eq!(block_tasks(&mut store, deps.as_ref(), AGENT0, offset), 1);
+            assert_eq!(block_tasks(&mut store, deps.as_ref(), AGENT1, offset), 1);
+        }
+
+        // proxy_call lets the agent without the slot execute a real task past the window
+        store.block_slots.remove(&mut deps.storage, height);
+        store
+            .create_task(
+                deps.as_mut(),
+                mock_info(PARTICIPANT0, &coins(1_000_000, NATIVE_DENOM)),
+                mock_env(),
+                TaskRequest {
+                    interval: Interval::Immediate,
                     boundary: None,
                     stop_on_fail: false,
                     actions: vec![Action {
-                        msg,
-                        gas_limit: Some(150_000),
+                        msg: BankMsg::Send {
+                            to_address: PARTICIPANT1.to_string(),
+                            amount: coins(1, NATIVE_DENOM),
+                        }
+                        .into(),
+                        gas_limit: None,
                     }],
                     rules: None,
                     cw20_coins: vec![],
+                    task_type: None,
                 },
-            },
-            send_funds.as_ref(),
-        )
-        .expect("Error adding task")
+            )
+            .unwrap();
+        let (slot, _) = store
+            .block_slots
+            .range(&deps.storage, None, None, Order::Ascending)
+            .next()
+            .unwrap()
+            .unwrap();
+        let offset = slot - height;
+        let idle_agent = if block_tasks(&mut store, deps.as_ref(), AGENT0, offset) == 0 {
+            AGENT0
+        } else {
+            AGENT1
+        };
+        assert_eq!(
+            block_tasks(&mut store, deps.as_ref(), idle_agent, offset),
+            0
+        );
+        let proxy_call = |store: &mut CwCroncat<'static>, deps: DepsMut, offset: u64| {
+            let mut env = mock_env();
+            env.block.height = height + offset;
+            store.proxy_call(deps, mock_info(idle_agent, &[]), env)
+        };
+        let res = proxy_call(&mut store, deps.as_mut(), offset + 2).unwrap();
+        assert!(res
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "agent" && attr.value == idle_agent));
+        assert!(store
+            .block_slots
+            .may_load(&deps.storage, slot)
+            .unwrap()
+            .is_none());
     }
 
-    fn contract_create_task(
-        contract: &CwCroncat,
-        deps: DepsMut,
-        info: &MessageInfo,
-    ) -> Result<Response, ContractError> {
-        // try adding task without app
-        let validator = String::from("you");
-        let amount = coin(3, NATIVE_DENOM);
-        let stake = StakingMsg::Delegate { validator, amount };
-        let msg: CosmosMsg = stake.clone().into();
-        // let send_funds = coins(1, NATIVE_DENOM);
+    #[test]
+    fn query_idle_agents() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.idle_grace_nanos = 100;
+        store.config.save(&mut deps.storage, &config).unwrap();
+        for agent in [AGENT0, AGENT1, AGENT2] {
+            deps.querier.update_balance(agent, coins(100, NATIVE_DENOM));
+            store
+                .register_agent(
+                    deps.as_mut(),
+                    mock_info(agent, &[]),
+                    mock_env(),
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap();
+        }
+        let active = vec![
+            Addr::unchecked(AGENT0),
+            Addr::unchecked(AGENT1),
+            Addr::unchecked(AGENT2),
+        ];
+        store
+            .agent_active_queue
+            .save(&mut deps.storage, &active)
+            .unwrap();
+        let mut agent = store
+            .agents
+            .load(&deps.storage, &Addr::unchecked(AGENT1))
+            .unwrap();
+        agent.total_tasks_executed = 3;
+        store
+            .agents
+            .save(&mut deps.storage, &Addr::unchecked(AGENT1), &agent)
+            .unwrap();
 
-        contract.create_task(
-            deps,
-            info.clone(),
-            mock_env(),
-            TaskRequest {
-                interval: Interval::Immediate,
-                boundary: None,
-                stop_on_fail: false,
-                actions: vec![Action {
-                    msg: msg.clone(),
-                    gas_limit: Some(150_000),
-                }],
-                rules: None,
-                cw20_coins: vec![],
-            },
-        )
+        let idle_agents = |nanos: u64, from_index: Option<u64>, limit: Option<u64>| {
+            let mut env = mock_env();
+            env.block.time = env.block.time.plus_nanos(nanos);
+            store
+                .query_idle_agents(deps.as_ref(), env, from_index, limit)
+                .unwrap()
+        };
+        // Still in their grace period
+        assert!(idle_agents(99, None, None).is_empty());
+        assert_eq!(
+            idle_agents(100, None, None),
+            vec![Addr::unchecked(AGENT0), Addr::unchecked(AGENT2)]
+        );
+        assert!(idle_agents(100, Some(1), Some(1)).is_empty());
+        assert_eq!(
+            idle_agents(100, Some(1), None),
+            vec![Addr::unchecked(AGENT2)]
+        );
     }
 
-    fn contract_register_agent(
-        sender: &str,
-        contract: &mut CwCroncat,
-        deps: DepsMut,
-    ) -> Result<Response, ContractError> {
-        contract.execute(
-            deps,
-            mock_env(),
-            MessageInfo {
-                sender: Addr::unchecked(sender),
-                funds: vec![],
-            },
-            ExecuteMsg::RegisterAgent {
-                payable_account_id: Some(AGENT_BENEFICIARY.to_string()),
-            },
-        )
-    }
+    #[test]
+    fn withdraw_swaps_through_dex_router() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.cw20_whitelist = vec![Addr::unchecked("cw20")];
+        config.dex_max_slippage = Decimal::percent(3);
+        store.config.save(&mut deps.storage, &config).unwrap();
+        deps.querier
+            .update_balance(AGENT0, coins(100, NATIVE_DENOM));
+        store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                Some(AGENT_BENEFICIARY.to_string()),
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        store
+            .update_reward_preference(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                RewardPreference::Cw20(Addr::unchecked("cw20")),
+            )
+            .unwrap();
+        let credit = |deps: DepsMut| {
+            let mut agent = store
+                .agents
+                .load(deps.storage, &Addr::unchecked(AGENT0))
+                .unwrap();
+            agent.balance.native = vec![coin(10, NATIVE_DENOM), coin(5, "meow")];
+            store
+                .agents
+                .save(deps.storage, &Addr::unchecked(AGENT0), &agent)
+                .unwrap();
+            let mut config = store.config.load(deps.storage).unwrap();
+            config.available_balance.native = vec![coin(1000, "meow"), coin(10, NATIVE_DENOM)];
+            config.total_agent_balance.native = agent.balance.native;
+            store.config.save(deps.storage, &config).unwrap();
+        };
 
-    fn get_stored_agent_status(app: &mut App, contract_addr: &Addr, agent: &str) -> AgentStatus {
-        let agent_info: AgentResponse = app
-            .wrap()
-            .query_wasm_smart(
-                &contract_addr.clone(),
-                &QueryMsg::GetAgent {
-                    account_id: agent.to_string(),
-                },
+        // No router, the reward denom is paid out as is
+        credit(deps.as_mut());
+        let res = store
+            .withdraw_agent_balance(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                None,
+                None,
             )
-            .expect("Error getting agent status");
-        agent_info.status
-    }
+            .unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: AGENT_BENEFICIARY.to_string(),
+                amount: vec![coin(10, NATIVE_DENOM), coin(5, "meow")],
+            })]
+        );
 
-    fn register_agent_exec(
-        app: &mut App,
-        contract_addr: &Addr,
-        agent: &str,
-        beneficiary: &str,
-    ) -> AppResponse {
-        app.execute_contract(
-            Addr::unchecked(agent),
-            contract_addr.clone(),
-            &ExecuteMsg::RegisterAgent {
-                payable_account_id: Some(beneficiary.to_string()),
-            },
-            &[],
-        )
-        .expect("Error registering agent")
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.dex_router = Some(Addr::unchecked("dex"));
+        store.config.save(&mut deps.storage, &config).unwrap();
+        credit(deps.as_mut());
+        let res = store
+            .withdraw_agent_balance(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                None,
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            res.messages,
+            vec![
+                SubMsg::new(WasmMsg::Execute {
+                    contract_addr: "dex".to_string(),
+                    msg: to_binary(&DexRouterExecuteMsg::Swap {
+                        ask_token: "cw20".to_string(),
+                        max_slippage: Decimal::percent(3),
+                        recipient: AGENT_BENEFICIARY.to_string(),
+                    })
+                    .unwrap(),
+                    funds: coins(10, NATIVE_DENOM),
+                }),
+                SubMsg::new(BankMsg::Send {
+                    to_address: AGENT_BENEFICIARY.to_string(),
+                    amount: coins(5, "meow"),
+                }),
+            ]
+        );
+        let config = store.config.load(&deps.storage).unwrap();
+        assert_eq!(config.available_balance.native, coins(995, "meow"));
+        assert!(config.total_agent_balance.native.is_empty());
     }
 
-    fn check_in_exec(
-        app: &mut App,
-        contract_addr: &Addr,
-        agent: &str,
-    ) -> Result<AppResponse, anyhow::Error> {
-        app.execute_contract(
-            Addr::unchecked(agent),
-            contract_addr.clone(),
-            &ExecuteMsg::CheckInAgent {},
-            &[],
-        )
+    #[test]
+    fn cw20_only_agent_reconciles() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        deps.querier
+            .update_balance(AGENT0, coins(100, NATIVE_DENOM));
+        store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        let reward = GenericBalance {
+            native: vec![],
+            cw20: vec![Cw20CoinVerified {
+                address: Addr::unchecked("cw20"),
+                amount: Uint128::new(7),
+            }],
+        };
+        // Paid out of a cw20 task deposit
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.allowed_reward_cw20 = vec![Addr::unchecked("cw20")];
+        config
+            .available_balance
+            .checked_add_cw20(&reward.cw20)
+            .unwrap();
+        store.config.save(&mut deps.storage, &config).unwrap();
+        store
+            .batch_credit_agents(
+                &mut deps.storage,
+                mock_env().block.time,
+                vec![(Addr::unchecked(AGENT0), reward.clone())],
+            )
+            .unwrap();
+
+        let drift = store.query_agent_balance_drift(deps.as_ref()).unwrap();
+        assert_eq!(
+            drift,
+            AgentBalanceDriftResponse {
+                tracked: reward.clone(),
+                computed: reward,
+                surplus: GenericBalance::default(),
+                deficit: GenericBalance::default(),
+            }
+        );
+
+        // The cw20 reward leaves available_balance along with the agent balance
+        store
+            .withdraw_agent_balance(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                None,
+                None,
+            )
+            .unwrap();
+        let config = store.config.load(&deps.storage).unwrap();
+        assert!(config.available_balance.cw20.is_empty());
+        let drift = store.query_agent_balance_drift(deps.as_ref()).unwrap();
+        assert_eq!(drift.computed, GenericBalance::default());
+        assert_eq!(drift.surplus, GenericBalance::default());
+        assert_eq!(drift.deficit, GenericBalance::default());
+
+        // Untracked cw20 shows up as a deficit
+        let mut agent = store
+            .agents
+            .load(&deps.storage, &Addr::unchecked(AGENT0))
+            .unwrap();
+        agent.balance.cw20 = vec![Cw20CoinVerified {
+            address: Addr::unchecked("cw20"),
+            amount: Uint128::new(2),
+        }];
+        store
+            .agents
+            .save(&mut deps.storage, &Addr::unchecked(AGENT0), &agent)
+            .unwrap();
+        let drift = store.query_agent_balance_drift(deps.as_ref()).unwrap();
+        assert_eq!(drift.deficit.cw20, agent.balance.cw20);
+        assert_eq!(drift.surplus, GenericBalance::default());
     }
 
-    fn get_agent_ids(app: &App, contract_addr: &Addr) -> (GetAgentIdsResponse, usize, usize) {
-        let res: GetAgentIdsResponse = app
-            .wrap()
-            .query_wasm_smart(contract_addr, &QueryMsg::GetAgentIds {})
+    #[test]
+    fn query_execution_distribution() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        for agent in [AGENT0, AGENT1, AGENT2] {
+            deps.querier.update_balance(agent, coins(100, NATIVE_DENOM));
+            store
+                .register_agent(
+                    deps.as_mut(),
+                    mock_info(agent, &[]),
+                    mock_env(),
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap();
+        }
+        let active = vec![
+            Addr::unchecked(AGENT0),
+            Addr::unchecked(AGENT1),
+            Addr::unchecked(AGENT2),
+        ];
+        store
+            .agent_active_queue
+            .save(&mut deps.storage, &active)
             .unwrap();
-        (res.clone(), res.active.len(), res.pending.len())
+
+        // Nothing executed yet
+        let distribution = store
+            .query_execution_distribution(deps.as_ref(), None, None)
+            .unwrap();
+        assert!(distribution
+            .iter()
+            .all(|(_, tasks, bps)| *tasks == 0 && *bps == 0));
+
+        for (agent_id, executed) in active.iter().zip([7, 2, 1]) {
+            let mut agent = store.agents.load(&deps.storage, agent_id).unwrap();
+            agent.total_tasks_executed = executed;
+            store
+                .agents
+                .save(&mut deps.storage, agent_id, &agent)
+                .unwrap();
+        }
+        let distribution = store
+            .query_execution_distribution(deps.as_ref(), None, None)
+            .unwrap();
+        assert_eq!(
+            distribution,
+            vec![
+                (Addr::unchecked(AGENT0), 7, 7000),
+                (Addr::unchecked(AGENT1), 2, 2000),
+                (Addr::unchecked(AGENT2), 1, 1000),
+            ]
+        );
+        // Pages keep the share out of the whole queue
+        let distribution = store
+            .query_execution_distribution(deps.as_ref(), Some(1), Some(1))
+            .unwrap();
+        assert_eq!(distribution, vec![(Addr::unchecked(AGENT1), 2, 2000)]);
     }
 
-    pub fn add_little_time(block: &mut BlockInfo) {
-        // block.time = block.time.plus_seconds(360);
-        block.time = block.time.plus_seconds(19);
-        block.height += 1;
+    /// Mock storage that logs every key read
+    #[derive(Default)]
+    struct CountingStorage {
+        inner: MockStorage,
+        reads: RefCell<Vec<Vec<u8>>>,
     }
 
-    pub fn add_one_duration_of_time(block: &mut BlockInfo) {
-        // block.time = block.time.plus_seconds(360);
-        block.time = block.time.plus_seconds(420);
-        block.height += 1;
+    impl CountingStorage {
+        fn reads_of(&self, key: &[u8]) -> usize {
+            self.reads
+                .borrow()
+                .iter()
+                .filter(|read| *read == key)
+                .count()
+        }
     }
 
-    #[test]
-    fn test_instantiate_sets_balance() {
-        let mut app = App::default();
-        let croncat_code = app.store_code(contract_template());
+    impl Storage for CountingStorage {
+        fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+            self.reads.borrow_mut().push(key.to_vec());
+            self.inner.get(key)
+        }
 
-        let sent_funds = coins(50, "ugrape");
+        fn range<'b>(
+            &'b self,
+            start: Option<&[u8]>,
+            end: Option<&[u8]>,
+            order: Order,
+        ) -> Box<dyn Iterator<Item = Record> + 'b> {
+            self.inner.range(start, end, order)
+        }
 
-        app.sudo(SudoMsg::Bank(BankSudo::Mint {
-            to_address: "grapestem".to_string(),
-            amount: sent_funds.clone(),
-        }))
-        .unwrap();
+        fn set(&mut self, key: &[u8], value: &[u8]) {
+            self.inner.set(key, value)
+        }
 
-        let croncat = app
-            .instantiate_contract(
-                croncat_code,
-                Addr::unchecked("grapestem"),
-                &InstantiateMsg {
-                    denom: "grape".to_string(),
-                    cw_rules_addr: "grapestem".to_string(),
-                    owner_id: None,
-                    gas_base_fee: None,
-                    agent_nomination_duration: None,
-                },
-                &sent_funds,
-                "cw croncat",
-                None,
-            )
-            .unwrap();
+        fn remove(&mut self, key: &[u8]) {
+            self.inner.remove(key)
+        }
+    }
 
-        let config: Config = from_slice(
-            &app.wrap()
-                .query_wasm_raw(&croncat, b"config")
-                .unwrap()
-                .unwrap(),
-        )
-        .unwrap();
-        assert_eq!(config.available_balance.native, sent_funds)
+    #[test]
+    fn accept_nomination_loads_queues_once() {
+        let mut deps = OwnedDeps {
+            storage: CountingStorage::default(),
+            api: MockApi::default(),
+            querier: MockQuerier::<Empty>::new(&[(MOCK_CONTRACT_ADDR, &coins(200, NATIVE_DENOM))]),
+            custom_query_type: PhantomData,
+        };
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        for agent in [AGENT0, AGENT1, AGENT2] {
+            deps.querier.update_balance(agent, coins(100, NATIVE_DENOM));
+            store
+                .register_agent(
+                    deps.as_mut(),
+                    mock_info(agent, &[]),
+                    mock_env(),
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap();
+        }
+        store
+            .agent_nomination_begin_time
+            .save(&mut deps.storage, &Some(mock_env().block.time))
+            .unwrap();
+
+        deps.storage.reads.borrow_mut().clear();
+        store
+            .accept_nomination_agent(deps.as_mut(), mock_info(AGENT1, &[]), mock_env())
+            .unwrap();
+        assert_eq!(deps.storage.reads_of(b"agent_active_queue"), 1);
+        assert_eq!(deps.storage.reads_of(b"agent_pending_queue"), 1);
+
+        // Same outcome as before
+        assert_eq!(
+            store.agent_active_queue.load(&deps.storage).unwrap(),
+            vec![Addr::unchecked(AGENT0), Addr::unchecked(AGENT1)]
+        );
+        assert_eq!(
+            store.agent_pending_queue.load(&deps.storage).unwrap(),
+            vec![Addr::unchecked(AGENT2)]
+        );
+        assert!(store
+            .agent_nomination_begin_time
+            .load(&deps.storage)
+            .unwrap()
+            .is_none());
     }
 
     #[test]
-    fn register_agent_fail_cases() {
-        let (mut app, cw_template_contract) = proper_instantiate();
-        let contract_addr = cw_template_contract.addr();
+    fn query_next_slot_for_agent() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        store
+            .agent_active_queue
+            .save(
+                &mut deps.storage,
+                &vec![
+                    Addr::unchecked(AGENT0),
+                    Addr::unchecked(AGENT1),
+                    Addr::unchecked(AGENT2),
+                ],
+            )
+            .unwrap();
+        store
+            .agent_pending_queue
+            .save(&mut deps.storage, &vec![Addr::unchecked(AGENT3)])
+            .unwrap();
 
-        // start first register
-        let msg = ExecuteMsg::RegisterAgent {
-            payable_account_id: Some(AGENT_BENEFICIARY.to_string()),
+        let next_slot = |store: &mut CwCroncat, deps: Deps, agent: &str, from_slot: u64| {
+            let res = store
+                .query(
+                    deps,
+                    mock_env(),
+                    QueryMsg::GetNextSlotForAgent {
+                        account_id: agent.to_string(),
+                        from_slot,
+                    },
+                )
+                .unwrap();
+            from_binary::<Option<u64>>(&res).unwrap()
         };
 
-        // Test funds fail register if sent
-        let rereg_err = app
-            .execute_contract(
-                Addr::unchecked(AGENT1),
-                contract_addr.clone(),
-                &msg,
-                &coins(37, "atom"),
+        // Without rotation the first agent leads every slot
+        for (agent, from_slot, expected) in [
+            (AGENT0, 0, Some(0)),
+            (AGENT0, 12346, Some(12346)),
+            (AGENT1, 0, None),
+            (AGENT2, 12345, None),
+            (AGENT3, 0, None),
+            (AGENT4, 0, None),
+        ] {
+            assert_eq!(
+                next_slot(&mut store, deps.as_ref(), agent, from_slot),
+                expected,
+                "{} from {}",
+                agent,
+                from_slot
+            );
+        }
+
+        // Rotating, slot `n` goes to the agent ranked `n % active agents`
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.rotate_queue_each_slot = true;
+        store.config.save(&mut deps.storage, &config).unwrap();
+        for (agent, from_slot, expected) in [
+            (AGENT0, 0, Some(0)),
+            (AGENT0, 1, Some(3)),
+            (AGENT1, 0, Some(1)),
+            (AGENT1, 1, Some(1)),
+            (AGENT1, 2, Some(4)),
+            (AGENT2, 12345, Some(12347)),
+            (AGENT2, 12346, Some(12347)),
+            (AGENT0, 12346, Some(12348)),
+            (AGENT3, 0, None),
+            (AGENT4, 0, None),
+        ] {
+            assert_eq!(
+                next_slot(&mut store, deps.as_ref(), agent, from_slot),
+                expected,
+                "{} from {}",
+                agent,
+                from_slot
+            );
+        }
+
+        // Weighted leaders depend on executions by the time of the slot
+        config.weighted_selection = true;
+        store.config.save(&mut deps.storage, &config).unwrap();
+        assert_eq!(next_slot(&mut store, deps.as_ref(), AGENT0, 0), None);
+    }
+
+    #[test]
+    fn slash_denom() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        deps.querier
+            .update_balance(AGENT1, coins(100, NATIVE_DENOM));
+        store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT1, &[]),
+                mock_env(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        let agent_id = Addr::unchecked(AGENT1);
+        let mut agent = store.agents.load(&deps.storage, &agent_id).unwrap();
+        agent.balance.native = vec![coin(4, NATIVE_DENOM), coin(10, "meow")];
+        agent.bond = coins(3, "meow");
+        store
+            .agents
+            .save(&mut deps.storage, &agent_id, &agent)
+            .unwrap();
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.total_agent_balance.native = vec![coin(4, NATIVE_DENOM), coin(13, "meow")];
+        config.slash_denom = Some("meow".to_string());
+        store.config.save(&mut deps.storage, &config).unwrap();
+        make_slashable(&store, &mut deps.storage, AGENT1);
+
+        let res = store
+            .slash_agent(
+                deps.as_mut(),
+                mock_info("creator", &[]),
+                mock_env(),
+                AGENT1.to_string(),
+            )
+            .unwrap();
+        assert!(res.messages.is_empty());
+        let agent = store.agents.load(&deps.storage, &agent_id).unwrap();
+        assert_eq!(agent.balance.native, coins(4, NATIVE_DENOM));
+        assert!(agent.bond.is_empty());
+        let config = store.config.load(&deps.storage).unwrap();
+        assert_eq!(config.total_agent_balance.native, coins(4, NATIVE_DENOM));
+        assert_eq!(config.protocol_revenue.native, coins(13, "meow"));
+
+        // Only the other denom is left
+        let err = store
+            .slash_agent(
+                deps.as_mut(),
+                mock_info("creator", &[]),
+                mock_env(),
+                AGENT1.to_string(),
             )
             .unwrap_err();
         assert_eq!(
+            err,
             ContractError::CustomError {
-                val: "Do not attach funds".to_string()
-            },
-            rereg_err.downcast().unwrap()
+                val: "Agent holds no meow to slash".to_string()
+            }
         );
+    }
 
-        // Test Can't register if contract is paused
-        let payload_1 = ExecuteMsg::UpdateSettings {
-            paused: Some(true),
-            owner_id: None,
-            // treasury_id: None,
-            agent_fee: None,
-            min_tasks_per_agent: None,
-            agents_eject_threshold: None,
-            gas_price: None,
-            proxy_callback_gas: None,
-            slot_granularity: None,
+    #[test]
+    fn slash_bps() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        deps.querier
+            .update_balance(AGENT1, coins(100, NATIVE_DENOM));
+        store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT1, &[]),
+                mock_env(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        let agent_id = Addr::unchecked(AGENT1);
+        let mut agent = store.agents.load(&deps.storage, &agent_id).unwrap();
+        agent.balance.native = coins(50, NATIVE_DENOM);
+        agent.bond = coins(1_000, NATIVE_DENOM);
+        store
+            .agents
+            .save(&mut deps.storage, &agent_id, &agent)
+            .unwrap();
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.total_agent_balance.native = coins(1_050, NATIVE_DENOM);
+        config.slash_bps = 2_500;
+        store.config.save(&mut deps.storage, &config).unwrap();
+        make_slashable(&store, &mut deps.storage, AGENT1);
+        let slash = |deps: DepsMut| {
+            store.slash_agent(
+                deps,
+                mock_info("creator", &[]),
+                mock_env(),
+                AGENT1.to_string(),
+            )
         };
 
-        app.execute_contract(
-            Addr::unchecked(ADMIN),
-            contract_addr.clone(),
-            &payload_1,
-            &[],
-        )
-        .unwrap();
-        let rereg_err = app
-            .execute_contract(Addr::unchecked(AGENT1), contract_addr.clone(), &msg, &[])
-            .unwrap_err();
+        // A quarter of the bond, the balance is left alone
+        slash(deps.as_mut()).unwrap();
+        let agent = store.agents.load(&deps.storage, &agent_id).unwrap();
+        assert_eq!(agent.balance.native, coins(50, NATIVE_DENOM));
+        assert_eq!(agent.bond, coins(750, NATIVE_DENOM));
+        let config = store.config.load(&deps.storage).unwrap();
+        assert_eq!(config.total_agent_balance.native, coins(800, NATIVE_DENOM));
+        assert_eq!(config.protocol_revenue.native, coins(250, NATIVE_DENOM));
+
+        // More than the bond takes what's left of it
+        let mut config = config;
+        config.slash_bps = 15_000;
+        store.config.save(&mut deps.storage, &config).unwrap();
+        slash(deps.as_mut()).unwrap();
+        let agent = store.agents.load(&deps.storage, &agent_id).unwrap();
+        assert_eq!(agent.balance.native, coins(50, NATIVE_DENOM));
+        assert!(agent.bond.is_empty());
+        let config = store.config.load(&deps.storage).unwrap();
+        assert_eq!(config.total_agent_balance.native, coins(50, NATIVE_DENOM));
+        assert_eq!(config.protocol_revenue.native, coins(1_000, NATIVE_DENOM));
+
+        let err = slash(deps.as_mut()).unwrap_err();
         assert_eq!(
-            ContractError::ContractPaused {
-                val: "Register agent paused".to_string()
-            },
-            rereg_err.downcast().unwrap()
+            err,
+            ContractError::CustomError {
+                val: "Agent has no bond to slash".to_string()
+            }
         );
+    }
 
-        // Test wallet rejected if doesnt have enough funds
-        let payload_2 = ExecuteMsg::UpdateSettings {
-            paused: Some(false),
-            owner_id: None,
-            // treasury_id: None,
-            agent_fee: None,
-            min_tasks_per_agent: None,
-            agents_eject_threshold: None,
-            gas_price: None,
-            proxy_callback_gas: None,
-            slot_granularity: None,
-        };
-
-        app.execute_contract(
-            Addr::unchecked(ADMIN),
-            contract_addr.clone(),
-            &payload_2,
-            &[],
-        )
-        .unwrap();
-        let rereg_err = app
-            .execute_contract(Addr::unchecked(AGENT0), contract_addr.clone(), &msg, &[])
-            .unwrap_err();
-        assert_eq!(
-            ContractError::CustomError {
-                val: "Insufficient funds".to_string()
+    fn position_hook_contract() -> Box<dyn Contract<Empty>> {
+        let contract = ContractWrapper::new(
+            |_: DepsMut, _: Env, _: MessageInfo, msg: AgentHookMsg| -> StdResult<Response> {
+                let AgentHookMsg::PendingPositionChanged {
+                    agent, position, ..
+                } = msg;
+                Ok(Response::new()
+                    .add_attribute("hook_agent", agent)
+                    .add_attribute("hook_position", position.to_string()))
             },
-            rereg_err.downcast().unwrap()
+            |_: DepsMut, _: Env, _: MessageInfo, _: Empty| -> StdResult<Response> {
+                Ok(Response::new())
+            },
+            |_: Deps, _: Env, _: Empty| -> StdResult<Binary> { to_binary(&()) },
         );
+        Box::new(contract)
     }
 
     #[test]
-    fn register_agent() {
+    fn position_hook_pinged_on_advance() {
         let (mut app, cw_template_contract) = proper_instantiate();
         let contract_addr = cw_template_contract.addr();
-        let blk_time = app.block_info().time;
-
-        // start first register
-        let msg = ExecuteMsg::RegisterAgent {
-            payable_account_id: Some(AGENT_BENEFICIARY.to_string()),
-        };
-        app.execute_contract(Addr::unchecked(AGENT1), contract_addr.clone(), &msg, &[])
+        let hook_id = app.store_code(position_hook_contract());
+        let hook_addr = app
+            .instantiate_contract(
+                hook_id,
+                Addr::unchecked(ADMIN),
+                &Empty {},
+                &[],
+                "Hook",
+                None,
+            )
             .unwrap();
-
-        // check state to see if worked
-        let (_, num_active_agents, num_pending_agents) = get_agent_ids(&app, &contract_addr);
-        assert_eq!(1, num_active_agents);
-        assert_eq!(0, num_pending_agents);
-
-        // message response matches expectations (same block, all the defaults)
-        let agent_info: AgentResponse = app
-            .wrap()
-            .query_wasm_smart(
-                &contract_addr.clone(),
-                &QueryMsg::GetAgent {
-                    account_id: AGENT1.to_string(),
+        for agent in [AGENT1, AGENT2, AGENT3, AGENT4] {
+            app.execute_contract(
+                Addr::unchecked(agent),
+                contract_addr.clone(),
+                &ExecuteMsg::RegisterAgent {
+                    payable_account_id: None,
+                    capabilities: None,
+                    accepted_terms: None,
+                    client_version: None,
                 },
+                &[],
             )
             .unwrap();
-        println!("agent_infoagent_info {:?}", agent_info);
-        assert_eq!(AgentStatus::Active, agent_info.status);
-        assert_eq!(
-            Addr::unchecked(AGENT_BENEFICIARY),
-            agent_info.payable_account_id
-        );
-        assert_eq!(GenericBalance::default(), agent_info.balance);
-        assert_eq!(0, agent_info.total_tasks_executed);
-        assert_eq!(0, agent_info.last_missed_slot);
-        assert_eq!(blk_time, agent_info.register_start);
+        }
+        for agent in [AGENT3, AGENT4] {
+            app.execute_contract(
+                Addr::unchecked(agent),
+                contract_addr.clone(),
+                &ExecuteMsg::SetPositionHook {
+                    hook: Some(hook_addr.to_string()),
+                },
+                &[],
+            )
+            .unwrap();
+        }
 
-        // test fail if try to re-register
-        let rereg_err = app
-            .execute_contract(Addr::unchecked(AGENT1), contract_addr.clone(), &msg, &[])
-            .unwrap_err();
+        // AGENT2 leaving moves everyone behind it up a spot
+        let res = app
+            .execute_contract(
+                Addr::unchecked(AGENT2),
+                contract_addr.clone(),
+                &ExecuteMsg::UnregisterAgent {},
+                &[],
+            )
+            .unwrap();
+        let pings: Vec<(String, String)> =
+            res.events
+                .iter()
+                .filter(|event| {
+                    event.attributes.iter().any(|attr| {
+                        attr.key == "_contract_addr" && attr.value == hook_addr.as_str()
+                    })
+                })
+                .filter_map(|event| {
+                    let value = |key: &str| {
+                        event
+                            .attributes
+                            .iter()
+                            .find(|attr| attr.key == key)
+                            .map(|attr| attr.value.clone())
+                    };
+                    Some((value("hook_agent")?, value("hook_position")?))
+                })
+                .collect();
         assert_eq!(
-            ContractError::CustomError {
-                val: "Agent already exists".to_string()
-            },
-            rereg_err.downcast().unwrap()
+            pings,
+            vec![
+                (AGENT3.to_string(), "0".to_string()),
+                (AGENT4.to_string(), "1".to_string())
+            ]
         );
-
-        // test another register, put into pending queue
-        let msg2 = ExecuteMsg::RegisterAgent {
-            payable_account_id: Some(AGENT_BENEFICIARY.to_string()),
-        };
-        app.execute_contract(Addr::unchecked(AGENT2), contract_addr.clone(), &msg2, &[])
-            .unwrap();
-
-        // check state to see if worked
-
-        let (_, num_active_agents, num_pending_agents) = get_agent_ids(&app, &contract_addr);
-        assert_eq!(1, num_active_agents);
-        assert_eq!(1, num_pending_agents);
     }
 
     #[test]
-    fn update_agent() {
-        let (mut app, cw_template_contract) = proper_instantiate();
-        let contract_addr = cw_template_contract.addr();
+    fn position_hook_step() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        let queue: Vec<Addr> = [AGENT1, AGENT2, AGENT3, AGENT4]
+            .iter()
+            .map(|agent| Addr::unchecked(*agent))
+            .collect();
+        for agent_id in queue.iter() {
+            let mut agent = new_agent(agent_id.clone(), mock_env().block.time, vec![]);
+            agent.position_hook = Some(Addr::unchecked("hook"));
+            store
+                .agents
+                .save(&mut deps.storage, agent_id, &agent)
+                .unwrap();
+        }
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.position_hook_step = 2;
 
-        // start first register
-        let msg1 = ExecuteMsg::RegisterAgent {
-            payable_account_id: Some(AGENT_BENEFICIARY.to_string()),
-        };
-        app.execute_contract(Addr::unchecked(AGENT1), contract_addr.clone(), &msg1, &[])
+        // Only AGENT3 moves into a lower pair of positions
+        let msgs = store
+            .position_hook_msgs(&deps.storage, &config, &queue, &queue[1..])
             .unwrap();
-
-        // Fails for non-existent agents
-        let msg = ExecuteMsg::UpdateAgent {
-            payable_account_id: AGENT0.to_string(),
-        };
-        let update_err = app
-            .execute_contract(Addr::unchecked(AGENT0), contract_addr.clone(), &msg, &[])
-            .unwrap_err();
         assert_eq!(
-            ContractError::AgentNotRegistered {},
-            update_err.downcast().unwrap()
+            msgs,
+            vec![SubMsg::reply_on_error(
+                WasmMsg::Execute {
+                    contract_addr: "hook".to_string(),
+                    msg: to_binary(&AgentHookMsg::PendingPositionChanged {
+                        agent: AGENT3.to_string(),
+                        previous_position: 2,
+                        position: 1,
+                    })
+                    .unwrap(),
+                    funds: vec![],
+                },
+                POSITION_HOOK_REPLY_ID,
+            )]
         );
+    }
 
-        app.execute_contract(Addr::unchecked(AGENT1), contract_addr.clone(), &msg, &[])
-            .unwrap();
+    #[test]
+    fn blacklisted_payable_account_rejected() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        deps.querier
+            .update_balance(AGENT0, coins(100, NATIVE_DENOM));
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.blacklist = vec![Addr::unchecked(AGENT_BENEFICIARY)];
+        store.config.save(&mut deps.storage, &config).unwrap();
+
+        let err = store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                Some(AGENT_BENEFICIARY.to_string()),
+                None,
+                None,
+                None,
+            )
+            .unwrap_err();
+        assert_eq!(err, ContractError::BlacklistedPayableAccount {});
 
-        // payable account was in fact updated
-        let agent_info: Agent = app
-            .wrap()
-            .query_wasm_smart(
-                &contract_addr.clone(),
-                &QueryMsg::GetAgent {
-                    account_id: AGENT1.to_string(),
-                },
+        store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                None,
+                None,
+                None,
+                None,
             )
             .unwrap();
-        assert_eq!(Addr::unchecked(AGENT0), agent_info.payable_account_id);
+        let err = store
+            .update_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                AGENT_BENEFICIARY.to_string(),
+                None,
+                None,
+            )
+            .unwrap_err();
+        assert_eq!(err, ContractError::BlacklistedPayableAccount {});
+        let agent = store
+            .agents
+            .load(&deps.storage, &Addr::unchecked(AGENT0))
+            .unwrap();
+        assert_eq!(agent.payable_account_id, Addr::unchecked(AGENT0));
     }
 
     #[test]
-    fn unregister_agent() {
-        let (mut app, cw_template_contract) = proper_instantiate();
-        let contract_addr = cw_template_contract.addr();
-
-        // start first register
-        let msg1 = ExecuteMsg::RegisterAgent {
-            payable_account_id: Some(AGENT_BENEFICIARY.to_string()),
-        };
-        app.execute_contract(Addr::unchecked(AGENT1), contract_addr.clone(), &msg1, &[])
+    fn query_missed_slot_stats() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.total_missed_slots = 12;
+        store.config.save(&mut deps.storage, &config).unwrap();
+
+        // Pending agents don't count
+        for (agent_id, consecutive_misses) in [(AGENT1, 0), (AGENT2, 3), (AGENT3, 5), (AGENT4, 9)] {
+            let mut agent = new_agent(Addr::unchecked(agent_id), mock_env().block.time, vec![]);
+            agent.consecutive_misses = consecutive_misses;
+            store
+                .agents
+                .save(&mut deps.storage, &Addr::unchecked(agent_id), &agent)
+                .unwrap();
+        }
+        store
+            .agent_active_queue
+            .save(
+                &mut deps.storage,
+                &vec![
+                    Addr::unchecked(AGENT1),
+                    Addr::unchecked(AGENT2),
+                    Addr::unchecked(AGENT3),
+                ],
+            )
+            .unwrap();
+        store
+            .agent_pending_queue
+            .save(&mut deps.storage, &vec![Addr::unchecked(AGENT4)])
             .unwrap();
 
-        // Fails for non-exist agents
-        let unreg_msg = ExecuteMsg::UnregisterAgent {};
-        let update_err = app
-            .execute_contract(
-                Addr::unchecked(AGENT0),
-                contract_addr.clone(),
-                &unreg_msg,
-                &[],
-            )
-            .unwrap_err();
+        let res = store
+            .query(deps.as_ref(), mock_env(), QueryMsg::GetMissedSlotStats {})
+            .unwrap();
+        let stats: MissedSlotStatsResponse = from_binary(&res).unwrap();
         assert_eq!(
-            ContractError::AgentNotRegistered {},
-            update_err.downcast().unwrap()
+            stats,
+            MissedSlotStatsResponse {
+                total_misses: 12,
+                agents_with_misses: 2,
+                worst_agent: Some((Addr::unchecked(AGENT3), 5)),
+            }
         );
+    }
 
-        // Get quick data about account before, to compare later
-        let agent_bal = app
-            .wrap()
-            .query_balance(&Addr::unchecked(AGENT1), NATIVE_DENOM)
+    #[test]
+    fn set_agent_reward_multiplier() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        deps.querier
+            .update_balance(AGENT0, coins(100, NATIVE_DENOM));
+        store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                None,
+                None,
+                None,
+                None,
+            )
             .unwrap();
-        assert_eq!(agent_bal, coin(2_000_000, NATIVE_DENOM));
-
-        // Attempt the unregister
-        app.execute_contract(
-            Addr::unchecked(AGENT1),
-            contract_addr.clone(),
-            &unreg_msg,
-            &[],
-        )
-        .unwrap();
 
-        // Agent should not exist now
-        let update_err = app
-            .execute_contract(
-                Addr::unchecked(AGENT1),
-                contract_addr.clone(),
-                &unreg_msg,
-                &[],
+        let err = store
+            .set_agent_reward_multiplier(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                AGENT0.to_string(),
+                15_000,
+            )
+            .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+
+        // Default cap is 2x
+        let err = store
+            .set_agent_reward_multiplier(
+                deps.as_mut(),
+                mock_info("creator", &[]),
+                AGENT0.to_string(),
+                20_001,
             )
             .unwrap_err();
         assert_eq!(
-            ContractError::AgentNotRegistered {},
-            update_err.downcast().unwrap()
+            err,
+            ContractError::CustomError {
+                val: "Reward multiplier can't be above 20000 bps".to_string()
+            }
         );
 
-        // Check that the agent was removed from the list of active or pending agents
-        let (_, num_active_agents, num_pending_agents) = get_agent_ids(&app, &contract_addr);
-        assert_eq!(0, num_active_agents);
-        assert_eq!(0, num_pending_agents);
-
-        // Agent should have appropriate balance change
-        // NOTE: Needs further checks when tasks can be performed
-        let agent_bal = app
-            .wrap()
-            .query_balance(&Addr::unchecked(AGENT1), NATIVE_DENOM)
+        store
+            .set_agent_reward_multiplier(
+                deps.as_mut(),
+                mock_info("creator", &[]),
+                AGENT0.to_string(),
+                15_000,
+            )
             .unwrap();
-        assert_eq!(agent_bal, coin(2000000, NATIVE_DENOM));
+        let agent = store
+            .agents
+            .load(&deps.storage, &Addr::unchecked(AGENT0))
+            .unwrap();
+        assert_eq!(agent.reward_multiplier_bps, 15_000);
     }
 
     #[test]
-    fn withdraw_agent_balance() {
-        let (mut app, cw_template_contract) = proper_instantiate();
-        let contract_addr = cw_template_contract.addr();
-
-        // start first register
-        let msg1 = ExecuteMsg::RegisterAgent {
-            payable_account_id: Some(AGENT_BENEFICIARY.to_string()),
-        };
-        app.execute_contract(Addr::unchecked(AGENT1), contract_addr.clone(), &msg1, &[])
+    fn withdraw_idempotency_key() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        deps.querier
+            .update_balance(AGENT0, coins(100, NATIVE_DENOM));
+        store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                None,
+                None,
+                None,
+                None,
+            )
             .unwrap();
-
-        // Fails for non-existent agents
-        let wthdrw_msg = ExecuteMsg::WithdrawReward {};
-        let update_err = app
-            .execute_contract(
-                Addr::unchecked(AGENT0),
-                contract_addr.clone(),
-                &wthdrw_msg,
-                &[],
+        let top_up = |storage: &mut dyn Storage| {
+            let agent_id = Addr::unchecked(AGENT0);
+            let mut agent = store.agents.load(storage, &agent_id).unwrap();
+            agent.balance.native = coins(10, NATIVE_DENOM);
+            store.agents.save(storage, &agent_id, &agent).unwrap();
+            let mut config = store.config.load(storage).unwrap();
+            config.available_balance.native = coins(100, NATIVE_DENOM);
+            config.total_agent_balance.native = coins(10, NATIVE_DENOM);
+            store.config.save(storage, &config).unwrap();
+        };
+        let withdraw = |deps: DepsMut, key: &str| {
+            store.withdraw_agent_balance(
+                deps,
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                Some(key.to_string()),
+                None,
             )
-            .unwrap_err();
+        };
+        let paid_out = vec![SubMsg::new(BankMsg::Send {
+            to_address: AGENT0.to_string(),
+            amount: coins(10, NATIVE_DENOM),
+        })];
+
+        let err = withdraw(deps.as_mut(), "").unwrap_err();
         assert_eq!(
-            ContractError::AgentNotRegistered {},
-            update_err.downcast().unwrap()
+            err,
+            ContractError::CustomError {
+                val: "Idempotency key must be 1 to 64 characters".to_string()
+            }
         );
 
-        // Get quick data about account before, to compare later
-        let agent_bal = app
-            .wrap()
-            .query_balance(&Addr::unchecked(AGENT1), NATIVE_DENOM)
+        top_up(&mut deps.storage);
+        let res = withdraw(deps.as_mut(), "k1").unwrap();
+        assert_eq!(res.messages, paid_out);
+
+        // Retrying with the same key doesn't pay out again
+        top_up(&mut deps.storage);
+        let res = withdraw(deps.as_mut(), "k1").unwrap();
+        assert!(res.messages.is_empty());
+        let replayed_from = mock_env().block.time.nanos().to_string();
+        assert!(res
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "replayed_from" && attr.value == replayed_from));
+        let agent = store
+            .agents
+            .load(&deps.storage, &Addr::unchecked(AGENT0))
             .unwrap();
-        assert_eq!(agent_bal, coin(2_000_000, NATIVE_DENOM));
+        assert_eq!(agent.balance.native, coins(10, NATIVE_DENOM));
 
-        // Attempt the withdraw
-        app.execute_contract(
-            Addr::unchecked(AGENT1),
-            contract_addr.clone(),
-            &wthdrw_msg,
-            &[],
-        )
-        .unwrap();
+        let res = withdraw(deps.as_mut(), "k2").unwrap();
+        assert_eq!(res.messages, paid_out);
 
-        // Agent should have appropriate balance change
-        // NOTE: Needs further checks when tasks can be performed
-        let agent_bal = app
-            .wrap()
-            .query_balance(&Addr::unchecked(AGENT1), NATIVE_DENOM)
-            .unwrap();
-        assert_eq!(agent_bal, coin(2_000_000, NATIVE_DENOM));
+        // Oldest keys get forgotten
+        for i in 0..MAX_IDEMPOTENCY_KEYS {
+            withdraw(deps.as_mut(), &format!("key{}", i)).unwrap();
+        }
+        top_up(&mut deps.storage);
+        let res = withdraw(deps.as_mut(), "k1").unwrap();
+        assert_eq!(res.messages, paid_out);
     }
 
     #[test]
-    fn accept_nomination_agent() {
-        let (mut app, cw_template_contract) = proper_instantiate();
-        let contract_addr = cw_template_contract.addr();
+    fn withdraw_memo() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        deps.querier
+            .update_balance(AGENT0, coins(100, NATIVE_DENOM));
+        store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
 
-        // Register AGENT1, who immediately becomes active
-        register_agent_exec(&mut app, &contract_addr, AGENT1, &AGENT_BENEFICIARY);
-        let res = add_task_exec(&mut app, &contract_addr, PARTICIPANT0);
-        let task_hash = res.events[1].attributes[4].clone().value;
+        let res = store
+            .withdraw_agent_balance(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                None,
+                Some("invoice 42".to_string()),
+            )
+            .unwrap();
+        assert!(res
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "memo" && attr.value == "invoice 42"));
+
+        // No memo, no attribute
+        let res = store
+            .withdraw_agent_balance(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                None,
+                None,
+            )
+            .unwrap();
+        assert!(res.attributes.iter().all(|attr| attr.key != "memo"));
+
+        let err = store
+            .withdraw_agent_balance(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                None,
+                Some("m".repeat(MAX_WITHDRAW_MEMO_LENGTH + 1)),
+            )
+            .unwrap_err();
         assert_eq!(
-            "7ea9a6d5ef5c78cb168afa96b43b5843b8f880627aa0580f4311403f907cbf93", task_hash,
-            "Unexpected task hash"
+            err,
+            ContractError::CustomError {
+                val: "Memo can't be longer than 256 characters".to_string()
+            }
         );
+    }
 
-        let msg_query_task = QueryMsg::GetTask { task_hash };
-        let query_task_res: StdResult<Option<TaskResponse>> = app
-            .wrap()
-            .query_wasm_smart(contract_addr.clone(), &msg_query_task);
-        assert!(
-            query_task_res.is_ok(),
-            "Did not successfully find the newly added task"
+    #[test]
+    fn refund_stray_funds() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        for agent in [AGENT0, AGENT1] {
+            deps.querier.update_balance(agent, coins(100, NATIVE_DENOM));
+            store
+                .register_agent(
+                    deps.as_mut(),
+                    mock_info(agent, &[]),
+                    mock_env(),
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap();
+        }
+        let stray = coins(5, NATIVE_DENOM);
+
+        // Off by default, attached funds fail the call
+        let err = store
+            .update_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &stray),
+                mock_env(),
+                AGENT_BENEFICIARY.to_string(),
+                None,
+                None,
+            )
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::CustomError {
+                val: "Do not attach funds".to_string()
+            }
         );
 
-        let mut num_tasks = get_task_total(&app, &contract_addr);
-        assert_eq!(num_tasks, 1);
-
-        // Now the task ratio is 1:2 (one agent per two tasks)
-        // No agent should be allowed to join or accept nomination
-        // Check that this fails
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.refund_stray_funds = true;
+        store.config.save(&mut deps.storage, &config).unwrap();
+        let refund = SubMsg::new(BankMsg::Send {
+            to_address: AGENT0.to_string(),
+            amount: stray.clone(),
+        });
+        let res = store
+            .update_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &stray),
+                mock_env(),
+                AGENT_BENEFICIARY.to_string(),
+                None,
+                None,
+            )
+            .unwrap();
+        assert_eq!(res.messages, vec![refund.clone()]);
 
-        // Register two agents
-        register_agent_exec(&mut app, &contract_addr, AGENT2, &AGENT_BENEFICIARY);
-        // Later, we'll have this agent try to nominate themselves before their time
-        register_agent_exec(&mut app, &contract_addr, AGENT3, &AGENT_BENEFICIARY);
+        store
+            .agent_nomination_begin_time
+            .save(&mut deps.storage, &Some(mock_env().block.time))
+            .unwrap();
+        let res = store
+            .accept_nomination_agent(deps.as_mut(), mock_info(AGENT1, &stray), mock_env())
+            .unwrap();
+        assert!(res.messages.contains(&SubMsg::new(BankMsg::Send {
+            to_address: AGENT1.to_string(),
+            amount: stray.clone(),
+        })));
+
+        // Updating several fields that each refund still sends the funds back once
+        let res = store
+            .update_agent_full(
+                deps.as_mut(),
+                mock_info(AGENT0, &stray),
+                mock_env(),
+                UpdateAgentParams {
+                    payable_account_id: Some(AGENT0.to_string()),
+                    capabilities: Some(vec![]),
+                    operators: Some(vec![AGENT2.to_string()]),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(res.messages, vec![refund.clone()]);
+        // Also when none of the updated fields go through `update_agent`
+        let res = store
+            .update_agent_full(
+                deps.as_mut(),
+                mock_info(AGENT0, &stray),
+                mock_env(),
+                UpdateAgentParams {
+                    operators: Some(vec![]),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        assert_eq!(res.messages, vec![refund]);
+    }
 
-        let (agent_ids_res, num_active_agents, _) = get_agent_ids(&app, &contract_addr);
-        assert_eq!(1, num_active_agents);
-        assert_eq!(2, agent_ids_res.pending.len());
+    #[test]
+    fn required_terms_hash() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        for agent in [AGENT0, AGENT1, AGENT2] {
+            deps.querier.update_balance(agent, coins(100, NATIVE_DENOM));
+        }
 
-        // Add three more tasks, so we can nominate another agent
-        add_task_exec(&mut app, &contract_addr, PARTICIPANT1);
-        add_task_exec(&mut app, &contract_addr, PARTICIPANT2);
-        add_task_exec(&mut app, &contract_addr, PARTICIPANT3);
+        // No terms required, registering without any works
+        store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
 
-        num_tasks = get_task_total(&app, &contract_addr);
-        assert_eq!(num_tasks, 4);
+        let terms = Binary::from(b"terms-v1");
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.required_terms_hash = Some(terms.clone());
+        store.config.save(&mut deps.storage, &config).unwrap();
+
+        for accepted_terms in [None, Some(Binary::from(b"terms-v0"))] {
+            let err = store
+                .register_agent(
+                    deps.as_mut(),
+                    mock_info(AGENT1, &[]),
+                    mock_env(),
+                    None,
+                    None,
+                    accepted_terms,
+                    None,
+                )
+                .unwrap_err();
+            assert_eq!(
+                err,
+                ContractError::CustomError {
+                    val: "Must accept the required terms".to_string()
+                }
+            );
+        }
 
-        // Fast forward time a little
-        app.update_block(add_little_time);
+        store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT1, &[]),
+                mock_env(),
+                None,
+                None,
+                Some(terms.clone()),
+                None,
+            )
+            .unwrap();
+        let agent = store
+            .agents
+            .load(&deps.storage, &Addr::unchecked(AGENT1))
+            .unwrap();
+        assert_eq!(agent.accepted_terms_hash, Some(terms));
+        let agent = store
+            .agents
+            .load(&deps.storage, &Addr::unchecked(AGENT0))
+            .unwrap();
+        assert_eq!(agent.accepted_terms_hash, None);
+    }
 
-        let mut agent_status = get_stored_agent_status(&mut app, &contract_addr, AGENT3);
-        assert_eq!(AgentStatus::Pending, agent_status);
-        agent_status = get_stored_agent_status(&mut app, &contract_addr, AGENT2);
-        assert_eq!(AgentStatus::Nominated, agent_status);
+    #[test]
+    fn min_client_version() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        for agent in [AGENT0, AGENT1] {
+            deps.querier.update_balance(agent, coins(100, NATIVE_DENOM));
+        }
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.min_client_version = Some("1.2.0".to_string());
+        store.config.save(&mut deps.storage, &config).unwrap();
+
+        // Outdated, missing and malformed versions are rejected
+        for (client_version, val) in [
+            (Some("1.1.9"), "Client version must be at least 1.2.0"),
+            (None, "Client version must be at least 1.2.0"),
+            (
+                Some("1.2"),
+                "Client version \"1.2\" must be MAJOR.MINOR.PATCH",
+            ),
+        ] {
+            let err = store
+                .register_agent(
+                    deps.as_mut(),
+                    mock_info(AGENT0, &[]),
+                    mock_env(),
+                    None,
+                    None,
+                    None,
+                    client_version.map(str::to_string),
+                )
+                .unwrap_err();
+            assert_eq!(
+                err,
+                ContractError::CustomError {
+                    val: val.to_string()
+                }
+            );
+        }
 
-        // Attempt to accept nomination
-        // First try with the agent second in line in the pending queue.
-        // This should fail because it's not time for them yet.
-        let mut check_in_res = check_in_exec(&mut app, &contract_addr, AGENT3);
-        assert!(
-            &check_in_res.is_err(),
-            "Should throw error when agent in second position tries to nominate before their time."
-        );
+        // Compatible version, compared numerically rather than as text
+        store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                None,
+                None,
+                None,
+                Some("1.10.0".to_string()),
+            )
+            .unwrap();
+        let agent = store
+            .agents
+            .load(&deps.storage, &Addr::unchecked(AGENT0))
+            .unwrap();
+        assert_eq!(agent.client_version, Some("1.10.0".to_string()));
+
+        // Updating can't downgrade below the minimum, but None keeps the current version
+        let err = store
+            .update_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                AGENT0.to_string(),
+                None,
+                Some("0.9.0".to_string()),
+            )
+            .unwrap_err();
         assert_eq!(
+            err,
             ContractError::CustomError {
-                val: "Must wait longer before accepting nomination".to_string()
-            },
-            check_in_res.unwrap_err().downcast().unwrap()
-        );
-
-        // Now try from person at the beginning of the pending queue
-        // This agent should succeed
-        check_in_res = check_in_exec(&mut app, &contract_addr, AGENT2);
-        assert!(
-            check_in_res.is_ok(),
-            "Agent at the front of the pending queue should be allowed to nominate themselves"
+                val: "Client version must be at least 1.2.0".to_string()
+            }
         );
+        store
+            .update_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                AGENT1.to_string(),
+                None,
+                None,
+            )
+            .unwrap();
+        let agent = store
+            .agents
+            .load(&deps.storage, &Addr::unchecked(AGENT0))
+            .unwrap();
+        assert_eq!(agent.client_version, Some("1.10.0".to_string()));
+    }
 
-        // Check that active and pending queues are correct
-        let (agent_ids_res, num_active_agents, _) = get_agent_ids(&app, &contract_addr);
-        assert_eq!(2, num_active_agents);
-        assert_eq!(1, agent_ids_res.pending.len());
-
-        // The agent that was second in the queue is now first,
-        // tries again, but there aren't enough tasks
-        check_in_res = check_in_exec(&mut app, &contract_addr, AGENT3);
+    #[test]
+    fn query_solvency() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        deps.querier
+            .update_balance(AGENT0, coins(100, NATIVE_DENOM));
+        store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(AGENT0, &[]),
+                mock_env(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        let set_owed = |store: &CwCroncat, storage: &mut dyn Storage, balance: u128, bond: u128| {
+            let agent_id = Addr::unchecked(AGENT0);
+            let mut agent = store.agents.load(storage, &agent_id).unwrap();
+            agent.balance.native = coins(balance, NATIVE_DENOM);
+            agent.bond = coins(bond, NATIVE_DENOM);
+            store.agents.save(storage, &agent_id, &agent).unwrap();
+        };
+        let solvency = |store: &mut CwCroncat, deps: Deps| -> SolvencyResponse {
+            let res = store
+                .query(deps, mock_env(), QueryMsg::GetSolvency {})
+                .unwrap();
+            from_binary(&res).unwrap()
+        };
 
-        let error_msg = check_in_res.unwrap_err();
+        set_owed(&store, &mut deps.storage, 150, 50);
         assert_eq!(
-            ContractError::CustomError {
-                val: "Not accepting new agents".to_string()
-            },
-            error_msg.downcast().unwrap()
+            solvency(&mut store, deps.as_ref()),
+            SolvencyResponse {
+                total_liabilities: GenericBalance {
+                    native: coins(200, NATIVE_DENOM),
+                    cw20: vec![],
+                },
+                contract_holdings: GenericBalance {
+                    native: coins(200, NATIVE_DENOM),
+                    cw20: vec![],
+                },
+                solvent: true,
+            }
         );
 
-        agent_status = get_stored_agent_status(&mut app, &contract_addr, AGENT3);
-        assert_eq!(AgentStatus::Pending, agent_status);
-
-        // Again, add three more tasks so we can nominate another agent
-        add_task_exec(&mut app, &contract_addr, PARTICIPANT4);
-        add_task_exec(&mut app, &contract_addr, PARTICIPANT5);
-        add_task_exec(&mut app, &contract_addr, PARTICIPANT6);
-
-        num_tasks = get_task_total(&app, &contract_addr);
-        assert_eq!(num_tasks, 7);
-
-        // Add another agent, since there's now the need
-        register_agent_exec(&mut app, &contract_addr, AGENT4, &AGENT_BENEFICIARY);
-        // Fast forward time past the duration of the first pending agent,
-        // allowing the second to nominate themselves
-        app.update_block(add_one_duration_of_time);
+        // Owes more than it holds
+        set_owed(&store, &mut deps.storage, 150, 51);
+        let res = solvency(&mut store, deps.as_ref());
+        assert_eq!(res.total_liabilities.native, coins(201, NATIVE_DENOM));
+        assert!(!res.solvent);
+    }
 
-        // Now that enough time has passed, both agents should see they're nominated
-        agent_status = get_stored_agent_status(&mut app, &contract_addr, AGENT3);
-        assert_eq!(AgentStatus::Nominated, agent_status);
-        agent_status = get_stored_agent_status(&mut app, &contract_addr, AGENT4);
-        assert_eq!(AgentStatus::Nominated, agent_status);
+    #[test]
+    fn distribute_bonus() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        let active = vec![
+            Addr::unchecked(AGENT0),
+            Addr::unchecked(AGENT1),
+            Addr::unchecked(AGENT2),
+        ];
+        for agent_id in active.iter() {
+            store
+                .agents
+                .save(
+                    &mut deps.storage,
+                    agent_id,
+                    &new_agent(agent_id.clone(), mock_env().block.time, vec![]),
+                )
+                .unwrap();
+        }
+        store
+            .agent_active_queue
+            .save(&mut deps.storage, &active)
+            .unwrap();
+        let bonus = coin(100, NATIVE_DENOM);
+        let balances = |deps: Deps| -> Vec<Vec<Coin>> {
+            active
+                .iter()
+                .map(|agent_id| {
+                    store
+                        .agents
+                        .load(deps.storage, agent_id)
+                        .unwrap()
+                        .balance
+                        .native
+                })
+                .collect()
+        };
 
-        // Agent second in line nominates themself
-        check_in_res = check_in_exec(&mut app, &contract_addr, AGENT4);
-        assert!(
-            check_in_res.is_ok(),
-            "Agent second in line should be able to nominate themselves"
+        let err = store
+            .distribute_bonus(
+                deps.as_mut(),
+                mock_info(AGENT0, std::slice::from_ref(&bonus)),
+                mock_env(),
+                bonus.clone(),
+            )
+            .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+        let err = store
+            .distribute_bonus(
+                deps.as_mut(),
+                mock_info("creator", &[]),
+                mock_env(),
+                bonus.clone(),
+            )
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::CustomError {
+                val: "Must attach exactly the bonus total".to_string()
+            }
         );
 
-        let (_, _, num_pending_agents) = get_agent_ids(&app, &contract_addr);
+        // Rounded down, the leftover unit goes to the treasury
+        let config_before = store.config.load(&deps.storage).unwrap();
+        let res = store
+            .distribute_bonus(
+                deps.as_mut(),
+                mock_info("creator", std::slice::from_ref(&bonus)),
+                mock_env(),
+                bonus.clone(),
+            )
+            .unwrap();
+        assert!(res
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "remainder" && attr.value == "1"));
+        assert_eq!(balances(deps.as_ref()), vec![coins(33, NATIVE_DENOM); 3]);
+        let config = store.config.load(&deps.storage).unwrap();
+        let mut expected_total = config_before.total_agent_balance.clone();
+        expected_total
+            .checked_add_native(&coins(99, NATIVE_DENOM))
+            .unwrap();
+        assert_eq!(config.total_agent_balance, expected_total);
+        let mut expected_revenue = config_before.protocol_revenue.clone();
+        expected_revenue
+            .checked_add_native(&coins(1, NATIVE_DENOM))
+            .unwrap();
+        assert_eq!(config.protocol_revenue, expected_revenue);
+
+        // Rounded up, the front of the queue gets the leftover units
+        let mut config = config;
+        config.reward_rounding = RewardRounding::RoundUp;
+        store.config.save(&mut deps.storage, &config).unwrap();
+        let bonus = coin(5, NATIVE_DENOM);
+        store
+            .distribute_bonus(
+                deps.as_mut(),
+                mock_info("creator", std::slice::from_ref(&bonus)),
+                mock_env(),
+                bonus,
+            )
+            .unwrap();
+        assert_eq!(
+            balances(deps.as_ref()),
+            vec![
+                coins(35, NATIVE_DENOM),
+                coins(35, NATIVE_DENOM),
+                coins(34, NATIVE_DENOM)
+            ]
+        );
+        assert_eq!(
+            store.config.load(&deps.storage).unwrap().protocol_revenue,
+            expected_revenue
+        );
 
-        // Ensure the pending list is empty, having the earlier index booted
+        // Shares are credited like rewards, a compounding agent gets its share bonded
+        let mut agent = store.agents.load(&deps.storage, &active[0]).unwrap();
+        agent.auto_compound = true;
+        store
+            .agents
+            .save(&mut deps.storage, &active[0], &agent)
+            .unwrap();
+        let bonus = coin(3, NATIVE_DENOM);
+        store
+            .distribute_bonus(
+                deps.as_mut(),
+                mock_info("creator", std::slice::from_ref(&bonus)),
+                mock_env(),
+                bonus,
+            )
+            .unwrap();
+        let agent = store.agents.load(&deps.storage, &active[0]).unwrap();
+        assert_eq!(agent.balance.native, coins(35, NATIVE_DENOM));
+        assert_eq!(agent.bond, coins(1, NATIVE_DENOM));
         assert_eq!(
-            num_pending_agents, 0,
-            "Expect the pending queue to be empty"
+            balances(deps.as_ref())[1..].to_vec(),
+            vec![coins(36, NATIVE_DENOM), coins(35, NATIVE_DENOM)]
         );
     }
 
     #[test]
-    fn test_get_agent_status() {
-        // Give the contract and the agents balances
-        let mut deps = cosmwasm_std::testing::mock_dependencies_with_balances(&[
-            (&MOCK_CONTRACT_ADDR, &[coin(6000, "atom")]),
-            (&AGENT0, &[coin(2_000_000, "atom")]),
-            (&AGENT1, &[coin(2_000_000, "atom")]),
-        ]);
-        let mut contract = CwCroncat::default();
-
-        // Instantiate
-        let msg = InstantiateMsg {
-            denom: "atom".to_string(),
-            owner_id: None,
-            gas_base_fee: None,
-            agent_nomination_duration: Some(360),
-            cw_rules_addr: "todo".to_string(),
+    fn distribute_bonus_shares() {
+        // Distributes `total` to `agents` fresh active agents,
+        // returns their balances and what the treasury got
+        let distribute = |agents: usize, total: u128, rounding: RewardRounding| {
+            let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+            let store = CwCroncat::default();
+            mock_init(&store, deps.as_mut()).unwrap();
+            let mut config = store.config.load(&deps.storage).unwrap();
+            config.reward_rounding = rounding;
+            store.config.save(&mut deps.storage, &config).unwrap();
+            let active: Vec<Addr> = (0..agents)
+                .map(|i| Addr::unchecked(format!("agent{}", i)))
+                .collect();
+            for agent_id in active.iter() {
+                store
+                    .agents
+                    .save(
+                        &mut deps.storage,
+                        agent_id,
+                        &new_agent(agent_id.clone(), mock_env().block.time, vec![]),
+                    )
+                    .unwrap();
+            }
+            store
+                .agent_active_queue
+                .save(&mut deps.storage, &active)
+                .unwrap();
+
+            let bonus = coin(total, NATIVE_DENOM);
+            store
+                .distribute_bonus(
+                    deps.as_mut(),
+                    mock_info("creator", std::slice::from_ref(&bonus)),
+                    mock_env(),
+                    bonus,
+                )
+                .unwrap();
+            let balances: Vec<u128> = active
+                .iter()
+                .map(|agent_id| {
+                    let agent = store.agents.load(&deps.storage, agent_id).unwrap();
+                    agent.balance.native.iter().map(|c| c.amount.u128()).sum()
+                })
+                .collect();
+            let revenue = store
+                .config
+                .load(&deps.storage)
+                .unwrap()
+                .protocol_revenue
+                .native
+                .iter()
+                .map(|c| c.amount.u128())
+                .sum::<u128>()
+                - config
+                    .protocol_revenue
+                    .native
+                    .iter()
+                    .map(|c| c.amount.u128())
+                    .sum::<u128>();
+            (balances, revenue)
         };
-        let mut info = mock_info(AGENT0, &coins(900_000, "atom"));
-        let res_init = contract
-            .instantiate(deps.as_mut(), mock_env(), info.clone(), msg)
-            .unwrap();
-        assert_eq!(0, res_init.messages.len());
-
-        let mut agent_status_res =
-            contract.get_agent_status(&deps.storage, mock_env(), Addr::unchecked(AGENT0));
-        assert_eq!(Err(ContractError::AgentNotRegistered {}), agent_status_res);
 
-        let agent_active_queue_opt: Vec<Addr> =
-            match deps.storage.get("agent_active_queue".as_bytes()) {
-                Some(vec) => from_slice(vec.as_ref()).expect("Could not load agent active queue"),
-                None => {
-                    panic!("Uninitialized agent_active_queue_opt");
-                }
-            };
-        assert!(
-            agent_active_queue_opt.is_empty(),
-            "Should not have an active queue yet"
+        // 100 over 7 is 14 each, with 2 left over
+        assert_eq!(
+            distribute(7, 100, RewardRounding::RoundDown),
+            (vec![14; 7], 2)
         );
-
-        // First registered agent becomes active
-        let mut register_agent_res = contract_register_agent(AGENT0, &mut contract, deps.as_mut());
-        assert!(
-            register_agent_res.is_ok(),
-            "Registering agent should succeed"
+        assert_eq!(
+            distribute(7, 100, RewardRounding::RoundUp),
+            (vec![15, 15, 14, 14, 14, 14, 14], 0)
         );
 
-        agent_status_res =
-            contract.get_agent_status(&deps.storage, mock_env(), Addr::unchecked(AGENT0));
-        assert_eq!(AgentStatus::Active, agent_status_res.unwrap());
+        // More agents than basis points still get their share
+        let (balances, revenue) = distribute(10_001, 20_003, RewardRounding::RoundDown);
+        assert!(balances.iter().all(|balance| *balance == 2));
+        assert_eq!(revenue, 1);
+        let (balances, revenue) = distribute(10_001, 10_003, RewardRounding::RoundUp);
+        assert_eq!(balances[..2], [2, 2]);
+        assert!(balances[2..].iter().all(|balance| *balance == 1));
+        assert_eq!(revenue, 0);
+        // Fewer units than agents, only the front of the queue gets one when rounding up
+        let (balances, revenue) = distribute(10_001, 3, RewardRounding::RoundUp);
+        assert_eq!(balances[..4], [1, 1, 1, 0]);
+        assert_eq!(balances.iter().sum::<u128>(), 3);
+        assert_eq!(revenue, 0);
+        let (balances, revenue) = distribute(10_001, 3, RewardRounding::RoundDown);
+        assert!(balances.iter().all(|balance| *balance == 0));
+        assert_eq!(revenue, 3);
+    }
 
-        // Add two tasks
-        let mut res_add_task = contract_create_task(&contract, deps.as_mut(), &info);
-        assert!(res_add_task.is_ok(), "Adding task should succeed.");
-        // Change sender so it's not a duplicate task
-        info.sender = Addr::unchecked(PARTICIPANT0);
-        res_add_task = contract_create_task(&contract, deps.as_mut(), &info);
-        assert!(res_add_task.is_ok(), "Adding task should succeed.");
+    #[test]
+    fn query_nomination_status() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        // AGENT0 is active, AGENT1 and AGENT2 are pending
+        for agent in [AGENT0, AGENT1, AGENT2] {
+            deps.querier.update_balance(agent, coins(100, NATIVE_DENOM));
+            store
+                .register_agent(
+                    deps.as_mut(),
+                    mock_info(agent, &[]),
+                    mock_env(),
+                    None,
+                    None,
+                    None,
+                    None,
+                )
+                .unwrap();
+        }
+        let status =
+            |store: &mut CwCroncat, deps: Deps, account_id: &str| -> NominationStatusResponse {
+                let res = store
+                    .query(
+                        deps,
+                        mock_env(),
+                        QueryMsg::GetNominationStatus {
+                            account_id: account_id.to_string(),
+                        },
+                    )
+                    .unwrap();
+                from_binary(&res).unwrap()
+            };
+        let blocked = |reason: NominationBlockReason| NominationStatusResponse {
+            nominated: false,
+            reason: Some(reason),
+        };
 
-        // Register an agent and make sure the status comes back as pending
-        register_agent_res = contract_register_agent(AGENT1, &mut contract, deps.as_mut());
-        assert!(
-            register_agent_res.is_ok(),
-            "Registering agent should succeed"
-        );
-        agent_status_res =
-            contract.get_agent_status(&deps.storage, mock_env(), Addr::unchecked(AGENT1));
         assert_eq!(
-            AgentStatus::Pending,
-            agent_status_res.unwrap(),
-            "New agent should be pending"
+            status(&mut store, deps.as_ref(), AGENT0),
+            blocked(NominationBlockReason::NotPending)
         );
+        assert!(store
+            .query(
+                deps.as_ref(),
+                mock_env(),
+                QueryMsg::GetNominationStatus {
+                    account_id: AGENT3.to_string(),
+                },
+            )
+            .is_err());
 
-        // Two more tasks are added
-        info.sender = Addr::unchecked(PARTICIPANT1);
-        res_add_task = contract_create_task(&contract, deps.as_mut(), &info);
-        assert!(res_add_task.is_ok(), "Adding task should succeed.");
-        info.sender = Addr::unchecked(PARTICIPANT2);
-        res_add_task = contract_create_task(&contract, deps.as_mut(), &info);
-        assert!(res_add_task.is_ok(), "Adding task should succeed.");
-
-        // Agent status is nominated
-        agent_status_res =
-            contract.get_agent_status(&deps.storage, mock_env(), Addr::unchecked(AGENT1));
+        // No tasks for another agent
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.min_tasks_per_agent = 1;
+        store.config.save(&mut deps.storage, &config).unwrap();
+        store.task_total.save(&mut deps.storage, &0).unwrap();
         assert_eq!(
-            AgentStatus::Nominated,
-            agent_status_res.unwrap(),
-            "New agent should have nominated status"
+            status(&mut store, deps.as_ref(), AGENT1),
+            blocked(NominationBlockReason::NoOpenSlots)
         );
-    }
 
-    #[test]
-    fn test_query_get_agent_tasks() {
-        let (mut app, cw_template_contract) = proper_instantiate();
-        let contract_addr = cw_template_contract.addr();
-        let block_info = app.block_info();
-        println!(
-            "test aloha\n\tcurrent block: {}\n\tcurrent time: {}",
-            block_info.height,
-            block_info.time.nanos()
+        // One more agent needed, before the nomination window opens
+        store.task_total.save(&mut deps.storage, &2).unwrap();
+        store
+            .agent_nomination_begin_time
+            .save(&mut deps.storage, &None)
+            .unwrap();
+        assert_eq!(
+            status(&mut store, deps.as_ref(), AGENT1),
+            blocked(NominationBlockReason::InGracePeriod)
         );
 
-        // Register AGENT1, who immediately becomes active
-        register_agent_exec(&mut app, &contract_addr, AGENT1, &AGENT_BENEFICIARY);
-        // Add five tasks total
-        // Three of them are block-based
-        add_block_task_exec(
-            &mut app,
-            &contract_addr,
-            PARTICIPANT0,
-            block_info.height + 6,
-        );
-        add_block_task_exec(
-            &mut app,
-            &contract_addr,
-            PARTICIPANT1,
-            block_info.height + 66,
+        // Only the front of the queue gets in right away
+        store
+            .agent_nomination_begin_time
+            .save(&mut deps.storage, &Some(mock_env().block.time))
+            .unwrap();
+        assert_eq!(
+            status(&mut store, deps.as_ref(), AGENT1),
+            NominationStatusResponse {
+                nominated: true,
+                reason: None,
+            }
         );
-        add_block_task_exec(
-            &mut app,
-            &contract_addr,
-            PARTICIPANT2,
-            block_info.height + 67,
+        assert_eq!(
+            status(&mut store, deps.as_ref(), AGENT2),
+            blocked(NominationBlockReason::BehindInQueue { position: 1 })
         );
-        // add_block_task_exec(&mut app, &contract_addr, PARTICIPANT3, block_info.height + 131);
-        // Two tasks use Cron instead of Block (for task interval)
-        add_cron_task_exec(&mut app, &contract_addr, PARTICIPANT4, 6); // 3 minutes
-        add_cron_task_exec(&mut app, &contract_addr, PARTICIPANT5, 53); // 53 minutes
-        let num_tasks = get_task_total(&app, &contract_addr);
-        assert_eq!(num_tasks, 5);
 
-        // Now the task ratio is 1:2 (one agent per two tasks)
-        // Register two agents, the first one succeeding
-        register_agent_exec(&mut app, &contract_addr, AGENT2, &AGENT_BENEFICIARY);
-        assert!(check_in_exec(&mut app, &contract_addr, AGENT2).is_ok());
-        // This next agent should fail because there's no enough tasks yet
-        // Later, we'll have this agent try to nominate themselves before their time
-        register_agent_exec(&mut app, &contract_addr, AGENT3, &AGENT_BENEFICIARY);
-        let failed_check_in = check_in_exec(&mut app, &contract_addr, AGENT3);
+        // Too few due tasks
+        config.min_tasks_for_nomination = 5;
+        store.config.save(&mut deps.storage, &config).unwrap();
         assert_eq!(
-            ContractError::CustomError {
-                val: "Not accepting new agents".to_string()
-            },
-            failed_check_in.unwrap_err().downcast().unwrap()
+            status(&mut store, deps.as_ref(), AGENT1),
+            blocked(NominationBlockReason::Suspended)
         );
+    }
 
-        let (_, num_active_agents, num_pending_agents) = get_agent_ids(&app, &contract_addr);
-        assert_eq!(2, num_active_agents);
-        assert_eq!(1, num_pending_agents);
+    #[test]
+    fn auto_activate_open_slots() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.auto_activate_open_slots = true;
+        config.max_agents = Some(2);
+        store.config.save(&mut deps.storage, &config).unwrap();
+        let register =
+            |deps: &mut OwnedDeps<MockStorage, MockApi, MockQuerier>, agent: &str| -> String {
+                deps.querier.update_balance(agent, coins(100, NATIVE_DENOM));
+                let res = store
+                    .register_agent(
+                        deps.as_mut(),
+                        mock_info(agent, &[]),
+                        mock_env(),
+                        None,
+                        None,
+                        None,
+                        None,
+                    )
+                    .unwrap();
+                res.attributes
+                    .into_iter()
+                    .find(|attr| attr.key == "agent_status")
+                    .unwrap()
+                    .value
+            };
 
-        // Fast forward time a little
-        app.update_block(|block| {
-            let height = 666;
-            block.time = block.time.plus_seconds(6 * height); // ~6 sec block time
-            block.height = block.height + height;
-        });
+        // Room left and nobody waiting
+        assert_eq!(register(&mut deps, AGENT0), "Active");
+        assert_eq!(register(&mut deps, AGENT1), "Active");
+        // Full
+        assert_eq!(register(&mut deps, AGENT2), "Pending");
 
-        // What happens when the only active agent queries to see if there's work for them
-        // calls:
-        // fn query_get_agent_tasks
-        let mut msg_agent_tasks = QueryMsg::GetAgentTasks {
-            account_id: AGENT1.to_string(),
-        };
-        let mut query_task_res: StdResult<Option<AgentTaskResponse>> = app
-            .wrap()
-            .query_wasm_smart(contract_addr.clone(), &msg_agent_tasks);
-        println!(
-            "test aloha query_task_res0 {:#?}",
-            query_task_res.as_ref().unwrap()
+        // Room again, but AGENT2 was waiting first
+        config.max_agents = Some(5);
+        store.config.save(&mut deps.storage, &config).unwrap();
+        assert_eq!(register(&mut deps, AGENT3), "Pending");
+        assert_eq!(
+            store.agent_active_queue.load(&deps.storage).unwrap(),
+            vec![Addr::unchecked(AGENT0), Addr::unchecked(AGENT1)]
         );
-        assert!(
-            query_task_res.is_ok(),
-            "Did not successfully find the newly added task"
+        assert_eq!(
+            store.agent_pending_queue.load(&deps.storage).unwrap(),
+            vec![Addr::unchecked(AGENT2), Addr::unchecked(AGENT3)]
         );
-        msg_agent_tasks = QueryMsg::GetAgentTasks {
-            account_id: AGENT2.to_string(),
-        };
-        query_task_res = app
-            .wrap()
-            .query_wasm_smart(contract_addr.clone(), &msg_agent_tasks);
-        println!("test aloha query_task_res1 {:#?}", query_task_res.unwrap());
-        // Should fail for random user not in the active queue
-        msg_agent_tasks = QueryMsg::GetAgentTasks {
-            // rando account
-            account_id: "juno1kqfjv53g7ll9u6ngvsu5l5nfv9ht24m4q4gdqz".to_string(),
-        };
-        query_task_res = app
-            .wrap()
-            .query_wasm_smart(contract_addr.clone(), &msg_agent_tasks);
-        println!("aloha query_task_res {:?}", query_task_res);
     }
 }
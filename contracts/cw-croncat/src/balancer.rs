@@ -5,8 +5,99 @@ use cosmwasm_std::Uint64;
 use cosmwasm_std::{Addr, Env, StdError, StdResult};
 use cosmwasm_std::{Deps, Storage};
 use cw_croncat_core::msg::AgentTaskResponse;
-use cw_croncat_core::types::SlotType;
-use cw_storage_plus::Item;
+use cw_croncat_core::types::{Agent, SelectionMode, SlotType, Task};
+use cw_storage_plus::{Item, Map};
+
+/// Index of the agent leading `slot` when selection is weighted.
+/// Each agent's chance is proportional to its weight, the slot gets scrambled
+/// so consecutive slots don't walk through the agents in queue order.
+/// Slots map to agents the same way on every call, so all agents agree on it
+fn weighted_slot_leader(weights: &[u64], slot: u64) -> usize {
+    let total: u64 = weights.iter().sum();
+    if total == 0 {
+        return 0;
+    }
+    // splitmix64 finalizer
+    let mut point = slot.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    point = (point ^ (point >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    point = (point ^ (point >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    point = (point ^ (point >> 31)) % total;
+    let mut cumulative = 0u64;
+    for (index, weight) in weights.iter().enumerate() {
+        cumulative += weight;
+        if point < cumulative {
+            return index;
+        }
+    }
+    weights.len() - 1
+}
+
+/// Index of the agent leading `slot` in lottery selection, weighted by `stakes`.
+/// Block hashes aren't available to contracts, so the slot seeds the draw:
+/// anyone can recompute the winner from the active queue and the bonds at that slot.
+/// With nothing bonded every agent gets the same chance
+fn stake_lottery_winner(stakes: &[u128], slot: u64) -> usize {
+    let weights: Vec<u64> = if stakes.iter().all(|stake| *stake == 0) {
+        vec![1; stakes.len()]
+    } else {
+        // Capped so the total can't overflow
+        let cap = u64::MAX as u128 / stakes.len() as u128;
+        stakes
+            .iter()
+            .map(|stake| (*stake).min(cap) as u64)
+            .collect()
+    };
+    weighted_slot_leader(&weights, slot)
+}
+
+/// Index into `active` of the agent leading `slot_id`, the first in line for its tasks.
+/// Rotating by one position every slot means that over `active.len()` consecutive
+/// slots each agent holds every queue position exactly once,
+/// so being first in the queue isn't a long-term advantage.
+/// Weighted selection instead puts an agent first with a chance proportional to
+/// its executed tasks plus one, so agents that never executed still get picked.
+/// Lottery selection does the same with the agents' bonds
+pub(crate) fn slot_leader_index<'a>(
+    deps: &Deps,
+    conf: &Config,
+    agents: &Map<'a, &'a Addr, Agent>,
+    active: &[Addr],
+    slot_id: u64,
+) -> StdResult<u64> {
+    if active.is_empty() {
+        return Ok(0);
+    }
+    Ok(if conf.selection == SelectionMode::Lottery {
+        let stakes = active
+            .iter()
+            .map(|addr| {
+                agents.load(deps.storage, addr).map(|agent| {
+                    agent
+                        .bond
+                        .iter()
+                        .filter(|coin| coin.denom == conf.native_denom)
+                        .map(|coin| coin.amount.u128())
+                        .sum::<u128>()
+                })
+            })
+            .collect::<StdResult<Vec<u128>>>()?;
+        stake_lottery_winner(&stakes, slot_id) as u64
+    } else if conf.weighted_selection {
+        let weights = active
+            .iter()
+            .map(|addr| {
+                agents
+                    .load(deps.storage, addr)
+                    .map(|agent| agent.total_tasks_executed.saturating_add(1))
+            })
+            .collect::<StdResult<Vec<u64>>>()?;
+        weighted_slot_leader(&weights, slot_id) as u64
+    } else if conf.rotate_queue_each_slot {
+        slot_id % active.len() as u64
+    } else {
+        0
+    })
+}
 
 #[derive(PartialEq, Eq)]
 pub enum BalancerMode {
@@ -14,14 +105,18 @@ pub enum BalancerMode {
     Equalizer,
 }
 pub trait Balancer<'a> {
+    #[allow(clippy::too_many_arguments)]
     fn get_agent_tasks(
         &mut self,
         deps: &Deps,
         env: &Env,
         config: &Item<'a, Config>,
         active_agents: &Item<'a, Vec<Addr>>,
+        agents: &Map<'a, &'a Addr, Agent>,
         agent_id: Addr,
         slot_items: (Option<u64>, Option<u64>),
+        slot_id: u64,
+        task: Option<&Task>,
     ) -> StdResult<Option<AgentTaskResponse>>;
     fn on_agent_unregister(
         &self,
@@ -92,23 +187,65 @@ impl<'a> Balancer<'a> for RoundRobinBalancer {
         _env: &Env,
         config: &Item<'a, Config>,
         active_agents: &Item<'a, Vec<Addr>>,
+        agents: &Map<'a, &'a Addr, Agent>,
         agent_id: Addr,
         slot_items: (Option<u64>, Option<u64>),
+        slot_id: u64,
+        task: Option<&Task>,
     ) -> StdResult<Option<AgentTaskResponse>> {
         let conf: Config = config.load(deps.storage)?;
-        let active = active_agents.load(deps.storage)?;
-        if !active.contains(&agent_id) {
+        let queue = active_agents.load(deps.storage)?;
+        if !queue.contains(&agent_id) {
             return Err(StdError::GenericErr {
                 msg: AgentNotRegistered {}.to_string(),
             });
         }
+        // Self-paused agents keep their place in the queue but don't share in the tasks.
+        // Neither do agents declining the type of `task`, unless all of them decline it
+        let mut candidates: Vec<(usize, Addr, bool)> = vec![];
+        for (index, addr) in queue.iter().enumerate() {
+            let agent = agents.may_load(deps.storage, addr)?;
+            let paused = *addr != agent_id && matches!(&agent, Some(a) if a.self_paused);
+            if !paused {
+                let accepts = match (task, agent.as_ref()) {
+                    (Some(task), Some(agent)) => agent.accepts_task(task),
+                    _ => true,
+                };
+                candidates.push((index, addr.clone(), accepts));
+            }
+        }
+        if candidates.iter().any(|(_, _, accepts)| *accepts) {
+            if candidates
+                .iter()
+                .any(|(_, addr, accepts)| *addr == agent_id && !accepts)
+            {
+                return Ok(Some(AgentTaskResponse {
+                    num_block_tasks: Uint64::zero(),
+                    num_block_tasks_extra: Uint64::zero(),
+                    num_cron_tasks: Uint64::zero(),
+                    num_cron_tasks_extra: Uint64::zero(),
+                }));
+            }
+            candidates.retain(|(_, _, accepts)| *accepts);
+        }
+        let (agent_active_indices, active): (Vec<usize>, Vec<Addr>) = candidates
+            .into_iter()
+            .map(|(index, addr, _)| (index, addr))
+            .unzip();
         let agent_count = active.len() as u64;
-        let agent_active_indices_config = conf.agent_active_indices;
-        let agent_active_indices: Vec<usize> = (0..active.len()).collect();
+        let agent_active_indices_config = &conf.agent_active_indices;
         let agent_index = active
             .iter()
             .position(|x| x == &agent_id)
             .expect("Agent is not active or not registered!") as u64;
+        let queue_index = queue
+            .iter()
+            .position(|x| x == &agent_id)
+            .expect("Agent is not active or not registered!");
+        let offset = slot_leader_index(deps, &conf, agents, &active, slot_id)?;
+        // Positions in the queue, or in the queue reordered by the balancer, shift the same way
+        let rotate = |index: u64| (index + agent_count - offset) % agent_count;
+        let rotated_index = rotate(agent_index);
 
         if slot_items == (None, None) {
             return Ok(None);
@@ -127,7 +264,7 @@ impl<'a> Balancer<'a> for RoundRobinBalancer {
                     }
                     if total_tasks <= active.len() as u64 {
                         let agent_tasks_total = 1u64.saturating_sub(
-                            agent_index.saturating_sub(total_tasks.saturating_sub(1)),
+                            rotated_index.saturating_sub(total_tasks.saturating_sub(1)),
                         );
                         (agent_tasks_total.into(), Uint64::zero())
                     } else {
@@ -141,16 +278,20 @@ impl<'a> Balancer<'a> for RoundRobinBalancer {
                                 .collect::<_>();
 
                         rich_agents.sort_by(|a, b| a.2.partial_cmp(&b.2).unwrap());
-                        let rich_indices: Vec<usize> =
-                            rich_agents.iter().map(|v| v.1 as usize).collect();
+                        let rich_indices: Vec<usize> = rich_agents
+                            .iter()
+                            .map(|v| v.1 as usize)
+                            .filter(|index| agent_active_indices.contains(index))
+                            .collect();
 
                         let mut diff = vect_difference(&agent_active_indices, &rich_indices);
                         diff.extend(rich_indices);
-                        let agent_index = diff
-                            .iter()
-                            .position(|x| x == &(agent_index as usize))
-                            .expect("Agent is not active or not registered!")
-                            as u64;
+                        let agent_index = rotate(
+                            diff.iter()
+                                .position(|x| x == &queue_index)
+                                .expect("Agent is not active or not registered!")
+                                as u64,
+                        );
 
                         let mut extra = 0u64;
                         if leftover > 0 {
@@ -194,17 +335,21 @@ impl<'a> Balancer<'a> for RoundRobinBalancer {
                         .collect::<_>();
 
                     rich_agents.sort_by(|a, b| a.2.partial_cmp(&b.2).unwrap());
-                    let rich_indices: Vec<usize> =
-                        rich_agents.iter().map(|v| v.1 as usize).collect();
+                    let rich_indices: Vec<usize> = rich_agents
+                        .iter()
+                        .map(|v| v.1 as usize)
+                        .filter(|index| agent_active_indices.contains(index))
+                        .collect();
 
                     let mut diff = vect_difference(&agent_active_indices, &rich_indices);
                     diff.extend(rich_indices);
 
-                    let agent_index = diff
-                        .iter()
-                        .position(|x| x == &(agent_index as usize))
-                        .expect("Agent is not active or not registered!")
-                        as u64;
+                    let agent_index = rotate(
+                        diff.iter()
+                            .position(|x| x == &queue_index)
+                            .expect("Agent is not active or not registered!")
+                            as u64,
+                    );
 
                     if total_tasks <= diff.len() as u64 {
                         let agent_tasks_total = 1u64.saturating_sub(
@@ -296,10 +441,14 @@ impl<'a> Balancer<'a> for RoundRobinBalancer {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::agent::new_agent;
     use crate::contract::GAS_BASE_FEE_JUNO;
     use cosmwasm_std::testing::{mock_dependencies_with_balance, mock_env};
-    use cosmwasm_std::{coins, Addr, Coin};
-    use cw_croncat_core::types::SlotType;
+    use cosmwasm_std::{coins, Addr, Coin, Decimal, Uint128};
+    use cw_croncat_core::types::{
+        BoundaryValidated, GenericBalance, Interval, PauseFlags, QueueRemovalMode, RewardRounding,
+        SelectionMode, SlashDestination, SlotType,
+    };
 
     use crate::CwCroncat;
     const AGENT0: &str = "cosmos1a7uhnpqthunr2rzj0ww0hwurpn42wyun6c5puz";
@@ -312,16 +461,22 @@ mod tests {
 
     fn mock_config() -> Config {
         Config {
-            paused: false,
+            paused: PauseFlags::default(),
             owner_id: Addr::unchecked(ADMIN),
             // treasury_id: None,
             min_tasks_per_agent: 3,
+            min_agents_for_tasks: 0,
             agent_active_indices: Vec::<(SlotType, u32, u32)>::with_capacity(0),
             agents_eject_threshold: 600, // how many slots an agent can miss before being ejected. 10 * 60 = 1hr
+            missed_slot_threshold: 600,
             available_balance: GenericBalance::default(),
             staked_balance: GenericBalance::default(),
+            total_agent_balance: GenericBalance::default(),
+            protocol_revenue: GenericBalance::default(),
+            execution_nonce: 0,
+            total_missed_slots: 0,
             agent_fee: Coin::new(5, NATIVE_DENOM.clone()), // TODO: CHANGE AMOUNT HERE!!! 0.0005 Juno (2000 tasks = 1 Juno)
-            gas_price: 1,
+            gas_price: Decimal::one(),
             gas_base_fee: GAS_BASE_FEE_JUNO,
             proxy_callback_gas: 3,
             slot_granularity: 60_000_000_000,
@@ -330,6 +485,45 @@ mod tests {
             agent_nomination_duration: 9,
             limit: 100,
             cw_rules_addr: Addr::unchecked("todo"),
+            slash_destination: SlashDestination::Treasury,
+            stale_timeout_nanos: 86_400_000_000_000,
+            registration_fee: None,
+            rotate_queue_each_slot: false,
+            max_agent_balance: None,
+            reward_rounding: RewardRounding::RoundDown,
+            loyalty_tiers: vec![],
+            max_agents: None,
+            max_pending_agents: None,
+            reward_escrow: None,
+            reward_min: None,
+            reward_max: None,
+            mass_exit_threshold: None,
+            allow_empty_active_set: true,
+            weighted_selection: false,
+            selection: SelectionMode::RoundRobin,
+            onboarders: vec![],
+            min_tasks_for_nomination: 0,
+            activation_cooldown_nanos: 0,
+            dust_threshold: Uint128::zero(),
+            reward_vesting_nanos: 0,
+            queue_removal_mode: QueueRemovalMode::PreserveOrder,
+            max_missed_nominations: 0,
+            exclusivity_window_slots: 0,
+            idle_grace_nanos: 0,
+            dex_router: None,
+            dex_max_slippage: Decimal::percent(1),
+            missed_slot_grace: 0,
+            slash_denom: None,
+            position_hook_step: 0,
+            blacklist: vec![],
+            max_reward_multiplier_bps: 20_000,
+            refund_stray_funds: false,
+            required_terms_hash: None,
+            auto_activate_open_slots: false,
+            slash_bps: 0,
+            allowed_reward_cw20: vec![],
+            slot_duration_nanos: 6_000_000_000,
+            min_client_version: None,
         }
     }
     #[test]
@@ -366,8 +560,11 @@ mod tests {
                 &env.clone(),
                 &store.config,
                 &store.agent_active_queue,
+                &store.agents,
                 Addr::unchecked(AGENT0),
                 slot,
+                env.block.height,
+                None,
             )
             .unwrap()
             .unwrap();
@@ -382,8 +579,11 @@ mod tests {
                 &env.clone(),
                 &store.config,
                 &store.agent_active_queue,
+                &store.agents,
                 Addr::unchecked(AGENT0),
                 slot,
+                env.block.height,
+                None,
             )
             .unwrap()
             .unwrap();
@@ -398,8 +598,11 @@ mod tests {
                 &env.clone(),
                 &store.config,
                 &store.agent_active_queue,
+                &store.agents,
                 Addr::unchecked(AGENT0),
                 slot,
+                env.block.height,
+                None,
             )
             .unwrap()
             .unwrap();
@@ -443,8 +646,11 @@ mod tests {
                 &env.clone(),
                 &store.config,
                 &store.agent_active_queue,
+                &store.agents,
                 Addr::unchecked(AGENT0),
                 slot,
+                env.block.height,
+                None,
             )
             .unwrap()
             .unwrap();
@@ -461,8 +667,11 @@ mod tests {
                 &env.clone(),
                 &store.config,
                 &store.agent_active_queue,
+                &store.agents,
                 Addr::unchecked(AGENT1),
                 slot,
+                env.block.height,
+                None,
             )
             .unwrap()
             .unwrap();
@@ -479,8 +688,11 @@ mod tests {
                 &env.clone(),
                 &store.config,
                 &store.agent_active_queue,
+                &store.agents,
                 Addr::unchecked(AGENT3),
                 slot,
+                env.block.height,
+                None,
             )
             .unwrap()
             .unwrap();
@@ -543,8 +755,11 @@ mod tests {
                 &env.clone(),
                 &store.config,
                 &store.agent_active_queue,
+                &store.agents,
                 Addr::unchecked(AGENT0),
                 slot,
+                env.block.height,
+                None,
             )
             .unwrap()
             .unwrap();
@@ -562,8 +777,11 @@ mod tests {
                 &env.clone(),
                 &store.config,
                 &store.agent_active_queue,
+                &store.agents,
                 Addr::unchecked(AGENT1),
                 slot,
+                env.block.height,
+                None,
             )
             .unwrap()
             .unwrap();
@@ -580,8 +798,11 @@ mod tests {
                 &env.clone(),
                 &store.config,
                 &store.agent_active_queue,
+                &store.agents,
                 Addr::unchecked(AGENT2),
                 slot,
+                env.block.height,
+                None,
             )
             .unwrap()
             .unwrap();
@@ -598,8 +819,11 @@ mod tests {
                 &env.clone(),
                 &store.config,
                 &store.agent_active_queue,
+                &store.agents,
                 Addr::unchecked(AGENT3),
                 slot,
+                env.block.height,
+                None,
             )
             .unwrap()
             .unwrap();
@@ -699,4 +923,452 @@ mod tests {
         config = store.config.load(&mut deps.storage).unwrap();
         assert_eq!(config.agent_active_indices, vec![])
     }
+
+    #[test]
+    fn test_rotate_queue_each_slot_ao_mode() {
+        let store = CwCroncat::default();
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut env = mock_env();
+        let mut balancer = RoundRobinBalancer::default();
+        let mut config = mock_config();
+
+        let active_agents = vec![
+            Addr::unchecked(AGENT0),
+            Addr::unchecked(AGENT1),
+            Addr::unchecked(AGENT2),
+        ];
+        store
+            .agent_active_queue
+            .save(&mut deps.storage, &active_agents)
+            .unwrap();
+
+        // Count how many of the single block tasks each agent gets over many slots
+        let mut distribution = |deps: &Deps, env: &mut Env| -> Vec<u64> {
+            let mut counts = vec![0u64; active_agents.len()];
+            for height in 0..300 {
+                env.block.height = height;
+                for (i, agent) in active_agents.iter().enumerate() {
+                    let result = balancer
+                        .get_agent_tasks(
+                            deps,
+                            env,
+                            &store.config,
+                            &store.agent_active_queue,
+                            &store.agents,
+                            agent.clone(),
+                            (Some(1), None),
+                            env.block.height,
+                            None,
+                        )
+                        .unwrap()
+                        .unwrap();
+                    counts[i] += result.num_block_tasks.u64();
+                }
+            }
+            counts
+        };
+
+        // Without rotation the first agent always wins
+        store.config.save(&mut deps.storage, &config).unwrap();
+        assert_eq!(distribution(&deps.as_ref(), &mut env), vec![300, 0, 0]);
+
+        // With rotation it averages out
+        config.rotate_queue_each_slot = true;
+        store.config.save(&mut deps.storage, &config).unwrap();
+        assert_eq!(distribution(&deps.as_ref(), &mut env), vec![100, 100, 100]);
+    }
+
+    #[test]
+    fn test_rotate_queue_each_slot_extra_tasks() {
+        let store = CwCroncat::default();
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut env = mock_env();
+        let mut config = mock_config();
+        config.rotate_queue_each_slot = true;
+        store.config.save(&mut deps.storage, &config).unwrap();
+
+        let active_agents = vec![
+            Addr::unchecked(AGENT0),
+            Addr::unchecked(AGENT1),
+            Addr::unchecked(AGENT2),
+        ];
+        store
+            .agent_active_queue
+            .save(&mut deps.storage, &active_agents)
+            .unwrap();
+
+        // Count the extra block tasks each agent gets over many slots
+        let mut distribution = |mode: BalancerMode, total_tasks: u64| -> Vec<u64> {
+            let mut balancer = RoundRobinBalancer::new(mode);
+            let mut counts = vec![0u64; active_agents.len()];
+            for height in 0..300 {
+                env.block.height = height;
+                for (i, agent) in active_agents.iter().enumerate() {
+                    let result = balancer
+                        .get_agent_tasks(
+                            &deps.as_ref(),
+                            &env,
+                            &store.config,
+                            &store.agent_active_queue,
+                            &store.agents,
+                            agent.clone(),
+                            (Some(total_tasks), None),
+                            env.block.height,
+                            None,
+                        )
+                        .unwrap()
+                        .unwrap();
+                    counts[i] += result.num_block_tasks_extra.u64();
+                }
+            }
+            counts
+        };
+
+        // One leftover task over the even split
+        assert_eq!(
+            distribution(BalancerMode::ActivationOrder, 4),
+            vec![100, 100, 100]
+        );
+        assert_eq!(
+            distribution(BalancerMode::Equalizer, 4),
+            vec![100, 100, 100]
+        );
+        assert_eq!(
+            distribution(BalancerMode::Equalizer, 1),
+            vec![100, 100, 100]
+        );
+    }
+
+    #[test]
+    fn test_self_paused_agents_skipped() {
+        let store = CwCroncat::default();
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let env = mock_env();
+        store
+            .config
+            .save(&mut deps.storage, &mock_config())
+            .unwrap();
+
+        let active_agents = vec![
+            Addr::unchecked(AGENT0),
+            Addr::unchecked(AGENT1),
+            Addr::unchecked(AGENT2),
+        ];
+        store
+            .agent_active_queue
+            .save(&mut deps.storage, &active_agents)
+            .unwrap();
+        for agent_id in active_agents.iter() {
+            let mut agent = new_agent(agent_id.clone(), env.block.time, vec![]);
+            agent.self_paused = *agent_id == AGENT0;
+            store
+                .agents
+                .save(&mut deps.storage, agent_id, &agent)
+                .unwrap();
+        }
+
+        // The two agents left split the tasks as if the paused one wasn't there
+        for mode in [BalancerMode::ActivationOrder, BalancerMode::Equalizer] {
+            let mut balancer = RoundRobinBalancer::new(mode);
+            let tasks: Vec<(u64, u64)> = active_agents[1..]
+                .iter()
+                .map(|agent_id| {
+                    let result = balancer
+                        .get_agent_tasks(
+                            &deps.as_ref(),
+                            &env,
+                            &store.config,
+                            &store.agent_active_queue,
+                            &store.agents,
+                            agent_id.clone(),
+                            (Some(3), None),
+                            env.block.height,
+                            None,
+                        )
+                        .unwrap()
+                        .unwrap();
+                    (
+                        result.num_block_tasks.u64(),
+                        result.num_block_tasks_extra.u64(),
+                    )
+                })
+                .collect();
+            assert_eq!(tasks, vec![(2, 1), (1, 0)]);
+        }
+    }
+
+    #[test]
+    fn test_declining_agents_skipped() {
+        let store = CwCroncat::default();
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let env = mock_env();
+        store
+            .config
+            .save(&mut deps.storage, &mock_config())
+            .unwrap();
+
+        let active_agents = vec![
+            Addr::unchecked(AGENT0),
+            Addr::unchecked(AGENT1),
+            Addr::unchecked(AGENT2),
+        ];
+        store
+            .agent_active_queue
+            .save(&mut deps.storage, &active_agents)
+            .unwrap();
+        let task = Task {
+            owner_id: Addr::unchecked(ADMIN),
+            interval: Interval::Once,
+            boundary: BoundaryValidated {
+                start: None,
+                end: None,
+            },
+            funds_withdrawn_recurring: Uint128::zero(),
+            stop_on_fail: false,
+            total_deposit: GenericBalance::default(),
+            amount_for_one_task: GenericBalance::default(),
+            actions: vec![],
+            rules: None,
+            task_type: Some("swap".to_string()),
+        };
+        let mut balancer = RoundRobinBalancer::default();
+        let mut block_tasks = |deps: Deps, agent_id: &Addr| {
+            balancer
+                .get_agent_tasks(
+                    &deps,
+                    &env,
+                    &store.config,
+                    &store.agent_active_queue,
+                    &store.agents,
+                    agent_id.clone(),
+                    (Some(1), None),
+                    env.block.height,
+                    Some(&task),
+                )
+                .unwrap()
+                .unwrap()
+                .num_block_tasks
+                .u64()
+        };
+
+        // AGENT0 only takes compound tasks, the swap goes to the next agent
+        for agent_id in active_agents.iter() {
+            let mut agent = new_agent(agent_id.clone(), env.block.time, vec![]);
+            if *agent_id == AGENT0 {
+                agent.accepted_task_types = vec!["compound".to_string()];
+            }
+            store
+                .agents
+                .save(&mut deps.storage, agent_id, &agent)
+                .unwrap();
+        }
+        let tasks: Vec<u64> = active_agents
+            .iter()
+            .map(|agent_id| block_tasks(deps.as_ref(), agent_id))
+            .collect();
+        assert_eq!(tasks, vec![0, 1, 0]);
+
+        // Declined by everyone, it's shared by everyone rather than left stuck
+        for agent_id in active_agents.iter() {
+            let mut agent = new_agent(agent_id.clone(), env.block.time, vec![]);
+            agent.accepted_task_types = vec!["compound".to_string()];
+            store
+                .agents
+                .save(&mut deps.storage, agent_id, &agent)
+                .unwrap();
+        }
+        let tasks: Vec<u64> = active_agents
+            .iter()
+            .map(|agent_id| block_tasks(deps.as_ref(), agent_id))
+            .collect();
+        assert_eq!(tasks, vec![1, 0, 0]);
+    }
+
+    #[test]
+    fn test_weighted_selection_ao_mode() {
+        let store = CwCroncat::default();
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut env = mock_env();
+        let mut balancer = RoundRobinBalancer::default();
+        let mut config = mock_config();
+        config.weighted_selection = true;
+        store.config.save(&mut deps.storage, &config).unwrap();
+
+        // Weights 1, 4 and 10
+        let active_agents = vec![
+            Addr::unchecked(AGENT0),
+            Addr::unchecked(AGENT1),
+            Addr::unchecked(AGENT2),
+        ];
+        store
+            .agent_active_queue
+            .save(&mut deps.storage, &active_agents)
+            .unwrap();
+        for (agent_id, total_tasks_executed) in active_agents.iter().zip([0, 3, 9]) {
+            let agent = Agent {
+                total_tasks_executed,
+                ..new_agent(agent_id.clone(), env.block.time, vec![])
+            };
+            store
+                .agents
+                .save(&mut deps.storage, agent_id, &agent)
+                .unwrap();
+        }
+
+        let mut distribution = |deps: &Deps, env: &mut Env| -> Vec<u64> {
+            let mut counts = vec![0u64; active_agents.len()];
+            for height in 0..1500 {
+                env.block.height = height;
+                for (i, agent) in active_agents.iter().enumerate() {
+                    let result = balancer
+                        .get_agent_tasks(
+                            deps,
+                            env,
+                            &store.config,
+                            &store.agent_active_queue,
+                            &store.agents,
+                            agent.clone(),
+                            (Some(1), None),
+                            env.block.height,
+                            None,
+                        )
+                        .unwrap()
+                        .unwrap();
+                    counts[i] += result.num_block_tasks.u64();
+                }
+            }
+            counts
+        };
+
+        // Exactly one agent leads each slot, more executed tasks lead more often
+        let counts = distribution(&deps.as_ref(), &mut env);
+        assert_eq!(counts.iter().sum::<u64>(), 1500);
+        assert!(counts[0] > 0);
+        assert!(counts[0] < counts[1] && counts[1] < counts[2]);
+        // and every call agrees on it
+        assert_eq!(distribution(&deps.as_ref(), &mut env), counts);
+
+        // The slot seeds the draw, so blocks passing within a slot keep its leader
+        let slot_id = env.block.time.nanos();
+        let leaders: Vec<Vec<u64>> = (0..10)
+            .map(|height| {
+                env.block.height = height;
+                active_agents
+                    .iter()
+                    .map(|agent| {
+                        balancer
+                            .get_agent_tasks(
+                                &deps.as_ref(),
+                                &env,
+                                &store.config,
+                                &store.agent_active_queue,
+                                &store.agents,
+                                agent.clone(),
+                                (None, Some(1)),
+                                slot_id,
+                                None,
+                            )
+                            .unwrap()
+                            .unwrap()
+                            .num_cron_tasks
+                            .u64()
+                    })
+                    .collect()
+            })
+            .collect();
+        assert!(leaders
+            .iter()
+            .all(|slot_leaders| *slot_leaders == leaders[0]));
+    }
+
+    #[test]
+    fn test_lottery_selection_ao_mode() {
+        let store = CwCroncat::default();
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let mut env = mock_env();
+        let mut balancer = RoundRobinBalancer::default();
+        let mut config = mock_config();
+        config.selection = SelectionMode::Lottery;
+        store.config.save(&mut deps.storage, &config).unwrap();
+
+        let active_agents = vec![
+            Addr::unchecked(AGENT0),
+            Addr::unchecked(AGENT1),
+            Addr::unchecked(AGENT2),
+        ];
+        store
+            .agent_active_queue
+            .save(&mut deps.storage, &active_agents)
+            .unwrap();
+        let save_bonds = |storage: &mut dyn Storage, bonds: [u128; 3]| {
+            for (agent_id, bond) in active_agents.iter().zip(bonds) {
+                let agent = Agent {
+                    // Other denoms don't count as stake
+                    bond: vec![Coin::new(bond, NATIVE_DENOM), Coin::new(1_000_000, "meow")],
+                    ..new_agent(agent_id.clone(), mock_env().block.time, vec![])
+                };
+                store.agents.save(storage, agent_id, &agent).unwrap();
+            }
+        };
+
+        let mut winners = |deps: &Deps, env: &mut Env| -> Vec<usize> {
+            (0..1500)
+                .map(|height| {
+                    env.block.height = height;
+                    let leaders: Vec<usize> = active_agents
+                        .iter()
+                        .enumerate()
+                        .filter(|(_, agent)| {
+                            balancer
+                                .get_agent_tasks(
+                                    deps,
+                                    env,
+                                    &store.config,
+                                    &store.agent_active_queue,
+                                    &store.agents,
+                                    (*agent).clone(),
+                                    (Some(1), None),
+                                    env.block.height,
+                                    None,
+                                )
+                                .unwrap()
+                                .unwrap()
+                                .num_block_tasks
+                                .u64()
+                                == 1
+                        })
+                        .map(|(i, _)| i)
+                        .collect();
+                    // Exactly one agent leads each slot
+                    assert_eq!(leaders.len(), 1);
+                    leaders[0]
+                })
+                .collect()
+        };
+        let counts = |winners: &[usize]| {
+            winners.iter().fold(vec![0u64; 3], |mut counts, winner| {
+                counts[*winner] += 1;
+                counts
+            })
+        };
+
+        save_bonds(&mut deps.storage, [100, 400, 1_000]);
+        let drawn = winners(&deps.as_ref(), &mut env);
+        let stake_counts = counts(&drawn);
+        assert!(stake_counts[0] > 0);
+        assert!(stake_counts[0] < stake_counts[1] && stake_counts[1] < stake_counts[2]);
+        // The same heights draw the same winners
+        assert_eq!(winners(&deps.as_ref(), &mut env), drawn);
+        assert_eq!(
+            (0..1500)
+                .map(|height| stake_lottery_winner(&[100, 400, 1_000], height))
+                .collect::<Vec<_>>(),
+            drawn
+        );
+
+        // Nothing bonded, everyone gets picked
+        save_bonds(&mut deps.storage, [0, 0, 0]);
+        let drawn = winners(&deps.as_ref(), &mut env);
+        assert!(counts(&drawn).iter().all(|count| *count > 0));
+    }
 }
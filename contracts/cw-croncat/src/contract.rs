@@ -3,27 +3,41 @@ use crate::helpers::GenericBalance;
 use crate::state::{Config, CwCroncat};
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::{
-    to_binary, Binary, Coin, Deps, DepsMut, Env, MessageInfo, Reply, Response, StdResult,
+    to_binary, Addr, Binary, Coin, Decimal, Deps, DepsMut, Env, MessageInfo, Order, Reply,
+    Response, StdResult, Uint128,
 };
 use cw2::set_contract_version;
-use cw_croncat_core::msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
-use cw_croncat_core::traits::ResultFailed;
-use cw_croncat_core::types::SlotType;
+use cw_croncat_core::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg};
+use cw_croncat_core::types::{
+    PauseFlags, QueueRemovalMode, RewardRounding, SelectionMode, SlashDestination, SlotType,
+};
 
 // version info for migration info
 const CONTRACT_NAME: &str = "crates.io:cw-croncat";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 const DEFAULT_NOMINATION_DURATION: u16 = 360;
+// how many slots in a row an agent can miss before it can be slashed
+pub(crate) const DEFAULT_MISSED_SLOT_THRESHOLD: u64 = 600;
+// 1 day
+pub(crate) const DEFAULT_STALE_TIMEOUT_NANOS: u64 = 86_400_000_000_000;
+// Block time estimate, 6 seconds
+pub(crate) const DEFAULT_SLOT_DURATION_NANOS: u64 = 6_000_000_000;
+// Loyalty tiers can't pay more than 2x the agent fee
+pub(crate) const MAX_LOYALTY_MULTIPLIER_BPS: u64 = 20_000;
+// Owner boosts for single agents go up to 2x unless configured otherwise
+pub(crate) const DEFAULT_MAX_REWARD_MULTIPLIER_BPS: u16 = 20_000;
 
 // default for juno
 pub(crate) const GAS_BASE_FEE_JUNO: u64 = 400_000;
+// Reply id of position hook pings, kept clear of the reply queue ids
+pub(crate) const POSITION_HOOK_REPLY_ID: u64 = u64::MAX;
 
 // #[cfg(not(feature = "library"))]
 impl<'a> CwCroncat<'a> {
     pub fn instantiate(
         &self,
         deps: DepsMut,
-        _env: Env,
+        env: Env,
         info: MessageInfo,
         msg: InstantiateMsg,
     ) -> Result<Response, ContractError> {
@@ -46,16 +60,22 @@ impl<'a> CwCroncat<'a> {
         };
 
         let config = Config {
-            paused: false,
+            paused: PauseFlags::default(),
             owner_id,
             // treasury_id: None,
             min_tasks_per_agent: 3,
+            min_agents_for_tasks: 0,
             agent_active_indices: vec![(SlotType::Block, 0, 0), (SlotType::Cron, 0, 0)],
             agents_eject_threshold: 600, // how many slots an agent can miss before being ejected. 10 * 60 = 1hr
+            missed_slot_threshold: DEFAULT_MISSED_SLOT_THRESHOLD,
             available_balance,
             staked_balance: GenericBalance::default(),
+            total_agent_balance: GenericBalance::default(),
+            protocol_revenue: GenericBalance::default(),
+            execution_nonce: 0,
+            total_missed_slots: 0,
             agent_fee: Coin::new(5, msg.denom.clone()), // TODO: CHANGE AMOUNT HERE!!! 0.0005 Juno (2000 tasks = 1 Juno)
-            gas_price: 1,
+            gas_price: Decimal::one(),
             proxy_callback_gas: 3,
             gas_base_fee,
             slot_granularity: 60_000_000_000,
@@ -68,6 +88,45 @@ impl<'a> CwCroncat<'a> {
                 .unwrap_or(DEFAULT_NOMINATION_DURATION),
             limit: 100,
             cw_rules_addr: cosmwasm_std::Addr::unchecked(&msg.cw_rules_addr), // deps.api.addr_validate(&msg.cw_rules_addr)?,
+            slash_destination: SlashDestination::Treasury,
+            stale_timeout_nanos: DEFAULT_STALE_TIMEOUT_NANOS,
+            registration_fee: None,
+            rotate_queue_each_slot: false,
+            max_agent_balance: None,
+            reward_rounding: RewardRounding::RoundDown,
+            loyalty_tiers: vec![],
+            max_agents: None,
+            max_pending_agents: None,
+            reward_escrow: None,
+            reward_min: None,
+            reward_max: None,
+            mass_exit_threshold: None,
+            allow_empty_active_set: true,
+            weighted_selection: false,
+            selection: SelectionMode::RoundRobin,
+            onboarders: vec![],
+            min_tasks_for_nomination: 0,
+            activation_cooldown_nanos: 0,
+            dust_threshold: Uint128::zero(),
+            reward_vesting_nanos: 0,
+            queue_removal_mode: QueueRemovalMode::PreserveOrder,
+            max_missed_nominations: 0,
+            exclusivity_window_slots: 0,
+            idle_grace_nanos: 0,
+            dex_router: None,
+            dex_max_slippage: Decimal::percent(1),
+            missed_slot_grace: 0,
+            slash_denom: None,
+            position_hook_step: 0,
+            blacklist: vec![],
+            max_reward_multiplier_bps: DEFAULT_MAX_REWARD_MULTIPLIER_BPS,
+            refund_stray_funds: false,
+            required_terms_hash: None,
+            auto_activate_open_slots: false,
+            slash_bps: 0,
+            allowed_reward_cw20: vec![],
+            slot_duration_nanos: DEFAULT_SLOT_DURATION_NANOS,
+            min_client_version: None,
         };
         set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
         self.config.save(deps.storage, &config)?;
@@ -79,11 +138,18 @@ impl<'a> CwCroncat<'a> {
         self.reply_index.save(deps.storage, &Default::default())?;
         self.agent_nomination_begin_time.save(deps.storage, &None)?;
         self.tasks_with_rules_total.save(deps.storage, &0)?;
+        let initial_agent = msg
+            .initial_agent
+            .map(|initial_agent| deps.api.addr_validate(&initial_agent))
+            .transpose()?;
+        if let Some(initial_agent) = initial_agent.clone() {
+            self.register_genesis_agent(deps.storage, &env, initial_agent)?;
+        }
 
         // all instantiated data
-        Ok(Response::new()
+        let mut response = Response::new()
             .add_attribute("method", "instantiate")
-            .add_attribute("paused", config.paused.to_string())
+            .add_attribute("paused", config.paused.any().to_string())
             .add_attribute("owner_id", config.owner_id.to_string())
             // .add_attribute(
             //     "treasury_id",
@@ -112,7 +178,37 @@ impl<'a> CwCroncat<'a> {
             .add_attribute("agent_fee", config.agent_fee.to_string())
             .add_attribute("gas_price", config.gas_price.to_string())
             .add_attribute("proxy_callback_gas", config.proxy_callback_gas.to_string())
-            .add_attribute("slot_granularity", config.slot_granularity.to_string()))
+            .add_attribute("slot_granularity", config.slot_granularity.to_string());
+        if let Some(initial_agent) = initial_agent {
+            response = response.add_attribute("initial_agent", initial_agent);
+        }
+        Ok(response)
+    }
+
+    /// Rewrites the config in the current layout, fields older versions didn't store get their defaults.
+    /// `total_agent_balance` is rebuilt from the agent balances, older versions didn't track it
+    pub fn migrate(
+        &self,
+        deps: DepsMut,
+        _env: Env,
+        _msg: MigrateMsg,
+    ) -> Result<Response, ContractError> {
+        let mut config: Config = self.config.load(deps.storage)?;
+        // Older versions didn't track the sum of agent balances
+        config.total_agent_balance = self.agent_liabilities(deps.storage)?;
+        self.config.save(deps.storage, &config)?;
+        // Nor index agents by payable account
+        let payables = self
+            .agents
+            .range(deps.storage, None, None, Order::Ascending)
+            .map(|item| item.map(|(agent_id, agent)| (agent_id, agent.payable_account_id)))
+            .collect::<StdResult<Vec<(Addr, Addr)>>>()?;
+        for (agent_id, payable_account_id) in payables {
+            self.index_payable(deps.storage, &payable_account_id, &agent_id)?;
+        }
+        set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+
+        Ok(Response::new().add_attribute("method", "migrate"))
     }
 
     pub fn execute(
@@ -124,19 +220,105 @@ impl<'a> CwCroncat<'a> {
     ) -> Result<Response, ContractError> {
         match msg {
             ExecuteMsg::UpdateSettings { .. } => self.update_settings(deps, info, msg),
+            ExecuteMsg::UpdatePauseFlags {
+                register,
+                execute,
+                withdraw,
+                create_task,
+            } => self.update_pause_flags(deps, info, register, execute, withdraw, create_task),
+            ExecuteMsg::UpdateMissedSlotThreshold { threshold } => {
+                self.update_missed_slot_threshold(deps, info, threshold)
+            }
+            ExecuteMsg::WithdrawProtocolRevenue { recipient } => {
+                self.withdraw_protocol_revenue(deps, info, recipient)
+            }
+            ExecuteMsg::SweepStuckFunds { denom, recipient } => {
+                self.sweep_stuck_funds(deps, info, env, denom, recipient)
+            }
             ExecuteMsg::MoveBalances {
                 balances,
                 account_id,
             } => self.move_balances(deps, info, env, balances, account_id),
 
-            ExecuteMsg::RegisterAgent { payable_account_id } => {
-                self.register_agent(deps, info, env, payable_account_id)
+            ExecuteMsg::RegisterAgent {
+                payable_account_id,
+                capabilities,
+                accepted_terms,
+                client_version,
+            } => self.register_agent(
+                deps,
+                info,
+                env,
+                payable_account_id,
+                capabilities,
+                accepted_terms,
+                client_version,
+            ),
+            ExecuteMsg::RegisterAgentFor {
+                target,
+                payable_account_id,
+            } => self.register_agent_for(deps, info, env, target, payable_account_id),
+            ExecuteMsg::UpdateAgent {
+                payable_account_id,
+                capabilities,
+                client_version,
+            } => self.update_agent(
+                deps,
+                info,
+                env,
+                payable_account_id,
+                capabilities,
+                client_version,
+            ),
+            ExecuteMsg::UpdateAgentOperators { operators } => {
+                self.update_agent_operators(deps, info, operators)
+            }
+            ExecuteMsg::UpdateAgentFull { params } => {
+                self.update_agent_full(deps, info, env, params)
+            }
+            ExecuteMsg::SetAcceptedTaskTypes { task_types } => {
+                self.set_accepted_task_types(deps, info, task_types)
+            }
+            ExecuteMsg::SetPositionHook { hook } => self.set_position_hook(deps, info, hook),
+            ExecuteMsg::SetStreamRewards { stream } => self.set_stream_rewards(deps, info, stream),
+            ExecuteMsg::MigrateAgent { new_address } => {
+                self.migrate_agent(deps, info, env, new_address)
             }
-            ExecuteMsg::UpdateAgent { payable_account_id } => {
-                self.update_agent(deps, info, env, payable_account_id)
+            ExecuteMsg::UpdateRewardPreference { preference } => {
+                self.update_reward_preference(deps, info, preference)
             }
+            ExecuteMsg::ToggleAgentPause {} => self.toggle_agent_pause(deps, info, env),
+            ExecuteMsg::ToggleAutoCompound {} => self.toggle_auto_compound(deps, info),
+            ExecuteMsg::CreateAgentPool { pool_id } => self.create_agent_pool(deps, info, pool_id),
+            ExecuteMsg::JoinAgentPool { pool_id } => self.join_agent_pool(deps, info, pool_id),
+            ExecuteMsg::ApproveAgentPoolMember {
+                pool_id,
+                agent_id,
+                weight,
+            } => self.approve_agent_pool_member(deps, info, pool_id, agent_id, weight),
+            ExecuteMsg::LeaveAgentPool {} => self.leave_agent_pool(deps, info),
             ExecuteMsg::UnregisterAgent {} => self.unregister_agent(deps, info, env),
-            ExecuteMsg::WithdrawReward {} => self.withdraw_agent_balance(deps, info, env),
+            ExecuteMsg::WithdrawReward {
+                idempotency_key,
+                memo,
+            } => self.withdraw_agent_balance(deps, info, env, idempotency_key, memo),
+            ExecuteMsg::WithdrawAllForPayable { start_after, limit } => {
+                self.withdraw_all_for_payable(deps, info, env, start_after, limit)
+            }
+            ExecuteMsg::SlashAgent { agent_id } => self.slash_agent(deps, info, env, agent_id),
+            ExecuteMsg::ReinstateAgent { account_id, refund } => {
+                self.reinstate_agent(deps, info, account_id, refund)
+            }
+            ExecuteMsg::SetAgentRewardMultiplier {
+                account_id,
+                multiplier_bps,
+            } => self.set_agent_reward_multiplier(deps, info, account_id, multiplier_bps),
+            ExecuteMsg::DistributeBonus { total } => self.distribute_bonus(deps, info, env, total),
+            ExecuteMsg::ReplaceActiveAgent { remove, promote } => {
+                self.replace_active_agent(deps, info, remove, promote)
+            }
+            ExecuteMsg::RotateActiveSet {} => self.rotate_active_set(deps, info, env),
+            ExecuteMsg::RepairQueues {} => self.repair_queues(deps, info),
             ExecuteMsg::CheckInAgent {} => self.accept_nomination_agent(deps, info, env),
 
             ExecuteMsg::CreateTask { task } => self.create_task(deps, info, env, task),
@@ -167,7 +349,80 @@ impl<'a> CwCroncat<'a> {
             QueryMsg::GetAgent { account_id } => {
                 to_binary(&self.query_get_agent(deps, env, account_id)?)
             }
+            QueryMsg::GetAgents { account_ids } => {
+                to_binary(&self.query_get_agents(deps, env, account_ids)?)
+            }
+            QueryMsg::GetAllAgents { start_after, limit } => {
+                to_binary(&self.query_all_agents(deps, env, start_after, limit)?)
+            }
+            QueryMsg::GetAgentBalance { account_id } => {
+                to_binary(&self.query_get_agent_balance(deps, account_id)?)
+            }
+            QueryMsg::GetAgentLiveness { account_id } => {
+                to_binary(&self.query_agent_liveness(deps, env, account_id)?)
+            }
+            QueryMsg::GetAgentTenure { account_id } => {
+                to_binary(&self.query_agent_tenure(deps, env, account_id)?)
+            }
+            QueryMsg::GetAgentRank { account_id } => {
+                to_binary(&self.query_agent_rank(deps, account_id)?)
+            }
+            QueryMsg::GetAgentExecutionWindow { account_id } => {
+                to_binary(&self.query_agent_execution_window(deps, env, account_id)?)
+            }
+            QueryMsg::GetNextSlotForAgent {
+                account_id,
+                from_slot,
+            } => to_binary(&self.query_next_slot_for_agent(deps, account_id, from_slot)?),
+            QueryMsg::GetProjectedRewards {
+                account_id,
+                horizon_slots,
+            } => to_binary(&self.query_projected_rewards(deps, env, account_id, horizon_slots)?),
+            QueryMsg::GetAgentRewardDenom { account_id } => {
+                to_binary(&self.query_agent_reward_denom(deps, account_id)?)
+            }
+            QueryMsg::GetNetRewardEstimate { account_id } => {
+                to_binary(&self.query_net_reward_estimate(deps, env, account_id)?)
+            }
+            QueryMsg::GetVestedBalance { account_id } => {
+                to_binary(&self.query_vested_balance(deps, env, account_id)?)
+            }
+            QueryMsg::GetAgentEscrow { account_id } => {
+                to_binary(&self.query_agent_escrow(deps, account_id)?)
+            }
             QueryMsg::GetAgentIds {} => to_binary(&self.query_get_agent_ids(deps)?),
+            QueryMsg::GetOldestPendingAgent {} => {
+                to_binary(&self.query_oldest_pending_agent(deps)?)
+            }
+            QueryMsg::GetAgentsWithCapability { capability } => {
+                to_binary(&self.query_agents_with_capability(deps, capability)?)
+            }
+            QueryMsg::GetAgentsByPayable { payable_account_id } => {
+                to_binary(&self.query_agents_by_payable(deps, payable_account_id)?)
+            }
+            QueryMsg::GetSlashableAgents {} => to_binary(&self.query_slashable_agents(deps)?),
+            QueryMsg::GetConfigChangeImpact { proposed } => {
+                to_binary(&self.query_config_change_impact(deps, proposed)?)
+            }
+            QueryMsg::GetMissedSlotStats {} => to_binary(&self.query_missed_slot_stats(deps)?),
+            QueryMsg::GetTopStreak {} => to_binary(&self.query_top_streak(deps)?),
+            QueryMsg::GetIdleAgents { from_index, limit } => {
+                to_binary(&self.query_idle_agents(deps, env, from_index, limit)?)
+            }
+            QueryMsg::GetExecutionDistribution { from_index, limit } => {
+                to_binary(&self.query_execution_distribution(deps, from_index, limit)?)
+            }
+            QueryMsg::GetTotalAgentValue {} => to_binary(&self.query_total_agent_value(deps)?),
+            QueryMsg::GetAgentBalanceDrift {} => to_binary(&self.query_agent_balance_drift(deps)?),
+            QueryMsg::GetSolvency {} => to_binary(&self.query_solvency(deps, env)?),
+            QueryMsg::GetQueueIntegrity {} => to_binary(&self.query_queue_integrity(deps)?),
+            QueryMsg::GetQueueCapacity {} => to_binary(&self.query_queue_capacity(deps)?),
+            QueryMsg::GetNominationConfig {} => {
+                to_binary(&self.query_nomination_config(deps, env)?)
+            }
+            QueryMsg::GetNominationStatus { account_id } => {
+                to_binary(&self.query_nomination_status(deps, env, account_id)?)
+            }
             QueryMsg::GetAgentTasks { account_id } => {
                 to_binary(&self.query_get_agent_tasks(deps, env, account_id)?)
             }
@@ -187,7 +442,16 @@ impl<'a> CwCroncat<'a> {
                 to_binary(&self.query_validate_interval(interval)?)
             }
             QueryMsg::GetSlotHashes { slot } => to_binary(&self.query_slot_tasks(deps, slot)?),
+            QueryMsg::GetTaskCreationAllowed {} => {
+                to_binary(&self.query_task_creation_allowed(deps)?)
+            }
             QueryMsg::GetSlotIds {} => to_binary(&self.query_slot_ids(deps)?),
+            QueryMsg::GetSlotDuration {} => to_binary(&self.query_slot_duration(deps)?),
+            QueryMsg::GetCurrentSlotBudget {} => {
+                to_binary(&self.query_current_slot_budget(deps, env)?)
+            }
+            QueryMsg::GetHealth {} => to_binary(&self.query_health(deps, env)?),
+            QueryMsg::GetProtocolRevenue {} => to_binary(&self.query_protocol_revenue(deps)?),
             QueryMsg::GetWalletBalances { wallet } => {
                 to_binary(&self.query_wallet_balances(deps, wallet)?)
             }
@@ -198,6 +462,10 @@ impl<'a> CwCroncat<'a> {
     }
 
     pub fn reply(&self, deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+        // A failing position hook shouldn't hold up the queue change that pinged it
+        if msg.id == POSITION_HOOK_REPLY_ID {
+            return Ok(Response::new().add_attribute("position_hook", "failed"));
+        }
         // Route the next fns with the reply queue id meta
         let queue_item = self
             .reply_queue
@@ -214,8 +482,8 @@ impl<'a> CwCroncat<'a> {
         {
             let task =
                 self.task_after_action(deps.storage, deps.api, queue_item, msg.result.is_ok())?;
-            let reply_submsg_failed = msg.result.failed();
-            let queue_item = self.rq_update_rq_item(deps.storage, msg.id, reply_submsg_failed)?;
+            let reply_error = msg.result.clone().into_result().err();
+            let queue_item = self.rq_update_rq_item(deps.storage, msg.id, reply_error)?;
             if queue_item.action_idx == task.actions.len() as u64 {
                 // Last action
                 self.rq_remove(deps.storage, msg.id);
@@ -242,11 +510,13 @@ mod tests {
         mock_dependencies_with_balance, mock_env, mock_info, MOCK_CONTRACT_ADDR,
     };
     use cosmwasm_std::{
-        coin, coins, from_binary, Addr, Binary, Event, Reply, SubMsgResponse, SubMsgResult,
+        coin, coins, from_binary, from_slice, to_vec, Addr, Binary, Event, Reply, Storage,
+        SubMsgResponse, SubMsgResult,
     };
-    use cw_croncat_core::msg::{GetConfigResponse, QueryMsg};
-    use cw_croncat_core::types::SlotType;
+    use cw_croncat_core::msg::{ConfigChange, GetConfigResponse, QueryMsg, UpdateAgentParams};
+    use cw_croncat_core::types::{AgentStatus, RewardPreference, SlotType};
     const AGENT0: &str = "cosmos1a7uhnpqthunr2rzj0ww0hwurpn42wyun6c5puz";
+    const AGENT1: &str = "cosmos17muvdgkep4ndptnyg38eufxsssq8jr3wnkysy8";
     #[test]
     fn configure() {
         let mut deps = mock_dependencies_with_balance(&coins(200, ""));
@@ -258,6 +528,7 @@ mod tests {
             gas_base_fee: None,
             agent_nomination_duration: Some(360),
             cw_rules_addr: "todo".to_string(),
+            initial_agent: None,
         };
         let info = mock_info("creator", &coins(1000, "meow"));
 
@@ -272,7 +543,7 @@ mod tests {
             .query(deps.as_ref(), mock_env(), QueryMsg::GetConfig {})
             .unwrap();
         let value: GetConfigResponse = from_binary(&res).unwrap();
-        assert_eq!(false, value.paused);
+        assert_eq!(PauseFlags::default(), value.paused);
         assert_eq!(info.sender, value.owner_id);
         // assert_eq!(None, value.treasury_id);
         assert_eq!(3, value.min_tasks_per_agent);
@@ -281,9 +552,10 @@ mod tests {
             value.agent_active_indices
         );
         assert_eq!(600, value.agents_eject_threshold);
+        assert_eq!(600, value.missed_slot_threshold);
         assert_eq!("atom", value.native_denom);
         assert_eq!(coin(5, "atom"), value.agent_fee);
-        assert_eq!(1, value.gas_price);
+        assert_eq!(Decimal::one(), value.gas_price);
         assert_eq!(3, value.proxy_callback_gas);
         assert_eq!(60_000_000_000, value.slot_granularity);
     }
@@ -359,6 +631,7 @@ mod tests {
                     task_is_extra: Some(false),
                     agent_id: Some(Addr::unchecked(AGENT0)),
                     failed: false,
+                    result: None,
                 },
             )
             .unwrap();
@@ -395,4 +668,521 @@ mod tests {
             .unwrap();
         assert_eq!(total, Some(0));
     }
+
+    #[test]
+    fn query_config_matches_instantiate() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, ""));
+        let mut store = CwCroncat::default();
+
+        let msg = InstantiateMsg {
+            denom: "juno".to_string(),
+            owner_id: Some("owner".to_string()),
+            gas_base_fee: Some(300_000u64.into()),
+            agent_nomination_duration: Some(20),
+            cw_rules_addr: "cw_rules".to_string(),
+            initial_agent: None,
+        };
+        store
+            .instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg)
+            .unwrap();
+
+        let res = store
+            .query(deps.as_ref(), mock_env(), QueryMsg::GetConfig {})
+            .unwrap();
+        let value: GetConfigResponse = from_binary(&res).unwrap();
+        assert_eq!(Addr::unchecked("owner"), value.owner_id);
+        assert_eq!("juno", value.native_denom);
+        assert_eq!(coin(5, "juno"), value.agent_fee);
+        assert_eq!(300_000, value.gas_base_fee);
+        assert_eq!(20, value.agent_nomination_duration);
+        assert_eq!(Addr::unchecked("cw_rules"), value.cw_rules_addr);
+        assert_eq!(Vec::<Addr>::new(), value.cw20_whitelist);
+        assert_eq!(100, value.limit);
+        assert_eq!(SlashDestination::Treasury, value.slash_destination);
+        assert_eq!(RewardRounding::RoundDown, value.reward_rounding);
+    }
+
+    // No wildcard arm on purpose: adding a message fails to compile until it's
+    // sorted here, and agent messages also need an entry in the test below
+    fn is_agent_execute_msg(msg: &ExecuteMsg) -> bool {
+        match msg {
+            ExecuteMsg::RegisterAgent { .. }
+            | ExecuteMsg::RegisterAgentFor { .. }
+            | ExecuteMsg::UpdateAgent { .. }
+            | ExecuteMsg::UpdateAgentOperators { .. }
+            | ExecuteMsg::UpdateAgentFull { .. }
+            | ExecuteMsg::SetAcceptedTaskTypes { .. }
+            | ExecuteMsg::SetPositionHook { .. }
+            | ExecuteMsg::SetStreamRewards { .. }
+            | ExecuteMsg::MigrateAgent { .. }
+            | ExecuteMsg::UpdateRewardPreference { .. }
+            | ExecuteMsg::ToggleAgentPause {}
+            | ExecuteMsg::ToggleAutoCompound {}
+            | ExecuteMsg::CreateAgentPool { .. }
+            | ExecuteMsg::JoinAgentPool { .. }
+            | ExecuteMsg::ApproveAgentPoolMember { .. }
+            | ExecuteMsg::LeaveAgentPool {}
+            | ExecuteMsg::CheckInAgent {}
+            | ExecuteMsg::UnregisterAgent {}
+            | ExecuteMsg::WithdrawReward { .. }
+            | ExecuteMsg::WithdrawAllForPayable { .. }
+            | ExecuteMsg::SlashAgent { .. }
+            | ExecuteMsg::ReinstateAgent { .. }
+            | ExecuteMsg::SetAgentRewardMultiplier { .. }
+            | ExecuteMsg::DistributeBonus { .. }
+            | ExecuteMsg::ReplaceActiveAgent { .. }
+            | ExecuteMsg::RotateActiveSet {}
+            | ExecuteMsg::RepairQueues {} => true,
+            ExecuteMsg::UpdateSettings { .. }
+            | ExecuteMsg::UpdatePauseFlags { .. }
+            | ExecuteMsg::UpdateMissedSlotThreshold { .. }
+            | ExecuteMsg::WithdrawProtocolRevenue { .. }
+            | ExecuteMsg::SweepStuckFunds { .. }
+            | ExecuteMsg::MoveBalances { .. }
+            | ExecuteMsg::CreateTask { .. }
+            | ExecuteMsg::RemoveTask { .. }
+            | ExecuteMsg::RefillTaskBalance { .. }
+            | ExecuteMsg::RefillTaskCw20Balance { .. }
+            | ExecuteMsg::ProxyCall { .. }
+            | ExecuteMsg::Receive(_)
+            | ExecuteMsg::WithdrawWalletBalance { .. } => false,
+        }
+    }
+
+    fn is_agent_query_msg(msg: &QueryMsg) -> bool {
+        match msg {
+            QueryMsg::GetAgent { .. }
+            | QueryMsg::GetAgents { .. }
+            | QueryMsg::GetAllAgents { .. }
+            | QueryMsg::GetAgentBalance { .. }
+            | QueryMsg::GetAgentLiveness { .. }
+            | QueryMsg::GetAgentTenure { .. }
+            | QueryMsg::GetAgentRank { .. }
+            | QueryMsg::GetAgentExecutionWindow { .. }
+            | QueryMsg::GetNextSlotForAgent { .. }
+            | QueryMsg::GetProjectedRewards { .. }
+            | QueryMsg::GetAgentRewardDenom { .. }
+            | QueryMsg::GetNetRewardEstimate { .. }
+            | QueryMsg::GetAgentEscrow { .. }
+            | QueryMsg::GetVestedBalance { .. }
+            | QueryMsg::GetAgentIds {}
+            | QueryMsg::GetOldestPendingAgent {}
+            | QueryMsg::GetAgentsWithCapability { .. }
+            | QueryMsg::GetAgentsByPayable { .. }
+            | QueryMsg::GetSlashableAgents {}
+            | QueryMsg::GetConfigChangeImpact { .. }
+            | QueryMsg::GetMissedSlotStats {}
+            | QueryMsg::GetTopStreak {}
+            | QueryMsg::GetIdleAgents { .. }
+            | QueryMsg::GetExecutionDistribution { .. }
+            | QueryMsg::GetTotalAgentValue {}
+            | QueryMsg::GetAgentBalanceDrift {}
+            | QueryMsg::GetSolvency {}
+            | QueryMsg::GetQueueIntegrity {}
+            | QueryMsg::GetQueueCapacity {}
+            | QueryMsg::GetNominationConfig {}
+            | QueryMsg::GetNominationStatus { .. }
+            | QueryMsg::GetAgentTasks { .. } => true,
+            QueryMsg::GetConfig {}
+            | QueryMsg::GetBalances {}
+            | QueryMsg::GetTasks { .. }
+            | QueryMsg::GetTasksWithRules { .. }
+            | QueryMsg::GetTasksByOwner { .. }
+            | QueryMsg::GetTask { .. }
+            | QueryMsg::GetTaskHash { .. }
+            | QueryMsg::ValidateInterval { .. }
+            | QueryMsg::GetSlotHashes { .. }
+            | QueryMsg::GetTaskCreationAllowed {}
+            | QueryMsg::GetSlotIds {}
+            | QueryMsg::GetSlotDuration {}
+            | QueryMsg::GetCurrentSlotBudget {}
+            | QueryMsg::GetHealth {}
+            | QueryMsg::GetProtocolRevenue {}
+            | QueryMsg::GetWalletBalances { .. }
+            | QueryMsg::GetState { .. } => false,
+        }
+    }
+
+    #[test]
+    fn agent_messages_are_dispatched() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, "atom"));
+        deps.querier.update_balance(AGENT0, coins(100, "atom"));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+
+        // Sender, message and the expected "method" attribute, None if the handler must reject it
+        let execute_msgs = vec![
+            (
+                AGENT0,
+                ExecuteMsg::RegisterAgent {
+                    payable_account_id: None,
+                    capabilities: None,
+                    accepted_terms: None,
+                    client_version: None,
+                },
+                Some("register_agent"),
+            ),
+            // Not an onboarder
+            (
+                AGENT0,
+                ExecuteMsg::RegisterAgentFor {
+                    target: AGENT1.to_string(),
+                    payable_account_id: None,
+                },
+                None,
+            ),
+            (
+                AGENT0,
+                ExecuteMsg::UpdateAgent {
+                    payable_account_id: AGENT0.to_string(),
+                    capabilities: None,
+                    client_version: None,
+                },
+                Some("update_agent"),
+            ),
+            (
+                AGENT0,
+                ExecuteMsg::UpdateAgentOperators { operators: vec![] },
+                Some("update_agent_operators"),
+            ),
+            (
+                AGENT0,
+                ExecuteMsg::UpdateAgentFull {
+                    params: UpdateAgentParams {
+                        auto_compound: Some(false),
+                        ..Default::default()
+                    },
+                },
+                Some("update_agent_full"),
+            ),
+            (
+                AGENT0,
+                ExecuteMsg::SetAcceptedTaskTypes { task_types: vec![] },
+                Some("set_accepted_task_types"),
+            ),
+            (
+                AGENT0,
+                ExecuteMsg::SetPositionHook { hook: None },
+                Some("set_position_hook"),
+            ),
+            (
+                AGENT0,
+                ExecuteMsg::SetStreamRewards { stream: true },
+                Some("set_stream_rewards"),
+            ),
+            (
+                AGENT0,
+                ExecuteMsg::UpdateRewardPreference {
+                    preference: RewardPreference::Native,
+                },
+                Some("update_reward_preference"),
+            ),
+            (
+                AGENT0,
+                ExecuteMsg::ToggleAgentPause {},
+                Some("toggle_agent_pause"),
+            ),
+            (
+                AGENT0,
+                ExecuteMsg::ToggleAutoCompound {},
+                Some("toggle_auto_compound"),
+            ),
+            (
+                AGENT0,
+                ExecuteMsg::CreateAgentPool {
+                    pool_id: "pool".to_string(),
+                },
+                Some("create_agent_pool"),
+            ),
+            // Already in it
+            (
+                AGENT0,
+                ExecuteMsg::JoinAgentPool {
+                    pool_id: "pool".to_string(),
+                },
+                None,
+            ),
+            (
+                AGENT0,
+                ExecuteMsg::ApproveAgentPoolMember {
+                    pool_id: "pool".to_string(),
+                    agent_id: AGENT0.to_string(),
+                    weight: 2,
+                },
+                Some("approve_agent_pool_member"),
+            ),
+            (
+                AGENT0,
+                ExecuteMsg::LeaveAgentPool {},
+                Some("leave_agent_pool"),
+            ),
+            // Already active
+            (AGENT0, ExecuteMsg::CheckInAgent {}, None),
+            (
+                AGENT0,
+                ExecuteMsg::WithdrawReward {
+                    idempotency_key: None,
+                    memo: None,
+                },
+                Some("withdraw_agent_balance"),
+            ),
+            (
+                AGENT0,
+                ExecuteMsg::WithdrawAllForPayable {
+                    start_after: None,
+                    limit: None,
+                },
+                Some("withdraw_all_for_payable"),
+            ),
+            // Hasn't missed any slots
+            (
+                "creator",
+                ExecuteMsg::SlashAgent {
+                    agent_id: AGENT0.to_string(),
+                },
+                None,
+            ),
+            // Nothing native was slashed
+            (
+                "creator",
+                ExecuteMsg::ReinstateAgent {
+                    account_id: AGENT0.to_string(),
+                    refund: true,
+                },
+                None,
+            ),
+            (
+                "creator",
+                ExecuteMsg::SetAgentRewardMultiplier {
+                    account_id: AGENT0.to_string(),
+                    multiplier_bps: 12_000,
+                },
+                Some("set_agent_reward_multiplier"),
+            ),
+            // The bonus has to be attached
+            (
+                "creator",
+                ExecuteMsg::DistributeBonus {
+                    total: coin(10, "atom"),
+                },
+                None,
+            ),
+            // Nobody pending
+            (
+                "creator",
+                ExecuteMsg::ReplaceActiveAgent {
+                    remove: AGENT0.to_string(),
+                    promote: AGENT1.to_string(),
+                },
+                None,
+            ),
+            ("creator", ExecuteMsg::RotateActiveSet {}, None),
+            (
+                "creator",
+                ExecuteMsg::RepairQueues {},
+                Some("repair_queues"),
+            ),
+            (
+                AGENT0,
+                ExecuteMsg::MigrateAgent {
+                    new_address: AGENT1.to_string(),
+                },
+                Some("migrate_agent"),
+            ),
+            (
+                AGENT1,
+                ExecuteMsg::UnregisterAgent {},
+                Some("unregister_agent"),
+            ),
+        ];
+        let query_msgs = vec![
+            QueryMsg::GetAgent {
+                account_id: AGENT0.to_string(),
+            },
+            QueryMsg::GetAgents {
+                account_ids: vec![AGENT0.to_string(), AGENT1.to_string()],
+            },
+            QueryMsg::GetAllAgents {
+                start_after: None,
+                limit: None,
+            },
+            QueryMsg::GetAgentBalance {
+                account_id: AGENT0.to_string(),
+            },
+            QueryMsg::GetAgentLiveness {
+                account_id: AGENT0.to_string(),
+            },
+            QueryMsg::GetAgentTenure {
+                account_id: AGENT0.to_string(),
+            },
+            QueryMsg::GetAgentRank {
+                account_id: AGENT0.to_string(),
+            },
+            QueryMsg::GetAgentExecutionWindow {
+                account_id: AGENT0.to_string(),
+            },
+            QueryMsg::GetNextSlotForAgent {
+                account_id: AGENT0.to_string(),
+                from_slot: 0,
+            },
+            QueryMsg::GetProjectedRewards {
+                account_id: AGENT0.to_string(),
+                horizon_slots: 10,
+            },
+            QueryMsg::GetAgentRewardDenom {
+                account_id: AGENT0.to_string(),
+            },
+            QueryMsg::GetNetRewardEstimate {
+                account_id: AGENT0.to_string(),
+            },
+            QueryMsg::GetAgentEscrow {
+                account_id: AGENT0.to_string(),
+            },
+            QueryMsg::GetVestedBalance {
+                account_id: AGENT0.to_string(),
+            },
+            QueryMsg::GetAgentIds {},
+            QueryMsg::GetOldestPendingAgent {},
+            QueryMsg::GetAgentsWithCapability {
+                capability: "ibc-relay".to_string(),
+            },
+            QueryMsg::GetAgentsByPayable {
+                payable_account_id: AGENT0.to_string(),
+            },
+            QueryMsg::GetSlashableAgents {},
+            QueryMsg::GetConfigChangeImpact {
+                proposed: ConfigChange::default(),
+            },
+            QueryMsg::GetMissedSlotStats {},
+            QueryMsg::GetTopStreak {},
+            QueryMsg::GetIdleAgents {
+                from_index: None,
+                limit: None,
+            },
+            QueryMsg::GetExecutionDistribution {
+                from_index: None,
+                limit: None,
+            },
+            QueryMsg::GetTotalAgentValue {},
+            QueryMsg::GetAgentBalanceDrift {},
+            QueryMsg::GetSolvency {},
+            QueryMsg::GetQueueIntegrity {},
+            QueryMsg::GetQueueCapacity {},
+            QueryMsg::GetNominationConfig {},
+            QueryMsg::GetNominationStatus {
+                account_id: AGENT0.to_string(),
+            },
+            QueryMsg::GetAgentTasks {
+                account_id: AGENT0.to_string(),
+            },
+        ];
+
+        for (sender, msg, expected_method) in execute_msgs {
+            assert!(is_agent_execute_msg(&msg));
+            let msg: ExecuteMsg = from_slice(&to_vec(&msg).unwrap()).unwrap();
+            let res = store.execute(deps.as_mut(), mock_env(), mock_info(sender, &[]), msg);
+
+            // Queries run while the agent is registered and active
+            if expected_method == Some("register_agent") {
+                for query_msg in query_msgs.iter() {
+                    assert!(is_agent_query_msg(query_msg));
+                    let query_msg: QueryMsg = from_slice(&to_vec(query_msg).unwrap()).unwrap();
+                    store.query(deps.as_ref(), mock_env(), query_msg).unwrap();
+                }
+            }
+
+            match expected_method {
+                Some(method) => {
+                    let res = res.unwrap();
+                    assert!(res
+                        .attributes
+                        .iter()
+                        .any(|a| a.key == "method" && a.value == method));
+                }
+                None => assert!(res.is_err()),
+            }
+        }
+    }
+
+    #[test]
+    fn instantiate_with_initial_agent() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, ""));
+        let store = CwCroncat::default();
+        let msg = InstantiateMsg {
+            denom: "atom".to_string(),
+            owner_id: None,
+            gas_base_fee: None,
+            agent_nomination_duration: None,
+            cw_rules_addr: "todo".to_string(),
+            initial_agent: Some(AGENT0.to_string()),
+        };
+        // The agent wallet holds nothing, it doesn't need to
+        let res = store
+            .instantiate(deps.as_mut(), mock_env(), mock_info("creator", &[]), msg)
+            .unwrap();
+        assert!(res
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "initial_agent" && attr.value == AGENT0));
+
+        let agent_ids = store.query_get_agent_ids(deps.as_ref()).unwrap();
+        assert_eq!(agent_ids.active, vec![Addr::unchecked(AGENT0)]);
+        assert!(agent_ids.pending.is_empty());
+        assert_eq!(
+            store
+                .get_agent_status(&deps.storage, mock_env(), Addr::unchecked(AGENT0))
+                .unwrap(),
+            AgentStatus::Active
+        );
+        let agent = store
+            .agents
+            .load(&deps.storage, &Addr::unchecked(AGENT0))
+            .unwrap();
+        assert_eq!(agent.payable_account_id, Addr::unchecked(AGENT0));
+    }
+
+    #[test]
+    fn migrate_legacy_state() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, "atom"));
+        let store = CwCroncat::default();
+        // Config and agent as the first release stored them
+        deps.storage.set(
+            b"config",
+            br#"{"paused":false,"owner_id":"creator","min_tasks_per_agent":3,"agent_active_indices":[["Block",0,0],["Cron",0,0]],"agents_eject_threshold":600,"agent_nomination_duration":360,"cw_rules_addr":"cw_rules","agent_fee":{"denom":"atom","amount":"5"},"gas_price":1,"gas_base_fee":300000,"proxy_callback_gas":3,"slot_granularity":60000000000,"cw20_whitelist":[],"native_denom":"atom","available_balance":{"native":[{"denom":"atom","amount":"200"}],"cw20":[]},"staked_balance":{"native":[],"cw20":[]},"limit":100}"#,
+        );
+        let agent_id = Addr::unchecked(AGENT0);
+        let legacy_agent = format!(
+            r#"{{"payable_account_id":"{}","balance":{{"native":[{{"denom":"atom","amount":"7"}}],"cw20":[]}},"total_tasks_executed":2,"last_missed_slot":0,"register_start":"1571797419879305533"}}"#,
+            AGENT0
+        );
+        deps.storage
+            .set(&store.agents.key(&agent_id), legacy_agent.as_bytes());
+
+        let res = store
+            .migrate(deps.as_mut(), mock_env(), MigrateMsg {})
+            .unwrap();
+        assert_eq!(res.attributes, vec![("method", "migrate")]);
+
+        let config = store.config.load(&deps.storage).unwrap();
+        assert!(!config.paused.register && !config.paused.execute && !config.paused.withdraw);
+        assert_eq!(config.missed_slot_threshold, DEFAULT_MISSED_SLOT_THRESHOLD);
+        assert_eq!(config.slot_duration_nanos, DEFAULT_SLOT_DURATION_NANOS);
+        assert!(config.allow_empty_active_set);
+        assert_eq!(config.total_agent_balance.native, coins(7, "atom"));
+
+        let agent = store.agents.load(&deps.storage, &agent_id).unwrap();
+        assert_eq!(agent.total_tasks_executed, 2);
+        assert_eq!(agent.reward_multiplier_bps, 10_000);
+        assert_eq!(agent.reward_preference, RewardPreference::Native);
+        assert_eq!(
+            store
+                .agents_by_payable
+                .load(&deps.storage, &agent_id)
+                .unwrap(),
+            vec![agent_id.clone()]
+        );
+
+        let version = cw2::get_contract_version(&deps.storage).unwrap();
+        assert_eq!(version.contract, CONTRACT_NAME);
+    }
 }
@@ -24,6 +24,33 @@ pub enum ContractError {
     #[error("Agent not registered")]
     AgentNotRegistered {},
 
+    #[error("Agent is paused")]
+    AgentPaused {},
+
+    #[error("Too many operators, max is {max:?}")]
+    TooManyOperators { max: usize },
+
+    #[error("Operator {operator:?} is already in use")]
+    OperatorInUse { operator: String },
+
+    #[error("Too many capabilities, max is {max:?}")]
+    TooManyCapabilities { max: usize },
+
+    #[error("Capability {capability:?} must be 1 to {max_length:?} characters")]
+    InvalidCapability {
+        capability: String,
+        max_length: usize,
+    },
+
+    #[error("Payable account can't be the contract itself")]
+    SelfPayableAccount {},
+
+    #[error("Payable account is blacklisted")]
+    BlacklistedPayableAccount {},
+
+    #[error("Cw20 {addr:?} isn't allowed as an agent reward")]
+    RewardCw20NotAllowed { addr: String },
+
     #[error("{val:?} is paused")]
     ContractPaused { val: String },
 
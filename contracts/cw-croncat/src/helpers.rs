@@ -6,13 +6,13 @@ use crate::state::{Config, QueueItem};
 use crate::ContractError::AgentNotRegistered;
 use crate::{ContractError, CwCroncat};
 use cosmwasm_std::{
-    to_binary, Addr, Api, BankMsg, Coin, CosmosMsg, Env, StdResult, Storage, SubMsg, SubMsgResult,
-    WasmMsg,
+    to_binary, Addr, Api, BankMsg, BlockInfo, Coin, CosmosMsg, Decimal, Env, StdResult, Storage,
+    SubMsg, SubMsgResult, Uint128, WasmMsg,
 };
 use cw20::{Cw20CoinVerified, Cw20ExecuteMsg};
 use cw_croncat_core::msg::ExecuteMsg;
 use cw_croncat_core::traits::{BalancesOperations, FindAndMutate};
-use cw_croncat_core::types::AgentStatus;
+use cw_croncat_core::types::{AgentStatus, SlotType};
 pub use cw_croncat_core::types::{GenericBalance, Task};
 //use regex::Regex;
 use schemars::JsonSchema;
@@ -77,6 +77,45 @@ pub(crate) fn send_tokens(
     Ok((msgs, coins))
 }
 
+/// Wall-clock nanos spanned by `slots` slots of `slot_type`.
+/// Block slots last `slot_duration_nanos`, cron slots `slot_granularity`
+pub(crate) fn slots_to_nanos(config: &Config, slot_type: SlotType, slots: u64) -> u64 {
+    let duration = match slot_type {
+        SlotType::Block => config.slot_duration_nanos,
+        SlotType::Cron => config.slot_granularity,
+    };
+    slots.saturating_mul(duration)
+}
+
+/// Splits a MAJOR.MINOR.PATCH client version into comparable parts
+pub(crate) fn parse_client_version(version: &str) -> Result<(u64, u64, u64), ContractError> {
+    let invalid = || ContractError::CustomError {
+        val: format!("Client version {:?} must be MAJOR.MINOR.PATCH", version),
+    };
+    let mut parts = version.split('.').map(|part| part.parse::<u64>());
+    match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(Ok(major)), Some(Ok(minor)), Some(Ok(patch)), None) => Ok((major, minor, patch)),
+        _ => Err(invalid()),
+    }
+}
+
+/// Cost of `gas` units at `gas_price`, rounded up to a whole coin amount
+pub(crate) fn gas_amount_ceil(gas: u64, gas_price: Decimal) -> Result<Uint128, ContractError> {
+    let overflow = || ContractError::CustomError {
+        val: "Gas cost overflows".to_string(),
+    };
+    // Any u64 fits in a Decimal, only the product can overflow
+    let cost = Decimal::from_ratio(gas, 1u128)
+        .checked_mul(gas_price)
+        .map_err(|_| overflow())?;
+    let amount = Uint128::new(1) * cost;
+    if Decimal::from_ratio(amount, 1u128) < cost {
+        amount.checked_add(Uint128::new(1)).map_err(|_| overflow())
+    } else {
+        Ok(amount)
+    }
+}
+
 /// has_cw_coins returns true if the list of CW20 coins has at least the required amount
 pub(crate) fn has_cw_coins(coins: &[Cw20CoinVerified], required: &Cw20CoinVerified) -> bool {
     coins
@@ -154,8 +193,9 @@ impl<'a> CwCroncat<'a> {
             let num_agents_to_accept =
                 self.agents_to_let_in(&min_tasks_per_agent, &num_active_agents, &total_tasks);
             let agent_nomination_begin_time = self.agent_nomination_begin_time.load(storage)?;
+            let suspended = self.nomination_suspended(storage, &env.block, &c)?;
             match agent_nomination_begin_time {
-                Some(begin_time) if num_agents_to_accept > 0 => {
+                Some(begin_time) if num_agents_to_accept > 0 && !suspended => {
                     let time_difference = block_time - begin_time.seconds();
 
                     let max_index = cmp::max(
@@ -182,6 +222,20 @@ impl<'a> CwCroncat<'a> {
         Ok(agent_status)
     }
 
+    /// Nomination waits while fewer tasks than `Config.min_tasks_for_nomination` are due,
+    /// more active agents would only split the same few rewards
+    pub(crate) fn nomination_suspended(
+        &self,
+        storage: &dyn Storage,
+        block: &BlockInfo,
+        config: &Config,
+    ) -> StdResult<bool> {
+        if config.min_tasks_for_nomination == 0 {
+            return Ok(false);
+        }
+        Ok(self.due_task_count(storage, block)? < config.min_tasks_for_nomination)
+    }
+
     pub fn agents_to_let_in(
         &self,
         max_tasks: &u64,
@@ -283,7 +337,7 @@ pub mod test_helpers {
         testing::{mock_env, mock_info},
         DepsMut, Empty, Response,
     };
-    use cw_croncat_core::msg::InstantiateMsg;
+    use cw_croncat_core::msg::{ExecuteMsg, InstantiateMsg};
 
     use crate::{ContractError, CwCroncat};
 
@@ -294,8 +348,63 @@ pub mod test_helpers {
             gas_base_fee: None,
             agent_nomination_duration: Some(360),
             cw_rules_addr: "todo".to_string(),
+            initial_agent: None,
         };
         let info = mock_info("creator", &coins(1000, "meow"));
         store.instantiate(deps, mock_env(), info.clone(), msg)
     }
+
+    /// UpdateSettings changing nothing, tests set the fields they change
+    pub fn update_settings_msg() -> ExecuteMsg {
+        ExecuteMsg::UpdateSettings {
+            owner_id: None,
+            slot_granularity: None,
+            paused: None,
+            agent_fee: None,
+            gas_price: None,
+            proxy_callback_gas: None,
+            min_tasks_per_agent: None,
+            agents_eject_threshold: None,
+            slash_destination: None,
+            stale_timeout_nanos: None,
+            registration_fee: None,
+            rotate_queue_each_slot: None,
+            min_agents_for_tasks: None,
+            max_agent_balance: None,
+            reward_rounding: None,
+            loyalty_tiers: None,
+            max_agents: None,
+            max_pending_agents: None,
+            reward_escrow: None,
+            reward_min: None,
+            reward_max: None,
+            mass_exit_threshold: None,
+            allow_empty_active_set: None,
+            weighted_selection: None,
+            selection: None,
+            onboarders: None,
+            min_tasks_for_nomination: None,
+            activation_cooldown_nanos: None,
+            dust_threshold: None,
+            reward_vesting_nanos: None,
+            queue_removal_mode: None,
+            max_missed_nominations: None,
+            exclusivity_window_slots: None,
+            idle_grace_nanos: None,
+            dex_router: None,
+            dex_max_slippage: None,
+            missed_slot_grace: None,
+            slash_denom: None,
+            position_hook_step: None,
+            blacklist: None,
+            max_reward_multiplier_bps: None,
+            refund_stray_funds: None,
+            required_terms_hash: None,
+            auto_activate_open_slots: None,
+            slash_bps: None,
+            allowed_reward_cw20: None,
+            slot_duration_nanos: None,
+            min_client_version: None,
+        }
+    }
 }
@@ -1,15 +1,145 @@
+use crate::agent::MAX_VESTING_CREDITS;
 use crate::balancer::Balancer;
+use crate::contract::MAX_LOYALTY_MULTIPLIER_BPS;
 use crate::error::ContractError;
-use crate::helpers::ReplyMsgParser;
+use crate::helpers::{slots_to_nanos, ReplyMsgParser};
 use crate::state::{Config, CwCroncat, QueueItem, TaskInfo};
 use cosmwasm_std::{
-    coin, Addr, Coin, Deps, DepsMut, Empty, Env, MessageInfo, Reply, Response, StdResult, Storage,
-    SubMsg,
+    coin, to_binary, Addr, BankMsg, BlockInfo, Coin, Deps, DepsMut, Empty, Env, MessageInfo, Reply,
+    Response, StdResult, Storage, SubMsg, Timestamp, Uint128,
 };
+use cw_croncat_core::msg::ExecutionResult;
 use cw_croncat_core::traits::{FindAndMutate, Intervals};
-use cw_croncat_core::types::{Agent, Interval, SlotType, Task};
+use cw_croncat_core::types::{Agent, GenericBalance, Interval, SlotType, Task, VestingCredit};
 use cw_rules_core::msg::QueryConstruct;
 
+/// Agent fee multiplied by the highest loyalty tier the agent reached and its own
+/// reward multiplier, then held within the configured reward bounds
+pub(crate) fn agent_reward(config: &Config, now: Timestamp, agent: &Agent) -> Coin {
+    Coin::new(
+        clamp_reward(config, unclamped_agent_reward(config, now, agent)).u128(),
+        config.agent_fee.denom.clone(),
+    )
+}
+
+/// What a task gets charged for executing with `agent`, along with the agent's reward.
+/// The task pays the reward before `reward_max` cuts it down, the cut goes to protocol revenue.
+/// Raising a reward to `reward_min` is paid by the task too
+pub(crate) fn task_reward_charge(config: &Config, now: Timestamp, agent: &Agent) -> (Coin, Coin) {
+    let reward = agent_reward(config, now, agent);
+    let charge = Coin::new(
+        unclamped_agent_reward(config, now, agent)
+            .max(reward.amount)
+            .u128(),
+        reward.denom.clone(),
+    );
+    (charge, reward)
+}
+
+/// Agent fee with the loyalty and agent multipliers, before the reward bounds
+fn unclamped_agent_reward(config: &Config, now: Timestamp, agent: &Agent) -> Uint128 {
+    let tenure = now.nanos().saturating_sub(agent.register_start.nanos());
+    let multiplier_bps = config
+        .loyalty_tiers
+        .iter()
+        .filter(|tier| tier.min_tenure_nanos <= tenure)
+        .map(|tier| tier.multiplier_bps)
+        .max()
+        .unwrap_or(10_000)
+        .clamp(10_000, MAX_LOYALTY_MULTIPLIER_BPS);
+    // The cap may have been lowered since the multiplier was set
+    let agent_multiplier_bps = agent
+        .reward_multiplier_bps
+        .min(config.max_reward_multiplier_bps);
+    let rounding = &config.reward_rounding;
+    rounding.scale(
+        rounding.scale(config.agent_fee.amount, multiplier_bps),
+        u64::from(agent_multiplier_bps),
+    )
+}
+
+/// Keeps a reward within `Config.reward_min` and `Config.reward_max`
+pub(crate) fn clamp_reward(config: &Config, mut amount: Uint128) -> Uint128 {
+    if let Some(reward_min) = config.reward_min {
+        amount = amount.max(reward_min);
+    }
+    if let Some(reward_max) = config.reward_max {
+        amount = amount.min(reward_max);
+    }
+    amount
+}
+
+/// Credits native `reward` to the agent, into the bond if it compounds.
+/// A cw20 reward preference is converted through the dex router at withdrawal, not here.
+/// Rewards below `Config.dust_threshold` are held back until the pending dust reaches it
+fn credit_reward(config: &Config, agent: &mut Agent, reward: &Coin) -> Result<(), ContractError> {
+    let mut reward = reward.clone();
+    if reward.amount < config.dust_threshold {
+        agent.pending_dust.find_checked_add(&reward)?;
+        let dust = agent
+            .pending_dust
+            .iter()
+            .find(|coin| coin.denom == reward.denom)
+            .map(|coin| coin.amount)
+            .unwrap_or_default();
+        if dust < config.dust_threshold {
+            return Ok(());
+        }
+        agent.pending_dust.retain(|coin| coin.denom != reward.denom);
+        reward.amount = dust;
+    }
+    if agent.auto_compound {
+        agent.bond.find_checked_add(&reward)?;
+    } else {
+        agent.balance.native.find_checked_add(&reward)?;
+    }
+    Ok(())
+}
+
+/// Whether executing `slot_id` now is later than `Config.missed_slot_grace` slots allow
+fn slot_missed(config: &Config, block: &BlockInfo, slot_id: u64, slot_type: SlotType) -> bool {
+    match slot_type {
+        SlotType::Block => block.height > slot_id.saturating_add(config.missed_slot_grace),
+        SlotType::Cron => {
+            let grace_nanos = slots_to_nanos(config, SlotType::Cron, config.missed_slot_grace);
+            block.time.nanos() > slot_id.saturating_add(grace_nanos)
+        }
+    }
+}
+
+/// Whether `slot_id` is past `Config.exclusivity_window_slots`, open to every active agent
+pub(crate) fn slot_open(
+    config: &Config,
+    block: &BlockInfo,
+    slot_id: u64,
+    slot_type: SlotType,
+) -> bool {
+    let window = config.exclusivity_window_slots;
+    window > 0
+        && match slot_type {
+            SlotType::Block => slot_id.saturating_add(window) <= block.height,
+            SlotType::Cron => {
+                slot_id.saturating_add(slots_to_nanos(config, SlotType::Cron, window))
+                    <= block.time.nanos()
+            }
+        }
+}
+
+/// Release time in nanos of the agent reward for `task`, if it holds enough deposit to be escrowed
+fn escrow_release_time(config: &Config, task: &Task, now: Timestamp) -> Option<u64> {
+    let escrow = config.reward_escrow.as_ref()?;
+    let deposit = task
+        .total_deposit
+        .native
+        .iter()
+        .find(|c| c.denom == escrow.min_task_deposit.denom)?;
+    if deposit.amount >= escrow.min_task_deposit.amount {
+        Some(now.nanos().saturating_add(escrow.dispute_window_nanos))
+    } else {
+        None
+    }
+}
+
 impl<'a> CwCroncat<'a> {
     /// Executes a task based on the current task slot
     /// Computes whether a task should continue further or not
@@ -23,7 +153,7 @@ impl<'a> CwCroncat<'a> {
     ) -> Result<Response, ContractError> {
         self.check_ready_for_proxy_call(deps.as_ref(), &info)?;
 
-        let agent = self.check_agent(deps.as_ref(), &info)?;
+        let (agent_id, agent) = self.check_agent(deps.as_ref(), &info)?;
 
         // get slot items, find the next task hash available
         // if empty slot found, let agent get paid for helping keep house clean
@@ -50,12 +180,21 @@ impl<'a> CwCroncat<'a> {
             some_hash = self.pop_slot_item(deps.storage, &slot.0.unwrap(), &SlotType::Block);
         }
         if some_hash.is_none() {
-            let base_reward = self.send_base_agent_reward(deps.storage, agent, &info)?;
-            //
+            let (base_reward, withdraw_msgs) =
+                self.send_base_agent_reward(deps.storage, &env, agent, &agent_id)?;
+            let result = ExecutionResult {
+                success: true,
+                agent: agent_id.clone(),
+                slot: slot_id,
+                reward: base_reward.clone(),
+                error: None,
+            };
             return Ok(Response::new()
+                .set_data(to_binary(&result)?)
                 .add_attribute("method", "proxy_call")
-                .add_attribute("agent", &info.sender)
-                .add_attribute("no_task_agent_base_reward", base_reward.to_string()));
+                .add_attribute("agent", &agent_id)
+                .add_attribute("no_task_agent_base_reward", base_reward.to_string())
+                .add_submessages(withdraw_msgs));
         }
 
         // Get the task details
@@ -64,11 +203,21 @@ impl<'a> CwCroncat<'a> {
         let some_task = self.tasks.may_load(deps.storage, &hash)?;
         if some_task.is_none() {
             // NOTE: This could should never get reached, however we cover just in case
-            let base_reward = self.send_base_agent_reward(deps.storage, agent, &info)?;
+            let (base_reward, withdraw_msgs) =
+                self.send_base_agent_reward(deps.storage, &env, agent, &agent_id)?;
+            let result = ExecutionResult {
+                success: true,
+                agent: agent_id.clone(),
+                slot: slot_id,
+                reward: base_reward.clone(),
+                error: None,
+            };
             return Ok(Response::new()
+                .set_data(to_binary(&result)?)
                 .add_attribute("method", "proxy_call")
-                .add_attribute("agent", &info.sender)
-                .add_attribute("no_task_agent_base_reward", base_reward.to_string()));
+                .add_attribute("agent", &agent_id)
+                .add_attribute("no_task_agent_base_reward", base_reward.to_string())
+                .add_submessages(withdraw_msgs));
         }
 
         //Get agent tasks with extra(if exists) from balancer
@@ -79,13 +228,24 @@ impl<'a> CwCroncat<'a> {
                 &env,
                 &self.config,
                 &self.agent_active_queue,
-                info.sender.clone(),
+                &self.agents,
+                agent_id.clone(),
                 slot,
+                slot_id,
+                some_task.as_ref(),
             )
             .unwrap()
             .unwrap();
-        //Balanacer gives not task to this agent, return error
-        let has_tasks = balancer_result.has_any_slot_tasks(slot_type);
+        // Balancer gives no task to this agent, return error.
+        // Agents declining the task's type get none, so the slot waits for an agent accepting it.
+        // Past the exclusivity window any active agent may take it
+        let has_tasks = balancer_result.has_any_slot_tasks(slot_type)
+            || slot_open(
+                &self.config.load(deps.storage)?,
+                &env.block,
+                slot_id,
+                slot_type,
+            );
         if !has_tasks {
             return Err(ContractError::NoTaskFound {});
         }
@@ -198,16 +358,62 @@ impl<'a> CwCroncat<'a> {
         // Task pays for gas even if it failed
         let mut agent = agent;
         let mut task = task;
-        let gas_used = coin(gas_used as u128, c.native_denom);
+        let (charge, reward) = task_reward_charge(&c, env.block.time, &agent);
+        let escrow_release = escrow_release_time(&c, &task, env.block.time);
+        let gas_used = coin(gas_used as u128, c.native_denom.clone());
         agent.balance.native.find_checked_add(&gas_used)?;
         task.total_deposit.native.find_checked_sub(&gas_used)?;
-        // calculate agent base reward
-        task.total_deposit.native.find_checked_sub(&c.agent_fee)?;
-        agent.balance.native.find_checked_add(&c.agent_fee)?;
+        // The task pays for the whole reward, loyalty and multiplier bonuses included
+        task.total_deposit.native.find_checked_sub(&charge)?;
+        self.add_reward_cut_to_revenue(deps.storage, &charge, &reward)?;
+        // Escrowed rewards get credited once the dispute window passes
+        let credit = match escrow_release {
+            Some(release_time) => {
+                self.escrow_reward(deps.storage, &agent_id, &hash, &reward, release_time)?;
+                self.add_total_agent_balance(deps.storage, std::slice::from_ref(&reward))?;
+                GenericBalance::default()
+            }
+            None => GenericBalance {
+                native: vec![reward.clone()],
+                cw20: vec![],
+            },
+        };
+        agent.last_execution = Some(env.block.time);
+        // Executing past the grace slots still counts as missing the slot
+        let missed_slot = slot_missed(&c, &env.block, slot_id, slot_type);
+        if missed_slot {
+            agent.consecutive_misses = agent.consecutive_misses.saturating_add(1);
+            agent.current_streak = 0;
+            agent.last_missed_slot = slot_id;
+            self.record_missed_slot(deps.storage)?;
+        } else {
+            agent.consecutive_misses = 0;
+            agent.current_streak = agent.current_streak.saturating_add(1);
+        }
+        self.add_total_agent_balance(deps.storage, &[gas_used])?;
+        self.agents.save(deps.storage, &agent_id, &agent)?;
+        sub_msgs.extend(self.batch_credit_agents(
+            deps.storage,
+            env.block.time,
+            vec![(agent_id.clone(), credit)],
+        )?);
+        let mut agent = self.agents.load(deps.storage, &agent_id)?;
+        let exec_nonce = self.next_execution_nonce(deps.storage)?;
+        if let Some(withdraw_msg) = self.withdraw_agent_excess(deps.storage, &mut agent)? {
+            sub_msgs.push(withdraw_msg);
+        }
 
-        self.agents.save(deps.storage, &info.sender, &agent)?;
+        self.agents.save(deps.storage, &agent_id, &agent)?;
         self.tasks.save(deps.storage, &hash, &task)?;
 
+        // Actions replying with an error flip it to failed before the callback
+        let result = ExecutionResult {
+            success: true,
+            agent: agent_id.clone(),
+            slot: slot_id,
+            reward,
+            error: None,
+        };
         // Keep track for later scheduling
         self.rq_push(
             deps.storage,
@@ -216,19 +422,23 @@ impl<'a> CwCroncat<'a> {
                 task_hash: Some(hash),
                 contract_addr: Some(self_addr),
                 task_is_extra: Some(balancer_result.has_any_slot_extra_tasks(slot_type)),
-                agent_id: Some(info.sender.clone()),
+                agent_id: Some(agent_id.clone()),
                 failed: false,
+                result: Some(result.clone()),
             },
         )?;
 
         // TODO: Add supported msgs if not a SubMessage?
         // Add the messages, reply handler responsible for task rescheduling
         let final_res = Response::new()
+            .set_data(to_binary(&result)?)
             .add_attribute("method", "proxy_call")
-            .add_attribute("agent", info.sender)
+            .add_attribute("agent", agent_id)
             .add_attribute("slot_id", slot_id.to_string())
             .add_attribute("slot_kind", format!("{:?}", slot_type))
             .add_attribute("task_hash", task.to_hash())
+            .add_attribute("exec_nonce", exec_nonce.to_string())
+            .add_attribute("missed_slot", missed_slot.to_string())
             .add_submessages(sub_msgs);
         Ok(final_res)
     }
@@ -247,7 +457,7 @@ impl<'a> CwCroncat<'a> {
         self.check_ready_for_proxy_call(deps.as_ref(), &info)?;
 
         let cfg: Config = self.config.load(deps.storage)?;
-        let agent = self.check_agent(deps.as_ref(), &info)?;
+        let (agent_id, agent) = self.check_agent(deps.as_ref(), &info)?;
 
         let some_task = self
             .tasks_with_rules
@@ -255,18 +465,18 @@ impl<'a> CwCroncat<'a> {
         let task = some_task.ok_or(ContractError::NoTaskFound {})?;
 
         // Check that this task can be executed in current slot
-        let task_ready = match task.interval {
+        let (task_ready, slot_id, slot_type) = match task.interval {
             Interval::Cron(_) => {
                 let block = self
                     .time_slots_rules
                     .load(deps.storage, task_hash.as_bytes())?;
-                env.block.height >= block
+                (env.block.height >= block, block, SlotType::Cron)
             }
             _ => {
                 let time = self
                     .block_slots_rules
                     .load(deps.storage, task_hash.as_bytes())?;
-                env.block.time.nanos() >= time
+                (env.block.time.nanos() >= time, time, SlotType::Block)
             }
         };
         if !task_ready {
@@ -283,7 +493,7 @@ impl<'a> CwCroncat<'a> {
         };
         // Check rules
         let (res, idx): (bool, Option<u64>) = deps.querier.query_wasm_smart(
-            cfg.cw_rules_addr,
+            &cfg.cw_rules_addr,
             &cw_rules_core::msg::QueryMsg::QueryConstruct(QueryConstruct {
                 rules: rules.clone(),
             }),
@@ -314,16 +524,67 @@ impl<'a> CwCroncat<'a> {
         // Task pays for gas even if it failed
         let mut agent = agent;
         let mut task = task;
-        let gas_used = coin(gas_used as u128, cfg.native_denom);
+        let (charge, reward) = task_reward_charge(&cfg, env.block.time, &agent);
+        let escrow_release = escrow_release_time(&cfg, &task, env.block.time);
+        let gas_used = coin(gas_used as u128, cfg.native_denom.clone());
         agent.balance.native.find_checked_add(&gas_used)?;
         task.total_deposit.native.find_checked_sub(&gas_used)?;
-        // calculate agent base reward
-        task.total_deposit.native.find_checked_sub(&cfg.agent_fee)?;
-        agent.balance.native.find_checked_add(&cfg.agent_fee)?;
+        // The task pays for the whole reward, loyalty and multiplier bonuses included
+        task.total_deposit.native.find_checked_sub(&charge)?;
+        self.add_reward_cut_to_revenue(deps.storage, &charge, &reward)?;
+        // Escrowed rewards get credited once the dispute window passes
+        let credit = match escrow_release {
+            Some(release_time) => {
+                self.escrow_reward(
+                    deps.storage,
+                    &agent_id,
+                    task_hash.as_bytes(),
+                    &reward,
+                    release_time,
+                )?;
+                self.add_total_agent_balance(deps.storage, std::slice::from_ref(&reward))?;
+                GenericBalance::default()
+            }
+            None => GenericBalance {
+                native: vec![reward.clone()],
+                cw20: vec![],
+            },
+        };
+        agent.last_execution = Some(env.block.time);
+        // Executing past the grace slots still counts as missing the slot
+        let missed_slot = slot_missed(&cfg, &env.block, slot_id, slot_type);
+        if missed_slot {
+            agent.consecutive_misses = agent.consecutive_misses.saturating_add(1);
+            agent.current_streak = 0;
+            agent.last_missed_slot = slot_id;
+            self.record_missed_slot(deps.storage)?;
+        } else {
+            agent.consecutive_misses = 0;
+            agent.current_streak = agent.current_streak.saturating_add(1);
+        }
+        self.add_total_agent_balance(deps.storage, &[gas_used])?;
+        self.agents.save(deps.storage, &agent_id, &agent)?;
+        sub_msgs.extend(self.batch_credit_agents(
+            deps.storage,
+            env.block.time,
+            vec![(agent_id.clone(), credit)],
+        )?);
+        let mut agent = self.agents.load(deps.storage, &agent_id)?;
+        let exec_nonce = self.next_execution_nonce(deps.storage)?;
+        if let Some(withdraw_msg) = self.withdraw_agent_excess(deps.storage, &mut agent)? {
+            sub_msgs.push(withdraw_msg);
+        }
 
-        self.agents.save(deps.storage, &info.sender, &agent)?;
+        self.agents.save(deps.storage, &agent_id, &agent)?;
         self.tasks_with_rules
             .save(deps.storage, task_hash.as_bytes(), &task)?;
+        let result = ExecutionResult {
+            success: true,
+            agent: agent_id.clone(),
+            slot: slot_id,
+            reward,
+            error: None,
+        };
         // Keep track for later scheduling
         self.rq_push(
             deps.storage,
@@ -332,18 +593,22 @@ impl<'a> CwCroncat<'a> {
                 task_hash: Some(task_hash.into_bytes()),
                 contract_addr: Some(env.contract.address),
                 task_is_extra: Some(false),
-                agent_id: Some(info.sender.clone()),
+                agent_id: Some(agent_id.clone()),
                 failed: false,
+                result: Some(result.clone()),
             },
         )?;
 
         // TODO: Add supported msgs if not a SubMessage?
         // Add the messages, reply handler responsible for task rescheduling
         let final_res = Response::new()
+            .set_data(to_binary(&result)?)
             .add_attribute("method", "proxy_call")
-            .add_attribute("agent", info.sender)
+            .add_attribute("agent", agent_id)
             .add_attribute("task_hash", task.to_hash())
             .add_attribute("task_with_rules", "true".to_string())
+            .add_attribute("exec_nonce", exec_nonce.to_string())
+            .add_attribute("missed_slot", missed_slot.to_string())
             .add_submessages(sub_msgs);
         Ok(final_res)
     }
@@ -363,6 +628,11 @@ impl<'a> CwCroncat<'a> {
         // let out_of_funds = call_total_balance > task.total_deposit;
 
         let agent_id = queue_item.agent_id.unwrap();
+        // The reply data replaces the proxy call's, so report the final outcome here
+        let response = match queue_item.result {
+            Some(result) => Response::new().set_data(to_binary(&result)?),
+            None => Response::new(),
+        };
         // if non-recurring, exit
         if task.interval == Interval::Once
             || (task.stop_on_fail && queue_item.failed)
@@ -371,7 +641,7 @@ impl<'a> CwCroncat<'a> {
             // Process task exit, if no future task can execute
             let rt = self.remove_task(deps.storage, task_hash, None);
             let resp = rt.unwrap_or_default();
-            return Ok(Response::new()
+            return Ok(response
                 .add_attribute("method", "proxy_callback")
                 .add_attributes(resp.attributes)
                 .add_submessages(resp.messages)
@@ -395,7 +665,7 @@ impl<'a> CwCroncat<'a> {
             // Task has been removed, complete and rebalance internal balancer
             self.complete_agent_task(deps.storage, env, msg, task_info)
                 .unwrap();
-            return Ok(Response::new()
+            return Ok(response
                 .add_attribute("method", "proxy_callback")
                 .add_attribute("ended_task", task_hash)
                 .add_attributes(resp.attributes)
@@ -442,7 +712,7 @@ impl<'a> CwCroncat<'a> {
                 }
             }
         }
-        Ok(Response::new()
+        Ok(response
             .add_attribute("method", "proxy_callback")
             .add_attribute("slot_id", next_id.to_string())
             .add_attribute("slot_kind", format!("{:?}", slot_kind)))
@@ -454,19 +724,215 @@ impl<'a> CwCroncat<'a> {
     pub(crate) fn send_base_agent_reward(
         &self,
         storage: &mut dyn Storage,
+        env: &Env,
         mut agent: Agent,
-        message: &MessageInfo,
-    ) -> Result<Coin, ContractError> {
+        agent_id: &Addr,
+    ) -> Result<(Coin, Vec<SubMsg>), ContractError> {
         let config: Config = self.config.load(storage)?;
 
-        let add_native = config.agent_fee;
-        agent.total_tasks_executed = agent.total_tasks_executed.saturating_add(1);
-        agent.balance.native.find_checked_add(&add_native)?;
+        let add_native = agent_reward(&config, env.block.time, &agent);
 
         // Reset missed slot
         agent.last_missed_slot = 0;
-        self.agents.save(storage, &message.sender, &agent)?;
-        Ok(add_native)
+        agent.last_execution = Some(env.block.time);
+        agent.consecutive_misses = 0;
+        agent.current_streak = agent.current_streak.saturating_add(1);
+        self.agents.save(storage, agent_id, &agent)?;
+        let credit = GenericBalance {
+            native: vec![add_native.clone()],
+            cw20: vec![],
+        };
+        let mut withdraw_msgs =
+            self.batch_credit_agents(storage, env.block.time, vec![(agent_id.clone(), credit)])?;
+        let mut agent = self.agents.load(storage, agent_id)?;
+        withdraw_msgs.extend(self.withdraw_agent_excess(storage, &mut agent)?);
+        self.agents.save(storage, agent_id, &agent)?;
+        Ok((add_native, withdraw_msgs))
+    }
+
+    /// Credits the reward to the agent, or to its pool's shared balance if it's in one.
+    /// Streaming agents get the send to their payable account back instead,
+    /// otherwise it starts vesting if `reward_vesting_nanos` is set.
+    /// Vesting rewards are bucketed by the period they finish vesting in,
+    /// a period being `reward_vesting_nanos / MAX_VESTING_CREDITS`.
+    /// Pooled, streamed and vesting rewards skip the dust threshold and compounding
+    pub(crate) fn credit_agent(
+        &self,
+        storage: &mut dyn Storage,
+        config: &Config,
+        agent_id: &Addr,
+        agent: &mut Agent,
+        reward: &Coin,
+        now: Timestamp,
+    ) -> Result<Option<SubMsg>, ContractError> {
+        match agent.pool_id.as_deref() {
+            Some(pool_id) => {
+                let mut balance = self
+                    .agent_pool_balances
+                    .may_load(storage, pool_id)?
+                    .unwrap_or_default();
+                balance.find_checked_add(reward)?;
+                self.agent_pool_balances.save(storage, pool_id, &balance)?;
+                Ok(None)
+            }
+            None if agent.stream_rewards => {
+                if reward.amount.is_zero() {
+                    return Ok(None);
+                }
+                let mut config: Config = self.config.load(storage)?;
+                config
+                    .available_balance
+                    .checked_sub_native(std::slice::from_ref(reward))?;
+                self.config.save(storage, &config)?;
+                Ok(Some(SubMsg::new(BankMsg::Send {
+                    to_address: agent.payable_account_id.to_string(),
+                    amount: vec![reward.clone()],
+                })))
+            }
+            None if config.reward_vesting_nanos > 0 => {
+                let mut credits = self
+                    .agent_vesting
+                    .may_load(storage, agent_id)?
+                    .unwrap_or_default();
+                let period = (config.reward_vesting_nanos / MAX_VESTING_CREDITS as u64).max(1);
+                let end_nanos =
+                    now.nanos().saturating_add(config.reward_vesting_nanos) / period * period;
+                let full = credits.len() >= MAX_VESTING_CREDITS;
+                match credits.last_mut() {
+                    Some(last)
+                        if last.amount.denom == reward.denom
+                            && (last.end_nanos == end_nanos || full) =>
+                    {
+                        // Release what vested so far, the rest vests on with the reward
+                        let vested = last.vested(now.nanos());
+                        let release = vested - last.released;
+                        if !release.is_zero() {
+                            agent
+                                .balance
+                                .native
+                                .find_checked_add(&Coin::new(release.u128(), &reward.denom))?;
+                        }
+                        *last = VestingCredit {
+                            amount: Coin::new(
+                                (last.amount.amount - vested + reward.amount).u128(),
+                                &reward.denom,
+                            ),
+                            start_nanos: now.nanos(),
+                            end_nanos: end_nanos.max(last.end_nanos),
+                            released: Uint128::zero(),
+                        };
+                    }
+                    _ => credits.push(VestingCredit {
+                        amount: reward.clone(),
+                        start_nanos: now.nanos(),
+                        end_nanos,
+                        released: Uint128::zero(),
+                    }),
+                }
+                self.agent_vesting.save(storage, agent_id, &credits)?;
+                Ok(None)
+            }
+            None => credit_reward(config, agent, reward).map(|_| None),
+        }
+    }
+
+    /// Keeps the running sum of agent balances in sync after crediting an agent
+    fn add_total_agent_balance(
+        &self,
+        storage: &mut dyn Storage,
+        credit: &[Coin],
+    ) -> Result<(), ContractError> {
+        let mut config: Config = self.config.load(storage)?;
+        config.total_agent_balance.checked_add_native(credit)?;
+        self.config.save(storage, &config)?;
+        Ok(())
+    }
+
+    /// Books what `reward_max` cut off a task's `charge` as protocol revenue.
+    /// The charge is already part of `available_balance` as task deposit
+    fn add_reward_cut_to_revenue(
+        &self,
+        storage: &mut dyn Storage,
+        charge: &Coin,
+        reward: &Coin,
+    ) -> Result<(), ContractError> {
+        let cut = charge.amount.saturating_sub(reward.amount);
+        if cut.is_zero() {
+            return Ok(());
+        }
+        let mut config: Config = self.config.load(storage)?;
+        config
+            .protocol_revenue
+            .checked_add_native(&[Coin::new(cut.u128(), charge.denom.clone())])?;
+        self.config.save(storage, &config)?;
+        Ok(())
+    }
+
+    /// Bumps `execution_nonce` for a task execution and returns the new value
+    fn next_execution_nonce(&self, storage: &mut dyn Storage) -> Result<u64, ContractError> {
+        let mut config: Config = self.config.load(storage)?;
+        config.execution_nonce += 1;
+        self.config.save(storage, &config)?;
+        Ok(config.execution_nonce)
+    }
+
+    /// Bumps the `total_missed_slots` running count
+    fn record_missed_slot(&self, storage: &mut dyn Storage) -> Result<(), ContractError> {
+        let mut config: Config = self.config.load(storage)?;
+        config.total_missed_slots = config.total_missed_slots.saturating_add(1);
+        self.config.save(storage, &config)?;
+        Ok(())
+    }
+
+    /// Credit rewards for a batch of executed tasks in a single pass
+    /// Each credit counts as one executed task for that agent, native credits go through `credit_agent`.
+    /// Rewards come out of funds already in `available_balance`,
+    /// the sum kept for agents is added to `total_agent_balance` once.
+    /// Cw20 credits must be in `allowed_reward_cw20`.
+    /// Returns the sends to streaming agents
+    pub(crate) fn batch_credit_agents(
+        &self,
+        storage: &mut dyn Storage,
+        now: Timestamp,
+        credits: Vec<(Addr, GenericBalance)>,
+    ) -> Result<Vec<SubMsg>, ContractError> {
+        let config: Config = self.config.load(storage)?;
+        let mut total = GenericBalance::default();
+        let mut stream_msgs = vec![];
+        for (agent_id, credit) in credits {
+            if let Some(cw20) = credit
+                .cw20
+                .iter()
+                .find(|cw20| !config.allowed_reward_cw20.contains(&cw20.address))
+            {
+                return Err(ContractError::RewardCw20NotAllowed {
+                    addr: cw20.address.to_string(),
+                });
+            }
+            let mut agent = self
+                .agents
+                .may_load(storage, &agent_id)?
+                .ok_or(ContractError::AgentNotRegistered {})?;
+            agent.total_tasks_executed = agent.total_tasks_executed.saturating_add(1);
+            for reward in credit.native.iter() {
+                match self.credit_agent(storage, &config, &agent_id, &mut agent, reward, now)? {
+                    Some(stream_msg) => stream_msgs.push(stream_msg),
+                    None => total.checked_add_native(std::slice::from_ref(reward))?,
+                }
+            }
+            agent.balance.checked_add_cw20(&credit.cw20)?;
+            self.agents.save(storage, &agent_id, &agent)?;
+            total.checked_add_cw20(&credit.cw20)?;
+        }
+
+        // Streamed rewards already updated the stored config
+        let mut config: Config = self.config.load(storage)?;
+        config
+            .total_agent_balance
+            .checked_add_native(&total.native)?;
+        config.total_agent_balance.checked_add_cw20(&total.cw20)?;
+        self.config.save(storage, &config)?;
+        Ok(stream_msgs)
     }
 
     fn check_ready_for_proxy_call(
@@ -480,7 +946,7 @@ impl<'a> CwCroncat<'a> {
             });
         }
         let c: Config = self.config.load(deps.storage)?;
-        if c.paused {
+        if c.paused.execute {
             return Err(ContractError::CustomError {
                 val: "Contract paused".to_string(),
             });
@@ -494,19 +960,31 @@ impl<'a> CwCroncat<'a> {
         Ok(())
     }
 
-    fn check_agent(&self, deps: Deps, info: &MessageInfo) -> Result<Agent, ContractError> {
+    /// Resolves the agent a call is made for
+    /// The sender is either the agent itself or one of its operators
+    fn check_agent(&self, deps: Deps, info: &MessageInfo) -> Result<(Addr, Agent), ContractError> {
+        let agent_id = self
+            .agent_operators
+            .may_load(deps.storage, &info.sender)?
+            .unwrap_or_else(|| info.sender.clone());
+
         // only registered agent signed, because micropayments will benefit long term
-        let agent_opt = self.agents.may_load(deps.storage, &info.sender)?;
+        let agent_opt = self.agents.may_load(deps.storage, &agent_id)?;
         if agent_opt.is_none() {
             return Err(ContractError::AgentNotRegistered {});
         }
         let active_agents: Vec<Addr> = self.agent_active_queue.load(deps.storage)?;
 
         // make sure agent is active
-        if !active_agents.contains(&info.sender) {
+        if !active_agents.contains(&agent_id) {
             return Err(ContractError::AgentNotRegistered {});
         }
-        Ok(agent_opt.unwrap())
+        let agent = agent_opt.unwrap();
+        // Self-paused agents don't get tasks
+        if agent.self_paused {
+            return Err(ContractError::AgentPaused {});
+        }
+        Ok((agent_id, agent))
     }
 
     // // Restrict bank msg so contract doesnt get drained
@@ -581,20 +1059,26 @@ impl<'a> CwCroncat<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::agent::new_agent;
     use crate::contract::GAS_BASE_FEE_JUNO;
+    use crate::helpers::test_helpers::{mock_init, update_settings_msg};
+    use cosmwasm_std::testing::{mock_dependencies_with_balance, mock_env, mock_info};
     use cosmwasm_std::{
-        coin, coins, to_binary, Addr, BankMsg, BlockInfo, Coin, CosmosMsg, Empty, StakingMsg,
-        Uint128, WasmMsg,
+        coin, coins, from_binary, to_binary, Addr, BankMsg, BlockInfo, Coin, CosmosMsg, Empty,
+        StakingMsg, Uint128, WasmMsg,
     };
     use cw_multi_test::{App, AppBuilder, Contract, ContractWrapper, Executor};
     use cw_rules_core::types::{HasBalanceGte, Rule};
     // use cw20::Balance;
     use crate::helpers::CwTemplateContract;
+    use cw20::Cw20CoinVerified;
     use cw_croncat_core::msg::{
-        AgentTaskResponse, ExecuteMsg, InstantiateMsg, QueryMsg, TaskRequest, TaskResponse,
-        TaskWithRulesResponse,
+        AgentTaskResponse, ExecuteMsg, InstantiateMsg, MissedSlotStatsResponse, QueryMsg,
+        TaskRequest, TaskResponse, TaskWithRulesResponse, VestedBalanceResponse,
+    };
+    use cw_croncat_core::types::{
+        Action, AgentResponse, Boundary, Interval, LoyaltyTier, RewardRounding,
     };
-    use cw_croncat_core::types::{Action, Boundary, Interval};
 
     pub fn contract_template() -> Box<dyn Contract<Empty>> {
         let contract = ContractWrapper::new(
@@ -619,6 +1103,7 @@ mod tests {
     const ANYONE: &str = "cosmos1t5u0jfg3ljsjrh2m9e47d4ny2hea7eehxrzdgd";
     const AGENT0: &str = "cosmos1a7uhnpqthunr2rzj0ww0hwurpn42wyun6c5puz";
     const AGENT1_BENEFICIARY: &str = "cosmos1t5u0jfg3ljsjrh2m9e47d4ny2hea7eehxrzdgd";
+    const AGENT0_OPERATOR: &str = "cosmos1k5k7y4hgy5lkq0kj3k3e9k38lquh0m66kxsu5c";
     const NATIVE_DENOM: &str = "atom";
 
     fn mock_app() -> App {
@@ -664,6 +1149,7 @@ mod tests {
             gas_base_fee: None,
             agent_nomination_duration: None,
             cw_rules_addr: cw_rules_addr.to_string(),
+            initial_agent: None,
         };
         let cw_template_contract_addr = app
             //Must send some available balance for rewards
@@ -718,6 +1204,7 @@ mod tests {
                 }],
                 rules: None,
                 cw20_coins: vec![],
+                task_type: None,
             },
         };
         let task_id_str =
@@ -740,17 +1227,10 @@ mod tests {
         );
 
         // Create task paused
-        let change_settings_msg = ExecuteMsg::UpdateSettings {
-            paused: Some(true),
-            owner_id: None,
-            // treasury_id: None,
-            agent_fee: None,
-            min_tasks_per_agent: None,
-            agents_eject_threshold: None,
-            gas_price: None,
-            proxy_callback_gas: None,
-            slot_granularity: None,
-        };
+        let mut change_settings_msg = update_settings_msg();
+        if let ExecuteMsg::UpdateSettings { paused, .. } = &mut change_settings_msg {
+            *paused = Some(true);
+        }
         app.execute_contract(
             Addr::unchecked(ADMIN),
             contract_addr.clone(),
@@ -773,20 +1253,14 @@ mod tests {
             res_err.downcast().unwrap()
         );
         // Set it back
+        let mut settings_msg = update_settings_msg();
+        if let ExecuteMsg::UpdateSettings { paused, .. } = &mut settings_msg {
+            *paused = Some(false);
+        }
         app.execute_contract(
             Addr::unchecked(ADMIN),
             contract_addr.clone(),
-            &ExecuteMsg::UpdateSettings {
-                paused: Some(false),
-                owner_id: None,
-                // treasury_id: None,
-                agent_fee: None,
-                min_tasks_per_agent: None,
-                agents_eject_threshold: None,
-                gas_price: None,
-                proxy_callback_gas: None,
-                slot_granularity: None,
-            },
+            &settings_msg,
             &vec![],
         )
         .unwrap();
@@ -808,6 +1282,9 @@ mod tests {
         // quick agent register
         let msg = ExecuteMsg::RegisterAgent {
             payable_account_id: Some(AGENT1_BENEFICIARY.to_string()),
+            capabilities: None,
+            accepted_terms: None,
+            client_version: None,
         };
         app.execute_contract(Addr::unchecked(AGENT0), contract_addr.clone(), &msg, &[])
             .unwrap();
@@ -891,7 +1368,10 @@ mod tests {
         // Doing this msg since its the easiest to guarantee success in reply
         let msg = CosmosMsg::Wasm(WasmMsg::Execute {
             contract_addr: contract_addr.to_string(),
-            msg: to_binary(&ExecuteMsg::WithdrawReward {})?,
+            msg: to_binary(&ExecuteMsg::WithdrawReward {
+                idempotency_key: None,
+                memo: None,
+            })?,
             funds: coins(1, NATIVE_DENOM),
         });
 
@@ -909,6 +1389,7 @@ mod tests {
                 }],
                 rules: None,
                 cw20_coins: vec![],
+                task_type: None,
             },
         };
 
@@ -935,6 +1416,9 @@ mod tests {
         // quick agent register
         let msg = ExecuteMsg::RegisterAgent {
             payable_account_id: Some(AGENT1_BENEFICIARY.to_string()),
+            capabilities: None,
+            accepted_terms: None,
+            client_version: None,
         };
         app.execute_contract(Addr::unchecked(AGENT0), contract_addr.clone(), &msg, &[])
             .unwrap();
@@ -1042,6 +1526,7 @@ mod tests {
                 }],
                 rules: None,
                 cw20_coins: vec![],
+                task_type: None,
             },
         };
 
@@ -1068,6 +1553,9 @@ mod tests {
         // quick agent register
         let msg = ExecuteMsg::RegisterAgent {
             payable_account_id: Some(AGENT1_BENEFICIARY.to_string()),
+            capabilities: None,
+            accepted_terms: None,
+            client_version: None,
         };
         app.execute_contract(Addr::unchecked(AGENT0), contract_addr.clone(), &msg, &[])
             .unwrap();
@@ -1167,6 +1655,7 @@ mod tests {
                 }],
                 rules: None,
                 cw20_coins: vec![],
+                task_type: None,
             },
         };
 
@@ -1256,7 +1745,10 @@ mod tests {
         // Doing this msg since its the easiest to guarantee success in reply
         let msg = CosmosMsg::Wasm(WasmMsg::Execute {
             contract_addr: contract_addr.to_string(),
-            msg: to_binary(&ExecuteMsg::WithdrawReward {})?,
+            msg: to_binary(&ExecuteMsg::WithdrawReward {
+                idempotency_key: None,
+                memo: None,
+            })?,
             funds: coins(1, NATIVE_DENOM),
         });
 
@@ -1271,6 +1763,7 @@ mod tests {
                 }],
                 rules: None,
                 cw20_coins: vec![],
+                task_type: None,
             },
         };
 
@@ -1297,6 +1790,9 @@ mod tests {
         // quick agent register
         let msg = ExecuteMsg::RegisterAgent {
             payable_account_id: Some(AGENT1_BENEFICIARY.to_string()),
+            capabilities: None,
+            accepted_terms: None,
+            client_version: None,
         };
         app.execute_contract(Addr::unchecked(AGENT0), contract_addr.clone(), &msg, &[])
             .unwrap();
@@ -1386,7 +1882,10 @@ mod tests {
         // Doing this msg since its the easiest to guarantee success in reply
         let msg = CosmosMsg::Wasm(WasmMsg::Execute {
             contract_addr: contract_addr.to_string(),
-            msg: to_binary(&ExecuteMsg::WithdrawReward {})?,
+            msg: to_binary(&ExecuteMsg::WithdrawReward {
+                idempotency_key: None,
+                memo: None,
+            })?,
             funds: coins(1, NATIVE_DENOM),
         });
 
@@ -1401,6 +1900,7 @@ mod tests {
                 }],
                 rules: None,
                 cw20_coins: vec![],
+                task_type: None,
             },
         };
 
@@ -1427,6 +1927,9 @@ mod tests {
         // quick agent register
         let msg = ExecuteMsg::RegisterAgent {
             payable_account_id: Some(AGENT1_BENEFICIARY.to_string()),
+            capabilities: None,
+            accepted_terms: None,
+            client_version: None,
         };
         app.execute_contract(Addr::unchecked(AGENT0), contract_addr.clone(), &msg, &[])
             .unwrap();
@@ -1514,19 +2017,28 @@ mod tests {
         // Doing this msg since its the easiest to guarantee success in reply
         let msg = CosmosMsg::Wasm(WasmMsg::Execute {
             contract_addr: contract_addr.to_string(),
-            msg: to_binary(&ExecuteMsg::WithdrawReward {})?,
+            msg: to_binary(&ExecuteMsg::WithdrawReward {
+                idempotency_key: None,
+                memo: None,
+            })?,
             funds: coins(1, NATIVE_DENOM),
         });
 
         let msg2 = CosmosMsg::Wasm(WasmMsg::Execute {
             contract_addr: contract_addr.to_string(),
-            msg: to_binary(&ExecuteMsg::WithdrawReward {})?,
+            msg: to_binary(&ExecuteMsg::WithdrawReward {
+                idempotency_key: None,
+                memo: None,
+            })?,
             funds: coins(2, NATIVE_DENOM),
         });
 
         let msg3 = CosmosMsg::Wasm(WasmMsg::Execute {
             contract_addr: contract_addr.to_string(),
-            msg: to_binary(&ExecuteMsg::WithdrawReward {})?,
+            msg: to_binary(&ExecuteMsg::WithdrawReward {
+                idempotency_key: None,
+                memo: None,
+            })?,
             funds: coins(3, NATIVE_DENOM),
         });
 
@@ -1541,6 +2053,7 @@ mod tests {
                 }],
                 rules: None,
                 cw20_coins: vec![],
+                task_type: None,
             },
         };
 
@@ -1555,6 +2068,7 @@ mod tests {
                 }],
                 rules: None,
                 cw20_coins: vec![],
+                task_type: None,
             },
         };
 
@@ -1569,6 +2083,7 @@ mod tests {
                 }],
                 rules: None,
                 cw20_coins: vec![],
+                task_type: None,
             },
         };
 
@@ -1603,6 +2118,9 @@ mod tests {
         // quick agent register
         let msg = ExecuteMsg::RegisterAgent {
             payable_account_id: Some(AGENT1_BENEFICIARY.to_string()),
+            capabilities: None,
+            accepted_terms: None,
+            client_version: None,
         };
         app.execute_contract(Addr::unchecked(AGENT0), contract_addr.clone(), &msg, &[])
             .unwrap();
@@ -1669,6 +2187,7 @@ mod tests {
                 }],
                 rules: None,
                 cw20_coins: vec![],
+                task_type: None,
             },
         };
         let amount_for_one_task = gas_limit + 1000;
@@ -1684,6 +2203,9 @@ mod tests {
         // quick agent register
         let msg = ExecuteMsg::RegisterAgent {
             payable_account_id: Some(AGENT1_BENEFICIARY.to_string()),
+            capabilities: None,
+            accepted_terms: None,
+            client_version: None,
         };
         app.execute_contract(Addr::unchecked(AGENT0), contract_addr.clone(), &msg, &[])
             .unwrap();
@@ -1726,6 +2248,7 @@ mod tests {
                 }],
                 rules: None,
                 cw20_coins: vec![],
+                task_type: None,
             },
         };
         // create 1 token off task
@@ -1742,6 +2265,9 @@ mod tests {
         // quick agent register
         let msg = ExecuteMsg::RegisterAgent {
             payable_account_id: Some(AGENT1_BENEFICIARY.to_string()),
+            capabilities: None,
+            accepted_terms: None,
+            client_version: None,
         };
         app.execute_contract(Addr::unchecked(AGENT0), contract_addr.clone(), &msg, &[])
             .unwrap();
@@ -1800,6 +2326,7 @@ mod tests {
                 ],
                 rules: None,
                 cw20_coins: vec![],
+                task_type: None,
             },
         };
         let gas_limit = GAS_BASE_FEE_JUNO;
@@ -1818,6 +2345,9 @@ mod tests {
         // quick agent register
         let msg = ExecuteMsg::RegisterAgent {
             payable_account_id: Some(AGENT1_BENEFICIARY.to_string()),
+            capabilities: None,
+            accepted_terms: None,
+            client_version: None,
         };
         app.execute_contract(Addr::unchecked(AGENT0), contract_addr.clone(), &msg, &[])
             .unwrap();
@@ -1871,6 +2401,7 @@ mod tests {
                 ],
                 rules: None,
                 cw20_coins: vec![],
+                task_type: None,
             },
         };
         let gas_limit = GAS_BASE_FEE_JUNO;
@@ -1890,6 +2421,9 @@ mod tests {
         // quick agent register
         let msg = ExecuteMsg::RegisterAgent {
             payable_account_id: Some(AGENT1_BENEFICIARY.to_string()),
+            capabilities: None,
+            accepted_terms: None,
+            client_version: None,
         };
         app.execute_contract(Addr::unchecked(AGENT0), contract_addr.clone(), &msg, &[])
             .unwrap();
@@ -1946,7 +2480,10 @@ mod tests {
             .unwrap();
         let contract_balance_before_withdraw =
             app.wrap().query_balance(&contract_addr, "atom").unwrap();
-        let withdraw_msg = ExecuteMsg::WithdrawReward {};
+        let withdraw_msg = ExecuteMsg::WithdrawReward {
+            idempotency_key: None,
+            memo: None,
+        };
         app.execute_contract(
             Addr::unchecked(AGENT0),
             contract_addr.clone(),
@@ -1993,6 +2530,7 @@ mod tests {
                 }],
                 rules: None,
                 cw20_coins: vec![],
+                task_type: None,
             },
         };
 
@@ -2013,6 +2551,9 @@ mod tests {
         // quick agent register
         let msg = ExecuteMsg::RegisterAgent {
             payable_account_id: Some(AGENT1_BENEFICIARY.to_string()),
+            capabilities: None,
+            accepted_terms: None,
+            client_version: None,
         };
         app.execute_contract(Addr::unchecked(AGENT0), contract_addr.clone(), &msg, &[])
             .unwrap();
@@ -2118,6 +2659,7 @@ mod tests {
                     required_balance: coins(1, "atom").into(),
                 })]),
                 cw20_coins: vec![],
+                task_type: None,
             },
         };
 
@@ -2133,6 +2675,9 @@ mod tests {
         // quick agent register
         let msg = ExecuteMsg::RegisterAgent {
             payable_account_id: Some(AGENT1_BENEFICIARY.to_string()),
+            capabilities: None,
+            accepted_terms: None,
+            client_version: None,
         };
         app.execute_contract(Addr::unchecked(AGENT0), contract_addr.clone(), &msg, &[])
             .unwrap();
@@ -2227,6 +2772,7 @@ mod tests {
                     required_balance: coins(1, "atom").into(),
                 })]),
                 cw20_coins: vec![],
+                task_type: None,
             },
         };
 
@@ -2242,6 +2788,9 @@ mod tests {
         // quick agent register
         let msg = ExecuteMsg::RegisterAgent {
             payable_account_id: Some(AGENT1_BENEFICIARY.to_string()),
+            capabilities: None,
+            accepted_terms: None,
+            client_version: None,
         };
         app.execute_contract(Addr::unchecked(AGENT0), contract_addr.clone(), &msg, &[])
             .unwrap();
@@ -2352,4 +2901,1359 @@ mod tests {
             .unwrap();
         assert!(tasks_with_rules.is_empty());
     }
+
+    #[test]
+    fn proxy_call_by_operator() {
+        let (mut app, cw_template_contract) = proper_instantiate();
+        let contract_addr = cw_template_contract.addr();
+
+        let send = BankMsg::Send {
+            to_address: String::from("addr1"),
+            amount: coins(3, NATIVE_DENOM),
+        };
+        let create_task_msg = ExecuteMsg::CreateTask {
+            task: TaskRequest {
+                interval: Interval::Once,
+                boundary: None,
+                stop_on_fail: false,
+                actions: vec![Action {
+                    msg: send.into(),
+                    gas_limit: None,
+                }],
+                rules: None,
+                cw20_coins: vec![],
+                task_type: None,
+            },
+        };
+        let gas_limit = GAS_BASE_FEE_JUNO;
+        let agent_fee = 5;
+        app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &create_task_msg,
+            &coins(u128::from(gas_limit + agent_fee + 3), NATIVE_DENOM),
+        )
+        .unwrap();
+
+        // quick agent register
+        let msg = ExecuteMsg::RegisterAgent {
+            payable_account_id: Some(AGENT1_BENEFICIARY.to_string()),
+            capabilities: None,
+            accepted_terms: None,
+            client_version: None,
+        };
+        app.execute_contract(Addr::unchecked(AGENT0), contract_addr.clone(), &msg, &[])
+            .unwrap();
+        app.update_block(add_little_time);
+
+        // Not an operator yet
+        let proxy_call_msg = ExecuteMsg::ProxyCall { task_hash: None };
+        let res_err: ContractError = app
+            .execute_contract(
+                Addr::unchecked(AGENT0_OPERATOR),
+                contract_addr.clone(),
+                &proxy_call_msg,
+                &[],
+            )
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+        assert_eq!(res_err, ContractError::AgentNotRegistered {});
+
+        // Operators are capped
+        let res_err: ContractError = app
+            .execute_contract(
+                Addr::unchecked(AGENT0),
+                contract_addr.clone(),
+                &ExecuteMsg::UpdateAgentOperators {
+                    operators: vec![AGENT0_OPERATOR.to_string(); 6],
+                },
+                &[],
+            )
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+        assert_eq!(res_err, ContractError::TooManyOperators { max: 5 });
+
+        app.execute_contract(
+            Addr::unchecked(AGENT0),
+            contract_addr.clone(),
+            &ExecuteMsg::UpdateAgentOperators {
+                operators: vec![AGENT0_OPERATOR.to_string()],
+            },
+            &[],
+        )
+        .unwrap();
+
+        // Operator executes on behalf of the agent
+        let res = app
+            .execute_contract(
+                Addr::unchecked(AGENT0_OPERATOR),
+                contract_addr.clone(),
+                &proxy_call_msg,
+                &[],
+            )
+            .unwrap();
+        assert!(res.events.iter().any(|ev| ev
+            .attributes
+            .iter()
+            .any(|attr| attr.key == "agent" && attr.value == AGENT0)));
+
+        // Rewards accrued to the agent, operator has no agent record
+        let agent: AgentResponse = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::GetAgent {
+                    account_id: AGENT0.to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(
+            agent.balance.native,
+            coins(u128::from(gas_limit + agent_fee), NATIVE_DENOM)
+        );
+        assert_eq!(agent.operators, vec![Addr::unchecked(AGENT0_OPERATOR)]);
+        let operator: Option<AgentResponse> = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::GetAgent {
+                    account_id: AGENT0_OPERATOR.to_string(),
+                },
+            )
+            .unwrap();
+        assert!(operator.is_none());
+
+        // Non-operator is rejected
+        let res_err: ContractError = app
+            .execute_contract(
+                Addr::unchecked(ANYONE),
+                contract_addr.clone(),
+                &proxy_call_msg,
+                &[],
+            )
+            .unwrap_err()
+            .downcast()
+            .unwrap();
+        assert_eq!(res_err, ContractError::AgentNotRegistered {});
+    }
+
+    #[test]
+    fn batch_credit_agents() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+
+        let agents = [AGENT0, ADMIN, ANYONE].map(Addr::unchecked);
+        for agent_id in agents.iter() {
+            store
+                .agents
+                .save(
+                    &mut deps.storage,
+                    agent_id,
+                    &Agent {
+                        balance: GenericBalance {
+                            native: coins(1, "meow"),
+                            cw20: vec![],
+                        },
+                        ..new_agent(agent_id.clone(), mock_env().block.time, vec![])
+                    },
+                )
+                .unwrap();
+        }
+
+        let credits = agents
+            .iter()
+            .enumerate()
+            .map(|(i, agent_id)| {
+                (
+                    agent_id.clone(),
+                    GenericBalance {
+                        native: coins(10 * (i as u128 + 1), "meow"),
+                        cw20: vec![],
+                    },
+                )
+            })
+            .collect();
+        store
+            .batch_credit_agents(&mut deps.storage, mock_env().block.time, credits)
+            .unwrap();
+
+        for (i, agent_id) in agents.iter().enumerate() {
+            let agent = store.agents.load(&deps.storage, agent_id).unwrap();
+            assert_eq!(agent.total_tasks_executed, 1);
+            assert_eq!(
+                agent.balance.native,
+                coins(10 * (i as u128 + 1) + 1, "meow")
+            );
+        }
+        // Paid out of what's already available, now owed to the agents
+        let config = store.config.load(&deps.storage).unwrap();
+        assert_eq!(config.available_balance.native, coins(1000, "meow"));
+        assert_eq!(config.total_agent_balance.native, coins(60, "meow"));
+
+        // Unknown agent fails the whole batch
+        let err = store
+            .batch_credit_agents(
+                &mut deps.storage,
+                mock_env().block.time,
+                vec![(Addr::unchecked(AGENT0_OPERATOR), GenericBalance::default())],
+            )
+            .unwrap_err();
+        assert_eq!(err, ContractError::AgentNotRegistered {});
+    }
+
+    #[test]
+    fn base_reward_over_max_agent_balance() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.max_agent_balance = Some(coin(10, NATIVE_DENOM));
+        config.available_balance.native = coins(100, NATIVE_DENOM);
+        store.config.save(&mut deps.storage, &config).unwrap();
+
+        let agent_id = Addr::unchecked(AGENT0);
+        let agent_with_balance = |amount: u128| Agent {
+            balance: GenericBalance {
+                native: coins(amount, NATIVE_DENOM),
+                cw20: vec![],
+            },
+            ..new_agent(
+                Addr::unchecked(AGENT1_BENEFICIARY),
+                mock_env().block.time,
+                vec![],
+            )
+        };
+
+        // Under the cap, agent fee is 5
+        let (_, withdraw_msgs) = store
+            .send_base_agent_reward(
+                &mut deps.storage,
+                &mock_env(),
+                agent_with_balance(0),
+                &agent_id,
+            )
+            .unwrap();
+        assert!(withdraw_msgs.is_empty());
+        let agent = store.agents.load(&deps.storage, &agent_id).unwrap();
+        assert_eq!(agent.balance.native, coins(5, NATIVE_DENOM));
+
+        // Over the cap, the excess gets sent to the payable account
+        let (_, withdraw_msgs) = store
+            .send_base_agent_reward(
+                &mut deps.storage,
+                &mock_env(),
+                agent_with_balance(8),
+                &agent_id,
+            )
+            .unwrap();
+        assert_eq!(
+            withdraw_msgs,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: AGENT1_BENEFICIARY.to_string(),
+                amount: coins(3, NATIVE_DENOM),
+            })]
+        );
+        let agent = store.agents.load(&deps.storage, &agent_id).unwrap();
+        assert_eq!(agent.balance.native, coins(10, NATIVE_DENOM));
+        let config = store.config.load(&deps.storage).unwrap();
+        assert_eq!(config.available_balance.native, coins(97, NATIVE_DENOM));
+    }
+
+    #[test]
+    fn base_reward_with_loyalty_tiers() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+
+        const DAY_NANOS: u64 = 86_400_000_000_000;
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.agent_fee = coin(10, NATIVE_DENOM);
+        config.loyalty_tiers = vec![
+            LoyaltyTier {
+                min_tenure_nanos: DAY_NANOS,
+                multiplier_bps: 12_500,
+            },
+            // Over the 2x cap
+            LoyaltyTier {
+                min_tenure_nanos: 30 * DAY_NANOS,
+                multiplier_bps: 50_000,
+            },
+        ];
+        store.config.save(&mut deps.storage, &config).unwrap();
+
+        let agent_id = Addr::unchecked(AGENT0);
+        let env = mock_env();
+        let agent_registered_days_ago = |days: u64| {
+            new_agent(
+                Addr::unchecked(AGENT1_BENEFICIARY),
+                Timestamp::from_nanos(env.block.time.nanos() - days * DAY_NANOS),
+                vec![],
+            )
+        };
+
+        // New agent gets the plain agent fee, then 1.25x, then capped at 2x
+        for (days, expected_reward) in [(0, 10), (1, 12), (365, 20)] {
+            let (reward, _) = store
+                .send_base_agent_reward(
+                    &mut deps.storage,
+                    &env,
+                    agent_registered_days_ago(days),
+                    &agent_id,
+                )
+                .unwrap();
+            assert_eq!(reward, coin(expected_reward, NATIVE_DENOM));
+            let agent = store.agents.load(&deps.storage, &agent_id).unwrap();
+            assert_eq!(agent.balance.native, coins(expected_reward, NATIVE_DENOM));
+        }
+        let mut config = store.config.load(&deps.storage).unwrap();
+        assert_eq!(config.total_agent_balance.native, coins(42, NATIVE_DENOM));
+
+        // 1.25x rounded up instead
+        config.reward_rounding = RewardRounding::RoundUp;
+        assert_eq!(
+            agent_reward(&config, env.block.time, &agent_registered_days_ago(1)),
+            coin(13, NATIVE_DENOM)
+        );
+    }
+
+    #[test]
+    fn agent_reward_within_bounds() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+
+        let env = mock_env();
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.reward_min = Some(Uint128::new(5));
+        config.reward_max = Some(Uint128::new(50));
+        let agent = new_agent(Addr::unchecked(AGENT1_BENEFICIARY), env.block.time, vec![]);
+
+        // Dust fee clamped up, oversized fee clamped down, anything in between unchanged.
+        // Tasks pay the clamped up reward, and the fee it was clamped down from
+        for (agent_fee, expected_reward, expected_charge) in
+            [(1, 5, 5), (100, 50, 100), (20, 20, 20)]
+        {
+            config.agent_fee = coin(agent_fee, NATIVE_DENOM);
+            assert_eq!(
+                agent_reward(&config, env.block.time, &agent),
+                coin(expected_reward, NATIVE_DENOM)
+            );
+            assert_eq!(
+                task_reward_charge(&config, env.block.time, &agent),
+                (
+                    coin(expected_charge, NATIVE_DENOM),
+                    coin(expected_reward, NATIVE_DENOM)
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn total_tasks_executed_saturates() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+
+        let agent_id = Addr::unchecked(AGENT0);
+        let agent = Agent {
+            total_tasks_executed: u64::MAX - 1,
+            ..new_agent(agent_id.clone(), mock_env().block.time, vec![])
+        };
+        store
+            .agents
+            .save(&mut deps.storage, &agent_id, &agent)
+            .unwrap();
+
+        // Reaches the max, then stays there
+        for _ in 0..2 {
+            let agent = store.agents.load(&deps.storage, &agent_id).unwrap();
+            store
+                .send_base_agent_reward(&mut deps.storage, &mock_env(), agent, &agent_id)
+                .unwrap();
+            let agent = store.agents.load(&deps.storage, &agent_id).unwrap();
+            assert_eq!(agent.total_tasks_executed, u64::MAX);
+        }
+
+        store
+            .batch_credit_agents(
+                &mut deps.storage,
+                mock_env().block.time,
+                vec![(agent_id.clone(), GenericBalance::default())],
+            )
+            .unwrap();
+        let agent = store.agents.load(&deps.storage, &agent_id).unwrap();
+        assert_eq!(agent.total_tasks_executed, u64::MAX);
+    }
+
+    #[test]
+    fn proxy_call_counts_executed_task() {
+        let (mut app, cw_template_contract) = proper_instantiate();
+        let contract_addr = cw_template_contract.addr();
+        let create_task_msg = ExecuteMsg::CreateTask {
+            task: TaskRequest {
+                interval: Interval::Immediate,
+                boundary: None,
+                stop_on_fail: false,
+                actions: vec![Action {
+                    msg: BankMsg::Send {
+                        to_address: "addr1".to_string(),
+                        amount: coins(3, NATIVE_DENOM),
+                    }
+                    .into(),
+                    gas_limit: None,
+                }],
+                rules: None,
+                cw20_coins: vec![],
+                task_type: None,
+            },
+        };
+        app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &create_task_msg,
+            &coins(1_000_020, NATIVE_DENOM),
+        )
+        .unwrap();
+        let msg = ExecuteMsg::RegisterAgent {
+            payable_account_id: Some(AGENT1_BENEFICIARY.to_string()),
+            capabilities: None,
+            accepted_terms: None,
+            client_version: None,
+        };
+        app.execute_contract(Addr::unchecked(AGENT0), contract_addr.clone(), &msg, &[])
+            .unwrap();
+
+        let total_tasks_executed = |app: &App| -> u64 {
+            let agent: AgentResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    contract_addr.clone(),
+                    &QueryMsg::GetAgent {
+                        account_id: AGENT0.to_string(),
+                    },
+                )
+                .unwrap();
+            agent.total_tasks_executed
+        };
+        assert_eq!(total_tasks_executed(&app), 0);
+
+        // Each run of the task counts, not just empty slot rewards
+        for executed in 1..=2 {
+            app.update_block(add_little_time);
+            let res = app
+                .execute_contract(
+                    Addr::unchecked(AGENT0),
+                    contract_addr.clone(),
+                    &ExecuteMsg::ProxyCall { task_hash: None },
+                    &[],
+                )
+                .unwrap();
+            assert!(res.events.iter().any(|event| event
+                .attributes
+                .iter()
+                .any(|attr| attr.key == "method" && attr.value == "proxy_callback")));
+            assert_eq!(total_tasks_executed(&app), executed);
+        }
+    }
+
+    #[test]
+    fn dust_rewards_accumulate() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        // Agent fee is 5, so every reward is dust
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.dust_threshold = Uint128::new(12);
+        store.config.save(&mut deps.storage, &config).unwrap();
+
+        let agent_id = Addr::unchecked(AGENT0);
+        let agent = new_agent(agent_id.clone(), mock_env().block.time, vec![]);
+        store
+            .agents
+            .save(&mut deps.storage, &agent_id, &agent)
+            .unwrap();
+
+        for (pending_dust, balance) in [
+            (coins(5, NATIVE_DENOM), vec![]),
+            (coins(10, NATIVE_DENOM), vec![]),
+            // Crossed the threshold, all of it gets credited
+            (vec![], coins(15, NATIVE_DENOM)),
+        ] {
+            let agent = store.agents.load(&deps.storage, &agent_id).unwrap();
+            store
+                .send_base_agent_reward(&mut deps.storage, &mock_env(), agent, &agent_id)
+                .unwrap();
+            let agent = store.agents.load(&deps.storage, &agent_id).unwrap();
+            assert_eq!(agent.pending_dust, pending_dust);
+            assert_eq!(agent.balance.native, balance);
+        }
+
+        // Rewards at the threshold skip the dust
+        config.dust_threshold = Uint128::new(5);
+        store.config.save(&mut deps.storage, &config).unwrap();
+        let agent = store.agents.load(&deps.storage, &agent_id).unwrap();
+        store
+            .send_base_agent_reward(&mut deps.storage, &mock_env(), agent, &agent_id)
+            .unwrap();
+        let agent = store.agents.load(&deps.storage, &agent_id).unwrap();
+        assert!(agent.pending_dust.is_empty());
+        assert_eq!(agent.balance.native, coins(20, NATIVE_DENOM));
+    }
+
+    #[test]
+    fn rewards_vest_linearly() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.reward_vesting_nanos = 100;
+        config.available_balance.native = coins(100, NATIVE_DENOM);
+        store.config.save(&mut deps.storage, &config).unwrap();
+
+        let agent_id = Addr::unchecked(AGENT0);
+        let agent = new_agent(agent_id.clone(), mock_env().block.time, vec![]);
+        store
+            .agents
+            .save(&mut deps.storage, &agent_id, &agent)
+            .unwrap();
+        // Agent fee is 5
+        store
+            .send_base_agent_reward(&mut deps.storage, &mock_env(), agent, &agent_id)
+            .unwrap();
+        let agent = store.agents.load(&deps.storage, &agent_id).unwrap();
+        assert!(agent.balance.native.is_empty());
+
+        // Mid-vest only part of it can be withdrawn
+        let mut env = mock_env();
+        env.block.time = env.block.time.plus_nanos(50);
+        let vested = store
+            .query_vested_balance(deps.as_ref(), env.clone(), AGENT0.to_string())
+            .unwrap();
+        assert_eq!(
+            vested,
+            VestedBalanceResponse {
+                vested: coins(2, NATIVE_DENOM),
+                vesting: coins(3, NATIVE_DENOM),
+            }
+        );
+        let messages = store
+            .withdraw_balances(&mut deps.storage, mock_info(AGENT0, &[]), env.block.time)
+            .unwrap();
+        assert_eq!(
+            messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: AGENT0.to_string(),
+                amount: coins(2, NATIVE_DENOM),
+            })]
+        );
+
+        // Fully vested, the rest is withdrawable and the credit is gone
+        env.block.time = env.block.time.plus_nanos(50);
+        let messages = store
+            .withdraw_balances(&mut deps.storage, mock_info(AGENT0, &[]), env.block.time)
+            .unwrap();
+        assert_eq!(
+            messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: AGENT0.to_string(),
+                amount: coins(3, NATIVE_DENOM),
+            })]
+        );
+        assert!(!store.agent_vesting.has(&deps.storage, &agent_id));
+    }
+
+    #[test]
+    fn vesting_credits_are_bucketed() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        let mut config = store.config.load(&deps.storage).unwrap();
+        // Periods of 5 nanos
+        config.reward_vesting_nanos = 100;
+        config.available_balance.native = coins(200, NATIVE_DENOM);
+        store.config.save(&mut deps.storage, &config).unwrap();
+
+        let agent_id = Addr::unchecked(AGENT0);
+        store
+            .agents
+            .save(
+                &mut deps.storage,
+                &agent_id,
+                &new_agent(agent_id.clone(), mock_env().block.time, vec![]),
+            )
+            .unwrap();
+        let mut env = mock_env();
+        let mut credit = |env: &Env| {
+            let agent = store.agents.load(&deps.storage, &agent_id).unwrap();
+            store
+                .send_base_agent_reward(&mut deps.storage, env, agent, &agent_id)
+                .unwrap();
+        };
+
+        // Rewards finishing in the same period share a credit
+        credit(&env);
+        credit(&env);
+        // Every later period opens a new one until the cap
+        for _ in 0..30 {
+            env.block.time = env.block.time.plus_nanos(5);
+            credit(&env);
+        }
+
+        let credits = store.agent_vesting.load(&deps.storage, &agent_id).unwrap();
+        assert_eq!(credits.len(), MAX_VESTING_CREDITS);
+        assert_eq!(credits[0].amount, coin(10, NATIVE_DENOM));
+        // Merging released what had vested, nothing got lost
+        let agent = store.agents.load(&deps.storage, &agent_id).unwrap();
+        assert!(!agent.balance.native.is_empty());
+        let total = credits
+            .iter()
+            .fold(agent.balance.native[0].amount, |total, credit| {
+                total + credit.amount.amount - credit.released
+            });
+        assert_eq!(total, Uint128::new(160));
+    }
+
+    #[test]
+    fn execution_nonce_increments() {
+        let (mut app, cw_template_contract) = proper_instantiate();
+        let contract_addr = cw_template_contract.addr();
+        let create_task_msg = ExecuteMsg::CreateTask {
+            task: TaskRequest {
+                interval: Interval::Immediate,
+                boundary: None,
+                stop_on_fail: false,
+                actions: vec![Action {
+                    msg: BankMsg::Send {
+                        to_address: "addr1".to_string(),
+                        amount: coins(3, NATIVE_DENOM),
+                    }
+                    .into(),
+                    gas_limit: None,
+                }],
+                rules: None,
+                cw20_coins: vec![],
+                task_type: None,
+            },
+        };
+        app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &create_task_msg,
+            &coins(1_000_020, NATIVE_DENOM),
+        )
+        .unwrap();
+        let msg = ExecuteMsg::RegisterAgent {
+            payable_account_id: Some(AGENT1_BENEFICIARY.to_string()),
+            capabilities: None,
+            accepted_terms: None,
+            client_version: None,
+        };
+        app.execute_contract(Addr::unchecked(AGENT0), contract_addr.clone(), &msg, &[])
+            .unwrap();
+
+        let proxy_call_msg = ExecuteMsg::ProxyCall { task_hash: None };
+        let mut nonces = vec![];
+        for _ in 0..2 {
+            app.update_block(add_little_time);
+            let res = app
+                .execute_contract(
+                    Addr::unchecked(AGENT0),
+                    contract_addr.clone(),
+                    &proxy_call_msg,
+                    &[],
+                )
+                .unwrap();
+            let nonce = res
+                .events
+                .iter()
+                .flat_map(|event| event.attributes.iter())
+                .find(|attr| attr.key == "exec_nonce")
+                .map(|attr| attr.value.clone())
+                .unwrap();
+            nonces.push(nonce);
+        }
+        assert_eq!(nonces, vec!["1".to_string(), "2".to_string()]);
+    }
+
+    #[test]
+    fn missed_slot_grace() {
+        let (mut app, cw_template_contract) = proper_instantiate();
+        let contract_addr = cw_template_contract.addr();
+        let mut grace_msg = update_settings_msg();
+        if let ExecuteMsg::UpdateSettings {
+            missed_slot_grace, ..
+        } = &mut grace_msg
+        {
+            *missed_slot_grace = Some(1);
+        }
+        app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &grace_msg,
+            &[],
+        )
+        .unwrap();
+        let create_task_msg = ExecuteMsg::CreateTask {
+            task: TaskRequest {
+                interval: Interval::Immediate,
+                boundary: None,
+                stop_on_fail: false,
+                actions: vec![Action {
+                    msg: BankMsg::Send {
+                        to_address: "addr1".to_string(),
+                        amount: coins(3, NATIVE_DENOM),
+                    }
+                    .into(),
+                    gas_limit: None,
+                }],
+                rules: None,
+                cw20_coins: vec![],
+                task_type: None,
+            },
+        };
+        app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &create_task_msg,
+            &coins(1_000_020, NATIVE_DENOM),
+        )
+        .unwrap();
+        let msg = ExecuteMsg::RegisterAgent {
+            payable_account_id: Some(AGENT1_BENEFICIARY.to_string()),
+            capabilities: None,
+            accepted_terms: None,
+            client_version: None,
+        };
+        app.execute_contract(Addr::unchecked(AGENT0), contract_addr.clone(), &msg, &[])
+            .unwrap();
+
+        // Executes after `blocks`, returns the executed slot and the agent's misses
+        let execute_after = |app: &mut App, blocks: u64| {
+            app.update_block(|block| block.height += blocks);
+            let res = app
+                .execute_contract(
+                    Addr::unchecked(AGENT0),
+                    contract_addr.clone(),
+                    &ExecuteMsg::ProxyCall { task_hash: None },
+                    &[],
+                )
+                .unwrap();
+            let slot_id: u64 = res
+                .events
+                .iter()
+                .flat_map(|event| event.attributes.iter())
+                .find(|attr| attr.key == "slot_id")
+                .map(|attr| attr.value.parse().unwrap())
+                .unwrap();
+            let agent: AgentResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    contract_addr.clone(),
+                    &QueryMsg::GetAgent {
+                        account_id: AGENT0.to_string(),
+                    },
+                )
+                .unwrap();
+            (slot_id, agent)
+        };
+
+        // One block late is within grace
+        let (slot_id, agent) = execute_after(&mut app, 2);
+        assert_eq!(app.block_info().height, slot_id + 1);
+        assert_eq!(agent.consecutive_misses, 0);
+        assert_eq!(agent.last_missed_slot, 0);
+
+        // Two blocks late is a miss
+        let (slot_id, agent) = execute_after(&mut app, 3);
+        assert_eq!(app.block_info().height, slot_id + 2);
+        assert_eq!(agent.consecutive_misses, 1);
+        assert_eq!(agent.last_missed_slot, slot_id);
+        let stats: MissedSlotStatsResponse = app
+            .wrap()
+            .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetMissedSlotStats {})
+            .unwrap();
+        assert_eq!(stats.total_misses, 1);
+        assert_eq!(stats.worst_agent, Some((Addr::unchecked(AGENT0), 1)));
+    }
+
+    fn execute_single_action(action: CosmosMsg) -> (ExecutionResult, String) {
+        let (mut app, cw_template_contract) = proper_instantiate();
+        let contract_addr = cw_template_contract.addr();
+        let create_task_msg = ExecuteMsg::CreateTask {
+            task: TaskRequest {
+                interval: Interval::Immediate,
+                boundary: None,
+                stop_on_fail: false,
+                actions: vec![Action {
+                    msg: action,
+                    gas_limit: Some(250_000),
+                }],
+                rules: None,
+                cw20_coins: vec![],
+                task_type: None,
+            },
+        };
+        app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &create_task_msg,
+            &coins(1_000_020, NATIVE_DENOM),
+        )
+        .unwrap();
+        let msg = ExecuteMsg::RegisterAgent {
+            payable_account_id: Some(AGENT1_BENEFICIARY.to_string()),
+            capabilities: None,
+            accepted_terms: None,
+            client_version: None,
+        };
+        app.execute_contract(Addr::unchecked(AGENT0), contract_addr.clone(), &msg, &[])
+            .unwrap();
+
+        app.update_block(add_little_time);
+        let res = app
+            .execute_contract(
+                Addr::unchecked(AGENT0),
+                contract_addr,
+                &ExecuteMsg::ProxyCall { task_hash: None },
+                &[],
+            )
+            .unwrap();
+        let slot_id = res
+            .events
+            .iter()
+            .flat_map(|event| event.attributes.iter())
+            .find(|attr| attr.key == "slot_id")
+            .map(|attr| attr.value.clone())
+            .unwrap();
+        (from_binary(&res.data.unwrap()).unwrap(), slot_id)
+    }
+
+    #[test]
+    fn execution_result_ack() {
+        let (result, slot_id) = execute_single_action(
+            BankMsg::Send {
+                to_address: "addr1".to_string(),
+                amount: coins(3, NATIVE_DENOM),
+            }
+            .into(),
+        );
+        assert_eq!(
+            result,
+            ExecutionResult {
+                success: true,
+                agent: Addr::unchecked(AGENT0),
+                slot: slot_id.parse().unwrap(),
+                reward: coin(5, NATIVE_DENOM),
+                error: None,
+            }
+        );
+
+        // Nobody validates as "you"
+        let (result, _) = execute_single_action(
+            StakingMsg::Delegate {
+                validator: "you".to_string(),
+                amount: coin(3, NATIVE_DENOM),
+            }
+            .into(),
+        );
+        assert!(!result.success);
+        assert_eq!(result.agent, Addr::unchecked(AGENT0));
+        assert_eq!(result.reward, coin(5, NATIVE_DENOM));
+        assert!(result.error.is_some());
+    }
+
+    #[test]
+    fn agent_reward_multiplier() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+
+        let env = mock_env();
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.agent_fee = coin(10, NATIVE_DENOM);
+        let mut agent = new_agent(Addr::unchecked(AGENT1_BENEFICIARY), env.block.time, vec![]);
+        assert_eq!(
+            agent_reward(&config, env.block.time, &agent),
+            coin(10, NATIVE_DENOM)
+        );
+
+        // 1.5x boost
+        agent.reward_multiplier_bps = 15_000;
+        assert_eq!(
+            agent_reward(&config, env.block.time, &agent),
+            coin(15, NATIVE_DENOM)
+        );
+
+        // Lowering the cap holds existing boosts under it
+        config.max_reward_multiplier_bps = 12_000;
+        assert_eq!(
+            agent_reward(&config, env.block.time, &agent),
+            coin(12, NATIVE_DENOM)
+        );
+    }
+
+    #[test]
+    fn proxy_call_charges_task_for_reward_bonus() {
+        let (mut app, cw_template_contract) = proper_instantiate();
+        let contract_addr = cw_template_contract.addr();
+        let create_task_msg = ExecuteMsg::CreateTask {
+            task: TaskRequest {
+                interval: Interval::Immediate,
+                boundary: None,
+                stop_on_fail: false,
+                actions: vec![Action {
+                    msg: BankMsg::Send {
+                        to_address: "addr1".to_string(),
+                        amount: coins(3, NATIVE_DENOM),
+                    }
+                    .into(),
+                    gas_limit: None,
+                }],
+                rules: None,
+                cw20_coins: vec![],
+                task_type: None,
+            },
+        };
+        app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &create_task_msg,
+            &coins(1_000_020, NATIVE_DENOM),
+        )
+        .unwrap();
+        let msg = ExecuteMsg::RegisterAgent {
+            payable_account_id: Some(AGENT1_BENEFICIARY.to_string()),
+            capabilities: None,
+            accepted_terms: None,
+            client_version: None,
+        };
+        app.execute_contract(Addr::unchecked(AGENT0), contract_addr.clone(), &msg, &[])
+            .unwrap();
+        app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &ExecuteMsg::SetAgentRewardMultiplier {
+                account_id: AGENT0.to_string(),
+                multiplier_bps: 15_000,
+            },
+            &[],
+        )
+        .unwrap();
+        app.update_block(add_little_time);
+
+        let task_deposit = |app: &App| -> Uint128 {
+            let tasks: Vec<TaskResponse> = app
+                .wrap()
+                .query_wasm_smart(
+                    contract_addr.clone(),
+                    &QueryMsg::GetTasks {
+                        from_index: None,
+                        limit: None,
+                    },
+                )
+                .unwrap();
+            tasks[0].total_deposit[0].amount
+        };
+        let deposit_before = task_deposit(&app);
+        app.execute_contract(
+            Addr::unchecked(AGENT0),
+            contract_addr.clone(),
+            &ExecuteMsg::ProxyCall { task_hash: None },
+            &[],
+        )
+        .unwrap();
+
+        // 1.5x the agent fee of 5, rounded down, on top of the gas and the sent 3
+        let reward = 7;
+        let gas = u128::from(GAS_BASE_FEE_JUNO);
+        let agent: AgentResponse = app
+            .wrap()
+            .query_wasm_smart(
+                contract_addr.clone(),
+                &QueryMsg::GetAgent {
+                    account_id: AGENT0.to_string(),
+                },
+            )
+            .unwrap();
+        assert_eq!(agent.balance.native, coins(gas + reward, NATIVE_DENOM));
+        assert_eq!(
+            deposit_before - task_deposit(&app),
+            Uint128::new(gas + reward + 3)
+        );
+    }
+
+    #[test]
+    fn proxy_call_conserves_funds() {
+        // Returns what left the contract, the protocol revenue and the reward of a proxy call
+        let proxy_call_with_reward_max = |reward_max: Option<Uint128>| {
+            let (mut app, cw_template_contract) = proper_instantiate();
+            let contract_addr = cw_template_contract.addr();
+            let mut settings_msg = update_settings_msg();
+            if let ExecuteMsg::UpdateSettings {
+                reward_max: max, ..
+            } = &mut settings_msg
+            {
+                *max = reward_max;
+            }
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                contract_addr.clone(),
+                &settings_msg,
+                &[],
+            )
+            .unwrap();
+            let create_task_msg = ExecuteMsg::CreateTask {
+                task: TaskRequest {
+                    interval: Interval::Immediate,
+                    boundary: None,
+                    stop_on_fail: false,
+                    actions: vec![Action {
+                        msg: BankMsg::Send {
+                            to_address: "addr1".to_string(),
+                            amount: coins(3, NATIVE_DENOM),
+                        }
+                        .into(),
+                        gas_limit: None,
+                    }],
+                    rules: None,
+                    cw20_coins: vec![],
+                    task_type: None,
+                },
+            };
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                contract_addr.clone(),
+                &create_task_msg,
+                &coins(1_000_020, NATIVE_DENOM),
+            )
+            .unwrap();
+            let msg = ExecuteMsg::RegisterAgent {
+                payable_account_id: Some(AGENT1_BENEFICIARY.to_string()),
+                capabilities: None,
+                accepted_terms: None,
+                client_version: None,
+            };
+            app.execute_contract(Addr::unchecked(AGENT0), contract_addr.clone(), &msg, &[])
+                .unwrap();
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                contract_addr.clone(),
+                &ExecuteMsg::SetAgentRewardMultiplier {
+                    account_id: AGENT0.to_string(),
+                    multiplier_bps: 15_000,
+                },
+                &[],
+            )
+            .unwrap();
+            app.update_block(add_little_time);
+
+            // Task deposit, protocol revenue and agent balance together
+            let ledger = |app: &App| -> Uint128 {
+                let tasks: Vec<TaskResponse> = app
+                    .wrap()
+                    .query_wasm_smart(
+                        contract_addr.clone(),
+                        &QueryMsg::GetTasks {
+                            from_index: None,
+                            limit: None,
+                        },
+                    )
+                    .unwrap();
+                let revenue: GenericBalance = app
+                    .wrap()
+                    .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetProtocolRevenue {})
+                    .unwrap();
+                let agent: AgentResponse = app
+                    .wrap()
+                    .query_wasm_smart(
+                        contract_addr.clone(),
+                        &QueryMsg::GetAgent {
+                            account_id: AGENT0.to_string(),
+                        },
+                    )
+                    .unwrap();
+                tasks[0].total_deposit[0].amount
+                    + revenue
+                        .native
+                        .iter()
+                        .chain(agent.balance.native.iter())
+                        .filter(|coin| coin.denom == NATIVE_DENOM)
+                        .map(|coin| coin.amount)
+                        .sum::<Uint128>()
+            };
+            let before = ledger(&app);
+            let res = app
+                .execute_contract(
+                    Addr::unchecked(AGENT0),
+                    contract_addr.clone(),
+                    &ExecuteMsg::ProxyCall { task_hash: None },
+                    &[],
+                )
+                .unwrap();
+            let result: ExecutionResult = from_binary(&res.data.unwrap()).unwrap();
+            let revenue: GenericBalance = app
+                .wrap()
+                .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetProtocolRevenue {})
+                .unwrap();
+            (before - ledger(&app), revenue.native, result.reward)
+        };
+
+        // Only the 3 sent by the task's action leaves the contract
+        let (sent, revenue, reward) = proxy_call_with_reward_max(None);
+        assert_eq!(sent, Uint128::new(3));
+        assert!(revenue.is_empty());
+        // The task still pays the full reward, the part over `reward_max` is protocol revenue
+        let (sent, revenue, capped_reward) = proxy_call_with_reward_max(Some(Uint128::new(1)));
+        assert_eq!(sent, Uint128::new(3));
+        assert_eq!(capped_reward, coin(1, NATIVE_DENOM));
+        assert_eq!(revenue, coins(reward.amount.u128() - 1, NATIVE_DENOM));
+    }
+
+    #[test]
+    fn stream_rewards() {
+        // Returns the reward sends in each execution, the agent balance and the beneficiary gains
+        let execute_twice = |stream: bool| -> (Vec<usize>, Uint128, Uint128) {
+            let (mut app, cw_template_contract) = proper_instantiate();
+            let contract_addr = cw_template_contract.addr();
+            let create_task_msg = ExecuteMsg::CreateTask {
+                task: TaskRequest {
+                    interval: Interval::Immediate,
+                    boundary: None,
+                    stop_on_fail: false,
+                    actions: vec![Action {
+                        msg: BankMsg::Send {
+                            to_address: "addr1".to_string(),
+                            amount: coins(3, NATIVE_DENOM),
+                        }
+                        .into(),
+                        gas_limit: None,
+                    }],
+                    rules: None,
+                    cw20_coins: vec![],
+                    task_type: None,
+                },
+            };
+            app.execute_contract(
+                Addr::unchecked(ADMIN),
+                contract_addr.clone(),
+                &create_task_msg,
+                &coins(1_000_020, NATIVE_DENOM),
+            )
+            .unwrap();
+            let msg = ExecuteMsg::RegisterAgent {
+                payable_account_id: Some(AGENT1_BENEFICIARY.to_string()),
+                capabilities: None,
+                accepted_terms: None,
+                client_version: None,
+            };
+            app.execute_contract(Addr::unchecked(AGENT0), contract_addr.clone(), &msg, &[])
+                .unwrap();
+            app.execute_contract(
+                Addr::unchecked(AGENT0),
+                contract_addr.clone(),
+                &ExecuteMsg::SetStreamRewards { stream },
+                &[],
+            )
+            .unwrap();
+            let beneficiary_before = app
+                .wrap()
+                .query_balance(AGENT1_BENEFICIARY, NATIVE_DENOM)
+                .unwrap()
+                .amount;
+
+            let proxy_call_msg = ExecuteMsg::ProxyCall { task_hash: None };
+            let mut sends = vec![];
+            for _ in 0..2 {
+                app.update_block(add_little_time);
+                let res = app
+                    .execute_contract(
+                        Addr::unchecked(AGENT0),
+                        contract_addr.clone(),
+                        &proxy_call_msg,
+                        &[],
+                    )
+                    .unwrap();
+                let reward_sends =
+                    res.events
+                        .iter()
+                        .filter(|event| event.ty == "transfer")
+                        .filter(|event| {
+                            event.attributes.iter().any(|attr| {
+                                attr.key == "recipient" && attr.value == AGENT1_BENEFICIARY
+                            })
+                        })
+                        .count();
+                sends.push(reward_sends);
+            }
+            let agent: AgentResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    contract_addr.clone(),
+                    &QueryMsg::GetAgent {
+                        account_id: AGENT0.to_string(),
+                    },
+                )
+                .unwrap();
+            assert_eq!(agent.stream_rewards, stream);
+            let beneficiary_after = app
+                .wrap()
+                .query_balance(AGENT1_BENEFICIARY, NATIVE_DENOM)
+                .unwrap()
+                .amount;
+            (
+                sends,
+                agent.balance.native[0].amount,
+                beneficiary_after - beneficiary_before,
+            )
+        };
+
+        // Claiming agent gets gas and rewards credited
+        let (sends, claim_balance, gains) = execute_twice(false);
+        assert_eq!(sends, vec![0, 0]);
+        assert!(gains.is_zero());
+
+        // Streaming agent only keeps the gas, rewards go out on each execution
+        let (sends, stream_balance, gains) = execute_twice(true);
+        assert_eq!(sends, vec![1, 1]);
+        assert!(!gains.is_zero());
+        assert_eq!(stream_balance + gains, claim_balance);
+    }
+
+    #[test]
+    fn top_streak() {
+        let (mut app, cw_template_contract) = proper_instantiate();
+        let contract_addr = cw_template_contract.addr();
+        let create_task_msg = ExecuteMsg::CreateTask {
+            task: TaskRequest {
+                interval: Interval::Immediate,
+                boundary: None,
+                stop_on_fail: false,
+                actions: vec![Action {
+                    msg: BankMsg::Send {
+                        to_address: "addr1".to_string(),
+                        amount: coins(3, NATIVE_DENOM),
+                    }
+                    .into(),
+                    gas_limit: None,
+                }],
+                rules: None,
+                cw20_coins: vec![],
+                task_type: None,
+            },
+        };
+        app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &create_task_msg,
+            &coins(2_000_040, NATIVE_DENOM),
+        )
+        .unwrap();
+        let msg = ExecuteMsg::RegisterAgent {
+            payable_account_id: Some(AGENT1_BENEFICIARY.to_string()),
+            capabilities: None,
+            accepted_terms: None,
+            client_version: None,
+        };
+        app.execute_contract(Addr::unchecked(AGENT0), contract_addr.clone(), &msg, &[])
+            .unwrap();
+        let top_streak = |app: &App| -> Option<(Addr, u64)> {
+            app.wrap()
+                .query_wasm_smart(contract_addr.clone(), &QueryMsg::GetTopStreak {})
+                .unwrap()
+        };
+        assert_eq!(top_streak(&app), None);
+
+        // Executes after `blocks`, returns the agent's streak
+        let execute_after = |app: &mut App, blocks: u64| {
+            app.update_block(|block| {
+                block.height += blocks;
+                block.time = block.time.plus_seconds(19 * blocks);
+            });
+            app.execute_contract(
+                Addr::unchecked(AGENT0),
+                contract_addr.clone(),
+                &ExecuteMsg::ProxyCall { task_hash: None },
+                &[],
+            )
+            .unwrap();
+            let agent: AgentResponse = app
+                .wrap()
+                .query_wasm_smart(
+                    contract_addr.clone(),
+                    &QueryMsg::GetAgent {
+                        account_id: AGENT0.to_string(),
+                    },
+                )
+                .unwrap();
+            agent.current_streak
+        };
+
+        // On time executions build the streak
+        assert_eq!(execute_after(&mut app, 1), 1);
+        assert_eq!(execute_after(&mut app, 1), 2);
+        assert_eq!(top_streak(&app), Some((Addr::unchecked(AGENT0), 2)));
+
+        // A miss resets it
+        assert_eq!(execute_after(&mut app, 3), 0);
+        assert_eq!(top_streak(&app), None);
+        assert_eq!(execute_after(&mut app, 1), 1);
+        assert_eq!(top_streak(&app), Some((Addr::unchecked(AGENT0), 1)));
+    }
+
+    #[test]
+    fn allowed_reward_cw20() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, NATIVE_DENOM));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.allowed_reward_cw20 = vec![Addr::unchecked("allowed")];
+        store.config.save(&mut deps.storage, &config).unwrap();
+
+        let agent_id = Addr::unchecked(AGENT0);
+        store
+            .agents
+            .save(
+                &mut deps.storage,
+                &agent_id,
+                &new_agent(agent_id.clone(), mock_env().block.time, vec![]),
+            )
+            .unwrap();
+        let credit = |address: &str| GenericBalance {
+            native: coins(10, NATIVE_DENOM),
+            cw20: vec![Cw20CoinVerified {
+                address: Addr::unchecked(address),
+                amount: Uint128::new(7),
+            }],
+        };
+
+        store
+            .batch_credit_agents(
+                &mut deps.storage,
+                mock_env().block.time,
+                vec![(agent_id.clone(), credit("allowed"))],
+            )
+            .unwrap();
+        let agent = store.agents.load(&deps.storage, &agent_id).unwrap();
+        assert_eq!(agent.balance, credit("allowed"));
+
+        // Nothing gets credited once a cw20 isn't allowed
+        let err = store
+            .batch_credit_agents(
+                &mut deps.storage,
+                mock_env().block.time,
+                vec![(agent_id.clone(), credit("meow"))],
+            )
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::RewardCw20NotAllowed {
+                addr: "meow".to_string()
+            }
+        );
+        assert_eq!(store.agents.load(&deps.storage, &agent_id).unwrap(), agent);
+    }
 }
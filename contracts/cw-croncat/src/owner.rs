@@ -1,18 +1,19 @@
 use crate::balancer::BalancerMode;
 use crate::error::ContractError;
-use crate::helpers::has_cw_coins;
+use crate::helpers::{has_cw_coins, parse_client_version, send_tokens, GenericBalance};
 use crate::state::{Config, CwCroncat};
 use cosmwasm_std::{
-    has_coins, to_binary, BankMsg, Coin, Deps, DepsMut, Env, MessageInfo, Order, Response,
+    has_coins, to_binary, BankMsg, Coin, Decimal, Deps, DepsMut, Env, MessageInfo, Order, Response,
     StdResult, SubMsg, Uint64, WasmMsg,
 };
 use cw20::{Balance, Cw20ExecuteMsg};
 use cw_croncat_core::msg::{
     BalancesResponse, CwCroncatResponse, ExecuteMsg, GetBalancesResponse, GetConfigResponse,
-    GetWalletBalancesResponse, QueueItemResponse, ReplyQueueResponse,
+    GetWalletBalancesResponse, HealthResponse, QueueItemResponse, ReplyQueueResponse,
     RoundRobinBalancerModeResponse, SlotResponse, SlotWithRuleResponse,
 };
 use cw_croncat_core::traits::FindAndMutate;
+use cw_croncat_core::types::{AgentStatus, PauseFlags, SlashDestination};
 
 impl<'a> CwCroncat<'a> {
     pub(crate) fn query_config(&self, deps: Deps) -> StdResult<GetConfigResponse> {
@@ -24,15 +25,65 @@ impl<'a> CwCroncat<'a> {
             min_tasks_per_agent: c.min_tasks_per_agent,
             agent_active_indices: c.agent_active_indices,
             agents_eject_threshold: c.agents_eject_threshold,
+            missed_slot_threshold: c.missed_slot_threshold,
             native_denom: c.native_denom,
             agent_fee: c.agent_fee,
             gas_price: c.gas_price,
             proxy_callback_gas: c.proxy_callback_gas,
             slot_granularity: c.slot_granularity,
             cw_rules_addr: c.cw_rules_addr,
+            agent_nomination_duration: c.agent_nomination_duration,
+            gas_base_fee: c.gas_base_fee,
+            cw20_whitelist: c.cw20_whitelist,
+            limit: c.limit,
+            slash_destination: c.slash_destination,
+            stale_timeout_nanos: c.stale_timeout_nanos,
+            registration_fee: c.registration_fee,
+            rotate_queue_each_slot: c.rotate_queue_each_slot,
+            min_agents_for_tasks: c.min_agents_for_tasks,
+            max_agent_balance: c.max_agent_balance,
+            reward_rounding: c.reward_rounding,
+            loyalty_tiers: c.loyalty_tiers,
+            max_agents: c.max_agents,
+            max_pending_agents: c.max_pending_agents,
+            reward_escrow: c.reward_escrow,
+            reward_min: c.reward_min,
+            reward_max: c.reward_max,
+            mass_exit_threshold: c.mass_exit_threshold,
+            allow_empty_active_set: c.allow_empty_active_set,
+            weighted_selection: c.weighted_selection,
+            selection: c.selection,
+            onboarders: c.onboarders,
+            min_tasks_for_nomination: c.min_tasks_for_nomination,
+            activation_cooldown_nanos: c.activation_cooldown_nanos,
+            dust_threshold: c.dust_threshold,
+            reward_vesting_nanos: c.reward_vesting_nanos,
+            queue_removal_mode: c.queue_removal_mode,
+            max_missed_nominations: c.max_missed_nominations,
+            exclusivity_window_slots: c.exclusivity_window_slots,
+            idle_grace_nanos: c.idle_grace_nanos,
+            dex_router: c.dex_router,
+            dex_max_slippage: c.dex_max_slippage,
+            missed_slot_grace: c.missed_slot_grace,
+            slash_denom: c.slash_denom,
+            position_hook_step: c.position_hook_step,
+            blacklist: c.blacklist,
+            max_reward_multiplier_bps: c.max_reward_multiplier_bps,
+            refund_stray_funds: c.refund_stray_funds,
+            required_terms_hash: c.required_terms_hash,
+            auto_activate_open_slots: c.auto_activate_open_slots,
+            slash_bps: c.slash_bps,
+            allowed_reward_cw20: c.allowed_reward_cw20,
+            slot_duration_nanos: c.slot_duration_nanos,
+            min_client_version: c.min_client_version,
         })
     }
 
+    /// Estimated nanos a block slot lasts, for converting block slots to wall-clock time
+    pub(crate) fn query_slot_duration(&self, deps: Deps) -> StdResult<u64> {
+        Ok(self.config.load(deps.storage)?.slot_duration_nanos)
+    }
+
     pub(crate) fn query_balances(&self, deps: Deps) -> StdResult<GetBalancesResponse> {
         let c: Config = self.config.load(deps.storage)?;
         Ok(GetBalancesResponse {
@@ -43,6 +94,40 @@ impl<'a> CwCroncat<'a> {
         })
     }
 
+    /// What the contract earned for itself, as opposed to what it owes agents and task owners
+    pub(crate) fn query_protocol_revenue(&self, deps: Deps) -> StdResult<GenericBalance> {
+        Ok(self.config.load(deps.storage)?.protocol_revenue)
+    }
+
+    /// Pause state and agent availability in one place, for ops dashboards
+    pub(crate) fn query_health(&self, deps: Deps, env: Env) -> StdResult<HealthResponse> {
+        let c: Config = self.config.load(deps.storage)?;
+        let active = self.agent_active_queue.load(deps.storage)?;
+        let pending = self.agent_pending_queue.load(deps.storage)?;
+
+        // The first pending agent is always nominated before the ones behind it
+        let has_nominated = match pending.first() {
+            Some(agent_id) => matches!(
+                self.get_agent_status(deps.storage, env, agent_id.clone()),
+                Ok(AgentStatus::Nominated)
+            ),
+            None => false,
+        };
+        let mut last_execution_nanos = 0;
+        for agent_id in active.iter() {
+            if let Some(last_execution) = self.agents.load(deps.storage, agent_id)?.last_execution {
+                last_execution_nanos = last_execution_nanos.max(last_execution.nanos());
+            }
+        }
+
+        Ok(HealthResponse {
+            paused: c.paused,
+            active_agents: active.len() as u64,
+            has_nominated,
+            last_execution_nanos,
+        })
+    }
+
     /// Returns user cw20 balances locked inside this contract
     pub(crate) fn query_wallet_balances(
         &self,
@@ -80,6 +165,46 @@ impl<'a> CwCroncat<'a> {
                 proxy_callback_gas,
                 min_tasks_per_agent,
                 agents_eject_threshold,
+                slash_destination,
+                stale_timeout_nanos,
+                registration_fee,
+                rotate_queue_each_slot,
+                min_agents_for_tasks,
+                max_agent_balance,
+                reward_rounding,
+                loyalty_tiers,
+                max_agents,
+                max_pending_agents,
+                reward_escrow,
+                reward_min,
+                reward_max,
+                mass_exit_threshold,
+                allow_empty_active_set,
+                weighted_selection,
+                selection,
+                onboarders,
+                min_tasks_for_nomination,
+                activation_cooldown_nanos,
+                dust_threshold,
+                reward_vesting_nanos,
+                queue_removal_mode,
+                max_missed_nominations,
+                exclusivity_window_slots,
+                idle_grace_nanos,
+                dex_router,
+                dex_max_slippage,
+                missed_slot_grace,
+                slash_denom,
+                position_hook_step,
+                blacklist,
+                max_reward_multiplier_bps,
+                refund_stray_funds,
+                required_terms_hash,
+                auto_activate_open_slots,
+                slash_bps,
+                allowed_reward_cw20,
+                slot_duration_nanos,
+                min_client_version,
                 // treasury_id,
             } => {
                 self.config
@@ -99,8 +224,9 @@ impl<'a> CwCroncat<'a> {
                         if let Some(slot_granularity) = slot_granularity {
                             config.slot_granularity = slot_granularity;
                         }
+                        // Legacy switch, pauses or resumes everything
                         if let Some(paused) = paused {
-                            config.paused = paused;
+                            config.paused = PauseFlags::all(paused);
                         }
                         if let Some(gas_price) = gas_price {
                             config.gas_price = gas_price;
@@ -117,6 +243,212 @@ impl<'a> CwCroncat<'a> {
                         if let Some(agents_eject_threshold) = agents_eject_threshold {
                             config.agents_eject_threshold = agents_eject_threshold;
                         }
+                        if let Some(slash_destination) = slash_destination {
+                            if let SlashDestination::Address(addr) = &slash_destination {
+                                api.addr_validate(addr.as_str())?;
+                            }
+                            config.slash_destination = slash_destination;
+                        }
+                        if let Some(stale_timeout_nanos) = stale_timeout_nanos {
+                            config.stale_timeout_nanos = stale_timeout_nanos;
+                        }
+                        // Zero amount turns the registration fee off
+                        if let Some(registration_fee) = registration_fee {
+                            config.registration_fee = if registration_fee.amount.is_zero() {
+                                None
+                            } else {
+                                Some(registration_fee)
+                            };
+                        }
+                        if let Some(rotate_queue_each_slot) = rotate_queue_each_slot {
+                            config.rotate_queue_each_slot = rotate_queue_each_slot;
+                        }
+                        if let Some(min_agents_for_tasks) = min_agents_for_tasks {
+                            config.min_agents_for_tasks = min_agents_for_tasks;
+                        }
+                        // Zero amount removes the cap
+                        if let Some(max_agent_balance) = max_agent_balance {
+                            config.max_agent_balance = if max_agent_balance.amount.is_zero() {
+                                None
+                            } else {
+                                Some(max_agent_balance)
+                            };
+                        }
+                        if let Some(reward_rounding) = reward_rounding {
+                            config.reward_rounding = reward_rounding;
+                        }
+                        if let Some(loyalty_tiers) = loyalty_tiers {
+                            config.loyalty_tiers = loyalty_tiers;
+                        }
+                        // Zero removes the cap
+                        if let Some(max_agents) = max_agents {
+                            config.max_agents = if max_agents == 0 {
+                                None
+                            } else {
+                                Some(max_agents)
+                            };
+                        }
+                        if let Some(max_pending_agents) = max_pending_agents {
+                            config.max_pending_agents = if max_pending_agents == 0 {
+                                None
+                            } else {
+                                Some(max_pending_agents)
+                            };
+                        }
+                        // Zero deposit threshold turns escrow off
+                        if let Some(reward_escrow) = reward_escrow {
+                            config.reward_escrow =
+                                if reward_escrow.min_task_deposit.amount.is_zero() {
+                                    None
+                                } else {
+                                    Some(reward_escrow)
+                                };
+                        }
+                        // Zero removes the bound
+                        if let Some(reward_min) = reward_min {
+                            config.reward_min = if reward_min.is_zero() {
+                                None
+                            } else {
+                                Some(reward_min)
+                            };
+                        }
+                        if let Some(reward_max) = reward_max {
+                            config.reward_max = if reward_max.is_zero() {
+                                None
+                            } else {
+                                Some(reward_max)
+                            };
+                        }
+                        // Zero exits turns the breaker off
+                        if let Some(mass_exit_threshold) = mass_exit_threshold {
+                            config.mass_exit_threshold = if mass_exit_threshold.max_exits == 0 {
+                                None
+                            } else {
+                                Some(mass_exit_threshold)
+                            };
+                        }
+                        if let Some(allow_empty_active_set) = allow_empty_active_set {
+                            config.allow_empty_active_set = allow_empty_active_set;
+                        }
+                        if let Some(weighted_selection) = weighted_selection {
+                            config.weighted_selection = weighted_selection;
+                        }
+                        if let Some(selection) = selection {
+                            config.selection = selection;
+                        }
+                        if let Some(onboarders) = onboarders {
+                            config.onboarders = onboarders
+                                .iter()
+                                .map(|onboarder| api.addr_validate(onboarder))
+                                .collect::<StdResult<Vec<_>>>()?;
+                        }
+                        if let Some(min_tasks_for_nomination) = min_tasks_for_nomination {
+                            config.min_tasks_for_nomination = min_tasks_for_nomination;
+                        }
+                        if let Some(activation_cooldown_nanos) = activation_cooldown_nanos {
+                            config.activation_cooldown_nanos = activation_cooldown_nanos;
+                        }
+                        if let Some(dust_threshold) = dust_threshold {
+                            config.dust_threshold = dust_threshold;
+                        }
+                        if let Some(reward_vesting_nanos) = reward_vesting_nanos {
+                            config.reward_vesting_nanos = reward_vesting_nanos;
+                        }
+                        if let Some(queue_removal_mode) = queue_removal_mode {
+                            config.queue_removal_mode = queue_removal_mode;
+                        }
+                        if let Some(max_missed_nominations) = max_missed_nominations {
+                            config.max_missed_nominations = max_missed_nominations;
+                        }
+                        if let Some(exclusivity_window_slots) = exclusivity_window_slots {
+                            config.exclusivity_window_slots = exclusivity_window_slots;
+                        }
+                        if let Some(idle_grace_nanos) = idle_grace_nanos {
+                            config.idle_grace_nanos = idle_grace_nanos;
+                        }
+                        // Empty address turns swapping off
+                        if let Some(dex_router) = dex_router {
+                            config.dex_router = if dex_router.is_empty() {
+                                None
+                            } else {
+                                Some(api.addr_validate(&dex_router)?)
+                            };
+                        }
+                        if let Some(dex_max_slippage) = dex_max_slippage {
+                            if dex_max_slippage > Decimal::one() {
+                                return Err(ContractError::CustomError {
+                                    val: "dex_max_slippage can't be above 100%".to_string(),
+                                });
+                            }
+                            config.dex_max_slippage = dex_max_slippage;
+                        }
+                        if let Some(missed_slot_grace) = missed_slot_grace {
+                            config.missed_slot_grace = missed_slot_grace;
+                        }
+                        // Empty denom slashes everything again
+                        if let Some(slash_denom) = slash_denom {
+                            config.slash_denom = if slash_denom.is_empty() {
+                                None
+                            } else {
+                                Some(slash_denom)
+                            };
+                        }
+                        if let Some(position_hook_step) = position_hook_step {
+                            config.position_hook_step = position_hook_step;
+                        }
+                        if let Some(blacklist) = blacklist {
+                            config.blacklist = blacklist
+                                .iter()
+                                .map(|addr| api.addr_validate(addr))
+                                .collect::<StdResult<_>>()?;
+                        }
+                        if let Some(max_reward_multiplier_bps) = max_reward_multiplier_bps {
+                            config.max_reward_multiplier_bps = max_reward_multiplier_bps;
+                        }
+                        if let Some(refund_stray_funds) = refund_stray_funds {
+                            config.refund_stray_funds = refund_stray_funds;
+                        }
+                        // Empty hash stops requiring terms
+                        if let Some(required_terms_hash) = required_terms_hash {
+                            config.required_terms_hash = if required_terms_hash.is_empty() {
+                                None
+                            } else {
+                                Some(required_terms_hash)
+                            };
+                        }
+                        if let Some(auto_activate_open_slots) = auto_activate_open_slots {
+                            config.auto_activate_open_slots = auto_activate_open_slots;
+                        }
+                        if let Some(slash_bps) = slash_bps {
+                            config.slash_bps = slash_bps;
+                        }
+                        if let Some(slot_duration_nanos) = slot_duration_nanos {
+                            config.slot_duration_nanos = slot_duration_nanos;
+                        }
+                        // Empty version stops requiring one
+                        if let Some(min_client_version) = min_client_version {
+                            config.min_client_version = if min_client_version.is_empty() {
+                                None
+                            } else {
+                                parse_client_version(&min_client_version)?;
+                                Some(min_client_version)
+                            };
+                        }
+                        if let Some(allowed_reward_cw20) = allowed_reward_cw20 {
+                            config.allowed_reward_cw20 = allowed_reward_cw20
+                                .iter()
+                                .map(|addr| api.addr_validate(addr))
+                                .collect::<StdResult<_>>()?;
+                        }
+                        if let (Some(reward_min), Some(reward_max)) =
+                            (config.reward_min, config.reward_max)
+                        {
+                            if reward_min > reward_max {
+                                return Err(ContractError::CustomError {
+                                    val: "reward_min can't be above reward_max".to_string(),
+                                });
+                            }
+                        }
                         Ok(config)
                     })?;
             }
@@ -125,7 +457,7 @@ impl<'a> CwCroncat<'a> {
         let c: Config = self.config.load(deps.storage)?;
         Ok(Response::new()
             .add_attribute("method", "update_settings")
-            .add_attribute("paused", c.paused.to_string())
+            .add_attribute("paused", c.paused.any().to_string())
             .add_attribute("owner_id", c.owner_id.to_string())
             // .add_attribute(
             //     "treasury_id",
@@ -152,6 +484,144 @@ impl<'a> CwCroncat<'a> {
             .add_attribute("slot_granularity", c.slot_granularity.to_string()))
     }
 
+    /// Pause or resume registration, execution, withdrawals and task creation independently
+    /// Flags left as None are unchanged
+    pub fn update_pause_flags(
+        &self,
+        deps: DepsMut,
+        info: MessageInfo,
+        register: Option<bool>,
+        execute: Option<bool>,
+        withdraw: Option<bool>,
+        create_task: Option<bool>,
+    ) -> Result<Response, ContractError> {
+        let c = self
+            .config
+            .update(deps.storage, |mut config| -> Result<_, ContractError> {
+                if info.sender != config.owner_id {
+                    return Err(ContractError::Unauthorized {});
+                }
+                if let Some(register) = register {
+                    config.paused.register = register;
+                }
+                if let Some(execute) = execute {
+                    config.paused.execute = execute;
+                }
+                if let Some(withdraw) = withdraw {
+                    config.paused.withdraw = withdraw;
+                }
+                if let Some(create_task) = create_task {
+                    config.paused.create_task = create_task;
+                }
+                Ok(config)
+            })?;
+
+        Ok(Response::new()
+            .add_attribute("method", "update_pause_flags")
+            .add_attribute("register", c.paused.register.to_string())
+            .add_attribute("execute", c.paused.execute.to_string())
+            .add_attribute("withdraw", c.paused.withdraw.to_string())
+            .add_attribute("create_task", c.paused.create_task.to_string()))
+    }
+
+    /// Sends all protocol revenue to `recipient`, owner only
+    pub fn withdraw_protocol_revenue(
+        &self,
+        deps: DepsMut,
+        info: MessageInfo,
+        recipient: String,
+    ) -> Result<Response, ContractError> {
+        let recipient = deps.api.addr_validate(&recipient)?;
+        let mut config: Config = self.config.load(deps.storage)?;
+        if info.sender != config.owner_id {
+            return Err(ContractError::Unauthorized {});
+        }
+        let (messages, sent) = send_tokens(&recipient, &config.protocol_revenue)?;
+        if messages.is_empty() {
+            return Err(ContractError::CustomError {
+                val: "No protocol revenue to withdraw".to_string(),
+            });
+        }
+        config.protocol_revenue.checked_sub_generic(&sent)?;
+        config.available_balance.checked_sub_generic(&sent)?;
+        self.config.save(deps.storage, &config)?;
+
+        Ok(Response::new()
+            .add_attribute("method", "withdraw_protocol_revenue")
+            .add_attribute("recipient", recipient)
+            .add_submessages(messages))
+    }
+
+    /// Sends whatever the contract holds in `denom` beyond `available_balance` to `recipient`, owner only.
+    /// Agent balances are part of `available_balance`, so only funds sent around the handlers go
+    pub fn sweep_stuck_funds(
+        &self,
+        deps: DepsMut,
+        info: MessageInfo,
+        env: Env,
+        denom: String,
+        recipient: String,
+    ) -> Result<Response, ContractError> {
+        let recipient = deps.api.addr_validate(&recipient)?;
+        let config: Config = self.config.load(deps.storage)?;
+        if info.sender != config.owner_id {
+            return Err(ContractError::Unauthorized {});
+        }
+        let held = deps
+            .querier
+            .query_balance(&env.contract.address, &denom)?
+            .amount;
+        let tracked = config
+            .available_balance
+            .native
+            .iter()
+            .find(|coin| coin.denom == denom)
+            .map(|coin| coin.amount)
+            .unwrap_or_default();
+        let surplus = held.saturating_sub(tracked);
+        if surplus.is_zero() {
+            return Err(ContractError::CustomError {
+                val: "No untracked funds to sweep".to_string(),
+            });
+        }
+
+        Ok(Response::new()
+            .add_attribute("method", "sweep_stuck_funds")
+            .add_attribute("recipient", recipient.clone())
+            .add_attribute("swept", surplus.to_string())
+            .add_message(BankMsg::Send {
+                to_address: recipient.to_string(),
+                amount: vec![Coin::new(surplus.u128(), denom)],
+            }))
+    }
+
+    /// Set how many slots in a row an agent can miss before it can be slashed
+    pub fn update_missed_slot_threshold(
+        &self,
+        deps: DepsMut,
+        info: MessageInfo,
+        threshold: u64,
+    ) -> Result<Response, ContractError> {
+        let c = self
+            .config
+            .update(deps.storage, |mut config| -> Result<_, ContractError> {
+                if info.sender != config.owner_id {
+                    return Err(ContractError::Unauthorized {});
+                }
+                if threshold == 0 {
+                    return Err(ContractError::CustomError {
+                        val: "missed_slot_threshold must be at least 1".to_string(),
+                    });
+                }
+                config.missed_slot_threshold = threshold;
+                Ok(config)
+            })?;
+
+        Ok(Response::new()
+            .add_attribute("method", "update_missed_slot_threshold")
+            .add_attribute("missed_slot_threshold", c.missed_slot_threshold.to_string()))
+    }
+
     /// Move Balance
     /// Allows owner to move balance to DAO or to let treasury transfer to itself only.
     /// This is a restricted method for moving funds utilized in growth management strategies.
@@ -401,13 +871,21 @@ impl<'a> CwCroncat<'a> {
 #[cfg(test)]
 mod tests {
     use crate::error::ContractError;
+    use crate::helpers::slots_to_nanos;
+    use crate::helpers::test_helpers::{mock_init, update_settings_msg};
     use crate::state::CwCroncat;
-    use cosmwasm_std::testing::{mock_dependencies_with_balance, mock_env, mock_info};
-    use cosmwasm_std::{coin, coins, from_binary, Addr, MessageInfo};
+    use cosmwasm_std::testing::{
+        mock_dependencies_with_balance, mock_env, mock_info, MOCK_CONTRACT_ADDR,
+    };
+    use cosmwasm_std::{
+        coin, coins, from_binary, Addr, BankMsg, Deps, MessageInfo, StdResult, SubMsg, Uint128,
+    };
     use cw20::Balance;
     use cw_croncat_core::msg::{
-        ExecuteMsg, GetBalancesResponse, GetConfigResponse, InstantiateMsg, QueryMsg,
+        ExecuteMsg, GetBalancesResponse, GetConfigResponse, HealthResponse, InstantiateMsg,
+        QueryMsg, SlashableAgentResponse,
     };
+    use cw_croncat_core::types::{PauseFlags, SlotType};
 
     #[test]
     fn update_settings() {
@@ -420,6 +898,7 @@ mod tests {
             gas_base_fee: None,
             agent_nomination_duration: Some(360),
             cw_rules_addr: "todo".to_string(),
+            initial_agent: None,
         };
         let info = MessageInfo {
             sender: Addr::unchecked("creator"),
@@ -431,17 +910,10 @@ mod tests {
             .unwrap();
         assert_eq!(0, res_init.messages.len());
 
-        let payload = ExecuteMsg::UpdateSettings {
-            paused: Some(true),
-            owner_id: None,
-            // treasury_id: None,
-            agent_fee: None,
-            min_tasks_per_agent: None,
-            agents_eject_threshold: None,
-            gas_price: None,
-            proxy_callback_gas: None,
-            slot_granularity: None,
-        };
+        let mut payload = update_settings_msg();
+        if let ExecuteMsg::UpdateSettings { paused, .. } = &mut payload {
+            *paused = Some(true);
+        }
 
         // non-owner fails
         let unauth_info = MessageInfo {
@@ -478,8 +950,27 @@ mod tests {
             .query(deps.as_ref(), mock_env(), QueryMsg::GetConfig {})
             .unwrap();
         let value: GetConfigResponse = from_binary(&res).unwrap();
-        assert_eq!(true, value.paused);
+        assert_eq!(PauseFlags::all(true), value.paused);
         assert_eq!(info.sender, value.owner_id);
+
+        // reward floor can't be above the ceiling
+        let mut settings_msg = update_settings_msg();
+        if let ExecuteMsg::UpdateSettings {
+            reward_min,
+            reward_max,
+            ..
+        } = &mut settings_msg
+        {
+            *reward_min = Some(Uint128::new(10));
+            *reward_max = Some(Uint128::new(5));
+        }
+        let res_fail = store.execute(deps.as_mut(), mock_env(), info, settings_msg);
+        assert_eq!(
+            res_fail.unwrap_err(),
+            ContractError::CustomError {
+                val: "reward_min can't be above reward_max".to_string()
+            }
+        );
     }
 
     #[test]
@@ -498,23 +989,14 @@ mod tests {
             gas_base_fee: None,
             agent_nomination_duration: Some(360),
             cw_rules_addr: "todo".to_string(),
+            initial_agent: None,
         };
         let res_init = store
             .instantiate(deps.as_mut(), mock_env(), info.clone(), msg)
             .unwrap();
         assert!(res_init.messages.is_empty());
 
-        let payload = ExecuteMsg::UpdateSettings {
-            paused: None,
-            owner_id: None,
-            // treasury_id: Some(Addr::unchecked("money_bags")),
-            agent_fee: None,
-            min_tasks_per_agent: None,
-            agents_eject_threshold: None,
-            gas_price: None,
-            proxy_callback_gas: None,
-            slot_granularity: None,
-        };
+        let payload = update_settings_msg();
         let info_setting = mock_info("owner_id", &coins(0, "meow"));
         let res_exec = store
             .execute(deps.as_mut(), mock_env(), info_setting, payload)
@@ -563,23 +1045,14 @@ mod tests {
             gas_base_fee: None,
             agent_nomination_duration: Some(360),
             cw_rules_addr: "todo".to_string(),
+            initial_agent: None,
         };
         let res_init = store
             .instantiate(deps.as_mut(), mock_env(), info.clone(), msg)
             .unwrap();
         assert!(res_init.messages.is_empty());
 
-        let payload = ExecuteMsg::UpdateSettings {
-            paused: None,
-            owner_id: None,
-            // treasury_id: Some(money_bags.clone()),
-            agent_fee: None,
-            min_tasks_per_agent: None,
-            agents_eject_threshold: None,
-            gas_price: None,
-            proxy_callback_gas: None,
-            slot_granularity: None,
-        };
+        let payload = update_settings_msg();
         let info_settings = mock_info("owner_id", &coins(0, "meow"));
         let res_exec = store
             .execute(deps.as_mut(), mock_env(), info_settings, payload)
@@ -667,4 +1140,432 @@ mod tests {
     //     // assert_eq!(true, value.paused);
     //     // assert_eq!(info.sender, value.owner_id);
     // }
+
+    #[test]
+    fn update_pause_flags() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, "atom"));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+
+        fn pause(
+            store: &mut CwCroncat,
+            deps: cosmwasm_std::DepsMut,
+            sender: &str,
+            flags: PauseFlags,
+        ) -> Result<cosmwasm_std::Response, ContractError> {
+            store.execute(
+                deps,
+                mock_env(),
+                mock_info(sender, &[]),
+                ExecuteMsg::UpdatePauseFlags {
+                    register: Some(flags.register),
+                    execute: Some(flags.execute),
+                    withdraw: Some(flags.withdraw),
+                    create_task: Some(flags.create_task),
+                },
+            )
+        }
+        // Results of registering, executing and withdrawing
+        fn run_all(store: &mut CwCroncat, mut deps: cosmwasm_std::DepsMut) -> [ContractError; 3] {
+            let info = mock_info("agent", &[]);
+            [
+                ExecuteMsg::RegisterAgent {
+                    payable_account_id: None,
+                    capabilities: None,
+                    accepted_terms: None,
+                    client_version: None,
+                },
+                ExecuteMsg::ProxyCall { task_hash: None },
+                ExecuteMsg::WithdrawReward {
+                    idempotency_key: None,
+                    memo: None,
+                },
+            ]
+            .map(|msg| {
+                store
+                    .execute(deps.branch(), mock_env(), info.clone(), msg)
+                    .unwrap_err()
+            })
+        }
+        let register_paused = ContractError::ContractPaused {
+            val: "Register agent paused".to_string(),
+        };
+        let execute_paused = ContractError::CustomError {
+            val: "Contract paused".to_string(),
+        };
+        let withdraw_paused = ContractError::ContractPaused {
+            val: "Withdraw agent balance".to_string(),
+        };
+
+        // Only owner
+        let err = pause(
+            &mut store,
+            deps.as_mut(),
+            "michael_scott",
+            PauseFlags::all(true),
+        )
+        .unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err);
+
+        let [register, execute, withdraw] = run_all(&mut store, deps.as_mut());
+        assert_ne!(register_paused, register);
+        assert_ne!(execute_paused, execute);
+        assert_ne!(withdraw_paused, withdraw);
+
+        let only_register = PauseFlags {
+            register: true,
+            ..PauseFlags::default()
+        };
+        pause(&mut store, deps.as_mut(), "creator", only_register).unwrap();
+        let [register, execute, withdraw] = run_all(&mut store, deps.as_mut());
+        assert_eq!(register_paused, register);
+        assert_ne!(execute_paused, execute);
+        assert_ne!(withdraw_paused, withdraw);
+
+        let only_execute = PauseFlags {
+            execute: true,
+            ..PauseFlags::default()
+        };
+        pause(&mut store, deps.as_mut(), "creator", only_execute).unwrap();
+        let [register, execute, withdraw] = run_all(&mut store, deps.as_mut());
+        assert_ne!(register_paused, register);
+        assert_eq!(execute_paused, execute);
+        assert_ne!(withdraw_paused, withdraw);
+
+        let only_withdraw = PauseFlags {
+            withdraw: true,
+            ..PauseFlags::default()
+        };
+        pause(&mut store, deps.as_mut(), "creator", only_withdraw).unwrap();
+        let [register, execute, withdraw] = run_all(&mut store, deps.as_mut());
+        assert_ne!(register_paused, register);
+        assert_ne!(execute_paused, execute);
+        assert_eq!(withdraw_paused, withdraw);
+
+        let res = store
+            .query(deps.as_ref(), mock_env(), QueryMsg::GetConfig {})
+            .unwrap();
+        let value: GetConfigResponse = from_binary(&res).unwrap();
+        assert_eq!(only_withdraw, value.paused);
+    }
+
+    #[test]
+    fn update_missed_slot_threshold() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, "atom"));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+
+        // Active agent that missed 3 slots in a row
+        let agent = Addr::unchecked("agent");
+        deps.querier
+            .update_balance(agent.clone(), coins(100, "atom"));
+        store
+            .execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info(agent.as_str(), &[]),
+                ExecuteMsg::RegisterAgent {
+                    payable_account_id: None,
+                    capabilities: None,
+                    accepted_terms: None,
+                    client_version: None,
+                },
+            )
+            .unwrap();
+        store
+            .agents
+            .update(&mut deps.storage, &agent, |a| -> StdResult<_> {
+                let mut a = a.unwrap();
+                a.consecutive_misses = 3;
+                Ok(a)
+            })
+            .unwrap();
+
+        fn update(
+            store: &mut CwCroncat,
+            deps: cosmwasm_std::DepsMut,
+            sender: &str,
+            threshold: u64,
+        ) -> Result<cosmwasm_std::Response, ContractError> {
+            store.execute(
+                deps,
+                mock_env(),
+                mock_info(sender, &[]),
+                ExecuteMsg::UpdateMissedSlotThreshold { threshold },
+            )
+        }
+        fn slashable(store: &mut CwCroncat, deps: cosmwasm_std::Deps) -> Vec<Addr> {
+            let res = store
+                .query(deps, mock_env(), QueryMsg::GetSlashableAgents {})
+                .unwrap();
+            let slashable: Vec<SlashableAgentResponse> = from_binary(&res).unwrap();
+            slashable.into_iter().map(|s| s.agent).collect()
+        }
+        assert!(slashable(&mut store, deps.as_ref()).is_empty());
+
+        // Only owner, and at least 1
+        let err = update(&mut store, deps.as_mut(), "michael_scott", 2).unwrap_err();
+        assert_eq!(ContractError::Unauthorized {}, err);
+        let err = update(&mut store, deps.as_mut(), "creator", 0).unwrap_err();
+        assert_eq!(
+            ContractError::CustomError {
+                val: "missed_slot_threshold must be at least 1".to_string()
+            },
+            err
+        );
+
+        // At the threshold the agent is spared, one below and it's slashable
+        update(&mut store, deps.as_mut(), "creator", 3).unwrap();
+        assert!(slashable(&mut store, deps.as_ref()).is_empty());
+        update(&mut store, deps.as_mut(), "creator", 2).unwrap();
+        assert_eq!(vec![agent.clone()], slashable(&mut store, deps.as_ref()));
+
+        // Raising it again takes effect right away
+        update(&mut store, deps.as_mut(), "creator", 3).unwrap();
+        assert!(slashable(&mut store, deps.as_ref()).is_empty());
+
+        let res = store
+            .query(deps.as_ref(), mock_env(), QueryMsg::GetConfig {})
+            .unwrap();
+        let value: GetConfigResponse = from_binary(&res).unwrap();
+        assert_eq!(3, value.missed_slot_threshold);
+    }
+
+    #[test]
+    fn query_health() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, "atom"));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        fn health(store: &mut CwCroncat, deps: cosmwasm_std::Deps) -> HealthResponse {
+            let res = store
+                .query(deps, mock_env(), QueryMsg::GetHealth {})
+                .unwrap();
+            from_binary(&res).unwrap()
+        }
+        assert_eq!(
+            HealthResponse {
+                paused: PauseFlags::default(),
+                active_agents: 0,
+                has_nominated: false,
+                last_execution_nanos: 0,
+            },
+            health(&mut store, deps.as_ref())
+        );
+
+        // First agent is active, second one pending
+        for agent in ["agent0", "agent1"] {
+            deps.querier.update_balance(agent, coins(100, "atom"));
+            store
+                .execute(
+                    deps.as_mut(),
+                    mock_env(),
+                    mock_info(agent, &[]),
+                    ExecuteMsg::RegisterAgent {
+                        payable_account_id: None,
+                        capabilities: None,
+                        accepted_terms: None,
+                        client_version: None,
+                    },
+                )
+                .unwrap();
+        }
+        let last_execution = mock_env().block.time.minus_seconds(10);
+        store
+            .agents
+            .update(
+                &mut deps.storage,
+                &Addr::unchecked("agent0"),
+                |a| -> StdResult<_> {
+                    let mut a = a.unwrap();
+                    a.last_execution = Some(last_execution);
+                    Ok(a)
+                },
+            )
+            .unwrap();
+        // Enough tasks to let the pending agent in
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.min_tasks_per_agent = 1;
+        store.config.save(&mut deps.storage, &config).unwrap();
+        store.task_total.save(&mut deps.storage, &2).unwrap();
+        store
+            .agent_nomination_begin_time
+            .save(&mut deps.storage, &Some(mock_env().block.time))
+            .unwrap();
+        store
+            .execute(
+                deps.as_mut(),
+                mock_env(),
+                mock_info("creator", &[]),
+                ExecuteMsg::UpdatePauseFlags {
+                    register: None,
+                    execute: None,
+                    withdraw: Some(true),
+                    create_task: None,
+                },
+            )
+            .unwrap();
+
+        assert_eq!(
+            HealthResponse {
+                paused: PauseFlags {
+                    withdraw: true,
+                    ..PauseFlags::default()
+                },
+                active_agents: 1,
+                has_nominated: true,
+                last_execution_nanos: last_execution.nanos(),
+            },
+            health(&mut store, deps.as_ref())
+        );
+    }
+
+    #[test]
+    fn protocol_revenue() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, "atom"));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.registration_fee = Some(coin(10, "atom"));
+        store.config.save(&mut deps.storage, &config).unwrap();
+
+        // Registration fee is kept as revenue
+        let agent = "agent";
+        deps.querier.update_balance(agent, coins(100, "atom"));
+        store
+            .register_agent(
+                deps.as_mut(),
+                mock_info(agent, &coins(10, "atom")),
+                mock_env(),
+                None,
+                None,
+                None,
+                None,
+            )
+            .unwrap();
+        let revenue = store.query_protocol_revenue(deps.as_ref()).unwrap();
+        assert_eq!(revenue.native, coins(10, "atom"));
+
+        let err = store
+            .withdraw_protocol_revenue(
+                deps.as_mut(),
+                mock_info("michael_scott", &[]),
+                "michael_scott".to_string(),
+            )
+            .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+
+        let res = store
+            .withdraw_protocol_revenue(
+                deps.as_mut(),
+                mock_info("creator", &[]),
+                "treasury".to_string(),
+            )
+            .unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: "treasury".to_string(),
+                amount: coins(10, "atom"),
+            })]
+        );
+        let revenue = store.query_protocol_revenue(deps.as_ref()).unwrap();
+        assert!(revenue.native.is_empty());
+
+        // Nothing left to send
+        let err = store
+            .withdraw_protocol_revenue(
+                deps.as_mut(),
+                mock_info("creator", &[]),
+                "treasury".to_string(),
+            )
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::CustomError {
+                val: "No protocol revenue to withdraw".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn sweep_stuck_funds() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, "atom"));
+        let store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        // Instantiation funds are tracked, 30 meow got sent around the handlers
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, vec![coin(1030, "meow")]);
+
+        let err = store
+            .sweep_stuck_funds(
+                deps.as_mut(),
+                mock_info("michael_scott", &[]),
+                mock_env(),
+                "meow".to_string(),
+                "michael_scott".to_string(),
+            )
+            .unwrap_err();
+        assert_eq!(err, ContractError::Unauthorized {});
+
+        let res = store
+            .sweep_stuck_funds(
+                deps.as_mut(),
+                mock_info("creator", &[]),
+                mock_env(),
+                "meow".to_string(),
+                "creator".to_string(),
+            )
+            .unwrap();
+        assert_eq!(
+            res.messages,
+            vec![SubMsg::new(BankMsg::Send {
+                to_address: "creator".to_string(),
+                amount: coins(30, "meow"),
+            })]
+        );
+        let config = store.config.load(&deps.storage).unwrap();
+        assert_eq!(config.available_balance.native, coins(1000, "meow"));
+
+        // Everything held is owed
+        deps.querier
+            .update_balance(MOCK_CONTRACT_ADDR, vec![coin(1000, "meow")]);
+        let err = store
+            .sweep_stuck_funds(
+                deps.as_mut(),
+                mock_info("creator", &[]),
+                mock_env(),
+                "meow".to_string(),
+                "creator".to_string(),
+            )
+            .unwrap_err();
+        assert_eq!(
+            err,
+            ContractError::CustomError {
+                val: "No untracked funds to sweep".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn slot_duration() {
+        let mut deps = mock_dependencies_with_balance(&coins(200, "atom"));
+        let mut store = CwCroncat::default();
+        mock_init(&store, deps.as_mut()).unwrap();
+        let query_duration = |store: &mut CwCroncat, deps: Deps| -> u64 {
+            let res = store
+                .query(deps, mock_env(), QueryMsg::GetSlotDuration {})
+                .unwrap();
+            from_binary(&res).unwrap()
+        };
+        assert_eq!(query_duration(&mut store, deps.as_ref()), 6_000_000_000);
+
+        let mut config = store.config.load(&deps.storage).unwrap();
+        config.slot_duration_nanos = 2_000_000_000;
+        config.slot_granularity = 30_000_000_000;
+        store.config.save(&mut deps.storage, &config).unwrap();
+        assert_eq!(query_duration(&mut store, deps.as_ref()), 2_000_000_000);
+        assert_eq!(slots_to_nanos(&config, SlotType::Block, 5), 10_000_000_000);
+        assert_eq!(slots_to_nanos(&config, SlotType::Cron, 3), 90_000_000_000);
+        assert_eq!(slots_to_nanos(&config, SlotType::Block, u64::MAX), u64::MAX);
+    }
 }
@@ -119,6 +119,7 @@ mod test {
             gas_base_fee: None,
             agent_nomination_duration: None,
             cw_rules_addr: "todo".to_string(),
+            initial_agent: None,
         };
         let cw_template_contract_addr = app
             //Must send some available balance for rewards
@@ -203,6 +204,7 @@ mod test {
                     address: cw20_contract.to_string(),
                     amount: 10u128.into(),
                 }],
+                task_type: None,
             },
         };
         app.execute_contract(
@@ -217,6 +219,9 @@ mod test {
         {
             let msg = ExecuteMsg::RegisterAgent {
                 payable_account_id: Some(AGENT1_BENEFICIARY.to_string()),
+                capabilities: None,
+                accepted_terms: None,
+                client_version: None,
             };
             app.execute_contract(Addr::unchecked(AGENT0), contract_addr.clone(), &msg, &[])
                 .unwrap();
@@ -329,6 +334,7 @@ mod test {
                     address: cw20_contract.to_string(),
                     amount: 10u128.into(),
                 }],
+                task_type: None,
             },
         };
         let mut resp = app
@@ -410,6 +416,7 @@ mod test {
                     address: cw20_contract.to_string(),
                     amount: 10u128.into(),
                 }],
+                task_type: None,
             },
         };
         let resp: ContractError = app
@@ -466,6 +473,7 @@ mod test {
                 }],
                 rules: None,
                 cw20_coins: vec![],
+                task_type: None,
             },
         };
         let resp: ContractError = app
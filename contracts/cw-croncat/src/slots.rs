@@ -2,6 +2,7 @@ use crate::CwCroncat;
 use cosmwasm_std::{BlockInfo, Order, StdResult, Storage};
 pub use cw_croncat_core::types::Interval;
 use cw_croncat_core::types::SlotType;
+use cw_storage_plus::Bound;
 
 impl<'a> CwCroncat<'a> {
     /// Get the slot with lowest height/timestamp
@@ -54,6 +55,32 @@ impl<'a> CwCroncat<'a> {
         ret
     }
 
+    /// Count the tasks in every block and time slot that is due
+    pub(crate) fn due_task_count(
+        &self,
+        storage: &dyn Storage,
+        block: &BlockInfo,
+    ) -> StdResult<u64> {
+        let mut due_tasks: u64 = 0;
+        for slot in self.block_slots.range(
+            storage,
+            None,
+            Some(Bound::inclusive(block.height)),
+            Order::Ascending,
+        ) {
+            due_tasks += slot?.1.len() as u64;
+        }
+        for slot in self.time_slots.range(
+            storage,
+            None,
+            Some(Bound::inclusive(block.time.nanos())),
+            Order::Ascending,
+        ) {
+            due_tasks += slot?.1.len() as u64;
+        }
+        Ok(due_tasks)
+    }
+
     /// Gets 1 slot hash item, and removes the hash from storage
     /// Cleans up a slot if empty
     pub(crate) fn pop_slot_item(
@@ -1,17 +1,27 @@
 use crate::{balancer::RoundRobinBalancer, ContractError};
-use cosmwasm_std::{Addr, Coin, StdResult, Storage, Timestamp};
+use cosmwasm_std::{Addr, Binary, Coin, Decimal, StdResult, Storage, Timestamp, Uint128};
 use cw20::Cw20CoinVerified;
+use cw_croncat_core::msg::ExecutionResult;
 use cw_storage_plus::{Index, IndexList, IndexedMap, Item, Map, MultiIndex};
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use crate::contract::{
+    DEFAULT_MAX_REWARD_MULTIPLIER_BPS, DEFAULT_MISSED_SLOT_THRESHOLD, DEFAULT_SLOT_DURATION_NANOS,
+    DEFAULT_STALE_TIMEOUT_NANOS,
+};
 use crate::helpers::Task;
-use cw_croncat_core::types::{Agent, GenericBalance, SlotType};
+use cw_croncat_core::types::{
+    deserialize_gas_price, deserialize_pause_flags, Agent, AgentPool, GenericBalance, LoyaltyTier,
+    MassExitThreshold, PauseFlags, QueueRemovalMode, RewardEscrow, RewardRounding, SelectionMode,
+    SlashDestination, SlotType, VestingCredit,
+};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
 pub struct Config {
     // Runtime
-    pub paused: bool,
+    #[serde(deserialize_with = "deserialize_pause_flags")]
+    pub paused: PauseFlags,
     pub owner_id: Addr,
 
     // Agent management
@@ -21,36 +31,193 @@ pub struct Config {
     // NOTE: Caveat, when there are odd number of tasks or agents, the overflow will be available to first-come, first-serve. This doesn't negate the possibility of a failed txn from race case choosing winner inside a block.
     // NOTE: The overflow will be adjusted to be handled by sweeper in next implementation.
     pub min_tasks_per_agent: u64,
+    // How many active agents are needed before tasks can be created, zero means no minimum
+    #[serde(default)]
+    pub min_agents_for_tasks: u64,
     pub agent_active_indices: Vec<(SlotType, u32, u32)>,
     // How many slots an agent can miss before being removed from the active queue
     pub agents_eject_threshold: u64,
+    // How many slots in a row an agent can miss before it can be slashed, at least 1
+    #[serde(default = "default_missed_slot_threshold")]
+    pub missed_slot_threshold: u64,
     // The duration a prospective agent has to nominate themselves.
     // When a task is created such that a new agent can join,
     // The agent at the zeroth index of the pending agent queue has this time to nominate
     // The agent at the first index has twice this time to nominate (which would remove the former agent from the pending queue)
     // Value is in seconds
     pub agent_nomination_duration: u16,
+    // Nomination is suspended while fewer tasks than this are due, zero never suspends it
+    #[serde(default)]
+    pub min_tasks_for_nomination: u64,
+    // How long an agent has to stay active after accepting a nomination, zero means no cooldown
+    #[serde(default)]
+    pub activation_cooldown_nanos: u64,
+    // Caps on the agent queues, none means unlimited
+    #[serde(default)]
+    pub max_agents: Option<u64>,
+    #[serde(default)]
+    pub max_pending_agents: Option<u64>,
+    // New agents skip the pending queue while it's empty and `max_agents` leaves room
+    #[serde(default)]
+    pub auto_activate_open_slots: bool,
+    // Pause execution when too many active agents leave at once, none disables the breaker
+    #[serde(default)]
+    pub mass_exit_threshold: Option<MassExitThreshold>,
+    // Whether the last active agent can leave while nobody is pending to take over
+    #[serde(default = "default_true")]
+    pub allow_empty_active_set: bool,
+    // Accounts allowed to register agents on behalf of someone else
+    #[serde(default)]
+    pub onboarders: Vec<Addr>,
+    // Rotate the active queue every block, so queue position doesn't favor early agents
+    #[serde(default)]
+    pub rotate_queue_each_slot: bool,
+    // Pick the agent leading each slot weighted by executed tasks, takes precedence over rotation
+    #[serde(default)]
+    pub weighted_selection: bool,
+    // Lottery picks the agent leading each slot by bonded stake, takes precedence over the above
+    #[serde(default)]
+    pub selection: SelectionMode,
     pub cw_rules_addr: Addr,
+    // Where funds taken from slashed agents go
+    #[serde(default)]
+    pub slash_destination: SlashDestination,
+    // How long an active agent can go without executing before it's reported as stale
+    #[serde(default = "default_stale_timeout_nanos")]
+    pub stale_timeout_nanos: u64,
 
     // Economics
     pub agent_fee: Coin,
-    pub gas_price: u32,
+    // Rewards below this wait in the agent's pending dust until they add up to it, zero credits everything
+    #[serde(default)]
+    pub dust_threshold: Uint128,
+    // Rewards vest linearly over this long before they can be withdrawn, zero pays them out right away
+    #[serde(default)]
+    pub reward_vesting_nanos: u64,
+    // How unregistering agents leave the active queue, trading activation order for O(1) removal
+    #[serde(default)]
+    pub queue_removal_mode: QueueRemovalMode,
+    // Pending agents get unregistered after letting this many nominations expire, zero never does
+    #[serde(default)]
+    pub max_missed_nominations: u64,
+    // Slots the round-robin assigned agent has to itself before any active agent may execute,
+    // zero keeps overdue slots assigned
+    #[serde(default)]
+    pub exclusivity_window_slots: u64,
+    // How long an active agent may go without executing before `GetIdleAgents` lists it
+    #[serde(default)]
+    pub idle_grace_nanos: u64,
+    // Router swapping rewards into an agent's preferred cw20 on withdrawal, none pays out the reward denom
+    #[serde(default)]
+    pub dex_router: Option<Addr>,
+    // Slippage bound passed along with every swap through `dex_router`
+    #[serde(default = "default_dex_max_slippage")]
+    pub dex_max_slippage: Decimal,
+    // Slots an agent may execute late before the execution counts as a missed slot
+    #[serde(default)]
+    pub missed_slot_grace: u64,
+    // Denom slashes take from the agent balance and bond, none takes everything the agent holds
+    #[serde(default)]
+    pub slash_denom: Option<String>,
+    // Share of the bond slashes take instead of everything, clamped to the bond. Zero slashes it all
+    #[serde(default)]
+    pub slash_bps: u16,
+    // Cw20 contracts agents can be credited rewards in, native rewards are always allowed
+    #[serde(default)]
+    pub allowed_reward_cw20: Vec<Addr>,
+    // Pending agents get their position hook pinged when they move into a lower bucket of this many positions, 0 pings every advance
+    #[serde(default)]
+    pub position_hook_step: u64,
+    // Addresses agents can't have their rewards paid out to
+    #[serde(default)]
+    pub blacklist: Vec<Addr>,
+    // Cap on the reward multiplier the owner can give a single agent
+    #[serde(default = "default_max_reward_multiplier_bps")]
+    pub max_reward_multiplier_bps: u16,
+    // Funds attached to handlers that take none get sent back instead of failing the call
+    #[serde(default)]
+    pub refund_stray_funds: bool,
+    // Terms of service hash agents must pass when registering, none lets anyone register
+    #[serde(default)]
+    pub required_terms_hash: Option<Binary>,
+    // Non-refundable fee agents pay to register, none means registering is free
+    #[serde(default)]
+    pub registration_fee: Option<Coin>,
+    // Agent balance above this gets sent to the payable account right away
+    #[serde(default)]
+    pub max_agent_balance: Option<Coin>,
+    // Who gets the remainder when rewards are split by basis points
+    #[serde(default)]
+    pub reward_rounding: RewardRounding,
+    // Agent fee multipliers by how long the agent has been registered, empty means always 1x
+    #[serde(default)]
+    pub loyalty_tiers: Vec<LoyaltyTier>,
+    // Escrow rewards of high-value tasks for a dispute window, none means rewards are withdrawable right away
+    #[serde(default)]
+    pub reward_escrow: Option<RewardEscrow>,
+    // Bounds on the per-task agent reward in agent fee denom, none means unbounded
+    #[serde(default)]
+    pub reward_min: Option<Uint128>,
+    #[serde(default)]
+    pub reward_max: Option<Uint128>,
+    // Price per gas unit in native denom, can be fractional
+    #[serde(deserialize_with = "deserialize_gas_price")]
+    pub gas_price: Decimal,
     pub gas_base_fee: u64,
     pub proxy_callback_gas: u32,
     pub slot_granularity: u64,
+    // Estimated wall-clock length of a block slot, cron slots last `slot_granularity`
+    #[serde(default = "default_slot_duration_nanos")]
+    pub slot_duration_nanos: u64,
+    // Agents must report at least this MAJOR.MINOR.PATCH client version to register, none allows any
+    #[serde(default)]
+    pub min_client_version: Option<String>,
 
     // Treasury
     // pub treasury_id: Option<Addr>,
     pub cw20_whitelist: Vec<Addr>, // TODO: Consider fee structure for whitelisted CW20s
     pub native_denom: String,
     pub available_balance: GenericBalance, // tasks + rewards balances
+    #[serde(default)]
+    pub total_agent_balance: GenericBalance, // running sum of all agent balances, part of available_balance
+    #[serde(default)]
+    pub protocol_revenue: GenericBalance, // registration fees and slashes kept by the contract, part of available_balance
+    #[serde(default)]
+    pub execution_nonce: u64, // bumped on every task execution, emitted as exec_nonce
+    #[serde(default)]
+    pub total_missed_slots: u64, // running count of slots executed past the grace, see GetMissedSlotStats
     pub staked_balance: GenericBalance, // surplus that is temporary staking (to be used in conjunction with external treasury)
 
     // The default amount of tasks to query
     pub limit: u64,
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+// Defaults for config fields missing from configs stored by older versions
+fn default_missed_slot_threshold() -> u64 {
+    DEFAULT_MISSED_SLOT_THRESHOLD
+}
+
+fn default_stale_timeout_nanos() -> u64 {
+    DEFAULT_STALE_TIMEOUT_NANOS
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_dex_max_slippage() -> Decimal {
+    Decimal::percent(1)
+}
+
+fn default_max_reward_multiplier_bps() -> u16 {
+    DEFAULT_MAX_REWARD_MULTIPLIER_BPS
+}
+
+fn default_slot_duration_nanos() -> u64 {
+    DEFAULT_SLOT_DURATION_NANOS
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct QueueItem {
     pub contract_addr: Option<Addr>,
     // This is used to track disjointed callbacks
@@ -65,6 +232,9 @@ pub struct QueueItem {
     pub task_is_extra: Option<bool>,
     pub agent_id: Option<Addr>,
     pub failed: bool,
+    // Reported as the response data once the last action replied
+    #[serde(default)]
+    pub result: Option<ExecutionResult>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -98,6 +268,24 @@ pub struct CwCroncat<'a> {
     pub config: Item<'a, Config>,
 
     pub agents: Map<'a, &'a Addr, Agent>,
+    /// Operator key to the agent it executes on behalf of
+    pub agent_operators: Map<'a, &'a Addr, Addr>,
+    // Reverse lookup, payable account to the agents paying out to it.
+    // Agents are free to share a payable account
+    pub agents_by_payable: Map<'a, &'a Addr, Vec<Addr>>,
+    // Agents sharing rewards by pool id, and the rewards they earned so far.
+    // Split by member weight whenever one of them withdraws or leaves
+    pub agent_pools: Map<'a, &'a str, AgentPool>,
+    pub agent_pool_balances: Map<'a, &'a str, Vec<Coin>>,
+    // Agent rewards still vesting, fully released ones get dropped on withdrawal
+    pub agent_vesting: Map<'a, &'a Addr, Vec<VestingCredit>>,
+    /// Rewards in their dispute window by agent, task hash and release time in nanos
+    pub agent_escrow: Map<'a, (&'a Addr, &'a [u8], u64), GenericBalance>,
+    // Native funds slashed from agents with the slash time in nanos and where they went.
+    // Treasury slashes are refundable on reinstatement
+    pub slash_history: Map<'a, &'a Addr, Vec<(u64, Coin, SlashDestination)>>,
+    // Recently used withdrawal idempotency keys per agent with the withdrawal time in nanos
+    pub withdraw_keys: Map<'a, &'a Addr, Vec<(String, u64)>>,
     // TODO: Assess if diff store structure is needed for these:
     pub agent_active_queue: Item<'a, Vec<Addr>>,
     pub agent_pending_queue: Item<'a, Vec<Addr>>,
@@ -129,6 +317,10 @@ pub struct CwCroncat<'a> {
     // Once an agent joins, fulfilling the need, this value changes to None
     pub agent_nomination_begin_time: Item<'a, Option<Timestamp>>,
 
+    // Nanos of recent active agent unregistrations, oldest first.
+    // Holds at most `mass_exit_threshold.max_exits + 1` entries
+    pub agent_exit_times: Item<'a, Vec<u64>>,
+
     pub balancer: RoundRobinBalancer,
     pub balances: Map<'a, &'a Addr, Vec<Cw20CoinVerified>>,
 }
@@ -164,6 +356,14 @@ impl<'a> CwCroncat<'a> {
         Self {
             config: Item::new("config"),
             agents: Map::new("agents"),
+            agent_operators: Map::new("agent_operators"),
+            agents_by_payable: Map::new("agents_by_payable"),
+            agent_pools: Map::new("agent_pools"),
+            agent_pool_balances: Map::new("agent_pool_balances"),
+            agent_vesting: Map::new("agent_vesting"),
+            agent_escrow: Map::new("agent_escrow"),
+            slash_history: Map::new("slash_history"),
+            withdraw_keys: Map::new("withdraw_keys"),
             agent_active_queue: Item::new("agent_active_queue"),
             agent_pending_queue: Item::new("agent_pending_queue"),
             tasks: IndexedMap::new(tasks_key, indexes),
@@ -177,6 +377,7 @@ impl<'a> CwCroncat<'a> {
             reply_queue: Map::new("reply_queue"),
             reply_index: Item::new("reply_index"),
             agent_nomination_begin_time: Item::new("agent_nomination_begin_time"),
+            agent_exit_times: Item::new("agent_exit_times"),
             balancer: RoundRobinBalancer::default(),
             balances: Map::new("balances"),
         }
@@ -225,14 +426,18 @@ impl<'a> CwCroncat<'a> {
         &self,
         storage: &mut dyn Storage,
         idx: u64,
-        failed: bool,
+        error: Option<String>,
     ) -> Result<QueueItem, ContractError> {
         self.reply_queue.update(storage, idx, |rq| {
             let mut rq = rq.ok_or(ContractError::UnknownReplyID {})?;
             // if first fails it means whole thing failed
             // for cases where we stop task on failure
-            if !rq.failed {
-                rq.failed = failed;
+            if !rq.failed && error.is_some() {
+                rq.failed = true;
+                if let Some(result) = rq.result.as_mut() {
+                    result.success = false;
+                    result.error = error;
+                }
             }
             rq.action_idx += 1;
             Ok(rq)
@@ -292,6 +497,7 @@ mod tests {
                 gas_limit: Some(150_000),
             }],
             rules: None,
+            task_type: None,
         };
         let task_id_str = "69217dd2b6334abe2544a12fcb89588f9cc5c62a298b8720706d9befa3d736d3";
         let task_id = task_id_str.to_string().into_bytes();
@@ -1,15 +1,18 @@
+use crate::agent::MAX_CAPABILITY_LENGTH;
 use crate::error::ContractError;
+use crate::manager::clamp_reward;
 use crate::slots::Interval;
 use crate::state::{Config, CwCroncat};
 use cosmwasm_std::Storage;
 use cosmwasm_std::{
-    to_binary, BankMsg, Deps, DepsMut, Env, MessageInfo, Order, Response, StdResult, SubMsg,
+    to_binary, BankMsg, Coin, Deps, DepsMut, Env, MessageInfo, Order, Response, StdResult, SubMsg,
     Uint128, WasmMsg,
 };
 use cw20::{Cw20Coin, Cw20CoinVerified, Cw20ExecuteMsg};
 use cw_croncat_core::error::CoreError;
 use cw_croncat_core::msg::{
-    GetSlotHashesResponse, GetSlotIdsResponse, TaskRequest, TaskResponse, TaskWithRulesResponse,
+    GetSlotHashesResponse, GetSlotIdsResponse, SlotBudgetResponse, TaskCreationAllowedResponse,
+    TaskRequest, TaskResponse, TaskWithRulesResponse,
 };
 use cw_croncat_core::traits::{BalancesOperations, Intervals};
 use cw_croncat_core::types::{BoundaryValidated, GenericBalance, SlotType, Task};
@@ -199,6 +202,44 @@ impl<'a> CwCroncat<'a> {
         })
     }
 
+    /// Get the reward agents can earn from the tasks due now, and an even share of it per active agent
+    /// Each due task pays the base agent fee, loyalty multipliers aren't counted
+    pub(crate) fn query_current_slot_budget(
+        &self,
+        deps: Deps,
+        env: Env,
+    ) -> StdResult<SlotBudgetResponse> {
+        let c: Config = self.config.load(deps.storage)?;
+        let due_tasks = self.due_task_count(deps.storage, &env.block)?;
+        let active_agents = self.agent_active_queue.load(deps.storage)?.len() as u64;
+
+        let total_reward = clamp_reward(&c, c.agent_fee.amount).checked_mul(due_tasks.into())?;
+        let per_agent_estimate = if active_agents == 0 {
+            Uint128::zero()
+        } else {
+            total_reward / Uint128::from(active_agents)
+        };
+        Ok(SlotBudgetResponse {
+            slot: env.block.height,
+            total_reward: Coin::new(total_reward.u128(), c.agent_fee.denom.clone()),
+            per_agent_estimate: Coin::new(per_agent_estimate.u128(), c.agent_fee.denom),
+        })
+    }
+
+    /// Check if there are enough active agents to run new tasks
+    pub(crate) fn query_task_creation_allowed(
+        &self,
+        deps: Deps,
+    ) -> StdResult<TaskCreationAllowedResponse> {
+        let c: Config = self.config.load(deps.storage)?;
+        let active_agents = self.agent_active_queue.load(deps.storage)?.len() as u64;
+        Ok(TaskCreationAllowedResponse {
+            allowed: active_agents >= c.min_agents_for_tasks,
+            active_agents,
+            min_required: c.min_agents_for_tasks,
+        })
+    }
+
     /// Allows any user or contract to pay for future txns based on a specific schedule
     /// contract, function id & other settings. When the task runs out of balance
     /// the task is no longer executed, any additional funds will be returned to task owner.
@@ -215,11 +256,17 @@ impl<'a> CwCroncat<'a> {
             });
         }
         let c: Config = self.config.load(deps.storage)?;
-        if c.paused {
+        if c.paused.create_task {
             return Err(ContractError::CustomError {
                 val: "Create task paused".to_string(),
             });
         }
+        let active_agents = self.agent_active_queue.load(deps.storage)?.len() as u64;
+        if active_agents < c.min_agents_for_tasks {
+            return Err(ContractError::CustomError {
+                val: "Not enough active agents".to_string(),
+            });
+        }
 
         let owner_id = info.sender;
         let cw20 = if !task.cw20_coins.is_empty() {
@@ -245,6 +292,16 @@ impl<'a> CwCroncat<'a> {
         } else {
             vec![]
         };
+        if let Some(task_type) = task.task_type.as_ref() {
+            if task_type.is_empty() || task_type.len() > MAX_CAPABILITY_LENGTH {
+                return Err(ContractError::CustomError {
+                    val: format!(
+                        "Task type must be 1 to {} characters",
+                        MAX_CAPABILITY_LENGTH
+                    ),
+                });
+            }
+        }
         let boundary = BoundaryValidated::validate_boundary(task.boundary, &task.interval)?;
         let mut item = Task {
             funds_withdrawn_recurring: Uint128::zero(),
@@ -259,6 +316,7 @@ impl<'a> CwCroncat<'a> {
             amount_for_one_task: Default::default(),
             actions: task.actions,
             rules: task.rules,
+            task_type: task.task_type,
         };
 
         if !item.is_valid_msg_calculate_usage(
@@ -687,6 +745,7 @@ mod tests {
     use cw_multi_test::{App, AppBuilder, Contract, ContractWrapper, Executor};
     use cw_rules_core::types::{HasBalanceGte, Rule};
     // use crate::error::ContractError;
+    use crate::helpers::test_helpers::update_settings_msg;
     use crate::helpers::CwTemplateContract;
     use cw_croncat_core::msg::{ExecuteMsg, GetBalancesResponse, InstantiateMsg, QueryMsg};
     use cw_croncat_core::types::{Action, Boundary};
@@ -736,6 +795,7 @@ mod tests {
             gas_base_fee: None,
             agent_nomination_duration: Some(360),
             cw_rules_addr: "todo".to_string(),
+            initial_agent: None,
         };
         let cw_template_contract_addr = app
             .instantiate_contract(cw_template_id, owner_addr, &msg, &[], "Manager", None)
@@ -775,6 +835,7 @@ mod tests {
                 gas_limit: Some(150_000),
             }],
             rules: None,
+            task_type: None,
         };
 
         // HASH CHECK!
@@ -839,6 +900,7 @@ mod tests {
                 }],
                 rules: None,
                 cw20_coins: vec![],
+                task_type: None,
             },
         };
 
@@ -900,6 +962,7 @@ mod tests {
                 }],
                 rules: None,
                 cw20_coins: vec![],
+                task_type: None,
             },
         };
 
@@ -1050,6 +1113,7 @@ mod tests {
                 }],
                 rules: None,
                 cw20_coins: vec![],
+                task_type: None,
             },
         };
         // let task_id_str = "95c916a53fa9d26deef094f7e1ee31c00a2d47b8bf474b2e06d39aebfb1fecc7".to_string();
@@ -1072,17 +1136,10 @@ mod tests {
         );
 
         // Create task paused
-        let change_settings_msg = ExecuteMsg::UpdateSettings {
-            paused: Some(true),
-            owner_id: None,
-            // treasury_id: None,
-            agent_fee: None,
-            agents_eject_threshold: None,
-            gas_price: None,
-            proxy_callback_gas: None,
-            slot_granularity: None,
-            min_tasks_per_agent: None,
-        };
+        let mut change_settings_msg = update_settings_msg();
+        if let ExecuteMsg::UpdateSettings { paused, .. } = &mut change_settings_msg {
+            *paused = Some(true);
+        }
         app.execute_contract(
             Addr::unchecked(ADMIN),
             contract_addr.clone(),
@@ -1105,24 +1162,76 @@ mod tests {
             res_err.downcast().unwrap()
         );
         // Set it back
+        let mut settings_msg = update_settings_msg();
+        if let ExecuteMsg::UpdateSettings { paused, .. } = &mut settings_msg {
+            *paused = Some(false);
+        }
         app.execute_contract(
             Addr::unchecked(ADMIN),
             contract_addr.clone(),
-            &ExecuteMsg::UpdateSettings {
-                paused: Some(false),
-                owner_id: None,
-                // treasury_id: None,
-                agent_fee: None,
-                agents_eject_threshold: None,
-                gas_price: None,
-                proxy_callback_gas: None,
-                slot_granularity: None,
-                min_tasks_per_agent: None,
-            },
+            &settings_msg,
             &vec![],
         )
         .unwrap();
 
+        // Pausing registrations leaves task creation alone
+        let pause_msg = |register: bool, create_task: bool| ExecuteMsg::UpdatePauseFlags {
+            register: Some(register),
+            execute: None,
+            withdraw: None,
+            create_task: Some(create_task),
+        };
+        app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &pause_msg(true, false),
+            &[],
+        )
+        .unwrap();
+        // Underfunded, so it fails past the pause check without creating the task
+        let res_err = app
+            .execute_contract(
+                Addr::unchecked(ANYONE),
+                contract_addr.clone(),
+                &create_task_msg,
+                &coins(13, "atom"),
+            )
+            .unwrap_err();
+        assert_ne!(
+            ContractError::CustomError {
+                val: "Create task paused".to_string()
+            },
+            res_err.downcast().unwrap()
+        );
+        app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &pause_msg(false, true),
+            &[],
+        )
+        .unwrap();
+        let res_err = app
+            .execute_contract(
+                Addr::unchecked(ANYONE),
+                contract_addr.clone(),
+                &create_task_msg,
+                &coins(300010, "atom"),
+            )
+            .unwrap_err();
+        assert_eq!(
+            ContractError::CustomError {
+                val: "Create task paused".to_string()
+            },
+            res_err.downcast().unwrap()
+        );
+        app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &pause_msg(false, false),
+            &[],
+        )
+        .unwrap();
+
         // Creator invalid
         let action_self = CosmosMsg::Wasm(WasmMsg::Execute {
             contract_addr: contract_addr.clone().into_string(),
@@ -1144,6 +1253,7 @@ mod tests {
                         }],
                         rules: None,
                         cw20_coins: vec![],
+                        task_type: None,
                     },
                 },
                 &coins(13, "atom"),
@@ -1172,6 +1282,7 @@ mod tests {
                         }],
                         rules: None,
                         cw20_coins: vec![],
+                        task_type: None,
                     },
                 },
                 &coins(13, "atom"),
@@ -1226,6 +1337,7 @@ mod tests {
                         }],
                         rules: None,
                         cw20_coins: vec![],
+                        task_type: None,
                     },
                 },
                 &coins(300010, "atom"),
@@ -1264,6 +1376,7 @@ mod tests {
                 }],
                 rules: None,
                 cw20_coins: vec![],
+                task_type: None,
             },
         };
         let task_id_str =
@@ -1359,6 +1472,7 @@ mod tests {
                     required_balance: coins(5, "bar").into(),
                 })]),
                 cw20_coins: vec![],
+                task_type: None,
             },
         };
 
@@ -1433,6 +1547,7 @@ mod tests {
                     required_balance: coins(5, "bar").into(),
                 })]),
                 cw20_coins: vec![],
+                task_type: None,
             },
         };
 
@@ -1447,6 +1562,7 @@ mod tests {
                 }],
                 rules: None,
                 cw20_coins: vec![],
+                task_type: None,
             },
         };
 
@@ -1533,6 +1649,7 @@ mod tests {
                 }],
                 rules: None,
                 cw20_coins: vec![],
+                task_type: None,
             },
         };
         let task_id_str =
@@ -1642,6 +1759,7 @@ mod tests {
                 }],
                 rules: None,
                 cw20_coins: vec![],
+                task_type: None,
             },
         };
         let task_id_str =
@@ -1726,6 +1844,7 @@ mod tests {
                 }],
                 rules: None,
                 cw20_coins: vec![],
+                task_type: None,
             },
         };
         // create 1 token off task
@@ -1780,6 +1899,7 @@ mod tests {
                 }],
                 rules: None,
                 cw20_coins: vec![],
+                task_type: None,
             },
         };
         // create 1 token off task
@@ -1812,4 +1932,176 @@ mod tests {
         );
         assert!(res.is_ok());
     }
+
+    #[test]
+    fn task_creation_needs_min_agents() {
+        let (mut app, cw_template_contract) = proper_instantiate();
+        let contract_addr = cw_template_contract.addr();
+
+        let mut settings_msg = update_settings_msg();
+        if let ExecuteMsg::UpdateSettings {
+            min_agents_for_tasks,
+            ..
+        } = &mut settings_msg
+        {
+            *min_agents_for_tasks = Some(1);
+        }
+        app.execute_contract(
+            Addr::unchecked(ADMIN),
+            contract_addr.clone(),
+            &settings_msg,
+            &[],
+        )
+        .unwrap();
+
+        let create_task_msg = ExecuteMsg::CreateTask {
+            task: TaskRequest {
+                interval: Interval::Immediate,
+                boundary: None,
+                stop_on_fail: false,
+                actions: vec![Action {
+                    msg: StakingMsg::Delegate {
+                        validator: String::from("you"),
+                        amount: coin(3, NATIVE_DENOM),
+                    }
+                    .into(),
+                    gas_limit: Some(150_000),
+                }],
+                rules: None,
+                cw20_coins: vec![],
+                task_type: None,
+            },
+        };
+
+        // Below the minimum
+        let allowed: TaskCreationAllowedResponse = app
+            .wrap()
+            .query_wasm_smart(&contract_addr, &QueryMsg::GetTaskCreationAllowed {})
+            .unwrap();
+        assert_eq!(
+            allowed,
+            TaskCreationAllowedResponse {
+                allowed: false,
+                active_agents: 0,
+                min_required: 1,
+            }
+        );
+        let err = app
+            .execute_contract(
+                Addr::unchecked(ANYONE),
+                contract_addr.clone(),
+                &create_task_msg,
+                &coins(300010, NATIVE_DENOM),
+            )
+            .unwrap_err();
+        assert_eq!(
+            ContractError::CustomError {
+                val: "Not enough active agents".to_string()
+            },
+            err.downcast().unwrap()
+        );
+
+        // At the minimum
+        app.execute_contract(
+            Addr::unchecked(VERY_RICH),
+            contract_addr.clone(),
+            &ExecuteMsg::RegisterAgent {
+                payable_account_id: None,
+                capabilities: None,
+                accepted_terms: None,
+                client_version: None,
+            },
+            &[],
+        )
+        .unwrap();
+        let allowed: TaskCreationAllowedResponse = app
+            .wrap()
+            .query_wasm_smart(&contract_addr, &QueryMsg::GetTaskCreationAllowed {})
+            .unwrap();
+        assert_eq!(
+            allowed,
+            TaskCreationAllowedResponse {
+                allowed: true,
+                active_agents: 1,
+                min_required: 1,
+            }
+        );
+        app.execute_contract(
+            Addr::unchecked(ANYONE),
+            contract_addr.clone(),
+            &create_task_msg,
+            &coins(300010, NATIVE_DENOM),
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn query_current_slot_budget() {
+        let (mut app, cw_template_contract) = proper_instantiate();
+        let contract_addr = cw_template_contract.addr();
+        let budget = |app: &App| -> SlotBudgetResponse {
+            app.wrap()
+                .query_wasm_smart(&contract_addr, &QueryMsg::GetCurrentSlotBudget {})
+                .unwrap()
+        };
+
+        // No tasks, no agents
+        let res = budget(&app);
+        assert_eq!(app.block_info().height, res.slot);
+        assert_eq!(coin(0, NATIVE_DENOM), res.total_reward);
+        assert_eq!(coin(0, NATIVE_DENOM), res.per_agent_estimate);
+
+        // Two tasks due next block, small enough for ANYONE to fund both
+        let gas_limit = 150_000;
+        let amount_for_one_task = gas_limit + 3;
+        for validator in ["you", "me"] {
+            let stake = StakingMsg::Delegate {
+                validator: validator.to_string(),
+                amount: coin(3, NATIVE_DENOM),
+            };
+            app.execute_contract(
+                Addr::unchecked(ANYONE),
+                contract_addr.clone(),
+                &ExecuteMsg::CreateTask {
+                    task: TaskRequest {
+                        interval: Interval::Immediate,
+                        boundary: None,
+                        stop_on_fail: false,
+                        actions: vec![Action {
+                            msg: stake.into(),
+                            gas_limit: Some(gas_limit),
+                        }],
+                        rules: None,
+                        cw20_coins: vec![],
+                        task_type: None,
+                    },
+                },
+                &coins(u128::from(amount_for_one_task * 2), NATIVE_DENOM),
+            )
+            .unwrap();
+        }
+        app.update_block(|block| block.height += 1);
+
+        // Agent fee is 5 per task, nobody to split it with yet
+        let res = budget(&app);
+        assert_eq!(app.block_info().height, res.slot);
+        assert_eq!(coin(10, NATIVE_DENOM), res.total_reward);
+        assert_eq!(coin(0, NATIVE_DENOM), res.per_agent_estimate);
+
+        app.execute_contract(
+            Addr::unchecked(ANYONE),
+            contract_addr.clone(),
+            &ExecuteMsg::RegisterAgent {
+                payable_account_id: None,
+                capabilities: None,
+                accepted_terms: None,
+                client_version: None,
+            },
+            &[],
+        )
+        .unwrap();
+        let res = budget(&app);
+        assert_eq!(coin(10, NATIVE_DENOM), res.total_reward);
+        assert_eq!(coin(10, NATIVE_DENOM), res.per_agent_estimate);
+    }
 }
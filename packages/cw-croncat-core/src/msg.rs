@@ -1,8 +1,12 @@
 use crate::types::{
     Action, AgentResponse, Boundary, BoundaryValidated, GenericBalance, Interval, Task,
 };
-use crate::types::{Agent, SlotType};
-use cosmwasm_std::{Addr, Coin, Timestamp, Uint64};
+use crate::types::{
+    Agent, LoyaltyTier, MassExitThreshold, NominationBlockReason, NominationMode, PauseFlags,
+    QueueRemovalMode, RewardEscrow, RewardPreference, RewardRounding, SelectionMode,
+    SlashDestination, SlotType,
+};
+use cosmwasm_std::{Addr, Binary, Coin, Decimal, Timestamp, Uint128, Uint64};
 use cw20::{Balance, Cw20Coin, Cw20CoinVerified};
 use cw_rules_core::types::Rule;
 use schemars::JsonSchema;
@@ -39,18 +43,44 @@ pub struct Croncat {
     config_response: Option<GetConfigResponse>,
     balance_response: Option<GetBalancesResponse>,
     get_agent_ids_response: Option<GetAgentIdsResponse>,
+    get_oldest_pending_agent_response: Option<Option<(Addr, u64)>>,
+    get_slashable_agents_response: Option<Vec<SlashableAgentResponse>>,
+    get_config_change_impact_response: Option<ConfigChangeImpactResponse>,
+    get_missed_slot_stats_response: Option<MissedSlotStatsResponse>,
+    get_agent_balance_drift_response: Option<AgentBalanceDriftResponse>,
+    get_solvency_response: Option<SolvencyResponse>,
+    get_queue_integrity_response: Option<QueueIntegrityResponse>,
+    get_queue_capacity_response: Option<QueueCapacityResponse>,
+    get_nomination_config_response: Option<NominationConfigResponse>,
+    get_nomination_status_response: Option<NominationStatusResponse>,
     get_agent_tasks_response: Option<AgentTaskResponse>,
     task_request: Option<TaskRequest>,
     task_response: Option<TaskResponse>,
     validate_interval_response: Option<bool>,
     get_agent_response: Option<Option<AgentResponse>>,
+    get_agents_response: Option<Vec<(Addr, Option<AgentResponse>)>>,
+    get_agent_balance_response: Option<Option<GetAgentBalanceResponse>>,
+    get_agent_liveness_response: Option<Option<AgentLivenessResponse>>,
+    get_agent_tenure_response: Option<AgentTenureResponse>,
+    get_agent_rank_response: Option<Option<u64>>,
+    get_agent_execution_window_response: Option<Option<AgentExecutionWindowResponse>>,
+    get_next_slot_for_agent_response: Option<Option<u64>>,
+    get_projected_rewards_response: Option<Coin>,
+    get_agent_reward_denom_response: Option<String>,
+    get_net_reward_estimate_response: Option<NetRewardEstimateResponse>,
+    get_agent_escrow_response: Option<Vec<AgentEscrowResponse>>,
+    get_vested_balance_response: Option<VestedBalanceResponse>,
     get_tasks_response: Option<Vec<TaskResponse>>,
     get_tasks_by_owner_response: Option<Vec<TaskResponse>>,
     get_task_response: Option<Option<TaskResponse>>,
     get_task_hash_response: Option<String>,
     get_slot_hashes_response: Option<GetSlotHashesResponse>,
     get_slot_ids_response: Option<GetSlotIdsResponse>,
+    get_current_slot_budget_response: Option<SlotBudgetResponse>,
+    get_health_response: Option<HealthResponse>,
+    get_protocol_revenue_response: Option<GenericBalance>,
     get_wallet_balances_response: Option<GetWalletBalancesResponse>,
+    execution_result: Option<ExecutionResult>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
@@ -61,8 +91,15 @@ pub struct InstantiateMsg {
     pub owner_id: Option<String>,
     pub gas_base_fee: Option<Uint64>,
     pub agent_nomination_duration: Option<u16>,
+    // Registered as the first active agent, without the wallet balance check
+    pub initial_agent: Option<String>,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct MigrateMsg {}
+
+// UpdateSettings carries every setting, messages are only built once per call
+#[allow(clippy::large_enum_variant)]
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ExecuteMsg {
@@ -71,12 +108,68 @@ pub enum ExecuteMsg {
         slot_granularity: Option<u64>,
         paused: Option<bool>,
         agent_fee: Option<Coin>,
-        gas_price: Option<u32>,
+        gas_price: Option<Decimal>,
         proxy_callback_gas: Option<u32>,
         min_tasks_per_agent: Option<u64>,
         agents_eject_threshold: Option<u64>,
+        slash_destination: Option<SlashDestination>,
+        stale_timeout_nanos: Option<u64>,
+        registration_fee: Option<Coin>,
+        rotate_queue_each_slot: Option<bool>,
+        min_agents_for_tasks: Option<u64>,
+        max_agent_balance: Option<Coin>,
+        reward_rounding: Option<RewardRounding>,
+        loyalty_tiers: Option<Vec<LoyaltyTier>>,
+        max_agents: Option<u64>,
+        max_pending_agents: Option<u64>,
+        reward_escrow: Option<RewardEscrow>,
+        reward_min: Option<Uint128>,
+        reward_max: Option<Uint128>,
+        mass_exit_threshold: Option<MassExitThreshold>,
+        allow_empty_active_set: Option<bool>,
+        weighted_selection: Option<bool>,
+        selection: Option<SelectionMode>,
+        onboarders: Option<Vec<String>>,
+        min_tasks_for_nomination: Option<u64>,
+        activation_cooldown_nanos: Option<u64>,
+        dust_threshold: Option<Uint128>,
+        reward_vesting_nanos: Option<u64>,
+        queue_removal_mode: Option<QueueRemovalMode>,
+        max_missed_nominations: Option<u64>,
+        exclusivity_window_slots: Option<u64>,
+        idle_grace_nanos: Option<u64>,
+        dex_router: Option<String>,
+        dex_max_slippage: Option<Decimal>,
+        missed_slot_grace: Option<u64>,
+        slash_denom: Option<String>,
+        position_hook_step: Option<u64>,
+        blacklist: Option<Vec<String>>,
+        max_reward_multiplier_bps: Option<u16>,
+        refund_stray_funds: Option<bool>,
+        required_terms_hash: Option<Binary>,
+        auto_activate_open_slots: Option<bool>,
+        slash_bps: Option<u16>,
+        allowed_reward_cw20: Option<Vec<String>>,
+        slot_duration_nanos: Option<u64>,
+        min_client_version: Option<String>,
         // treasury_id: Option<String>,
     },
+    UpdatePauseFlags {
+        register: Option<bool>,
+        execute: Option<bool>,
+        withdraw: Option<bool>,
+        create_task: Option<bool>,
+    },
+    UpdateMissedSlotThreshold {
+        threshold: u64,
+    },
+    WithdrawProtocolRevenue {
+        recipient: String,
+    },
+    SweepStuckFunds {
+        denom: String,
+        recipient: String,
+    },
     MoveBalances {
         balances: Vec<Balance>,
         account_id: String,
@@ -84,13 +177,95 @@ pub enum ExecuteMsg {
 
     RegisterAgent {
         payable_account_id: Option<String>,
+        capabilities: Option<Vec<String>>,
+        // Must match `required_terms_hash` when the config sets one
+        accepted_terms: Option<Binary>,
+        // Semver of the agent's keeper software, see `min_client_version`
+        client_version: Option<String>,
+    },
+    RegisterAgentFor {
+        target: String,
+        payable_account_id: Option<String>,
     },
     UpdateAgent {
         payable_account_id: String,
+        // None keeps the current capabilities
+        capabilities: Option<Vec<String>>,
+        // None keeps the current client version
+        client_version: Option<String>,
+    },
+    UpdateAgentOperators {
+        operators: Vec<String>,
+    },
+    UpdateAgentFull {
+        params: UpdateAgentParams,
     },
+    // Empty accepts all task types
+    SetAcceptedTaskTypes {
+        task_types: Vec<String>,
+    },
+    SetPositionHook {
+        hook: Option<String>,
+    },
+    SetStreamRewards {
+        stream: bool,
+    },
+    MigrateAgent {
+        new_address: String,
+    },
+    UpdateRewardPreference {
+        preference: RewardPreference,
+    },
+    ToggleAgentPause {},
+    ToggleAutoCompound {},
+    CreateAgentPool {
+        pool_id: String,
+    },
+    JoinAgentPool {
+        pool_id: String,
+    },
+    ApproveAgentPoolMember {
+        pool_id: String,
+        agent_id: String,
+        weight: u64,
+    },
+    LeaveAgentPool {},
     CheckInAgent {},
     UnregisterAgent {},
-    WithdrawReward {},
+    WithdrawReward {
+        // Repeating a key makes the withdrawal a no-op, left out of the msg when unset
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        idempotency_key: Option<String>,
+        // Bookkeeping tag echoed back as the `memo` attribute
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        memo: Option<String>,
+    },
+    // Payable account withdraws the agents paying out to it in one payout,
+    // a page of them at a time ordered by address
+    WithdrawAllForPayable {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    SlashAgent {
+        agent_id: String,
+    },
+    ReinstateAgent {
+        account_id: String,
+        refund: bool,
+    },
+    SetAgentRewardMultiplier {
+        account_id: String,
+        multiplier_bps: u16,
+    },
+    DistributeBonus {
+        total: Coin,
+    },
+    ReplaceActiveAgent {
+        remove: String,
+        promote: String,
+    },
+    RotateActiveSet {},
+    RepairQueues {},
 
     CreateTask {
         task: TaskRequest,
@@ -123,7 +298,79 @@ pub enum QueryMsg {
     GetAgent {
         account_id: String,
     },
+    GetAgents {
+        account_ids: Vec<String>,
+    },
+    GetAllAgents {
+        start_after: Option<String>,
+        limit: Option<u32>,
+    },
+    GetAgentBalance {
+        account_id: String,
+    },
+    GetAgentLiveness {
+        account_id: String,
+    },
+    GetAgentTenure {
+        account_id: String,
+    },
+    GetAgentRank {
+        account_id: String,
+    },
+    GetAgentExecutionWindow {
+        account_id: String,
+    },
+    GetNextSlotForAgent {
+        account_id: String,
+        from_slot: u64,
+    },
+    GetProjectedRewards {
+        account_id: String,
+        horizon_slots: u64,
+    },
+    GetAgentRewardDenom {
+        account_id: String,
+    },
+    GetNetRewardEstimate {
+        account_id: String,
+    },
+    GetAgentEscrow {
+        account_id: String,
+    },
+    GetVestedBalance {
+        account_id: String,
+    },
     GetAgentIds {},
+    GetOldestPendingAgent {},
+    GetAgentsWithCapability {
+        capability: String,
+    },
+    GetAgentsByPayable {
+        payable_account_id: String,
+    },
+    GetSlashableAgents {},
+    GetConfigChangeImpact {
+        proposed: ConfigChange,
+    },
+    GetMissedSlotStats {},
+    GetTopStreak {},
+    GetIdleAgents {
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    },
+    GetExecutionDistribution {
+        from_index: Option<u64>,
+        limit: Option<u64>,
+    },
+    GetTotalAgentValue {},
+    GetAgentBalanceDrift {},
+    GetSolvency {},
+    GetQueueIntegrity {},
+    GetQueueCapacity {},
+    GetNominationConfig {},
+    GetNominationStatus {
+        account_id: String,
+    },
     GetAgentTasks {
         account_id: String,
     },
@@ -147,10 +394,15 @@ pub enum QueryMsg {
     ValidateInterval {
         interval: Interval,
     },
+    GetTaskCreationAllowed {},
     GetSlotHashes {
         slot: Option<u64>,
     },
     GetSlotIds {},
+    GetSlotDuration {},
+    GetCurrentSlotBudget {},
+    GetHealth {},
+    GetProtocolRevenue {},
     GetWalletBalances {
         wallet: String,
     },
@@ -160,20 +412,114 @@ pub enum QueryMsg {
     },
 }
 
+/// Swap interface `Config.dex_router` has to implement.
+/// The attached funds get swapped into `ask_token` and sent to `recipient`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DexRouterExecuteMsg {
+    Swap {
+        ask_token: String,
+        max_slippage: Decimal,
+        recipient: String,
+    },
+}
+
+/// Interface of `Agent.position_hook`, pinged as the agent advances in the pending queue
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AgentHookMsg {
+    PendingPositionChanged {
+        agent: String,
+        previous_position: u64,
+        position: u64,
+    },
+}
+
+/// Agent fields to change at once, None keeps the current value
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, JsonSchema)]
+pub struct UpdateAgentParams {
+    pub payable_account_id: Option<String>,
+    pub capabilities: Option<Vec<String>>,
+    pub operators: Option<Vec<String>>,
+    pub reward_preference: Option<RewardPreference>,
+    pub accepted_task_types: Option<Vec<String>>,
+    pub auto_compound: Option<bool>,
+}
+
+/// Config values to preview with `GetConfigChangeImpact`, None keeps the current value
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq, JsonSchema)]
+pub struct ConfigChange {
+    // Raises or lowers the wallet balance agents need to cover gas
+    pub gas_price: Option<Decimal>,
+    pub blacklist: Option<Vec<String>>,
+}
+
+/// Registered agents that wouldn't be let in under a proposed config
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct ConfigChangeImpactResponse {
+    pub agents_affected: u64,
+    pub agents_evicted: Vec<Addr>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct GetConfigResponse {
-    pub paused: bool,
+    pub paused: PauseFlags,
     pub owner_id: Addr,
     // pub treasury_id: Option<Addr>,
     pub min_tasks_per_agent: u64,
     pub agent_active_indices: Vec<(SlotType, u32, u32)>,
     pub agents_eject_threshold: u64,
     pub agent_fee: Coin,
-    pub gas_price: u32,
+    pub gas_price: Decimal,
     pub proxy_callback_gas: u32,
     pub slot_granularity: u64,
     pub native_denom: String,
     pub cw_rules_addr: Addr,
+    pub agent_nomination_duration: u16,
+    pub gas_base_fee: u64,
+    pub cw20_whitelist: Vec<Addr>,
+    pub limit: u64,
+    pub slash_destination: SlashDestination,
+    pub stale_timeout_nanos: u64,
+    pub registration_fee: Option<Coin>,
+    pub rotate_queue_each_slot: bool,
+    pub min_agents_for_tasks: u64,
+    pub max_agent_balance: Option<Coin>,
+    pub reward_rounding: RewardRounding,
+    pub loyalty_tiers: Vec<LoyaltyTier>,
+    pub max_agents: Option<u64>,
+    pub max_pending_agents: Option<u64>,
+    pub reward_escrow: Option<RewardEscrow>,
+    pub reward_min: Option<Uint128>,
+    pub reward_max: Option<Uint128>,
+    pub mass_exit_threshold: Option<MassExitThreshold>,
+    pub allow_empty_active_set: bool,
+    pub weighted_selection: bool,
+    pub selection: SelectionMode,
+    pub onboarders: Vec<Addr>,
+    pub missed_slot_threshold: u64,
+    pub min_tasks_for_nomination: u64,
+    pub activation_cooldown_nanos: u64,
+    pub dust_threshold: Uint128,
+    pub reward_vesting_nanos: u64,
+    pub queue_removal_mode: QueueRemovalMode,
+    pub max_missed_nominations: u64,
+    pub exclusivity_window_slots: u64,
+    pub idle_grace_nanos: u64,
+    pub dex_router: Option<Addr>,
+    pub dex_max_slippage: Decimal,
+    pub missed_slot_grace: u64,
+    pub slash_denom: Option<String>,
+    pub position_hook_step: u64,
+    pub blacklist: Vec<Addr>,
+    pub max_reward_multiplier_bps: u16,
+    pub refund_stray_funds: bool,
+    pub required_terms_hash: Option<Binary>,
+    pub auto_activate_open_slots: bool,
+    pub slash_bps: u16,
+    pub allowed_reward_cw20: Vec<Addr>,
+    pub slot_duration_nanos: u64,
+    pub min_client_version: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -184,17 +530,165 @@ pub struct GetBalancesResponse {
     pub cw20_whitelist: Vec<Addr>,
 }
 
+/// Key operational signals in one query, for monitoring
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct HealthResponse {
+    pub paused: PauseFlags,
+    pub active_agents: u64,
+    // Whether a pending agent can check in right now
+    pub has_nominated: bool,
+    // Most recent execution of any active agent, zero if none executed yet
+    pub last_execution_nanos: u64,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct GetWalletBalancesResponse {
     pub cw20_balances: Vec<Cw20CoinVerified>,
 }
 
+/// Outcome of a task execution, set as the response data of the execution path so
+/// relayers and cross-chain consumers can decode it like an ack. `error` is the first failed action
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ExecutionResult {
+    pub success: bool,
+    pub agent: Addr,
+    pub slot: u64,
+    pub reward: Coin,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct GetAgentBalanceResponse {
+    pub native: Vec<Coin>,
+    pub cw20: Vec<Cw20Coin>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
 pub struct GetAgentIdsResponse {
     pub active: Vec<Addr>,
     pub pending: Vec<Addr>,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct AgentLivenessResponse {
+    // Zero if the agent never executed
+    pub last_execution_nanos: u64,
+    // u64::MAX if the agent never executed
+    pub nanos_since: u64,
+}
+
+/// Part of the current slot an active agent is meant to submit in, the end is exclusive
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct AgentExecutionWindowResponse {
+    pub window_start_nanos: u64,
+    pub window_end_nanos: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct AgentTenureResponse {
+    pub register_start_nanos: u64,
+    // Whole seconds since registering
+    pub tenure_seconds: u64,
+}
+
+/// Agent reward held for a task until its dispute window passes
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AgentEscrowResponse {
+    pub task_hash: String,
+    pub balance: GenericBalance,
+    pub release_time_nanos: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct SlashableAgentResponse {
+    pub agent: Addr,
+    pub consecutive_misses: u64,
+}
+
+/// `total_misses` is every missed slot counted so far, the rest is
+/// the current consecutive misses of active agents
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct MissedSlotStatsResponse {
+    pub total_misses: u64,
+    pub agents_with_misses: u64,
+    pub worst_agent: Option<(Addr, u64)>,
+}
+
+/// `total_agent_balance` against the sum of what agents actually hold,
+/// `surplus` and `deficit` are both empty when they match
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct AgentBalanceDriftResponse {
+    pub tracked: GenericBalance,
+    pub computed: GenericBalance,
+    pub surplus: GenericBalance,
+    pub deficit: GenericBalance,
+}
+
+/// Everything owed to agents against what the contract holds, per token
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SolvencyResponse {
+    pub total_liabilities: GenericBalance,
+    pub contract_holdings: GenericBalance,
+    pub solvent: bool,
+}
+
+/// What an agent would earn for its next task after paying gas, `net` is zero when it'd lose money
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct NetRewardEstimateResponse {
+    pub gross_reward: Coin,
+    /// Gas the task pays back to the agent
+    pub gas_reimbursement: Coin,
+    pub estimated_gas_cost: Coin,
+    pub net: Coin,
+    pub profitable: bool,
+}
+
+/// Vesting rewards of an agent, `vested` is withdrawable and `vesting` still locked
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct VestedBalanceResponse {
+    pub vested: Vec<Coin>,
+    pub vesting: Vec<Coin>,
+}
+
+/// Inconsistencies in the agent queues, all empty when the queues are healthy
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct QueueIntegrityResponse {
+    pub active_dupes: Vec<Addr>,
+    pub pending_dupes: Vec<Addr>,
+    // Queue entries without an agent record
+    pub orphaned_queue_entries: Vec<Addr>,
+}
+
+/// Parameters deciding which pending agents are nominated to check in
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct NominationConfigResponse {
+    pub mode: NominationMode,
+    // Seconds
+    pub nomination_duration: u16,
+    // How many more agents the current tasks need
+    pub open_slots: u64,
+    pub min_tasks_per_agent: u64,
+    pub nomination_begin_time: Option<Timestamp>,
+    // Fewer due tasks than `min_tasks_for_nomination`, pending agents stay pending
+    pub nomination_suspended: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct NominationStatusResponse {
+    pub nominated: bool,
+    // None when nominated
+    pub reason: Option<NominationBlockReason>,
+}
+
+/// Agent queue lengths and their configured caps, none means unlimited
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct QueueCapacityResponse {
+    pub active_used: u64,
+    pub active_max: Option<u64>,
+    pub pending_used: u64,
+    pub pending_max: Option<u64>,
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
 pub struct AgentTaskResponse {
     pub num_block_tasks: Uint64,
@@ -240,6 +734,14 @@ pub struct TaskRequest {
     pub actions: Vec<Action>,
     pub rules: Option<Vec<Rule>>,
     pub cw20_coins: Vec<Cw20Coin>,
+    pub task_type: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct TaskCreationAllowedResponse {
+    pub allowed: bool,
+    pub active_agents: u64,
+    pub min_required: u64,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -405,6 +907,13 @@ pub struct GetSlotIdsResponse {
     pub block_ids: Vec<u64>,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct SlotBudgetResponse {
+    pub slot: u64,
+    pub total_reward: Coin,
+    pub per_agent_estimate: Coin,
+}
+
 #[cfg(test)]
 mod tests {
     use cosmwasm_std::{coin, coins, BankMsg, CosmosMsg, Timestamp, Uint128};
@@ -431,6 +940,25 @@ mod tests {
             total_tasks_executed: 0,
             last_missed_slot: 3,
             register_start: Timestamp::from_nanos(5),
+            operators: vec![],
+            last_execution: None,
+            consecutive_misses: 0,
+            self_paused: false,
+            capabilities: vec![],
+            reward_preference: RewardPreference::Native,
+            bond: vec![],
+            auto_compound: false,
+            last_activation: None,
+            pending_dust: vec![],
+            pool_id: None,
+            accepted_task_types: vec![],
+            missed_nominations: 0,
+            position_hook: None,
+            reward_multiplier_bps: 10_000,
+            accepted_terms_hash: None,
+            stream_rewards: false,
+            current_streak: 0,
+            client_version: None,
         }
         .into();
 
@@ -456,21 +984,76 @@ mod tests {
                 gas_limit: Some(150_000),
             }],
             rules: None,
+            task_type: None,
         }
         .into();
 
         let config_response = GetConfigResponse {
-            paused: true,
+            paused: PauseFlags::all(true),
             owner_id: Addr::unchecked("bob"),
             min_tasks_per_agent: 5,
             agent_active_indices: vec![(SlotType::Block, 10, 5)],
             agents_eject_threshold: 5,
             agent_fee: coin(5, "earth"),
-            gas_price: 2,
+            gas_price: Decimal::permille(25),
             proxy_callback_gas: 3,
             slot_granularity: 1,
             native_denom: "juno".to_string(),
             cw_rules_addr: Addr::unchecked("bob"),
+            agent_nomination_duration: 10,
+            gas_base_fee: 300_000,
+            cw20_whitelist: vec![Addr::unchecked("bob")],
+            limit: 100,
+            slash_destination: SlashDestination::Burn,
+            stale_timeout_nanos: 1,
+            registration_fee: Some(coin(3, "test")),
+            rotate_queue_each_slot: true,
+            min_agents_for_tasks: 1,
+            max_agent_balance: Some(coin(10, "test")),
+            reward_rounding: RewardRounding::RoundUp,
+            loyalty_tiers: vec![LoyaltyTier {
+                min_tenure_nanos: 5,
+                multiplier_bps: 15_000,
+            }],
+            max_agents: Some(10),
+            max_pending_agents: None,
+            reward_escrow: Some(RewardEscrow {
+                min_task_deposit: coin(1000, "test"),
+                dispute_window_nanos: 5,
+            }),
+            reward_min: Some(Uint128::new(2)),
+            reward_max: None,
+            mass_exit_threshold: Some(MassExitThreshold {
+                max_exits: 3,
+                window_nanos: 5,
+            }),
+            allow_empty_active_set: true,
+            weighted_selection: false,
+            selection: SelectionMode::Lottery,
+            onboarders: vec![Addr::unchecked("onboarder")],
+            missed_slot_threshold: 4,
+            min_tasks_for_nomination: 2,
+            activation_cooldown_nanos: 10,
+            dust_threshold: Uint128::new(3),
+            reward_vesting_nanos: 100,
+            queue_removal_mode: QueueRemovalMode::SwapRemove,
+            max_missed_nominations: 3,
+            exclusivity_window_slots: 2,
+            idle_grace_nanos: 1000,
+            dex_router: Some(Addr::unchecked("dex")),
+            dex_max_slippage: Decimal::percent(2),
+            missed_slot_grace: 1,
+            slash_denom: Some("test".to_string()),
+            position_hook_step: 2,
+            blacklist: vec![Addr::unchecked("bob")],
+            max_reward_multiplier_bps: 20_000,
+            refund_stray_funds: true,
+            required_terms_hash: Some(Binary::from(b"terms")),
+            auto_activate_open_slots: true,
+            slash_bps: 2_500,
+            allowed_reward_cw20: vec![Addr::unchecked("bob")],
+            slot_duration_nanos: 6_000_000_000,
+            min_client_version: Some("1.2.0".to_string()),
         }
         .into();
         let balance_response = GetBalancesResponse {
@@ -485,6 +1068,72 @@ mod tests {
             pending: vec![Addr::unchecked("bob")],
         }
         .into();
+        let get_oldest_pending_agent_response = Some((Addr::unchecked("bob"), 5)).into();
+        let get_slashable_agents_response = vec![SlashableAgentResponse {
+            agent: Addr::unchecked("bob"),
+            consecutive_misses: 5,
+        }]
+        .into();
+        let get_config_change_impact_response = ConfigChangeImpactResponse {
+            agents_affected: 1,
+            agents_evicted: vec![Addr::unchecked("bob")],
+        }
+        .into();
+        let get_missed_slot_stats_response = MissedSlotStatsResponse {
+            total_misses: 7,
+            agents_with_misses: 1,
+            worst_agent: Some((Addr::unchecked("bob"), 5)),
+        }
+        .into();
+        let get_agent_balance_drift_response = AgentBalanceDriftResponse {
+            tracked: GenericBalance {
+                native: coins(5, "test"),
+                cw20: vec![],
+            },
+            computed: GenericBalance::default(),
+            surplus: GenericBalance {
+                native: coins(5, "test"),
+                cw20: vec![],
+            },
+            deficit: GenericBalance::default(),
+        }
+        .into();
+        let get_solvency_response = SolvencyResponse {
+            total_liabilities: GenericBalance {
+                native: coins(5, "test"),
+                cw20: vec![],
+            },
+            contract_holdings: GenericBalance::default(),
+            solvent: false,
+        }
+        .into();
+        let get_queue_integrity_response = QueueIntegrityResponse {
+            active_dupes: vec![Addr::unchecked("bob")],
+            pending_dupes: vec![],
+            orphaned_queue_entries: vec![Addr::unchecked("alice")],
+        }
+        .into();
+        let get_queue_capacity_response = QueueCapacityResponse {
+            active_used: 1,
+            active_max: Some(3),
+            pending_used: 2,
+            pending_max: None,
+        }
+        .into();
+        let get_nomination_config_response = NominationConfigResponse {
+            mode: NominationMode::Fifo,
+            nomination_duration: 360,
+            open_slots: 1,
+            min_tasks_per_agent: 3,
+            nomination_begin_time: Some(Timestamp::from_nanos(5)),
+            nomination_suspended: false,
+        }
+        .into();
+        let get_nomination_status_response = NominationStatusResponse {
+            nominated: false,
+            reason: Some(NominationBlockReason::BehindInQueue { position: 2 }),
+        }
+        .into();
         let get_agent_tasks_response = AgentTaskResponse {
             num_block_tasks: 1u64.into(),
             num_block_tasks_extra: 2u64.into(),
@@ -502,6 +1151,7 @@ mod tests {
             actions: vec![],
             rules: None, // TODO
             cw20_coins: vec![],
+            task_type: None,
         }
         .into();
         let task_response_raw = TaskResponse {
@@ -527,8 +1177,75 @@ mod tests {
             total_tasks_executed: 2,
             last_missed_slot: 2,
             register_start: Timestamp::from_nanos(5),
+            operators: vec![Addr::unchecked("alice")],
+            last_execution: Some(Timestamp::from_nanos(6)),
+            consecutive_misses: 1,
+            self_paused: false,
+            capabilities: vec!["ibc-relay".to_string()],
+            reward_preference: RewardPreference::Cw20(Addr::unchecked("cw20")),
+            bond: coins(5, "test"),
+            auto_compound: true,
+            last_activation: Some(Timestamp::from_nanos(7)),
+            pending_dust: coins(1, "test"),
+            pool_id: Some("pool".to_string()),
+            accepted_task_types: vec!["swap".to_string()],
+            missed_nominations: 1,
+            position_hook: None,
+            reward_multiplier_bps: 10_000,
+            accepted_terms_hash: None,
+            stream_rewards: false,
+            current_streak: 0,
+            client_version: None,
+            is_stale: false,
         })
         .into();
+        let get_agents_response = vec![(Addr::unchecked("alice"), None)].into();
+        let get_agent_balance_response = Some(GetAgentBalanceResponse {
+            native: coins(5, "test"),
+            cw20: vec![Cw20Coin {
+                address: "bob".to_string(),
+                amount: 5u128.into(),
+            }],
+        })
+        .into();
+        let get_agent_liveness_response = Some(AgentLivenessResponse {
+            last_execution_nanos: 5,
+            nanos_since: 10,
+        })
+        .into();
+        let get_agent_tenure_response = AgentTenureResponse {
+            register_start_nanos: 5,
+            tenure_seconds: 10,
+        }
+        .into();
+        let get_agent_rank_response = Some(2).into();
+        let get_agent_execution_window_response = Some(AgentExecutionWindowResponse {
+            window_start_nanos: 5,
+            window_end_nanos: 10,
+        })
+        .into();
+        let get_next_slot_for_agent_response = Some(12345).into();
+        let get_projected_rewards_response = coin(100, "test").into();
+        let get_agent_reward_denom_response = "test".to_string().into();
+        let get_net_reward_estimate_response = NetRewardEstimateResponse {
+            gross_reward: coin(5, "test"),
+            gas_reimbursement: coin(6, "test"),
+            estimated_gas_cost: coin(7, "test"),
+            net: coin(0, "test"),
+            profitable: false,
+        }
+        .into();
+        let get_agent_escrow_response = vec![AgentEscrowResponse {
+            task_hash: "test".to_string(),
+            balance: generic_balance.clone(),
+            release_time_nanos: 5,
+        }]
+        .into();
+        let get_vested_balance_response = VestedBalanceResponse {
+            vested: coins(2, "test"),
+            vesting: coins(3, "test"),
+        }
+        .into();
         let get_tasks_response = vec![task_response_raw.clone()].into();
         let get_tasks_by_owner_response = vec![task_response_raw.clone()].into();
         let get_task_response = Some(task_response_raw).into();
@@ -545,6 +1262,20 @@ mod tests {
             block_ids: vec![3],
         }
         .into();
+        let get_current_slot_budget_response = SlotBudgetResponse {
+            slot: 3,
+            total_reward: coin(10, "test"),
+            per_agent_estimate: coin(5, "test"),
+        }
+        .into();
+        let get_protocol_revenue_response = generic_balance.clone().into();
+        let get_health_response = HealthResponse {
+            paused: PauseFlags::default(),
+            active_agents: 2,
+            has_nominated: true,
+            last_execution_nanos: 5,
+        }
+        .into();
         let get_wallet_balances_response = GetWalletBalancesResponse {
             cw20_balances: vec![Cw20CoinVerified {
                 address: Addr::unchecked("Bob"),
@@ -552,24 +1283,58 @@ mod tests {
             }],
         }
         .into();
+        let execution_result = ExecutionResult {
+            success: false,
+            agent: Addr::unchecked("bob"),
+            slot: 5,
+            reward: coin(5, "test"),
+            error: Some("failed".to_string()),
+        }
+        .into();
         let croncat = Croncat {
             agent,
             task,
             config_response,
             balance_response,
             get_agent_ids_response,
+            get_oldest_pending_agent_response,
+            get_slashable_agents_response,
+            get_config_change_impact_response,
+            get_missed_slot_stats_response,
+            get_agent_balance_drift_response,
+            get_solvency_response,
+            get_queue_integrity_response,
+            get_queue_capacity_response,
+            get_nomination_config_response,
+            get_nomination_status_response,
             get_agent_tasks_response,
             task_request,
             task_response,
             validate_interval_response,
             get_agent_response,
+            get_agents_response,
+            get_agent_balance_response,
+            get_agent_liveness_response,
+            get_agent_tenure_response,
+            get_agent_rank_response,
+            get_agent_execution_window_response,
+            get_next_slot_for_agent_response,
+            get_projected_rewards_response,
+            get_agent_reward_denom_response,
+            get_net_reward_estimate_response,
+            get_agent_escrow_response,
+            get_vested_balance_response,
             get_tasks_response,
             get_tasks_by_owner_response,
             get_task_response,
             get_task_hash_response,
             get_slot_hashes_response,
             get_slot_ids_response,
+            get_current_slot_budget_response,
+            get_health_response,
+            get_protocol_revenue_response,
             get_wallet_balances_response,
+            execution_result,
         };
 
         let ser = serde_json_wasm::to_string(&croncat);
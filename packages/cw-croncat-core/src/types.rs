@@ -1,7 +1,7 @@
 use cosmwasm_std::{
-    coin, Addr, Api, BankMsg, Coin, CosmosMsg, Empty, Env, GovMsg, IbcMsg, OverflowError,
-    OverflowOperation::Sub, StakingMsg, StdError, SubMsgResult, Timestamp, Uint128, Uint64,
-    WasmMsg,
+    coin, Addr, Api, BankMsg, Binary, Coin, CosmosMsg, Decimal, Empty, Env, GovMsg, IbcMsg,
+    OverflowError, OverflowOperation::Sub, StakingMsg, StdError, SubMsgResult, Timestamp, Uint128,
+    Uint64, WasmMsg,
 };
 use cron_schedule::Schedule;
 use cw20::{Cw20CoinVerified, Cw20ExecuteMsg};
@@ -35,6 +35,247 @@ pub enum AgentStatus {
     Nominated,
 }
 
+/// Why a registered agent isn't nominated to check in
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub enum NominationBlockReason {
+    // Active already, only pending agents get nominated
+    NotPending,
+    // The current tasks don't need another agent
+    NoOpenSlots,
+    // Nominations go in queue order, `position` is zero-based in the pending queue
+    BehindInQueue { position: u64 },
+    // Slots opened but the nomination window hasn't started yet
+    InGracePeriod,
+    // Fewer due tasks than `min_tasks_for_nomination`
+    Suspended,
+}
+
+/// Asset an agent wants rewards credited in
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema, Default)]
+pub enum RewardPreference {
+    #[default]
+    Native,
+    // Must be a whitelisted cw20
+    Cw20(Addr),
+}
+
+/// How pending agents get picked to check in
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub enum NominationMode {
+    /// Pending queue order, each nomination duration that passes lets one more agent from the queue in
+    Fifo,
+}
+
+/// Where funds taken from a slashed agent end up
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema, Default)]
+pub enum SlashDestination {
+    /// Funds stay in the contract `available_balance`
+    #[default]
+    Treasury,
+    /// Funds get burned
+    Burn,
+    /// Funds get sent to an address, e.g. a community pool
+    Address(Addr),
+}
+
+/// How an agent is taken out of the active queue.
+/// Finding the agent is a scan either way, the difference is what happens to the agents behind it
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema, Default)]
+pub enum QueueRemovalMode {
+    /// Everyone behind the removed agent moves up one spot, keeping activation order. O(n)
+    #[default]
+    PreserveOrder,
+    /// The last agent takes the freed spot. O(1), but the queue no longer
+    /// reflects activation order, which activation order balancing relies on
+    SwapRemove,
+}
+
+impl QueueRemovalMode {
+    /// Removes the first occurrence of `item` from `queue`, returning the position it had
+    pub fn remove<T: PartialEq>(&self, queue: &mut Vec<T>, item: &T) -> Option<usize> {
+        let index = queue.iter().position(|queued| queued == item)?;
+        self.remove_at(queue, index);
+        Some(index)
+    }
+
+    /// Removes the item at `index` from `queue`. Panics if it's out of bounds, like `Vec::remove`
+    pub fn remove_at<T>(&self, queue: &mut Vec<T>, index: usize) -> T {
+        match self {
+            QueueRemovalMode::PreserveOrder => queue.remove(index),
+            QueueRemovalMode::SwapRemove => queue.swap_remove(index),
+        }
+    }
+}
+
+/// How the agent leading each slot is picked
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema, Default)]
+pub enum SelectionMode {
+    /// Active queue order, see `rotate_queue_each_slot` and `weighted_selection`
+    #[default]
+    RoundRobin,
+    /// Weighted by each agent's bond in the native denom, seeded by the block height
+    Lottery,
+}
+
+/// Which side gets the remainder when a reward is split by basis points
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema, Default)]
+pub enum RewardRounding {
+    /// Agent share is rounded down, the remainder stays in the treasury
+    #[default]
+    RoundDown,
+    /// Agent share is rounded up, the treasury takes the rest
+    RoundUp,
+}
+
+impl RewardRounding {
+    /// Splits `amount` into the agent share of `agent_bps` and the treasury share.
+    /// Both shares always add up to `amount`, `agent_bps` above 10_000 counts as 10_000
+    pub fn split(&self, amount: Uint128, agent_bps: u64) -> (Uint128, Uint128) {
+        let agent_bps = u128::from(agent_bps.min(10_000));
+        // amount * bps / 10_000 without overflowing
+        let (quotient, remainder) = (amount.u128() / 10_000, amount.u128() % 10_000);
+        let mut agent_share = quotient * agent_bps + remainder * agent_bps / 10_000;
+        if *self == RewardRounding::RoundUp && (remainder * agent_bps) % 10_000 != 0 {
+            agent_share += 1;
+        }
+        let agent_share = Uint128::new(agent_share);
+        (agent_share, amount - agent_share)
+    }
+
+    /// `amount` multiplied by `bps`, rounded like the agent share of `split`.
+    /// Unlike `split`, `bps` above 10_000 multiplies the amount up, saturating at the max
+    pub fn scale(&self, amount: Uint128, bps: u64) -> Uint128 {
+        let whole = amount.saturating_mul(Uint128::from(bps / 10_000));
+        whole.saturating_add(self.split(amount, bps % 10_000).0)
+    }
+}
+
+/// Reward multiplier for agents that stay registered for a while.
+/// An agent registered for at least `min_tenure_nanos` gets its agent fee
+/// multiplied by `multiplier_bps` (10_000 is 1x). The highest tier reached applies,
+/// and the contract caps the multiplier at 2x
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct LoyaltyTier {
+    pub min_tenure_nanos: u64,
+    pub multiplier_bps: u64,
+}
+
+/// Holds the agent reward of high-value tasks for a dispute window before it can be withdrawn
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RewardEscrow {
+    /// Tasks holding at least this much deposit (same denom) get their rewards escrowed
+    pub min_task_deposit: Coin,
+    pub dispute_window_nanos: u64,
+}
+
+/// Agents sharing their rewards, split between members by weight
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq, JsonSchema)]
+pub struct AgentPool {
+    /// Lets agents in and sets their weights, passes to the next member when it leaves
+    pub creator: Addr,
+    /// Members with their weight in the split
+    pub members: Vec<(Addr, u64)>,
+    /// Agents waiting for the creator to let them in
+    pub join_requests: Vec<Addr>,
+}
+
+/// An agent reward that becomes withdrawable linearly between `start_nanos` and `end_nanos`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct VestingCredit {
+    pub amount: Coin,
+    pub start_nanos: u64,
+    pub end_nanos: u64,
+    /// Part of the amount already moved into the agent balance
+    pub released: Uint128,
+}
+
+impl VestingCredit {
+    /// How much of the credit vested by `now`, including the released part
+    pub fn vested(&self, now: u64) -> Uint128 {
+        if now >= self.end_nanos {
+            return self.amount.amount;
+        }
+        let elapsed = now.saturating_sub(self.start_nanos);
+        self.amount
+            .amount
+            .multiply_ratio(elapsed, self.end_nanos - self.start_nanos)
+    }
+}
+
+/// Circuit breaker for a coordinated exit of active agents
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, JsonSchema)]
+pub struct MassExitThreshold {
+    /// More active agents unregistering than this within the window pauses execution
+    pub max_exits: u64,
+    pub window_nanos: u64,
+}
+
+/// Independent pause switches, so part of the contract can keep running during incidents
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, JsonSchema)]
+pub struct PauseFlags {
+    /// New agent registrations and agent updates
+    pub register: bool,
+    /// Proxy calls by agents
+    pub execute: bool,
+    /// Agent reward withdrawals, including on unregister
+    pub withdraw: bool,
+    /// Task creation
+    #[serde(default)]
+    pub create_task: bool,
+}
+
+impl PauseFlags {
+    /// Same value for every flag, what the single `paused` bool used to mean
+    pub fn all(paused: bool) -> Self {
+        PauseFlags {
+            register: paused,
+            execute: paused,
+            withdraw: paused,
+            create_task: paused,
+        }
+    }
+
+    pub fn any(&self) -> bool {
+        self.register || self.execute || self.withdraw || self.create_task
+    }
+}
+
+/// Reads pause flags stored either as the legacy bool or as `PauseFlags`
+pub fn deserialize_pause_flags<'de, D>(deserializer: D) -> Result<PauseFlags, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StoredPause {
+        Legacy(bool),
+        Flags(PauseFlags),
+    }
+
+    Ok(match StoredPause::deserialize(deserializer)? {
+        StoredPause::Legacy(paused) => PauseFlags::all(paused),
+        StoredPause::Flags(flags) => flags,
+    })
+}
+
+/// Reads a gas price stored either as the legacy integer or as a `Decimal`
+pub fn deserialize_gas_price<'de, D>(deserializer: D) -> Result<Decimal, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StoredGasPrice {
+        Legacy(u32),
+        Price(Decimal),
+    }
+
+    Ok(match StoredGasPrice::deserialize(deserializer)? {
+        StoredGasPrice::Legacy(gas_price) => Decimal::from_ratio(gas_price, 1u128),
+        StoredGasPrice::Price(gas_price) => gas_price,
+    })
+}
+
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Agent {
     // Where rewards get transferred
@@ -57,6 +298,99 @@ pub struct Agent {
     // Agent will be responsible to constantly monitor when it is their turn to join in active agent set (done as part of agent code loops)
     // Example data: 1633890060000000000 or 0
     pub register_start: Timestamp,
+
+    // Keys allowed to submit execution txns on behalf of this agent
+    // Rewards still accrue to the agent itself
+    #[serde(default)]
+    pub operators: Vec<Addr>,
+
+    // Timestamp of the last proxy call made by this agent, none if it never executed
+    #[serde(default)]
+    pub last_execution: Option<Timestamp>,
+
+    // Slots missed in a row since the last execution
+    #[serde(default)]
+    pub consecutive_misses: u64,
+
+    // Agent opted out of getting tasks for now, but stays in its queue
+    #[serde(default)]
+    pub self_paused: bool,
+
+    // Free-form tags like "ibc-relay", for matching agents to tasks that need them
+    #[serde(default)]
+    pub capabilities: Vec<String>,
+
+    // Asset the agent wants its rewards credited in
+    #[serde(default)]
+    pub reward_preference: RewardPreference,
+
+    // Compounded rewards, locked while registered and paid out on unregister
+    #[serde(default)]
+    pub bond: Vec<Coin>,
+
+    // Credit rewards to the bond instead of the withdrawable balance
+    #[serde(default)]
+    pub auto_compound: bool,
+
+    // When the agent last accepted a nomination, it can't step down for `activation_cooldown_nanos`
+    #[serde(default)]
+    pub last_activation: Option<Timestamp>,
+
+    // Rewards below `dust_threshold`, credited once they add up to it
+    #[serde(default)]
+    pub pending_dust: Vec<Coin>,
+
+    // Pool sharing this agent's rewards, see `agent_pools`
+    #[serde(default)]
+    pub pool_id: Option<String>,
+
+    // Task types the agent wants to execute, empty accepts all of them
+    #[serde(default)]
+    pub accepted_task_types: Vec<String>,
+
+    // Nominations that expired before the agent accepted, see `max_missed_nominations`
+    #[serde(default)]
+    pub missed_nominations: u64,
+
+    // Contract pinged with `AgentHookMsg` as the agent advances in the pending queue
+    #[serde(default)]
+    pub position_hook: Option<Addr>,
+
+    // Reward boost set by the owner, 10_000 is 1x, held under `max_reward_multiplier_bps`
+    #[serde(default = "default_reward_multiplier_bps")]
+    pub reward_multiplier_bps: u16,
+
+    // Terms of service hash the agent accepted when registering, see `required_terms_hash`
+    #[serde(default)]
+    pub accepted_terms_hash: Option<Binary>,
+
+    // Rewards are sent to the payable account on each execution instead of credited to the balance
+    #[serde(default)]
+    pub stream_rewards: bool,
+
+    // Successful executions in a row, reset on a missed slot
+    #[serde(default)]
+    pub current_streak: u64,
+
+    // Keeper software version reported at register or update
+    #[serde(default)]
+    pub client_version: Option<String>,
+}
+
+// Agents stored by older versions had no multiplier, 1x
+fn default_reward_multiplier_bps() -> u16 {
+    10_000
+}
+
+impl Agent {
+    /// Untyped tasks only count for agents accepting all types
+    pub fn accepts_task(&self, task: &Task) -> bool {
+        self.accepted_task_types.is_empty()
+            || task
+                .task_type
+                .iter()
+                .any(|task_type| self.accepted_task_types.contains(task_type))
+    }
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
@@ -68,6 +402,27 @@ pub struct AgentResponse {
     pub total_tasks_executed: u64,
     pub last_missed_slot: u64,
     pub register_start: Timestamp,
+    pub operators: Vec<Addr>,
+    pub last_execution: Option<Timestamp>,
+    pub consecutive_misses: u64,
+    pub self_paused: bool,
+    pub capabilities: Vec<String>,
+    pub reward_preference: RewardPreference,
+    pub bond: Vec<Coin>,
+    pub auto_compound: bool,
+    pub last_activation: Option<Timestamp>,
+    pub pending_dust: Vec<Coin>,
+    pub pool_id: Option<String>,
+    pub accepted_task_types: Vec<String>,
+    pub missed_nominations: u64,
+    pub position_hook: Option<Addr>,
+    pub reward_multiplier_bps: u16,
+    pub accepted_terms_hash: Option<Binary>,
+    pub stream_rewards: bool,
+    pub current_streak: u64,
+    pub client_version: Option<String>,
+    // Computed, true if the agent is active but didn't execute for longer than the stale timeout
+    pub is_stale: bool,
 }
 
 /// Defines the spacing of execution
@@ -229,6 +584,9 @@ pub struct Task {
     /// required to complete before task action
     /// Rules MUST return the ResolverResponse type
     pub rules: Option<Vec<Rule>>,
+    /// Category like "swap" or "ibc", agents can limit themselves to some of them
+    #[serde(default)]
+    pub task_type: Option<String>,
     // TODO: funds! should we support funds being attached?
 }
 
@@ -686,6 +1044,137 @@ mod tests {
     use cw_rules_core::types::HasBalanceGte;
     use hex::ToHex;
 
+    #[test]
+    fn deserialize_legacy_gas_price() {
+        #[derive(Deserialize)]
+        struct StoredConfig {
+            #[serde(deserialize_with = "deserialize_gas_price")]
+            gas_price: Decimal,
+        }
+
+        let legacy: StoredConfig = serde_json_wasm::from_str(r#"{"gas_price":2}"#).unwrap();
+        assert_eq!(legacy.gas_price, Decimal::percent(200));
+        let fractional: StoredConfig =
+            serde_json_wasm::from_str(r#"{"gas_price":"0.025"}"#).unwrap();
+        assert_eq!(fractional.gas_price, Decimal::permille(25));
+    }
+
+    #[test]
+    fn queue_removal_modes() {
+        let queue = vec!["a", "b", "c", "d"];
+
+        let mut preserved = queue.clone();
+        assert_eq!(
+            QueueRemovalMode::PreserveOrder.remove(&mut preserved, &"b"),
+            Some(1)
+        );
+        assert_eq!(preserved, vec!["a", "c", "d"]);
+
+        // Last one fills the gap
+        let mut swapped = queue.clone();
+        assert_eq!(
+            QueueRemovalMode::SwapRemove.remove(&mut swapped, &"b"),
+            Some(1)
+        );
+        assert_eq!(swapped, vec!["a", "d", "c"]);
+
+        // Removing the tail doesn't reorder anything
+        let mut swapped = queue.clone();
+        QueueRemovalMode::SwapRemove.remove(&mut swapped, &"d");
+        assert_eq!(swapped, vec!["a", "b", "c"]);
+
+        let mut missing = queue.clone();
+        assert_eq!(
+            QueueRemovalMode::SwapRemove.remove(&mut missing, &"e"),
+            None
+        );
+        assert_eq!(missing, queue);
+
+        let mut swapped = queue.clone();
+        assert_eq!(QueueRemovalMode::SwapRemove.remove_at(&mut swapped, 0), "a");
+        assert_eq!(swapped, vec!["d", "b", "c"]);
+    }
+
+    #[test]
+    fn reward_rounding_split() {
+        // 1001 * 33.33% = 333.6333
+        let amount = Uint128::new(1001);
+        assert_eq!(
+            (Uint128::new(333), Uint128::new(668)),
+            RewardRounding::RoundDown.split(amount, 3333)
+        );
+        assert_eq!(
+            (Uint128::new(334), Uint128::new(667)),
+            RewardRounding::RoundUp.split(amount, 3333)
+        );
+
+        // Even splits don't get rounded up
+        assert_eq!(
+            (Uint128::new(500), Uint128::new(500)),
+            RewardRounding::RoundUp.split(Uint128::new(1000), 5000)
+        );
+
+        // Dust, the whole amount goes to one side
+        assert_eq!(
+            (Uint128::zero(), Uint128::new(1)),
+            RewardRounding::RoundDown.split(Uint128::new(1), 9999)
+        );
+        assert_eq!(
+            (Uint128::new(1), Uint128::zero()),
+            RewardRounding::RoundUp.split(Uint128::new(1), 1)
+        );
+
+        // Over 100% is capped
+        assert_eq!(
+            (Uint128::new(7), Uint128::zero()),
+            RewardRounding::RoundUp.split(Uint128::new(7), 20_000)
+        );
+
+        // No overflow on big amounts, and shares always add up
+        for rounding in [RewardRounding::RoundDown, RewardRounding::RoundUp] {
+            for amount in [Uint128::MAX, Uint128::new(9_999), Uint128::new(123_457)] {
+                for agent_bps in [0, 1, 2500, 6667, 9999, 10_000] {
+                    let (agent_share, treasury_share) = rounding.split(amount, agent_bps);
+                    assert_eq!(amount, agent_share + treasury_share);
+                }
+            }
+        }
+        assert_eq!(
+            (Uint128::new(u128::MAX / 2 + 1), Uint128::new(u128::MAX / 2)),
+            RewardRounding::RoundUp.split(Uint128::MAX, 5000)
+        );
+    }
+
+    #[test]
+    fn reward_rounding_scale() {
+        // 5 * 1.25 = 6.25
+        let amount = Uint128::new(5);
+        assert_eq!(
+            Uint128::new(6),
+            RewardRounding::RoundDown.scale(amount, 12_500)
+        );
+        assert_eq!(
+            Uint128::new(7),
+            RewardRounding::RoundUp.scale(amount, 12_500)
+        );
+        assert_eq!(
+            Uint128::new(10),
+            RewardRounding::RoundUp.scale(amount, 20_000)
+        );
+        assert_eq!(
+            Uint128::new(2),
+            RewardRounding::RoundDown.scale(amount, 5_000)
+        );
+        assert_eq!(
+            Uint128::new(3),
+            RewardRounding::RoundUp.scale(amount, 5_000)
+        );
+        assert_eq!(
+            Uint128::MAX,
+            RewardRounding::RoundDown.scale(Uint128::MAX, 30_000)
+        );
+    }
+
     #[test]
     fn is_valid_msg_once_block_based() {
         let mut task = Task {
@@ -709,6 +1198,7 @@ mod tests {
                 gas_limit: Some(5),
             }],
             rules: None,
+            task_type: None,
         };
         assert!(task
             .is_valid_msg_calculate_usage(
@@ -745,6 +1235,7 @@ mod tests {
                 gas_limit: Some(5),
             }],
             rules: None,
+            task_type: None,
         };
         assert!(task
             .is_valid_msg_calculate_usage(
@@ -781,6 +1272,7 @@ mod tests {
                 gas_limit: Some(5),
             }],
             rules: None,
+            task_type: None,
         };
         assert!(task
             .is_valid_msg_calculate_usage(
@@ -818,6 +1310,7 @@ mod tests {
                 gas_limit: Some(5),
             }],
             rules: None,
+            task_type: None,
         };
         assert!(!task
             .is_valid_msg_calculate_usage(
@@ -854,6 +1347,7 @@ mod tests {
                 gas_limit: Some(5),
             }],
             rules: None,
+            task_type: None,
         };
         assert!(!task
             .is_valid_msg_calculate_usage(
@@ -892,6 +1386,7 @@ mod tests {
                 gas_limit: Some(5),
             }],
             rules: None,
+            task_type: None,
         };
         assert!(!task
             .is_valid_msg_calculate_usage(
@@ -927,6 +1422,7 @@ mod tests {
                 gas_limit: Some(5),
             }],
             rules: None,
+            task_type: None,
         };
         assert!(!task
             .is_valid_msg_calculate_usage(
@@ -963,6 +1459,7 @@ mod tests {
                 gas_limit: Some(5),
             }],
             rules: None,
+            task_type: None,
         };
         assert!(task
             .is_valid_msg_calculate_usage(
@@ -1002,6 +1499,7 @@ mod tests {
                 gas_limit: Some(5),
             }],
             rules: None,
+            task_type: None,
         };
         assert!(task
             .is_valid_msg_calculate_usage(
@@ -1197,6 +1695,7 @@ mod tests {
                 address: "foo".to_string(),
                 required_balance: coins(5, "atom").into(),
             })]),
+            task_type: None,
         };
 
         let message = format!(